@@ -7,8 +7,15 @@
 //! the `heapless` crate and run in `#![no_std]` environments.
 
 use std::cell::UnsafeCell;
+use std::marker::PhantomData;
 use std::mem::MaybeUninit;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+pub mod schedulability;
+
+use schedulability::{CriticalSection, Policy};
 
 // ============================================================================
 // Heapless Vec - Fixed-capacity vector without heap allocation
@@ -149,6 +156,7 @@ impl<const N: usize> HeaplessString<N> {
     }
 
     /// Push a character to the string.
+    #[allow(clippy::result_unit_err)] // mirrors heapless::String
     pub fn push(&mut self, ch: char) -> Result<(), ()> {
         let mut buf = [0u8; 4];
         let encoded = ch.encode_utf8(&mut buf);
@@ -161,6 +169,7 @@ impl<const N: usize> HeaplessString<N> {
     }
 
     /// Push a string slice.
+    #[allow(clippy::result_unit_err)] // mirrors heapless::String
     pub fn push_str(&mut self, s: &str) -> Result<(), ()> {
         if self.len + s.len() > N {
             return Err(());
@@ -285,69 +294,163 @@ impl<T, const N: usize> Drop for RingBuffer<T, N> {
 // Memory Pool - Fixed-size object allocator
 // ============================================================================
 
-/// A memory pool for fixed-size allocations.
+/// Marks the end of the free list.
+const POOL_NIL: u32 = u32::MAX;
+
+/// Usage statistics for a [`MemoryPool`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    pub capacity: usize,
+    pub in_use: usize,
+    /// Highest number of blocks ever allocated at the same time.
+    pub peak_in_use: usize,
+    pub allocations: usize,
+    /// Allocation attempts that found the pool empty.
+    pub failures: usize,
+}
+
+/// A lock-free memory pool for fixed-size allocations.
 /// This is similar to `heapless::pool::Pool`.
+///
+/// Free blocks form a Treiber stack. The stack head packs a 32-bit block
+/// index with a 32-bit tag that changes on every update, so a CAS cannot
+/// succeed against a head that was popped and pushed back in between (the
+/// ABA problem). `alloc` and `free` never block or allocate, which makes
+/// them safe to call from interrupt-like contexts.
 pub struct MemoryPool<T, const N: usize> {
-    storage: UnsafeCell<[MaybeUninit<T>; N]>,
-    free_list: UnsafeCell<[usize; N]>,
-    free_count: AtomicUsize,
+    storage: [UnsafeCell<MaybeUninit<T>>; N],
+    /// `next[i]` is the block after `i` in the free list.
+    next: [AtomicU32; N],
+    /// `(tag << 32) | index` of the first free block.
+    head: AtomicU64,
+    in_use: AtomicUsize,
+    peak_in_use: AtomicUsize,
+    allocations: AtomicUsize,
+    failures: AtomicUsize,
 }
 
+// SAFETY: Each block is owned by at most one PoolBox at a time; the free
+// list itself only uses atomics
+unsafe impl<T: Send, const N: usize> Send for MemoryPool<T, N> {}
+unsafe impl<T: Send, const N: usize> Sync for MemoryPool<T, N> {}
+
 /// A handle to an allocated object from the pool.
+///
+/// The block returns to the pool when the handle is dropped.
 pub struct PoolBox<'a, T, const N: usize> {
     pool: &'a MemoryPool<T, N>,
     index: usize,
+    // Sharing or sending the box shares or sends the T inside it
+    _value: PhantomData<&'a mut T>,
 }
 
 impl<T, const N: usize> MemoryPool<T, N> {
     /// Create a new memory pool.
     pub fn new() -> Self {
-        let mut free_list: [usize; N] = [0; N];
-        for i in 0..N {
-            free_list[i] = i;
-        }
+        assert!(N < POOL_NIL as usize, "pool too large for 32-bit indices");
         Self {
-            storage: UnsafeCell::new(unsafe { MaybeUninit::uninit().assume_init() }),
-            free_list: UnsafeCell::new(free_list),
-            free_count: AtomicUsize::new(N),
+            storage: std::array::from_fn(|_| UnsafeCell::new(MaybeUninit::uninit())),
+            next: std::array::from_fn(|i| {
+                AtomicU32::new(if i + 1 < N { (i + 1) as u32 } else { POOL_NIL })
+            }),
+            head: AtomicU64::new(if N > 0 { 0 } else { POOL_NIL as u64 }),
+            in_use: AtomicUsize::new(0),
+            peak_in_use: AtomicUsize::new(0),
+            allocations: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
         }
     }
 
     /// Allocate an object from the pool.
+    /// Returns `None` (and counts a failure) if every block is in use.
     pub fn alloc(&self, value: T) -> Option<PoolBox<'_, T, N>> {
-        let count = self.free_count.load(Ordering::Acquire);
-        if count == 0 {
+        let Some(index) = self.pop_free() else {
+            self.failures.fetch_add(1, Ordering::Relaxed);
             return None;
-        }
-
-        // Note: This is a simplified implementation
-        // Real implementation would use atomic CAS for thread safety
-        let new_count = count - 1;
-        self.free_count.store(new_count, Ordering::Release);
+        };
 
-        let free_list = unsafe { &mut *self.free_list.get() };
-        let index = free_list[new_count];
+        // SAFETY: The block was just unlinked from the free list, so no one
+        // else can reach it
+        unsafe { (*self.storage[index].get()).write(value) };
 
-        let storage = unsafe { &mut *self.storage.get() };
-        storage[index].write(value);
+        let in_use = self.in_use.fetch_add(1, Ordering::Relaxed) + 1;
+        self.peak_in_use.fetch_max(in_use, Ordering::Relaxed);
+        self.allocations.fetch_add(1, Ordering::Relaxed);
 
-        Some(PoolBox { pool: self, index })
+        Some(PoolBox {
+            pool: self,
+            index,
+            _value: PhantomData,
+        })
     }
 
     /// Return the number of available slots.
     pub fn available(&self) -> usize {
-        self.free_count.load(Ordering::Relaxed)
+        N - self.in_use.load(Ordering::Relaxed)
     }
 
-    fn free(&self, index: usize) {
-        let storage = unsafe { &mut *self.storage.get() };
-        unsafe { storage[index].assume_init_drop() };
+    /// Snapshot of the usage counters.
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            capacity: N,
+            in_use: self.in_use.load(Ordering::Relaxed),
+            peak_in_use: self.peak_in_use.load(Ordering::Relaxed),
+            allocations: self.allocations.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+        }
+    }
 
-        let free_list = unsafe { &mut *self.free_list.get() };
-        let count = self.free_count.load(Ordering::Acquire);
-        free_list[count] = index;
-        self.free_count.store(count + 1, Ordering::Release);
+    fn pop_free(&self) -> Option<usize> {
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            let index = head as u32;
+            if index == POOL_NIL {
+                return None;
+            }
+            // May read a stale link if another thread pops this block first;
+            // the tag makes the CAS below fail in that case
+            let next = self.next[index as usize].load(Ordering::Relaxed);
+            let new_head = pack_head(tag_of(head).wrapping_add(1), next);
+            match self.head.compare_exchange_weak(
+                head,
+                new_head,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Some(index as usize),
+                Err(current) => head = current,
+            }
+        }
     }
+
+    fn free(&self, index: usize) {
+        // SAFETY: Called once per allocation, by the PoolBox that owned it
+        unsafe { (*self.storage[index].get()).assume_init_drop() };
+        self.in_use.fetch_sub(1, Ordering::Relaxed);
+
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            self.next[index].store(head as u32, Ordering::Relaxed);
+            let new_head = pack_head(tag_of(head).wrapping_add(1), index as u32);
+            match self.head.compare_exchange_weak(
+                head,
+                new_head,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
+        }
+    }
+}
+
+fn pack_head(tag: u32, index: u32) -> u64 {
+    ((tag as u64) << 32) | index as u64
+}
+
+fn tag_of(head: u64) -> u32 {
+    (head >> 32) as u32
 }
 
 impl<T, const N: usize> Default for MemoryPool<T, N> {
@@ -360,15 +463,15 @@ impl<'a, T, const N: usize> std::ops::Deref for PoolBox<'a, T, N> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        let storage = unsafe { &*self.pool.storage.get() };
-        unsafe { storage[self.index].assume_init_ref() }
+        // SAFETY: The block is initialized and exclusively ours
+        unsafe { (*self.pool.storage[self.index].get()).assume_init_ref() }
     }
 }
 
 impl<'a, T, const N: usize> std::ops::DerefMut for PoolBox<'a, T, N> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        let storage = unsafe { &mut *self.pool.storage.get() };
-        unsafe { storage[self.index].assume_init_mut() }
+        // SAFETY: The block is initialized and exclusively ours
+        unsafe { (*self.pool.storage[self.index].get()).assume_init_mut() }
     }
 }
 
@@ -456,6 +559,241 @@ impl<T, const N: usize> Default for SpscQueue<T, N> {
     }
 }
 
+// ============================================================================
+// Lock-Free MPMC Queue - Multiple Producers Multiple Consumers
+// ============================================================================
+
+/// One queue cell: the sequence number says whose turn it is.
+struct MpmcSlot<T> {
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// A bounded lock-free multi-producer multi-consumer queue.
+/// This is Dmitry Vyukov's sequence-numbered ring, as used by
+/// `heapless::mpmc::Queue`.
+///
+/// Slot `i` starts with sequence `i`. A producer at position `pos` may fill
+/// the slot when its sequence equals `pos`, then publishes `pos + 1`; a
+/// consumer may empty it when the sequence equals `pos + 1`, then hands it
+/// to the next lap with `pos + N`. Producers and consumers only contend on
+/// their own position counter, and all `N` slots are usable.
+pub struct MpmcQueue<T, const N: usize> {
+    slots: [MpmcSlot<T>; N],
+    enqueue_pos: AtomicUsize,
+    dequeue_pos: AtomicUsize,
+}
+
+// SAFETY: A slot's value is only accessed by the thread that won the CAS
+// for that position, and handed over via the slot's sequence number
+unsafe impl<T: Send, const N: usize> Send for MpmcQueue<T, N> {}
+unsafe impl<T: Send, const N: usize> Sync for MpmcQueue<T, N> {}
+
+impl<T, const N: usize> MpmcQueue<T, N> {
+    /// Create a new empty queue.
+    pub fn new() -> Self {
+        assert!(N > 0, "queue capacity must be non-zero");
+        Self {
+            slots: std::array::from_fn(|i| MpmcSlot {
+                sequence: AtomicUsize::new(i),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            }),
+            enqueue_pos: AtomicUsize::new(0),
+            dequeue_pos: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the capacity of the queue.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Approximate number of queued elements (exact when no other thread
+    /// is operating on the queue).
+    pub fn len(&self) -> usize {
+        let tail = self.enqueue_pos.load(Ordering::Acquire);
+        let head = self.dequeue_pos.load(Ordering::Acquire);
+        tail.saturating_sub(head).min(N)
+    }
+
+    /// Check if the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Enqueue from any thread.
+    /// Returns Err if the queue is full.
+    pub fn enqueue(&self, value: T) -> Result<(), T> {
+        let mut pos = self.enqueue_pos.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % N];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let diff = sequence as isize - pos as isize;
+
+            if diff == 0 {
+                // The slot is free for this lap; claim the position
+                match self.enqueue_pos.compare_exchange_weak(
+                    pos,
+                    pos + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.sequence.store(pos + 1, Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                return Err(value); // Queue is full
+            } else {
+                // Another producer claimed this position; catch up
+                pos = self.enqueue_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Dequeue from any thread.
+    /// Returns None if the queue is empty.
+    pub fn dequeue(&self) -> Option<T> {
+        let mut pos = self.dequeue_pos.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % N];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let diff = sequence as isize - (pos + 1) as isize;
+
+            if diff == 0 {
+                match self.dequeue_pos.compare_exchange_weak(
+                    pos,
+                    pos + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        slot.sequence.store(pos + N, Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                return None; // Queue is empty
+            } else {
+                pos = self.dequeue_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl<T, const N: usize> Default for MpmcQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for MpmcQueue<T, N> {
+    fn drop(&mut self) {
+        while self.dequeue().is_some() {}
+    }
+}
+
+/// Outcome of [`stress_mpmc`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MpmcStressReport {
+    pub produced: usize,
+    pub consumed: usize,
+    /// Times a producer found the queue full and retried.
+    pub full_retries: usize,
+    /// Every item was received exactly once.
+    pub exactly_once: bool,
+    /// Each consumer saw every producer's items in the order they were sent.
+    pub per_producer_fifo: bool,
+}
+
+/// Hammer an `MpmcQueue<_, N>` with `producers` x `items_per_producer`
+/// items drained by `consumers` threads, and check nothing was lost,
+/// duplicated or reordered.
+///
+/// Panics if there are no consumers but more items than the queue holds,
+/// since the producers would then spin on a full queue forever.
+pub fn stress_mpmc<const N: usize>(
+    producers: usize,
+    consumers: usize,
+    items_per_producer: usize,
+) -> MpmcStressReport {
+    let total = producers * items_per_producer;
+    assert!(
+        consumers > 0 || total <= N,
+        "{} items cannot fit in a queue of {} without consumers",
+        total,
+        N
+    );
+    // Items are (producer, sequence) pairs so order can be checked per producer
+    let queue: Arc<MpmcQueue<(usize, usize), N>> = Arc::new(MpmcQueue::new());
+    let consumed = Arc::new(AtomicUsize::new(0));
+    let retries = Arc::new(AtomicUsize::new(0));
+
+    let producer_handles: Vec<_> = (0..producers)
+        .map(|producer| {
+            let (queue, retries) = (Arc::clone(&queue), Arc::clone(&retries));
+            thread::spawn(move || {
+                for seq in 0..items_per_producer {
+                    let mut item = (producer, seq);
+                    while let Err(rejected) = queue.enqueue(item) {
+                        item = rejected;
+                        retries.fetch_add(1, Ordering::Relaxed);
+                        thread::yield_now();
+                    }
+                }
+            })
+        })
+        .collect();
+
+    let consumer_handles: Vec<_> = (0..consumers)
+        .map(|_| {
+            let (queue, consumed) = (Arc::clone(&queue), Arc::clone(&consumed));
+            thread::spawn(move || {
+                let mut received = Vec::new();
+                while consumed.load(Ordering::Relaxed) < total {
+                    match queue.dequeue() {
+                        Some(item) => {
+                            received.push(item);
+                            consumed.fetch_add(1, Ordering::Relaxed);
+                        }
+                        None => thread::yield_now(),
+                    }
+                }
+                received
+            })
+        })
+        .collect();
+
+    for handle in producer_handles {
+        handle.join().unwrap();
+    }
+    let mut seen = vec![vec![0u32; items_per_producer]; producers];
+    let mut per_producer_fifo = true;
+    for handle in consumer_handles {
+        let mut last_seq = vec![None; producers];
+        for (producer, seq) in handle.join().unwrap() {
+            seen[producer][seq] += 1;
+            if last_seq[producer].is_some_and(|last| last >= seq) {
+                per_producer_fifo = false;
+            }
+            last_seq[producer] = Some(seq);
+        }
+    }
+
+    MpmcStressReport {
+        produced: total,
+        consumed: consumed.load(Ordering::Relaxed),
+        full_retries: retries.load(Ordering::Relaxed),
+        exactly_once: seen.iter().flatten().all(|&count| count == 1),
+        per_producer_fifo,
+    }
+}
+
 // ============================================================================
 // Priority Queue - For task scheduling
 // ============================================================================
@@ -467,20 +805,60 @@ pub struct PriorityTask<T> {
     pub data: T,
 }
 
+/// Identifies an entry in a [`PriorityQueue`] for removal or re-prioritising.
+///
+/// Handles carry a generation, so a handle to an entry that has since been
+/// popped or removed is rejected even if its slot was reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PqHandle {
+    slot: usize,
+    generation: u32,
+}
+
+struct PqEntry<T> {
+    priority: u8,
+    /// Insertion order, used to keep equal priorities FIFO.
+    seq: u64,
+    data: T,
+}
+
+/// Marks a slot that is not in the heap.
+const PQ_NONE: usize = usize::MAX;
+
 /// A fixed-capacity priority queue for task scheduling.
-/// Uses a simple sorted array approach (not a heap for clarity).
+///
+/// A binary min-heap ordered by `(priority, insertion sequence)`, so insert
+/// and pop are O(log N) and entries of equal priority come out in FIFO
+/// order. Entries live in fixed slots and the heap stores slot indices,
+/// which lets a [`PqHandle`] find its entry for O(log N) removal or
+/// priority change.
 pub struct PriorityQueue<T, const N: usize> {
-    // Store as (priority, data) tuples, sorted by priority
-    buffer: [MaybeUninit<(u8, T)>; N],
+    entries: [MaybeUninit<PqEntry<T>>; N],
+    /// Heap of slot indices.
+    heap: [usize; N],
+    /// `position[slot]` is the slot's index in `heap`, or `PQ_NONE`.
+    position: [usize; N],
+    generation: [u32; N],
+    /// Stack of unused slots.
+    free: [usize; N],
+    free_len: usize,
     len: usize,
+    next_seq: u64,
 }
 
 impl<T, const N: usize> PriorityQueue<T, N> {
     /// Create a new empty priority queue.
     pub fn new() -> Self {
         Self {
-            buffer: unsafe { MaybeUninit::uninit().assume_init() },
+            entries: std::array::from_fn(|_| MaybeUninit::uninit()),
+            heap: [0; N],
+            position: [PQ_NONE; N],
+            generation: [0; N],
+            // Hand out slot 0 first
+            free: std::array::from_fn(|i| N - 1 - i),
+            free_len: N,
             len: 0,
+            next_seq: 0,
         }
     }
 
@@ -501,35 +879,29 @@ impl<T, const N: usize> PriorityQueue<T, N> {
 
     /// Insert a task with given priority.
     /// Lower priority numbers are higher priority.
-    pub fn insert(&mut self, priority: u8, data: T) -> Result<(), T> {
+    pub fn insert(&mut self, priority: u8, data: T) -> Result<PqHandle, T> {
         if self.is_full() {
             return Err(data);
         }
 
-        // Find insertion point (maintain sorted order, lower priority number = higher priority)
-        let mut insert_idx = self.len;
-        for i in 0..self.len {
-            let existing_priority = unsafe { (*self.buffer[i].as_ptr()).0 };
-            if priority < existing_priority {
-                insert_idx = i;
-                break;
-            }
-        }
-
-        // Shift elements to make room
-        for i in (insert_idx..self.len).rev() {
-            unsafe {
-                let src = self.buffer[i].as_ptr();
-                let dst = self.buffer[i + 1].as_mut_ptr();
-                std::ptr::copy_nonoverlapping(src, dst, 1);
-            }
-        }
+        self.free_len -= 1;
+        let slot = self.free[self.free_len];
+        let seq = self.take_seq();
+        self.entries[slot].write(PqEntry {
+            priority,
+            seq,
+            data,
+        });
 
-        // Insert new element
-        self.buffer[insert_idx].write((priority, data));
+        self.heap[self.len] = slot;
+        self.position[slot] = self.len;
         self.len += 1;
+        self.sift_up(self.len - 1);
 
-        Ok(())
+        Ok(PqHandle {
+            slot,
+            generation: self.generation[slot],
+        })
     }
 
     /// Get the highest priority task (lowest priority number).
@@ -537,22 +909,7 @@ impl<T, const N: usize> PriorityQueue<T, N> {
         if self.is_empty() {
             return None;
         }
-
-        // Read the first element
-        let (priority, data) = unsafe { self.buffer[0].assume_init_read() };
-
-        // Shift all elements down
-        for i in 1..self.len {
-            unsafe {
-                let src = self.buffer[i].as_ptr();
-                let dst = self.buffer[i - 1].as_mut_ptr();
-                std::ptr::copy_nonoverlapping(src, dst, 1);
-            }
-        }
-
-        self.len -= 1;
-
-        Some(PriorityTask { priority, data })
+        Some(self.remove_at(0))
     }
 
     /// Peek at the highest priority task.
@@ -560,12 +917,146 @@ impl<T, const N: usize> PriorityQueue<T, N> {
         if self.is_empty() {
             return None;
         }
-        let (priority, ref data) = unsafe { &*self.buffer[0].as_ptr() };
+        let entry = self.entry(self.heap[0]);
         Some(PriorityTask {
-            priority: *priority,
-            data,
+            priority: entry.priority,
+            data: &entry.data,
         })
     }
+
+    /// Remove a specific task, wherever it is in the queue.
+    pub fn remove(&mut self, handle: PqHandle) -> Option<PriorityTask<T>> {
+        let index = self.index_of(handle)?;
+        Some(self.remove_at(index))
+    }
+
+    /// Change a task's priority. The task goes behind any tasks already
+    /// queued at the new priority, like `vTaskPrioritySet` moving a task to
+    /// the end of its new ready list. Returns false for a stale handle.
+    pub fn change_priority(&mut self, handle: PqHandle, priority: u8) -> bool {
+        let Some(index) = self.index_of(handle) else {
+            return false;
+        };
+        let seq = self.take_seq();
+        // SAFETY: Slots referenced by the heap are initialized
+        let entry = unsafe { self.entries[handle.slot].assume_init_mut() };
+        entry.priority = priority;
+        entry.seq = seq;
+
+        self.sift_up(index);
+        self.sift_down(self.position[handle.slot]);
+        true
+    }
+
+    /// Look at a task by handle.
+    pub fn get(&self, handle: PqHandle) -> Option<PriorityTask<&T>> {
+        let index = self.index_of(handle)?;
+        let entry = self.entry(self.heap[index]);
+        Some(PriorityTask {
+            priority: entry.priority,
+            data: &entry.data,
+        })
+    }
+
+    /// Is the handle's task still queued?
+    pub fn contains(&self, handle: PqHandle) -> bool {
+        self.index_of(handle).is_some()
+    }
+
+    /// Pop tasks in order while their priority is at or above `threshold`
+    /// (priority number `<= threshold`). Tasks below the threshold stay
+    /// queued even if the iterator is not run to completion.
+    pub fn drain_ready(&mut self, threshold: u8) -> DrainReady<'_, T, N> {
+        DrainReady {
+            queue: self,
+            threshold,
+        }
+    }
+
+    fn take_seq(&mut self) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        seq
+    }
+
+    fn entry(&self, slot: usize) -> &PqEntry<T> {
+        // SAFETY: Only called with slots that are in the heap
+        unsafe { self.entries[slot].assume_init_ref() }
+    }
+
+    fn key(&self, index: usize) -> (u8, u64) {
+        let entry = self.entry(self.heap[index]);
+        (entry.priority, entry.seq)
+    }
+
+    fn index_of(&self, handle: PqHandle) -> Option<usize> {
+        if handle.slot >= N || self.generation[handle.slot] != handle.generation {
+            return None;
+        }
+        match self.position[handle.slot] {
+            PQ_NONE => None,
+            index => Some(index),
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        self.position[self.heap[a]] = a;
+        self.position[self.heap[b]] = b;
+    }
+
+    fn sift_up(&mut self, mut index: usize) {
+        while index > 0 {
+            let parent = (index - 1) / 2;
+            if self.key(index) >= self.key(parent) {
+                break;
+            }
+            self.swap(index, parent);
+            index = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut index: usize) {
+        loop {
+            let mut smallest = index;
+            for child in [2 * index + 1, 2 * index + 2] {
+                if child < self.len && self.key(child) < self.key(smallest) {
+                    smallest = child;
+                }
+            }
+            if smallest == index {
+                break;
+            }
+            self.swap(index, smallest);
+            index = smallest;
+        }
+    }
+
+    fn remove_at(&mut self, index: usize) -> PriorityTask<T> {
+        let slot = self.heap[index];
+        let last = self.len - 1;
+        self.swap(index, last);
+        self.len -= 1;
+        self.position[slot] = PQ_NONE;
+        if index < self.len {
+            // The former last entry now sits at `index`; restore heap order
+            let moved = self.heap[index];
+            self.sift_up(index);
+            self.sift_down(self.position[moved]);
+        }
+
+        // Invalidate outstanding handles before the slot is reused
+        self.generation[slot] = self.generation[slot].wrapping_add(1);
+        self.free[self.free_len] = slot;
+        self.free_len += 1;
+
+        // SAFETY: The slot was in the heap and is now unreachable
+        let entry = unsafe { self.entries[slot].assume_init_read() };
+        PriorityTask {
+            priority: entry.priority,
+            data: entry.data,
+        }
+    }
 }
 
 impl<T, const N: usize> Default for PriorityQueue<T, N> {
@@ -577,9 +1068,26 @@ impl<T, const N: usize> Default for PriorityQueue<T, N> {
 impl<T, const N: usize> Drop for PriorityQueue<T, N> {
     fn drop(&mut self) {
         // Drop all remaining elements
-        for i in 0..self.len {
-            unsafe { self.buffer[i].assume_init_drop() };
+        for index in 0..self.len {
+            unsafe { self.entries[self.heap[index]].assume_init_drop() };
+        }
+    }
+}
+
+/// Iterator returned by [`PriorityQueue::drain_ready`].
+pub struct DrainReady<'a, T, const N: usize> {
+    queue: &'a mut PriorityQueue<T, N>,
+    threshold: u8,
+}
+
+impl<T, const N: usize> Iterator for DrainReady<'_, T, N> {
+    type Item = PriorityTask<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.queue.peek()?.priority > self.threshold {
+            return None;
         }
+        self.queue.pop()
     }
 }
 
@@ -647,6 +1155,12 @@ impl<T: Copy + Default, const N: usize> StaticBuffer<T, N> {
     }
 }
 
+impl<T: Copy + Default, const N: usize> Default for StaticBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 // ============================================================================
 // Deadline Scheduling Simulation
 // ============================================================================
@@ -658,6 +1172,7 @@ pub struct RealTimeTask {
     pub period_ms: u32,    // Task period
     pub deadline_ms: u32,  // Relative deadline
     pub wcet_ms: u32,      // Worst-case execution time
+    pub jitter_ms: u32,    // Maximum release jitter
     pub last_release: u64, // Last release time
 }
 
@@ -829,6 +1344,35 @@ fn demo_memory_pool() {
     // Allocate again
     let _block3 = pool.alloc([0u8; 64]).unwrap();
     println!("Available after new allocation: {}", pool.available());
+
+    // Share one pool between "tasks" and an "ISR"; no locks involved
+    let shared: Arc<MemoryPool<u64, 8>> = Arc::new(MemoryPool::new());
+    let handles: Vec<_> = (0..4)
+        .map(|id| {
+            let pool = Arc::clone(&shared);
+            thread::spawn(move || {
+                // Each thread holds up to 3 blocks at a time, so 4 threads
+                // can compete for the 8 blocks
+                let mut held = Vec::new();
+                for i in 0..1000 {
+                    if let Some(block) = pool.alloc(id * 1000 + i) {
+                        held.push(block);
+                    }
+                    if held.len() == 3 {
+                        held.clear();
+                    }
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let stats = shared.stats();
+    println!(
+        "Shared pool after 4 threads x 1000 allocations: {} in use, peak {}/{}, {} failures",
+        stats.in_use, stats.peak_in_use, stats.capacity, stats.failures
+    );
     println!();
 }
 
@@ -854,6 +1398,54 @@ fn demo_spsc_queue() {
     println!("\n");
 }
 
+fn demo_mpmc_queue() {
+    println!("=== MPMC Queue Demo ===\n");
+
+    // Two "ISRs" feed one queue that a worker task drains
+    let queue: Arc<MpmcQueue<(&str, u32), 4>> = Arc::new(MpmcQueue::new());
+    println!(
+        "Lock-free multi-producer multi-consumer queue (capacity {})",
+        queue.capacity()
+    );
+
+    let producers: Vec<_> = ["uart", "adc"]
+        .into_iter()
+        .map(|source| {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || {
+                for i in 0..3 {
+                    while queue.enqueue((source, i)).is_err() {
+                        thread::yield_now();
+                    }
+                }
+            })
+        })
+        .collect();
+    // More events than slots: drain while the producers are still running
+    let mut events = Vec::new();
+    while events.len() < 6 {
+        match queue.dequeue() {
+            Some(event) => events.push(event),
+            None => thread::yield_now(),
+        }
+    }
+    for producer in producers {
+        producer.join().unwrap();
+    }
+    println!("Drained {} events: {:?}", events.len(), events);
+
+    let report = stress_mpmc::<64>(8, 4, 10_000);
+    println!(
+        "Stress test (8 producers, 4 consumers): {}/{} items, {} full retries, exactly once: {}, FIFO per producer: {}",
+        report.consumed,
+        report.produced,
+        report.full_retries,
+        report.exactly_once,
+        report.per_producer_fifo
+    );
+    println!();
+}
+
 fn demo_priority_queue() {
     println!("=== Priority Queue Demo ===\n");
 
@@ -862,10 +1454,22 @@ fn demo_priority_queue() {
     // Insert tasks with different priorities
     pq.insert(3, "Low priority task").unwrap();
     pq.insert(1, "High priority task").unwrap();
-    pq.insert(2, "Medium priority task").unwrap();
+    let medium = pq.insert(2, "Medium priority task").unwrap();
     pq.insert(0, "Critical task").unwrap();
+    pq.insert(1, "Second high priority task").unwrap();
+    let stale = pq.insert(3, "Cancelled task").unwrap();
+
+    // Handles allow O(log N) re-prioritising and cancellation
+    pq.change_priority(medium, 1);
+    pq.remove(stale);
+    println!("Boosted medium task to priority 1, cancelled one task");
 
-    println!("Tasks in priority order:");
+    println!("Ready tasks at priority 0-1 (FIFO within a level):");
+    for task in pq.drain_ready(1) {
+        println!("  Priority {}: {}", task.priority, task.data);
+    }
+
+    println!("Remaining tasks:");
     while let Some(task) = pq.pop() {
         println!("  Priority {}: {}", task.priority, task.data);
     }
@@ -902,6 +1506,7 @@ fn demo_schedulability_analysis() {
             period_ms: 10,
             deadline_ms: 10,
             wcet_ms: 2,
+            jitter_ms: 0,
             last_release: 0,
         },
         RealTimeTask {
@@ -909,6 +1514,7 @@ fn demo_schedulability_analysis() {
             period_ms: 20,
             deadline_ms: 20,
             wcet_ms: 5,
+            jitter_ms: 0,
             last_release: 0,
         },
         RealTimeTask {
@@ -916,6 +1522,7 @@ fn demo_schedulability_analysis() {
             period_ms: 100,
             deadline_ms: 100,
             wcet_ms: 10,
+            jitter_ms: 0,
             last_release: 0,
         },
     ];
@@ -945,6 +1552,113 @@ fn demo_schedulability_analysis() {
         }
     }
     println!();
+
+    demo_exact_analysis();
+}
+
+fn demo_exact_analysis() {
+    println!("=== Exact Schedulability Analysis ===\n");
+
+    // A task set the utilisation bound rejects, but which is schedulable
+    // once priorities follow deadlines
+    let tasks = [
+        RealTimeTask {
+            name: "Motor PWM",
+            period_ms: 5,
+            deadline_ms: 5,
+            wcet_ms: 1,
+            jitter_ms: 0,
+            last_release: 0,
+        },
+        RealTimeTask {
+            name: "Sensor Fusion",
+            period_ms: 10,
+            deadline_ms: 10,
+            wcet_ms: 3,
+            jitter_ms: 1,
+            last_release: 0,
+        },
+        RealTimeTask {
+            name: "Comms",
+            period_ms: 30,
+            deadline_ms: 4,
+            wcet_ms: 2,
+            jitter_ms: 0,
+            last_release: 0,
+        },
+        RealTimeTask {
+            name: "Logger",
+            period_ms: 60,
+            deadline_ms: 60,
+            wcet_ms: 20,
+            jitter_ms: 0,
+            last_release: 0,
+        },
+    ];
+
+    // Sensor Fusion and Logger share the SPI bus
+    let sections = [
+        CriticalSection {
+            task: 1,
+            resource: 0,
+            duration: 1,
+        },
+        CriticalSection {
+            task: 3,
+            resource: 0,
+            duration: 2,
+        },
+    ];
+
+    println!(
+        "Utilisation: {:.1}%",
+        schedulability::utilization(&tasks) * 100.0
+    );
+    println!("Liu & Layland test:");
+    let passes = RealTimeTask::is_schedulable_rm(&tasks);
+    println!("  Passes bound: {}", passes);
+
+    if let Err(error) = schedulability::validate(&tasks) {
+        println!("Invalid task set: {:?}", error);
+        return;
+    }
+    // Validated above, so the analyses below cannot fail
+    let order = schedulability::deadline_monotonic_order(&tasks);
+    let blocking = schedulability::blocking_times(&tasks, &order, &sections);
+    println!("\nDeadline-monotonic RTA (with blocking and jitter):");
+    for result in schedulability::response_times(&tasks, &order, &blocking).unwrap() {
+        let task = &tasks[result.task];
+        match result.response {
+            Some(r) => println!(
+                "  {:14} B={}ms R={}ms (deadline {}ms) ✓",
+                task.name, result.blocking, r, task.deadline_ms
+            ),
+            None => println!("  {:14} B={}ms deadline miss ✗", task.name, result.blocking),
+        }
+    }
+
+    println!("\nEDF processor-demand analysis:");
+    println!(
+        "  {:?}",
+        schedulability::edf_demand_analysis(&tasks).unwrap()
+    );
+
+    println!("\nRate-monotonic timeline (first 30ms):");
+    let rm = schedulability::rate_monotonic_order(&tasks);
+    let timeline = schedulability::simulate(&tasks, &Policy::FixedPriority(rm), 30).unwrap();
+    print!("{}", timeline.render(&tasks));
+    for miss in &timeline.misses {
+        println!(
+            "  {} missed deadline at {}ms ({}ms of work left)",
+            tasks[miss.task].name, miss.deadline, miss.remaining
+        );
+    }
+
+    println!("\nDeadline-monotonic timeline (first 30ms):");
+    let timeline = schedulability::simulate(&tasks, &Policy::FixedPriority(order), 30).unwrap();
+    print!("{}", timeline.render(&tasks));
+    println!("  Deadline misses: {}", timeline.misses.len());
+    println!();
 }
 
 fn main() {
@@ -956,6 +1670,7 @@ fn main() {
     demo_ring_buffer();
     demo_memory_pool();
     demo_spsc_queue();
+    demo_mpmc_queue();
     demo_priority_queue();
     demo_static_allocation();
     demo_schedulability_analysis();
@@ -965,11 +1680,161 @@ fn main() {
     println!("  • HeaplessVec - Fixed-capacity vector without allocation");
     println!("  • HeaplessString - Fixed-capacity string without allocation");
     println!("  • RingBuffer - FIFO queue with constant-time operations");
-    println!("  • MemoryPool - O(1) allocation from fixed pool");
+    println!("  • MemoryPool - Lock-free O(1) allocation from fixed pool");
     println!("  • SpscQueue - Lock-free single-producer single-consumer");
-    println!("  • PriorityQueue - Task scheduling by priority");
+    println!("  • MpmcQueue - Lock-free multi-producer multi-consumer");
+    println!("  • PriorityQueue - Binary heap with stable order and handles");
     println!("  • StaticBuffer - Global static allocation");
-    println!("  • Schedulability analysis - RM and response time analysis");
+    println!("  • Schedulability analysis - RM bound, RTA with blocking/jitter, EDF");
+    println!("  • Timeline simulation - per-task schedule with deadline misses");
     println!();
     println!("For production use, see the `heapless` crate.");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_exhaustion_and_stats() {
+        let pool: MemoryPool<String, 2> = MemoryPool::new();
+        let a = pool.alloc("a".to_string()).unwrap();
+        let b = pool.alloc("b".to_string()).unwrap();
+        assert!(pool.alloc("c".to_string()).is_none());
+        assert_eq!(pool.available(), 0);
+
+        drop(a);
+        let c = pool.alloc("c".to_string()).unwrap();
+        assert_eq!((b.as_str(), c.as_str()), ("b", "c"));
+        drop((b, c));
+
+        let stats = pool.stats();
+        assert_eq!(stats.in_use, 0);
+        assert_eq!(stats.peak_in_use, 2);
+        assert_eq!(stats.allocations, 3);
+        assert_eq!(stats.failures, 1);
+    }
+
+    #[test]
+    fn test_pool_concurrent_blocks_are_exclusive() {
+        const THREADS: usize = 8;
+        let pool: Arc<MemoryPool<usize, 4>> = Arc::new(MemoryPool::new());
+        let handles: Vec<_> = (0..THREADS)
+            .map(|id| {
+                let pool = Arc::clone(&pool);
+                thread::spawn(move || {
+                    for _ in 0..10_000 {
+                        if let Some(mut block) = pool.alloc(id) {
+                            // Nobody else may touch this block while we hold it
+                            *block = id;
+                            thread::yield_now();
+                            assert_eq!(*block, id);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let stats = pool.stats();
+        assert_eq!(stats.in_use, 0);
+        assert!(stats.peak_in_use <= 4);
+        assert_eq!(stats.allocations + stats.failures, THREADS * 10_000);
+        assert_eq!(pool.available(), 4);
+    }
+
+    #[test]
+    fn test_mpmc_full_and_empty() {
+        let queue: MpmcQueue<u8, 3> = MpmcQueue::new();
+        assert_eq!(queue.dequeue(), None);
+        for i in 0..3 {
+            queue.enqueue(i).unwrap();
+        }
+        assert_eq!(queue.enqueue(9), Err(9));
+        assert_eq!(queue.len(), 3);
+
+        // Wrap around a few laps
+        for i in 3..10 {
+            assert_eq!(queue.dequeue(), Some(i - 3));
+            queue.enqueue(i).unwrap();
+        }
+        assert_eq!(queue.dequeue(), Some(7));
+    }
+
+    #[test]
+    fn test_mpmc_stress() {
+        let report = stress_mpmc::<8>(6, 6, 20_000);
+        assert_eq!(report.consumed, report.produced);
+        assert!(report.exactly_once);
+        assert!(report.per_producer_fifo);
+
+        // Without consumers, only what fits in the queue can be produced
+        let report = stress_mpmc::<8>(2, 0, 4);
+        assert_eq!((report.produced, report.consumed), (8, 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "without consumers")]
+    fn test_mpmc_stress_rejects_unbounded_wait() {
+        stress_mpmc::<8>(2, 0, 5);
+    }
+
+    #[test]
+    fn test_mpmc_drops_remaining_items() {
+        let marker = Arc::new(());
+        let queue: MpmcQueue<Arc<()>, 4> = MpmcQueue::new();
+        queue.enqueue(Arc::clone(&marker)).unwrap();
+        queue.enqueue(Arc::clone(&marker)).unwrap();
+        drop(queue);
+        assert_eq!(Arc::strong_count(&marker), 1);
+    }
+
+    #[test]
+    fn test_priority_queue_fifo_within_priority() {
+        let mut pq: PriorityQueue<u32, 16> = PriorityQueue::new();
+        for i in 0..12 {
+            pq.insert((i % 3) as u8, i).unwrap();
+        }
+        let order: Vec<u32> = std::iter::from_fn(|| pq.pop().map(|t| t.data)).collect();
+        assert_eq!(order, vec![0, 3, 6, 9, 1, 4, 7, 10, 2, 5, 8, 11]);
+    }
+
+    #[test]
+    fn test_priority_queue_handles() {
+        let mut pq: PriorityQueue<&str, 4> = PriorityQueue::new();
+        let a = pq.insert(5, "a").unwrap();
+        let b = pq.insert(5, "b").unwrap();
+        let c = pq.insert(7, "c").unwrap();
+        pq.insert(6, "d").unwrap();
+        assert_eq!(pq.insert(0, "full"), Err("full"));
+
+        assert!(pq.change_priority(c, 1));
+        assert_eq!(pq.get(c).unwrap().priority, 1);
+        assert_eq!(pq.remove(b).unwrap().data, "b");
+        assert!(!pq.contains(b));
+
+        // The freed slot is reused, but the old handle stays invalid
+        let e = pq.insert(5, "e").unwrap();
+        assert!(pq.remove(b).is_none());
+        assert!(!pq.change_priority(b, 0));
+
+        let order: Vec<&str> = std::iter::from_fn(|| pq.pop().map(|t| t.data)).collect();
+        assert_eq!(order, vec!["c", "a", "e", "d"]);
+        assert!(!pq.contains(a) && !pq.contains(e));
+    }
+
+    #[test]
+    fn test_priority_queue_drain_ready() {
+        let mut pq: PriorityQueue<String, 8> = PriorityQueue::new();
+        for (prio, name) in [(4, "idle"), (1, "isr"), (2, "ctrl"), (1, "comm")] {
+            pq.insert(prio, name.to_string()).unwrap();
+        }
+
+        let ready: Vec<String> = pq.drain_ready(2).map(|t| t.data).collect();
+        assert_eq!(ready, vec!["isr", "comm", "ctrl"]);
+        assert_eq!(pq.len(), 1);
+        assert_eq!(pq.peek().unwrap().data, "idle");
+    }
+}
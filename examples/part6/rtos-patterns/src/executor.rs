@@ -0,0 +1,224 @@
+//! Embassy-style single-threaded executor
+//!
+//! Tasks are ordinary `Future`s. A task is only polled again after its
+//! [`Waker`] fires, exactly like Embassy's run queue: a task parked on an
+//! empty channel costs nothing until a sender wakes it.
+//!
+//! Wakers are `Send + Sync`, so an ISR-like thread may wake a task, but the
+//! futures themselves are polled on the thread that calls
+//! [`Executor::run_until_idle`].
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+/// Handle returned by [`Executor::spawn`].
+pub type AsyncTaskId = usize;
+
+type BoxedTask = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Wakes a task by pushing its id onto the shared run queue.
+struct TaskWaker {
+    id: AsyncTaskId,
+    run_queue: Arc<Mutex<VecDeque<AsyncTaskId>>>,
+    /// Set while the id is in the run queue, so repeated wakes between two
+    /// polls enqueue the task only once.
+    queued: AtomicBool,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            self.run_queue.lock().unwrap().push_back(self.id);
+        }
+    }
+}
+
+struct TaskSlot {
+    future: Option<BoxedTask>,
+    waker: Arc<TaskWaker>,
+    polls: u64,
+    done: bool,
+}
+
+/// A cooperative executor that only polls woken tasks.
+pub struct Executor {
+    tasks: RefCell<Vec<TaskSlot>>,
+    run_queue: Arc<Mutex<VecDeque<AsyncTaskId>>>,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Self {
+            tasks: RefCell::new(Vec::new()),
+            run_queue: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    /// Spawn a task. It is polled for the first time on the next run.
+    ///
+    /// May be called from inside a running task.
+    pub fn spawn<F: Future<Output = ()> + 'static>(&self, future: F) -> AsyncTaskId {
+        let mut tasks = self.tasks.borrow_mut();
+        let id = tasks.len();
+        let waker = Arc::new(TaskWaker {
+            id,
+            run_queue: Arc::clone(&self.run_queue),
+            queued: AtomicBool::new(false),
+        });
+        waker.wake_by_ref();
+        tasks.push(TaskSlot {
+            future: Some(Box::pin(future)),
+            waker,
+            polls: 0,
+            done: false,
+        });
+        id
+    }
+
+    /// Poll woken tasks until none are left to run. Returns the number of
+    /// polls performed.
+    pub fn run_until_idle(&self) -> usize {
+        let mut polls = 0;
+        while let Some(id) = self.next_woken() {
+            // Take the future out so the task may spawn while being polled
+            let (future, waker) = {
+                let mut tasks = self.tasks.borrow_mut();
+                let slot = &mut tasks[id];
+                slot.waker.queued.store(false, Ordering::Release);
+                match slot.future.take() {
+                    Some(future) => (future, Waker::from(Arc::clone(&slot.waker))),
+                    // Already finished; a stale wake-up
+                    None => continue,
+                }
+            };
+
+            let mut future = future;
+            let mut cx = Context::from_waker(&waker);
+            let result = future.as_mut().poll(&mut cx);
+            polls += 1;
+
+            let mut tasks = self.tasks.borrow_mut();
+            let slot = &mut tasks[id];
+            slot.polls += 1;
+            match result {
+                Poll::Ready(()) => slot.done = true,
+                Poll::Pending => slot.future = Some(future),
+            }
+        }
+        polls
+    }
+
+    /// Has the task run to completion?
+    pub fn is_done(&self, id: AsyncTaskId) -> bool {
+        self.tasks.borrow().get(id).is_some_and(|slot| slot.done)
+    }
+
+    /// How many times the task has been polled.
+    pub fn poll_count(&self, id: AsyncTaskId) -> u64 {
+        self.tasks.borrow().get(id).map_or(0, |slot| slot.polls)
+    }
+
+    /// Number of spawned tasks that have not completed.
+    pub fn pending_tasks(&self) -> usize {
+        self.tasks.borrow().iter().filter(|slot| !slot.done).count()
+    }
+
+    fn next_woken(&self) -> Option<AsyncTaskId> {
+        self.run_queue.lock().unwrap().pop_front()
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Give other tasks a chance to run (Embassy's `yield_now`).
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            Poll::Ready(())
+        } else {
+            self.yielded = true;
+            // Re-queue ourselves behind everything already woken
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_yield_interleaves_tasks() {
+        let executor = Executor::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        for name in ["a", "b"] {
+            let log = Rc::clone(&log);
+            executor.spawn(async move {
+                for step in 0..2 {
+                    log.borrow_mut().push((name, step));
+                    yield_now().await;
+                }
+            });
+        }
+
+        assert_eq!(executor.run_until_idle(), 6);
+        assert_eq!(*log.borrow(), vec![("a", 0), ("b", 0), ("a", 1), ("b", 1)]);
+        assert_eq!(executor.pending_tasks(), 0);
+    }
+
+    #[test]
+    fn test_unwoken_task_is_not_polled() {
+        let executor = Executor::new();
+        let parked: Rc<RefCell<Option<Waker>>> = Rc::new(RefCell::new(None));
+
+        let slot = Rc::clone(&parked);
+        let id = executor.spawn(std::future::poll_fn(move |cx| {
+            if slot.borrow().is_some() {
+                return Poll::Ready(());
+            }
+            *slot.borrow_mut() = Some(cx.waker().clone());
+            Poll::Pending
+        }));
+
+        executor.run_until_idle();
+        executor.run_until_idle();
+        assert_eq!(executor.poll_count(id), 1);
+
+        // Waking from another thread re-queues the task exactly once
+        let waker = parked.borrow().clone().unwrap();
+        std::thread::spawn(move || {
+            waker.wake_by_ref();
+            waker.wake();
+        })
+        .join()
+        .unwrap();
+        assert_eq!(executor.run_until_idle(), 1);
+        assert!(executor.is_done(id));
+    }
+}
@@ -6,12 +6,25 @@
 //! Note: This is a conceptual example. Real RTOS development uses
 //! frameworks like Embassy or bindings to FreeRTOS, Zephyr, etc.
 
-use std::cell::RefCell;
 use std::collections::VecDeque;
-use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::Duration;
+
+pub mod executor;
+pub mod priority_mutex;
+pub mod scheduler;
+pub mod timer_service;
+
+use executor::{yield_now, Executor};
+use priority_mutex::{LockError, PriorityMutex, TaskRegistry};
+use scheduler::{Scheduler, TaskAction};
+use timer_service::{TimerMode, TimerService};
 
 // ============================================================================
 // Task Priorities and States (FreeRTOS-style)
@@ -40,10 +53,15 @@ pub enum TaskState {
 }
 
 /// Task Control Block (TCB) - metadata for a task.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TaskControlBlock {
     pub name: &'static str,
+    /// Effective priority. A [`TaskRegistry`] raises it above
+    /// `base_priority` by priority inheritance; [`Scheduler::set_priority`]
+    /// keeps the two equal.
     pub priority: TaskPriority,
+    /// Priority assigned by the application.
+    pub base_priority: TaskPriority,
     pub state: TaskState,
     pub stack_size: usize,
     pub stack_high_water_mark: usize,
@@ -55,6 +73,7 @@ impl TaskControlBlock {
         Self {
             name,
             priority,
+            base_priority: priority,
             state: TaskState::Ready,
             stack_size,
             stack_high_water_mark: stack_size,
@@ -68,8 +87,13 @@ impl TaskControlBlock {
 // ============================================================================
 
 /// A fixed-size message queue for inter-task communication.
+///
+/// Blocked senders and receivers park on a condition variable and are woken
+/// as soon as space or a message becomes available.
 pub struct MessageQueue<T, const N: usize> {
     buffer: Mutex<VecDeque<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
     capacity: usize,
 }
 
@@ -77,44 +101,39 @@ impl<T, const N: usize> MessageQueue<T, N> {
     pub fn new() -> Self {
         Self {
             buffer: Mutex::new(VecDeque::with_capacity(N)),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
             capacity: N,
         }
     }
 
-    /// Send a message (blocks if full - simplified).
+    /// Send a message, blocking up to `timeout_ms` while the queue is full.
+    ///
+    /// On timeout the item is handed back to the caller.
     pub fn send(&self, item: T, timeout_ms: u32) -> Result<(), T> {
-        let start = Instant::now();
-        loop {
-            {
-                let mut queue = self.buffer.lock().unwrap();
-                if queue.len() < self.capacity {
-                    queue.push_back(item);
-                    return Ok(());
-                }
-            }
-            if start.elapsed() > Duration::from_millis(timeout_ms as u64) {
-                // In real RTOS, would return the item
-                return Err(item);
-            }
-            thread::sleep(Duration::from_millis(1));
+        let queue = self.buffer.lock().unwrap();
+        let (mut queue, _) = self
+            .not_full
+            .wait_timeout_while(queue, timeout(timeout_ms), |q| q.len() >= self.capacity)
+            .unwrap();
+        if queue.len() >= self.capacity {
+            return Err(item);
         }
+        queue.push_back(item);
+        self.not_empty.notify_one();
+        Ok(())
     }
 
-    /// Receive a message (blocks if empty).
+    /// Receive a message, blocking up to `timeout_ms` while the queue is empty.
     pub fn receive(&self, timeout_ms: u32) -> Option<T> {
-        let start = Instant::now();
-        loop {
-            {
-                let mut queue = self.buffer.lock().unwrap();
-                if let Some(item) = queue.pop_front() {
-                    return Some(item);
-                }
-            }
-            if start.elapsed() > Duration::from_millis(timeout_ms as u64) {
-                return None;
-            }
-            thread::sleep(Duration::from_millis(1));
-        }
+        let queue = self.buffer.lock().unwrap();
+        let (mut queue, _) = self
+            .not_empty
+            .wait_timeout_while(queue, timeout(timeout_ms), |q| q.is_empty())
+            .unwrap();
+        let item = queue.pop_front()?;
+        self.not_full.notify_one();
+        Some(item)
     }
 
     /// Check number of messages waiting.
@@ -134,13 +153,19 @@ impl<T, const N: usize> Default for MessageQueue<T, N> {
     }
 }
 
+/// Convert an RTOS-style millisecond timeout into a `Duration`.
+fn timeout(timeout_ms: u32) -> Duration {
+    Duration::from_millis(timeout_ms as u64)
+}
+
 // ============================================================================
 // Semaphore (Counting and Binary)
 // ============================================================================
 
 /// A counting semaphore for resource management.
 pub struct Semaphore {
-    count: AtomicUsize,
+    count: Mutex<usize>,
+    available: Condvar,
     max_count: usize,
 }
 
@@ -148,7 +173,8 @@ impl Semaphore {
     /// Create a counting semaphore.
     pub fn new(initial_count: usize, max_count: usize) -> Self {
         Self {
-            count: AtomicUsize::new(initial_count),
+            count: Mutex::new(initial_count),
+            available: Condvar::new(),
             max_count,
         }
     }
@@ -158,47 +184,34 @@ impl Semaphore {
         Self::new(if initial { 1 } else { 0 }, 1)
     }
 
-    /// Take (acquire) the semaphore.
+    /// Take (acquire) the semaphore, blocking up to `timeout_ms`.
     pub fn take(&self, timeout_ms: u32) -> bool {
-        let start = Instant::now();
-        loop {
-            let current = self.count.load(Ordering::Acquire);
-            if current > 0 {
-                if self
-                    .count
-                    .compare_exchange(current, current - 1, Ordering::AcqRel, Ordering::Relaxed)
-                    .is_ok()
-                {
-                    return true;
-                }
-            }
-            if start.elapsed() > Duration::from_millis(timeout_ms as u64) {
-                return false;
-            }
-            thread::yield_now();
+        let count = self.count.lock().unwrap();
+        let (mut count, _) = self
+            .available
+            .wait_timeout_while(count, timeout(timeout_ms), |c| *c == 0)
+            .unwrap();
+        if *count == 0 {
+            return false;
         }
+        *count -= 1;
+        true
     }
 
-    /// Give (release) the semaphore.
+    /// Give (release) the semaphore, waking one waiter.
     pub fn give(&self) -> bool {
-        loop {
-            let current = self.count.load(Ordering::Acquire);
-            if current >= self.max_count {
-                return false; // Already at max
-            }
-            if self
-                .count
-                .compare_exchange(current, current + 1, Ordering::AcqRel, Ordering::Relaxed)
-                .is_ok()
-            {
-                return true;
-            }
+        let mut count = self.count.lock().unwrap();
+        if *count >= self.max_count {
+            return false; // Already at max
         }
+        *count += 1;
+        self.available.notify_one();
+        true
     }
 
     /// Get current count.
     pub fn count(&self) -> usize {
-        self.count.load(Ordering::Relaxed)
+        *self.count.lock().unwrap()
     }
 }
 
@@ -207,66 +220,69 @@ impl Semaphore {
 // ============================================================================
 
 /// Event flags for task synchronization.
+///
+/// Every change to the flag word wakes all waiters so each can re-check
+/// its own bit mask.
 pub struct EventFlags {
-    flags: AtomicU32,
+    flags: Mutex<u32>,
+    changed: Condvar,
 }
 
 impl EventFlags {
     pub fn new() -> Self {
         Self {
-            flags: AtomicU32::new(0),
+            flags: Mutex::new(0),
+            changed: Condvar::new(),
         }
     }
 
     /// Set specific flags.
     pub fn set(&self, bits: u32) {
-        self.flags.fetch_or(bits, Ordering::Release);
+        *self.flags.lock().unwrap() |= bits;
+        self.changed.notify_all();
     }
 
     /// Clear specific flags.
     pub fn clear(&self, bits: u32) {
-        self.flags.fetch_and(!bits, Ordering::Release);
+        *self.flags.lock().unwrap() &= !bits;
     }
 
     /// Wait for any of the specified flags.
     pub fn wait_any(&self, bits: u32, timeout_ms: u32, clear_on_exit: bool) -> Option<u32> {
-        let start = Instant::now();
-        loop {
-            let current = self.flags.load(Ordering::Acquire);
-            if (current & bits) != 0 {
-                if clear_on_exit {
-                    self.flags.fetch_and(!(current & bits), Ordering::Release);
-                }
-                return Some(current & bits);
-            }
-            if start.elapsed() > Duration::from_millis(timeout_ms as u64) {
-                return None;
-            }
-            thread::yield_now();
+        let flags = self.flags.lock().unwrap();
+        let (mut flags, _) = self
+            .changed
+            .wait_timeout_while(flags, timeout(timeout_ms), |f| *f & bits == 0)
+            .unwrap();
+        let triggered = *flags & bits;
+        if triggered == 0 {
+            return None;
+        }
+        if clear_on_exit {
+            *flags &= !triggered;
         }
+        Some(triggered)
     }
 
     /// Wait for all of the specified flags.
     pub fn wait_all(&self, bits: u32, timeout_ms: u32, clear_on_exit: bool) -> bool {
-        let start = Instant::now();
-        loop {
-            let current = self.flags.load(Ordering::Acquire);
-            if (current & bits) == bits {
-                if clear_on_exit {
-                    self.flags.fetch_and(!bits, Ordering::Release);
-                }
-                return true;
-            }
-            if start.elapsed() > Duration::from_millis(timeout_ms as u64) {
-                return false;
-            }
-            thread::yield_now();
+        let flags = self.flags.lock().unwrap();
+        let (mut flags, _) = self
+            .changed
+            .wait_timeout_while(flags, timeout(timeout_ms), |f| *f & bits != bits)
+            .unwrap();
+        if *flags & bits != bits {
+            return false;
+        }
+        if clear_on_exit {
+            *flags &= !bits;
         }
+        true
     }
 
     /// Get current flags.
     pub fn get(&self) -> u32 {
-        self.flags.load(Ordering::Relaxed)
+        *self.flags.lock().unwrap()
     }
 }
 
@@ -277,164 +293,84 @@ impl Default for EventFlags {
 }
 
 // ============================================================================
-// Software Timer Service
-// ============================================================================
-
-/// Timer callback type.
-pub type TimerCallback = Box<dyn Fn() + Send + 'static>;
-
-/// Timer mode.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum TimerMode {
-    OneShot,
-    Periodic,
-}
-
-/// Software timer (simplified).
-pub struct SoftwareTimer {
-    name: &'static str,
-    period_ms: u32,
-    mode: TimerMode,
-    active: AtomicBool,
-    callback: Mutex<Option<TimerCallback>>,
-}
-
-impl SoftwareTimer {
-    pub fn new(name: &'static str, period_ms: u32, mode: TimerMode) -> Self {
-        Self {
-            name,
-            period_ms,
-            mode,
-            active: AtomicBool::new(false),
-            callback: Mutex::new(None),
-        }
-    }
-
-    pub fn set_callback<F: Fn() + Send + 'static>(&self, callback: F) {
-        *self.callback.lock().unwrap() = Some(Box::new(callback));
-    }
-
-    pub fn start(&self) {
-        self.active.store(true, Ordering::Release);
-        println!(
-            "Timer '{}' started ({}ms, {:?})",
-            self.name, self.period_ms, self.mode
-        );
-    }
-
-    pub fn stop(&self) {
-        self.active.store(false, Ordering::Release);
-        println!("Timer '{}' stopped", self.name);
-    }
-
-    pub fn is_active(&self) -> bool {
-        self.active.load(Ordering::Acquire)
-    }
-
-    /// Simulate timer tick (would be called by timer ISR).
-    pub fn tick(&self) {
-        if self.is_active() {
-            if let Some(callback) = self.callback.lock().unwrap().as_ref() {
-                callback();
-            }
-            if self.mode == TimerMode::OneShot {
-                self.stop();
-            }
-        }
-    }
-}
-
-// ============================================================================
-// Embassy-style Async Patterns (Conceptual)
+// Channel (Embassy-style async channel)
 // ============================================================================
 
-/// Simulated async task spawner (Embassy-style).
-pub struct Executor {
-    tasks: RefCell<Vec<Box<dyn FnMut() -> bool>>>,
-}
-
-impl Executor {
-    pub fn new() -> Self {
-        Self {
-            tasks: RefCell::new(Vec::new()),
-        }
-    }
-
-    /// Spawn a task (simplified - real Embassy uses proper futures).
-    pub fn spawn<F: FnMut() -> bool + 'static>(&self, task: F) {
-        self.tasks.borrow_mut().push(Box::new(task));
-    }
-
-    /// Run the executor (simplified polling).
-    pub fn run(&self, iterations: usize) {
-        for i in 0..iterations {
-            println!("  Executor tick {}", i);
-            let mut tasks = self.tasks.borrow_mut();
-            tasks.retain_mut(|task| task()); // Keep tasks that return true
-            if tasks.is_empty() {
-                println!("  All tasks complete");
-                break;
-            }
-            thread::sleep(Duration::from_millis(10));
-        }
+/// Park `waker` in `wakers` unless an equivalent one is already there, so a
+/// future polled repeatedly while pending registers (and is woken) once.
+fn register_waker(wakers: &mut Vec<Waker>, waker: &Waker) {
+    if !wakers.iter().any(|parked| parked.will_wake(waker)) {
+        wakers.push(waker.clone());
     }
 }
 
-impl Default for Executor {
-    fn default() -> Self {
-        Self::new()
-    }
+struct ChannelState<T> {
+    queue: VecDeque<T>,
+    send_wakers: Vec<Waker>,
+    recv_wakers: Vec<Waker>,
 }
 
-// ============================================================================
-// Channel (Embassy-style async channel)
-// ============================================================================
-
-/// A simple channel for async communication.
+/// A bounded channel usable from async tasks and plain threads alike.
+///
+/// Async senders waiting for space and receivers waiting for data park
+/// their wakers here and are woken by the opposite operation.
 pub struct Channel<T, const N: usize> {
-    queue: Mutex<VecDeque<T>>,
-    sender_waker: AtomicBool,
-    receiver_waker: AtomicBool,
+    state: Mutex<ChannelState<T>>,
 }
 
 impl<T, const N: usize> Channel<T, N> {
     pub fn new() -> Self {
         Self {
-            queue: Mutex::new(VecDeque::with_capacity(N)),
-            sender_waker: AtomicBool::new(false),
-            receiver_waker: AtomicBool::new(false),
+            state: Mutex::new(ChannelState {
+                queue: VecDeque::with_capacity(N),
+                send_wakers: Vec::new(),
+                recv_wakers: Vec::new(),
+            }),
         }
     }
 
     /// Try to send (non-blocking).
     pub fn try_send(&self, value: T) -> Result<(), T> {
-        let mut queue = self.queue.lock().unwrap();
-        if queue.len() >= N {
+        let mut state = self.state.lock().unwrap();
+        if state.queue.len() >= N {
             return Err(value);
         }
-        queue.push_back(value);
-        self.receiver_waker.store(true, Ordering::Release);
+        state.queue.push_back(value);
+        state.recv_wakers.drain(..).for_each(Waker::wake);
         Ok(())
     }
 
     /// Try to receive (non-blocking).
     pub fn try_recv(&self) -> Option<T> {
-        let mut queue = self.queue.lock().unwrap();
-        let result = queue.pop_front();
+        let mut state = self.state.lock().unwrap();
+        let result = state.queue.pop_front();
         if result.is_some() {
-            self.sender_waker.store(true, Ordering::Release);
+            state.send_wakers.drain(..).for_each(Waker::wake);
         }
         result
     }
 
+    /// Send, waiting asynchronously while the channel is full.
+    pub fn send(&self, value: T) -> SendFuture<'_, T, N> {
+        SendFuture {
+            channel: self,
+            value: Some(value),
+        }
+    }
+
+    /// Receive, waiting asynchronously while the channel is empty.
+    pub fn recv(&self) -> RecvFuture<'_, T, N> {
+        RecvFuture { channel: self }
+    }
+
     /// Check if there are pending items.
     pub fn is_empty(&self) -> bool {
-        self.queue.lock().unwrap().is_empty()
+        self.state.lock().unwrap().queue.is_empty()
     }
 
     /// Check if channel is full.
     pub fn is_full(&self) -> bool {
-        self.queue.lock().unwrap().len() >= N
+        self.state.lock().unwrap().queue.len() >= N
     }
 }
 
@@ -444,52 +380,111 @@ impl<T, const N: usize> Default for Channel<T, N> {
     }
 }
 
+/// Future returned by [`Channel::send`].
+pub struct SendFuture<'a, T, const N: usize> {
+    channel: &'a Channel<T, N>,
+    value: Option<T>,
+}
+
+// The pending value is never pinned in place, so moving the future is fine
+impl<T, const N: usize> Unpin for SendFuture<'_, T, N> {}
+
+impl<T, const N: usize> Future for SendFuture<'_, T, N> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        let value = this
+            .value
+            .take()
+            .expect("SendFuture polled after completion");
+        match this.channel.try_send(value) {
+            Ok(()) => Poll::Ready(()),
+            Err(value) => {
+                this.value = Some(value);
+                let mut state = this.channel.state.lock().unwrap();
+                // Space may have appeared between try_send and taking the lock
+                if state.queue.len() < N {
+                    cx.waker().wake_by_ref();
+                } else {
+                    register_waker(&mut state.send_wakers, cx.waker());
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Future returned by [`Channel::recv`].
+pub struct RecvFuture<'a, T, const N: usize> {
+    channel: &'a Channel<T, N>,
+}
+
+impl<T, const N: usize> Future for RecvFuture<'_, T, N> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.channel.state.lock().unwrap();
+        match state.queue.pop_front() {
+            Some(value) => {
+                state.send_wakers.drain(..).for_each(Waker::wake);
+                Poll::Ready(value)
+            }
+            None => {
+                register_waker(&mut state.recv_wakers, cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
 // ============================================================================
 // Signal (Embassy-style)
 // ============================================================================
 
 /// A signal for task notification.
+///
+/// Holds at most one value; signalling again before it is taken overwrites
+/// the previous value.
 pub struct Signal<T: Copy> {
-    value: Mutex<Option<T>>,
-    signaled: AtomicBool,
+    state: Mutex<SignalState<T>>,
+}
+
+struct SignalState<T> {
+    value: Option<T>,
+    wakers: Vec<Waker>,
 }
 
 impl<T: Copy> Signal<T> {
     pub fn new() -> Self {
         Self {
-            value: Mutex::new(None),
-            signaled: AtomicBool::new(false),
+            state: Mutex::new(SignalState {
+                value: None,
+                wakers: Vec::new(),
+            }),
         }
     }
 
-    /// Signal with a value.
+    /// Signal with a value, waking any waiting task.
     pub fn signal(&self, value: T) {
-        *self.value.lock().unwrap() = Some(value);
-        self.signaled.store(true, Ordering::Release);
+        let mut state = self.state.lock().unwrap();
+        state.value = Some(value);
+        state.wakers.drain(..).for_each(Waker::wake);
     }
 
-    /// Wait for signal (blocking, simplified).
-    pub fn wait(&self) -> T {
-        loop {
-            if self.signaled.swap(false, Ordering::AcqRel) {
-                return self.value.lock().unwrap().take().unwrap();
-            }
-            thread::yield_now();
-        }
+    /// Wait asynchronously for the signal and take its value.
+    pub fn wait(&self) -> WaitFuture<'_, T> {
+        WaitFuture { signal: self }
     }
 
     /// Try to get signaled value.
     pub fn try_take(&self) -> Option<T> {
-        if self.signaled.swap(false, Ordering::AcqRel) {
-            self.value.lock().unwrap().take()
-        } else {
-            None
-        }
+        self.state.lock().unwrap().value.take()
     }
 
     /// Check if signaled.
     pub fn is_signaled(&self) -> bool {
-        self.signaled.load(Ordering::Acquire)
+        self.state.lock().unwrap().value.is_some()
     }
 }
 
@@ -499,84 +494,26 @@ impl<T: Copy> Default for Signal<T> {
     }
 }
 
-// ============================================================================
-// Mutex with Priority Inheritance (Conceptual)
-// ============================================================================
-
-/// A mutex that tracks owner priority for priority inheritance.
-pub struct PriorityMutex<T> {
-    data: Mutex<T>,
-    owner_priority: AtomicUsize,
-    locked: AtomicBool,
+/// Future returned by [`Signal::wait`].
+pub struct WaitFuture<'a, T: Copy> {
+    signal: &'a Signal<T>,
 }
 
-impl<T> PriorityMutex<T> {
-    pub fn new(data: T) -> Self {
-        Self {
-            data: Mutex::new(data),
-            owner_priority: AtomicUsize::new(0),
-            locked: AtomicBool::new(false),
-        }
-    }
-
-    /// Lock with priority (for priority inheritance).
-    pub fn lock(&self, task_priority: TaskPriority) -> PriorityMutexGuard<'_, T> {
-        // In a real RTOS, this would:
-        // 1. If mutex is held by lower-priority task, boost that task's priority
-        // 2. Block current task until mutex is available
-        // 3. Record current task as owner
+impl<T: Copy> Future for WaitFuture<'_, T> {
+    type Output = T;
 
-        while self
-            .locked
-            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
-            .is_err()
-        {
-            // Check for priority inversion
-            let owner_prio = self.owner_priority.load(Ordering::Acquire);
-            if (task_priority as usize) > owner_prio {
-                println!(
-                    "    [Priority inheritance: boosting owner from {} to {}]",
-                    owner_prio, task_priority as usize
-                );
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.signal.state.lock().unwrap();
+        match state.value.take() {
+            Some(value) => Poll::Ready(value),
+            None => {
+                register_waker(&mut state.wakers, cx.waker());
+                Poll::Pending
             }
-            thread::yield_now();
-        }
-
-        self.owner_priority
-            .store(task_priority as usize, Ordering::Release);
-
-        PriorityMutexGuard {
-            mutex: self,
-            guard: self.data.lock().unwrap(),
         }
     }
 }
 
-pub struct PriorityMutexGuard<'a, T> {
-    mutex: &'a PriorityMutex<T>,
-    guard: std::sync::MutexGuard<'a, T>,
-}
-
-impl<T> std::ops::Deref for PriorityMutexGuard<'_, T> {
-    type Target = T;
-    fn deref(&self) -> &Self::Target {
-        &self.guard
-    }
-}
-
-impl<T> std::ops::DerefMut for PriorityMutexGuard<'_, T> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.guard
-    }
-}
-
-impl<T> Drop for PriorityMutexGuard<'_, T> {
-    fn drop(&mut self) {
-        self.mutex.owner_priority.store(0, Ordering::Release);
-        self.mutex.locked.store(false, Ordering::Release);
-    }
-}
-
 // ============================================================================
 // Resource Pool Pattern
 // ============================================================================
@@ -648,18 +585,73 @@ impl<T, const N: usize> Drop for PooledResource<'_, T, N> {
 fn demo_task_management() {
     println!("=== Task Management Demo ===\n");
 
-    let tasks = vec![
-        TaskControlBlock::new("SensorTask", TaskPriority::High, 1024),
+    let mut scheduler = Scheduler::new();
+
+    // ControlTask runs every 4 ticks and preempts everything else
+    let control = scheduler.spawn(
         TaskControlBlock::new("ControlTask", TaskPriority::RealTime, 2048),
+        |ctx| {
+            ctx.use_stack(900);
+            TaskAction::Delay(4)
+        },
+    );
+
+    // SensorTask samples every 2 ticks
+    scheduler.spawn(
+        TaskControlBlock::new("SensorTask", TaskPriority::High, 1024),
+        |ctx| {
+            ctx.use_stack(300);
+            TaskAction::Delay(2)
+        },
+    );
+
+    // Two Normal tasks share the remaining time round-robin
+    scheduler.spawn(
         TaskControlBlock::new("DisplayTask", TaskPriority::Normal, 512),
+        |ctx| {
+            ctx.use_stack(200);
+            TaskAction::Yield
+        },
+    );
+    scheduler.spawn(
+        TaskControlBlock::new("CommsTask", TaskPriority::Normal, 512),
+        |ctx| {
+            ctx.use_stack(350);
+            TaskAction::Yield
+        },
+    );
+
+    // LoggingTask only runs when nothing else is Ready - here, never
+    let logging = scheduler.spawn(
         TaskControlBlock::new("LoggingTask", TaskPriority::Low, 256),
-    ];
+        |_| TaskAction::Yield,
+    );
+
+    println!("Running scheduler for 12 ticks:");
+    for _ in 0..12 {
+        let tick = scheduler.tick_count();
+        match scheduler.tick() {
+            Some(id) => println!("  tick {:2}: {}", tick, scheduler.tcb(id).unwrap().name),
+            None => println!("  tick {:2}: <idle>", tick),
+        }
+    }
+
+    println!(
+        "\nSuspending ControlTask, LoggingTask waited {} ticks so far",
+        scheduler.ticks_since_run(logging).unwrap()
+    );
+    scheduler.suspend(control).unwrap();
 
-    println!("Task Control Blocks:");
-    for task in &tasks {
+    println!("\nTask Control Blocks:");
+    for (_, task) in scheduler.tasks() {
         println!(
-            "  {} - Priority: {:?}, Stack: {} bytes, State: {:?}",
-            task.name, task.priority, task.stack_size, task.state
+            "  {:12} - Priority: {:?}, State: {:?}, Runs: {}, Stack free (min): {}/{} bytes",
+            task.name,
+            task.priority,
+            task.state,
+            task.run_count,
+            task.stack_high_water_mark,
+            task.stack_size
         );
     }
     println!();
@@ -669,6 +661,7 @@ fn demo_message_queue() {
     println!("=== Message Queue Demo ===\n");
 
     #[derive(Debug)]
+    #[allow(dead_code)]
     struct SensorReading {
         sensor_id: u8,
         value: f32,
@@ -789,23 +782,55 @@ fn demo_software_timer() {
     println!("=== Software Timer Demo ===\n");
 
     let counter = Arc::new(AtomicU32::new(0));
-    let counter_clone = Arc::clone(&counter);
+    let mut service = TimerService::new();
 
-    let timer = SoftwareTimer::new("HeartbeatTimer", 100, TimerMode::Periodic);
-    timer.set_callback(move || {
+    let counter_clone = Arc::clone(&counter);
+    let heartbeat = service.create("HeartbeatTimer", 100, TimerMode::Periodic, move |_| {
         let count = counter_clone.fetch_add(1, Ordering::Relaxed) + 1;
-        println!("  Timer callback executed (count: {})", count);
+        println!("  Heartbeat (count: {})", count);
+    });
+
+    // The watchdog is kicked by another thread; if kicks stop, it fires once
+    let watchdog = service.create("WatchdogTimer", 250, TimerMode::OneShot, |timer| {
+        println!("  {} expired - no kick received!", timer.name());
     });
 
-    timer.start();
+    heartbeat.start();
+    watchdog.start();
 
-    // Simulate timer service running
-    for _ in 0..3 {
-        thread::sleep(Duration::from_millis(100));
-        timer.tick();
+    println!("Virtual clock (deterministic):");
+    for step in 0..4 {
+        // Kick the watchdog from a "task" thread for the first two steps
+        if step < 2 {
+            let watchdog = watchdog.clone();
+            thread::spawn(move || watchdog.reset()).join().unwrap();
+        }
+        service.advance(100);
+        println!("  t = {} ms", service.now());
     }
 
-    timer.stop();
+    heartbeat.change_period(50);
+    service.advance(100);
+    println!(
+        "  t = {} ms after change_period(50): {} heartbeats",
+        service.now(),
+        service.fire_count(heartbeat.id())
+    );
+    heartbeat.stop();
+
+    println!("\nTimer daemon on the wall clock:");
+    let counter_clone = Arc::clone(&counter);
+    let fast = service.create("FastTimer", 20, TimerMode::Periodic, move |_| {
+        counter_clone.fetch_add(1, Ordering::Relaxed);
+    });
+    let daemon = service.spawn();
+    fast.start();
+    thread::sleep(Duration::from_millis(110));
+    let service = daemon.shutdown();
+    println!(
+        "  FastTimer fired {} times in ~110 ms",
+        service.fire_count(fast.id())
+    );
     println!("  Final count: {}", counter.load(Ordering::Relaxed));
     println!();
 }
@@ -814,28 +839,57 @@ fn demo_embassy_style() {
     println!("=== Embassy-style Async Demo ===\n");
 
     let executor = Executor::new();
-    let counter = Arc::new(AtomicU32::new(0));
+    let channel: Rc<Channel<u32, 2>> = Rc::new(Channel::new());
+    let done: Rc<Signal<u32>> = Rc::new(Signal::new());
+
+    // Task 1: Blinky toggles an LED, yielding between toggles
+    let blinky = executor.spawn(async {
+        let mut led = false;
+        for _ in 0..3 {
+            led = !led;
+            println!("  Blinky: LED {}", if led { "ON" } else { "OFF" });
+            yield_now().await;
+        }
+    });
 
-    // Spawn task 1: Blinky
-    let counter1 = Arc::clone(&counter);
-    let mut blink_state = false;
-    executor.spawn(move || {
-        blink_state = !blink_state;
-        println!("  Blinky: LED {}", if blink_state { "ON" } else { "OFF" });
-        counter1.fetch_add(1, Ordering::Relaxed) < 3
+    // Task 2: Sensor produces readings faster than the channel drains
+    let tx = Rc::clone(&channel);
+    let sensor = executor.spawn(async move {
+        for reading in 1..=5 {
+            tx.send(reading * 10).await;
+            println!("  Sensor: sent {}", reading * 10);
+        }
     });
 
-    // Spawn task 2: Sensor polling
-    let counter2 = Arc::clone(&counter);
-    let mut reading = 0;
-    executor.spawn(move || {
-        reading += 1;
-        println!("  Sensor: Reading {}", reading);
-        counter2.fetch_add(1, Ordering::Relaxed) < 4
+    // Task 3: Consumer sums readings and signals the total
+    let (rx, signal) = (Rc::clone(&channel), Rc::clone(&done));
+    executor.spawn(async move {
+        let mut total = 0;
+        for _ in 0..5 {
+            let value = rx.recv().await;
+            println!("  Consumer: received {}", value);
+            total += value;
+        }
+        signal.signal(total);
+    });
+
+    // Task 4: Reporter sleeps until the signal fires
+    let signal = Rc::clone(&done);
+    let reporter = executor.spawn(async move {
+        let total = signal.wait().await;
+        println!("  Reporter: total = {}", total);
     });
 
     println!("Running executor:");
-    executor.run(10);
+    let polls = executor.run_until_idle();
+    println!(
+        "  {} polls in total (blinky {}, sensor {}, reporter {}), {} tasks pending",
+        polls,
+        executor.poll_count(blinky),
+        executor.poll_count(sensor),
+        executor.poll_count(reporter),
+        executor.pending_tasks()
+    );
     println!();
 }
 
@@ -904,35 +958,96 @@ fn demo_signal() {
 fn demo_priority_mutex() {
     println!("=== Priority Mutex Demo ===\n");
 
-    let shared_data = Arc::new(PriorityMutex::new(0u32));
-
-    let data1 = Arc::clone(&shared_data);
-    let data2 = Arc::clone(&shared_data);
-
-    println!("Low priority task acquires mutex first:");
-
-    // Low priority task
-    let low_prio = thread::spawn(move || {
-        let mut guard = data1.lock(TaskPriority::Low);
-        println!("  Low priority: Holding mutex, value = {}", *guard);
-        thread::sleep(Duration::from_millis(50));
-        *guard += 1;
-        println!("  Low priority: Updated value to {}", *guard);
-    });
-
-    thread::sleep(Duration::from_millis(10));
-
-    // High priority task
-    let high_prio = thread::spawn(move || {
-        println!("  High priority: Attempting to acquire mutex...");
-        let mut guard = data2.lock(TaskPriority::High);
-        println!("  High priority: Acquired mutex, value = {}", *guard);
-        *guard += 10;
-        println!("  High priority: Updated value to {}", *guard);
-    });
+    let registry = TaskRegistry::new();
+    let low = registry.register(TaskControlBlock::new("LowTask", TaskPriority::Low, 512));
+    let mid = registry.register(TaskControlBlock::new("MidTask", TaskPriority::Normal, 512));
+    let high = registry.register(TaskControlBlock::new("HighTask", TaskPriority::High, 512));
+
+    let bus = Arc::new(PriorityMutex::new(&registry, "I2C bus", 0u32));
+    let log = Arc::new(PriorityMutex::new(&registry, "log buffer", 0u32));
+
+    println!("LowTask acquires the I2C bus first:");
+    let guard = bus.lock(&low).unwrap();
+
+    // MidTask holds the log buffer and then waits for the bus
+    let mid_thread = {
+        let (bus, log, mid) = (Arc::clone(&bus), Arc::clone(&log), mid.clone());
+        thread::spawn(move || {
+            let mut log_guard = log.lock(&mid).unwrap();
+            let mut bus_guard = bus.lock(&mid).unwrap();
+            *bus_guard += 1;
+            *log_guard += 1;
+        })
+    };
+    while mid.state() != TaskState::Blocked {
+        thread::sleep(Duration::from_millis(1));
+    }
+    println!(
+        "  MidTask blocked on the bus -> LowTask boosted to {:?}",
+        low.priority()
+    );
+
+    // HighTask waits for the log buffer held by MidTask: the boost chains
+    let high_thread = {
+        let (log, high) = (Arc::clone(&log), high.clone());
+        thread::spawn(move || {
+            *log.lock(&high).unwrap() += 10;
+        })
+    };
+    while high.state() != TaskState::Blocked {
+        thread::sleep(Duration::from_millis(1));
+    }
+    println!(
+        "  HighTask blocked on the log -> MidTask boosted to {:?}, LowTask to {:?}",
+        mid.priority(),
+        low.priority()
+    );
+    for inversion in registry.priority_inversions() {
+        let waiter = registry.tcb(inversion.waiter).unwrap();
+        let owner = registry.tcb(inversion.owner).unwrap();
+        println!(
+            "  Inversion on '{}': {} waits for {} (boosted to {:?})",
+            inversion.mutex, waiter.name, owner.name, inversion.owner_effective
+        );
+    }
 
-    low_prio.join().unwrap();
-    high_prio.join().unwrap();
+    drop(guard);
+    mid_thread.join().unwrap();
+    high_thread.join().unwrap();
+    println!(
+        "  After unlock: LowTask {:?}, MidTask {:?}, HighTask {:?}",
+        low.priority(),
+        mid.priority(),
+        high.priority()
+    );
+
+    println!("\nDeadlock detection:");
+    let guard = bus.lock(&low).unwrap();
+    let mid_thread = {
+        let (bus, log, mid) = (Arc::clone(&bus), Arc::clone(&log), mid.clone());
+        thread::spawn(move || {
+            let _log_guard = log.lock(&mid).unwrap();
+            drop(bus.lock(&mid).unwrap());
+        })
+    };
+    while mid.state() != TaskState::Blocked {
+        thread::sleep(Duration::from_millis(1));
+    }
+    match log.lock(&low) {
+        Err(LockError::Deadlock(cycle)) => {
+            let names: Vec<_> = cycle
+                .iter()
+                .map(|&id| registry.tcb(id).unwrap().name)
+                .collect();
+            println!(
+                "  LowTask -> log buffer refused, cycle: {}",
+                names.join(" -> ")
+            );
+        }
+        other => println!("  Unexpected result: {:?}", other.map(|_| ())),
+    }
+    drop(guard);
+    mid_thread.join().unwrap();
     println!();
 }
 
@@ -989,20 +1104,149 @@ fn main() {
 
     println!("=== Summary ===\n");
     println!("FreeRTOS-style patterns demonstrated:");
-    println!("  • Task Control Blocks (TCB)");
+    println!("  • Task Control Blocks (TCB) and preemptive scheduling");
     println!("  • Message Queues (xQueue)");
     println!("  • Semaphores (counting and binary)");
     println!("  • Event Flags (Event Groups)");
-    println!("  • Software Timers");
+    println!("  • Software Timer service (timer daemon + command queue)");
     println!();
     println!("Embassy-style async patterns:");
-    println!("  • Executor and task spawning");
-    println!("  • Channels for async communication");
+    println!("  • Waker-driven executor and task spawning");
+    println!("  • Channels with async send/recv");
     println!("  • Signals for notifications");
     println!();
     println!("Advanced patterns:");
-    println!("  • Priority Mutex (priority inheritance)");
+    println!("  • Priority Mutex (priority inheritance, modelled outside the scheduler)");
     println!("  • Resource Pools");
     println!();
     println!("For real RTOS development, see Embassy or FreeRTOS bindings.");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Instant;
+
+    #[test]
+    fn test_queue_wakes_blocked_receiver() {
+        let queue: Arc<MessageQueue<u32, 2>> = Arc::new(MessageQueue::new());
+        let rx = Arc::clone(&queue);
+        let receiver = thread::spawn(move || rx.receive(5_000));
+
+        thread::sleep(Duration::from_millis(20));
+        queue.send(7, 0).unwrap();
+        assert_eq!(receiver.join().unwrap(), Some(7));
+
+        // A full queue hands the item back once the timeout expires
+        queue.send(1, 0).unwrap();
+        queue.send(2, 0).unwrap();
+        let start = Instant::now();
+        assert_eq!(queue.send(3, 30), Err(3));
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert_eq!(queue.spaces_available(), 0);
+    }
+
+    #[test]
+    fn test_semaphore_give_wakes_taker() {
+        let sem = Arc::new(Semaphore::binary(false));
+        assert!(!sem.take(0));
+
+        let giver = Arc::clone(&sem);
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            giver.give()
+        });
+        assert!(sem.take(5_000));
+        assert!(handle.join().unwrap());
+        assert_eq!(sem.count(), 0);
+        assert!(sem.give());
+        assert!(!sem.give());
+    }
+
+    #[test]
+    fn test_event_flags_wait_all() {
+        let flags = Arc::new(EventFlags::new());
+        let setter = Arc::clone(&flags);
+        let handle = thread::spawn(move || {
+            setter.set(0x01);
+            thread::sleep(Duration::from_millis(10));
+            setter.set(0x02);
+        });
+
+        assert!(flags.wait_all(0x03, 5_000, true));
+        handle.join().unwrap();
+        assert_eq!(flags.get(), 0);
+        assert_eq!(flags.wait_any(0x04, 10, false), None);
+    }
+
+    #[test]
+    fn test_async_channel_and_signal() {
+        let executor = Executor::new();
+        let channel: Rc<Channel<u8, 1>> = Rc::new(Channel::new());
+        let signal: Rc<Signal<u8>> = Rc::new(Signal::new());
+
+        let tx = Rc::clone(&channel);
+        let producer = executor.spawn(async move {
+            for value in 1..=3 {
+                tx.send(value).await;
+            }
+        });
+        let (rx, done) = (Rc::clone(&channel), Rc::clone(&signal));
+        executor.spawn(async move {
+            let mut sum = 0;
+            for _ in 0..3 {
+                sum += rx.recv().await;
+            }
+            done.signal(sum);
+        });
+        let waiter = Rc::clone(&signal);
+        let reporter = executor.spawn(async move {
+            assert_eq!(waiter.wait().await, 6);
+        });
+
+        executor.run_until_idle();
+        assert!(executor.is_done(producer));
+        assert!(executor.is_done(reporter));
+        // The reporter slept until signalled: first poll plus the wake-up
+        assert_eq!(executor.poll_count(reporter), 2);
+        assert!(channel.is_empty());
+    }
+
+    #[test]
+    fn test_repolling_registers_one_waker() {
+        struct CountWakes(AtomicUsize);
+        impl std::task::Wake for CountWakes {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        let wakes = Arc::new(CountWakes(AtomicUsize::new(0)));
+        let waker = Waker::from(Arc::clone(&wakes));
+        let mut cx = Context::from_waker(&waker);
+        let channel: Channel<u8, 1> = Channel::new();
+        let signal: Signal<u8> = Signal::new();
+
+        // A select-style loop re-polls pending futures with the same waker
+        let mut recv = channel.recv();
+        let mut wait = signal.wait();
+        for _ in 0..10 {
+            assert!(Pin::new(&mut recv).poll(&mut cx).is_pending());
+            assert!(Pin::new(&mut wait).poll(&mut cx).is_pending());
+        }
+        assert_eq!(channel.state.lock().unwrap().recv_wakers.len(), 1);
+        assert_eq!(signal.state.lock().unwrap().wakers.len(), 1);
+
+        channel.try_send(1).unwrap();
+        signal.signal(9);
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 2);
+
+        let mut send = channel.send(2);
+        for _ in 0..10 {
+            assert!(Pin::new(&mut send).poll(&mut cx).is_pending());
+        }
+        assert_eq!(channel.state.lock().unwrap().send_wakers.len(), 1);
+        assert_eq!(channel.try_recv(), Some(1));
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 3);
+    }
+}
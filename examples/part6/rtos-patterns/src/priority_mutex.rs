@@ -0,0 +1,512 @@
+//! Mutexes with priority inheritance
+//!
+//! A [`TaskRegistry`] plays the role of the kernel: it owns the TCB of every
+//! task that takes part in locking, plus the owner and wait list of every
+//! [`PriorityMutex`]. All bookkeeping happens under one registry lock, the
+//! host-side equivalent of a kernel critical section.
+//!
+//! Effective priority follows the usual inheritance rule:
+//!
+//! ```text
+//! effective(task) = max(base(task),
+//!                       effective(w) for every w blocked on a mutex task holds)
+//! ```
+//!
+//! Because the rule is applied transitively, chains such as
+//! High -> waits on B (held by Mid) -> Mid waits on A (held by Low) boost Low
+//! all the way to High. Unlocking one mutex only drops the boost that mutex
+//! contributed, so nested locks keep the owner raised as long as needed.
+//!
+//! The registry is a stand-alone model for threads that really block. Its
+//! TCBs are separate from the ones the simulated-tick
+//! [`Scheduler`](crate::scheduler::Scheduler) dispatches, and nothing here
+//! calls into that scheduler: it does not prevent priority inversion among
+//! `Scheduler` tasks. Inherited priorities are only visible through
+//! [`TaskHandle::priority`] and [`TaskRegistry::priority_inversions`].
+
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use crate::scheduler::TaskId;
+use crate::{TaskControlBlock, TaskPriority, TaskState};
+
+/// Identifies a mutex inside a [`TaskRegistry`].
+pub type MutexId = usize;
+
+/// Errors returned by [`PriorityMutex::lock`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockError {
+    /// The task already owns this mutex (it is not recursive).
+    AlreadyOwned,
+    /// The task handle was registered with a different [`TaskRegistry`].
+    ForeignTask,
+    /// Blocking would close a wait-for cycle. Holds the tasks in the cycle,
+    /// starting with the task that tried to lock.
+    Deadlock(Vec<TaskId>),
+}
+
+/// A high-priority task blocked behind a lower-priority owner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PriorityInversion {
+    pub mutex: &'static str,
+    pub owner: TaskId,
+    pub waiter: TaskId,
+    /// The owner's effective priority in the registry, after inheritance.
+    pub owner_effective: TaskPriority,
+}
+
+struct TaskRecord {
+    tcb: TaskControlBlock,
+    waiting_on: Option<MutexId>,
+    held: Vec<MutexId>,
+    /// State to return to once the task holds no mutex and waits for none
+    idle_state: TaskState,
+}
+
+struct MutexRecord {
+    name: &'static str,
+    owner: Option<TaskId>,
+    waiters: Vec<TaskId>,
+}
+
+#[derive(Default)]
+struct KernelState {
+    tasks: Vec<TaskRecord>,
+    mutexes: Vec<MutexRecord>,
+}
+
+impl KernelState {
+    /// Recompute every effective priority from scratch.
+    ///
+    /// Iterates to a fixed point so inheritance propagates along chains of
+    /// any length; priorities only ever rise, so this always terminates.
+    fn recompute_priorities(&mut self) {
+        for task in &mut self.tasks {
+            task.tcb.priority = task.tcb.base_priority;
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for mutex in &self.mutexes {
+                let Some(owner) = mutex.owner else { continue };
+                for &waiter in &mutex.waiters {
+                    let inherited = self.tasks[waiter].tcb.priority;
+                    if inherited > self.tasks[owner].tcb.priority {
+                        self.tasks[owner].tcb.priority = inherited;
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Follow the wait-for graph from `mutex`'s owner. Returns the cycle if
+    /// it leads back to `task`.
+    fn wait_cycle(&self, task: TaskId, mutex: MutexId) -> Option<Vec<TaskId>> {
+        let mut cycle = vec![task];
+        let mut next = self.mutexes[mutex].owner;
+        while let Some(owner) = next {
+            if owner == task {
+                return Some(cycle);
+            }
+            if cycle.contains(&owner) {
+                // A cycle that does not involve `task`; not ours to report
+                return None;
+            }
+            cycle.push(owner);
+            next = self.tasks[owner]
+                .waiting_on
+                .and_then(|m| self.mutexes[m].owner);
+        }
+        None
+    }
+
+    /// Give `mutex` to `task` and record the ownership.
+    fn grant(&mut self, mutex: MutexId, task: TaskId) {
+        self.mutexes[mutex].owner = Some(task);
+        let record = &mut self.tasks[task];
+        record.waiting_on = None;
+        record.held.push(mutex);
+        record.tcb.state = TaskState::Running;
+    }
+}
+
+/// Kernel-side bookkeeping shared by tasks and priority mutexes.
+pub struct TaskRegistry {
+    state: Mutex<KernelState>,
+    changed: Condvar,
+}
+
+impl TaskRegistry {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(KernelState::default()),
+            changed: Condvar::new(),
+        })
+    }
+
+    /// Register a task so it can lock priority mutexes.
+    pub fn register(self: &Arc<Self>, tcb: TaskControlBlock) -> TaskHandle {
+        let mut state = self.state.lock().unwrap();
+        let id = state.tasks.len();
+        let tcb_state = tcb.state;
+        state.tasks.push(TaskRecord {
+            tcb,
+            waiting_on: None,
+            held: Vec::new(),
+            idle_state: tcb_state,
+        });
+        TaskHandle {
+            registry: Arc::clone(self),
+            id,
+        }
+    }
+
+    /// Snapshot of a task's control block.
+    pub fn tcb(&self, id: TaskId) -> Option<TaskControlBlock> {
+        let state = self.state.lock().unwrap();
+        state.tasks.get(id).map(|task| task.tcb.clone())
+    }
+
+    /// Every place where a task is blocked behind a lower base-priority owner.
+    pub fn priority_inversions(&self) -> Vec<PriorityInversion> {
+        let state = self.state.lock().unwrap();
+        let mut inversions = Vec::new();
+        for mutex in &state.mutexes {
+            let Some(owner) = mutex.owner else { continue };
+            let owner_tcb = &state.tasks[owner].tcb;
+            for &waiter in &mutex.waiters {
+                if state.tasks[waiter].tcb.base_priority > owner_tcb.base_priority {
+                    inversions.push(PriorityInversion {
+                        mutex: mutex.name,
+                        owner,
+                        waiter,
+                        owner_effective: owner_tcb.priority,
+                    });
+                }
+            }
+        }
+        inversions
+    }
+
+    /// Would `task` deadlock if it blocked on `mutex` right now?
+    pub fn would_deadlock(
+        self: &Arc<Self>,
+        task: &TaskHandle,
+        mutex: MutexId,
+    ) -> Result<Option<Vec<TaskId>>, LockError> {
+        self.check_task(task)?;
+        let state = self.state.lock().unwrap();
+        Ok(state.wait_cycle(task.id, mutex))
+    }
+
+    /// Task ids index this registry's tables, so a handle from another
+    /// registry must be rejected before it is used.
+    fn check_task(self: &Arc<Self>, task: &TaskHandle) -> Result<(), LockError> {
+        if Arc::ptr_eq(self, &task.registry) {
+            Ok(())
+        } else {
+            Err(LockError::ForeignTask)
+        }
+    }
+
+    fn add_mutex(&self, name: &'static str) -> MutexId {
+        let mut state = self.state.lock().unwrap();
+        state.mutexes.push(MutexRecord {
+            name,
+            owner: None,
+            waiters: Vec::new(),
+        });
+        state.mutexes.len() - 1
+    }
+}
+
+/// A task's identity within a [`TaskRegistry`].
+#[derive(Clone)]
+pub struct TaskHandle {
+    registry: Arc<TaskRegistry>,
+    id: TaskId,
+}
+
+impl TaskHandle {
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// Current effective priority (base priority plus any inheritance).
+    pub fn priority(&self) -> TaskPriority {
+        self.registry.state.lock().unwrap().tasks[self.id]
+            .tcb
+            .priority
+    }
+
+    /// Priority assigned at creation.
+    pub fn base_priority(&self) -> TaskPriority {
+        self.registry.state.lock().unwrap().tasks[self.id]
+            .tcb
+            .base_priority
+    }
+
+    /// Current scheduling state (Blocked while waiting for a mutex).
+    pub fn state(&self) -> TaskState {
+        self.registry.state.lock().unwrap().tasks[self.id].tcb.state
+    }
+}
+
+/// A mutex whose owner inherits the priority of its highest-priority waiter.
+pub struct PriorityMutex<T> {
+    registry: Arc<TaskRegistry>,
+    id: MutexId,
+    data: Mutex<T>,
+}
+
+impl<T> PriorityMutex<T> {
+    pub fn new(registry: &Arc<TaskRegistry>, name: &'static str, data: T) -> Self {
+        Self {
+            registry: Arc::clone(registry),
+            id: registry.add_mutex(name),
+            data: Mutex::new(data),
+        }
+    }
+
+    pub fn id(&self) -> MutexId {
+        self.id
+    }
+
+    /// Lock on behalf of `task`, blocking while another task owns the mutex.
+    ///
+    /// While blocked, the owner (and anything the owner is itself waiting
+    /// behind) runs at least at `task`'s priority.
+    pub fn lock(&self, task: &TaskHandle) -> Result<PriorityMutexGuard<'_, T>, LockError> {
+        let registry = &self.registry;
+        registry.check_task(task)?;
+        let mut state = registry.state.lock().unwrap();
+        let record = &mut state.tasks[task.id];
+        if record.held.is_empty() {
+            record.idle_state = record.tcb.state;
+        }
+
+        match state.mutexes[self.id].owner {
+            None => state.grant(self.id, task.id),
+            Some(owner) if owner == task.id => return Err(LockError::AlreadyOwned),
+            Some(_) => {
+                if let Some(cycle) = state.wait_cycle(task.id, self.id) {
+                    return Err(LockError::Deadlock(cycle));
+                }
+                state.mutexes[self.id].waiters.push(task.id);
+                let record = &mut state.tasks[task.id];
+                record.waiting_on = Some(self.id);
+                record.tcb.state = TaskState::Blocked;
+                state.recompute_priorities();
+
+                // Ownership is handed over directly on unlock, so waking up
+                // as the owner means the lock is ours.
+                state = registry
+                    .changed
+                    .wait_while(state, |s| s.mutexes[self.id].owner != Some(task.id))
+                    .unwrap();
+                state.recompute_priorities();
+            }
+        }
+        drop(state);
+
+        Ok(PriorityMutexGuard {
+            mutex: self,
+            task: task.id,
+            guard: Some(self.data.lock().unwrap()),
+        })
+    }
+}
+
+pub struct PriorityMutexGuard<'a, T> {
+    mutex: &'a PriorityMutex<T>,
+    task: TaskId,
+    guard: Option<MutexGuard<'a, T>>,
+}
+
+impl<T> std::ops::Deref for PriorityMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl<T> std::ops::DerefMut for PriorityMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.guard.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for PriorityMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // Release the data before the next owner is allowed to take it
+        self.guard.take();
+
+        let registry = &self.mutex.registry;
+        let id = self.mutex.id;
+        let mut state = registry.state.lock().unwrap();
+        let record = &mut state.tasks[self.task];
+        record.held.retain(|&m| m != id);
+        if record.held.is_empty() {
+            record.tcb.state = record.idle_state;
+        }
+        state.mutexes[id].owner = None;
+
+        // Hand over to the highest-priority waiter, FIFO among equals
+        let waiters = &state.mutexes[id].waiters;
+        let next = waiters
+            .iter()
+            .enumerate()
+            .max_by(|(ia, a), (ib, b)| {
+                let pa = state.tasks[**a].tcb.priority;
+                let pb = state.tasks[**b].tcb.priority;
+                pa.cmp(&pb).then(ib.cmp(ia))
+            })
+            .map(|(index, _)| index);
+        if let Some(index) = next {
+            let waiter = state.mutexes[id].waiters.remove(index);
+            state.grant(id, waiter);
+        }
+
+        state.recompute_priorities();
+        drop(state);
+        registry.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    fn task(registry: &Arc<TaskRegistry>, name: &'static str, prio: TaskPriority) -> TaskHandle {
+        registry.register(TaskControlBlock::new(name, prio, 512))
+    }
+
+    fn wait_until_blocked(task: &TaskHandle) {
+        while task.state() != TaskState::Blocked {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_owner_inherits_waiter_priority() {
+        let registry = TaskRegistry::new();
+        let low = task(&registry, "low", TaskPriority::Low);
+        let high = task(&registry, "high", TaskPriority::High);
+        let mutex = Arc::new(PriorityMutex::new(&registry, "m", 0));
+
+        let guard = mutex.lock(&low).unwrap();
+        let waiter = {
+            let (mutex, high) = (Arc::clone(&mutex), high.clone());
+            thread::spawn(move || *mutex.lock(&high).unwrap() += 1)
+        };
+        wait_until_blocked(&high);
+
+        assert_eq!(low.priority(), TaskPriority::High);
+        assert_eq!(registry.priority_inversions().len(), 1);
+
+        drop(guard);
+        waiter.join().unwrap();
+        assert_eq!(low.priority(), TaskPriority::Low);
+        assert!(registry.priority_inversions().is_empty());
+        // Holding nothing any more, both tasks are back in their prior state
+        assert_eq!(low.state(), TaskState::Ready);
+        assert_eq!(high.state(), TaskState::Ready);
+    }
+
+    #[test]
+    fn test_chained_inheritance_and_nested_release() {
+        let registry = TaskRegistry::new();
+        let low = task(&registry, "low", TaskPriority::Low);
+        let mid = task(&registry, "mid", TaskPriority::Normal);
+        let high = task(&registry, "high", TaskPriority::RealTime);
+        let a = Arc::new(PriorityMutex::new(&registry, "a", ()));
+        let b = Arc::new(PriorityMutex::new(&registry, "b", ()));
+
+        let guard_a = a.lock(&low).unwrap();
+        let (mid_go, mid_release) = std::sync::mpsc::channel::<()>();
+        let mid_thread = {
+            let (a, b, mid) = (Arc::clone(&a), Arc::clone(&b), mid.clone());
+            thread::spawn(move || {
+                let _b = b.lock(&mid).unwrap();
+                let _a = a.lock(&mid).unwrap();
+                mid_release.recv().unwrap();
+            })
+        };
+        wait_until_blocked(&mid);
+        assert_eq!(low.priority(), TaskPriority::Normal);
+
+        let high_thread = {
+            let (b, high) = (Arc::clone(&b), high.clone());
+            thread::spawn(move || drop(b.lock(&high).unwrap()))
+        };
+        wait_until_blocked(&high);
+        assert_eq!(mid.priority(), TaskPriority::RealTime);
+        assert_eq!(low.priority(), TaskPriority::RealTime);
+
+        // Releasing A drops Low back down; Mid now holds both and stays boosted
+        drop(guard_a);
+        while mid.state() == TaskState::Blocked {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(low.priority(), TaskPriority::Low);
+        assert_eq!(mid.priority(), TaskPriority::RealTime);
+
+        mid_go.send(()).unwrap();
+        mid_thread.join().unwrap();
+        high_thread.join().unwrap();
+        assert_eq!(mid.priority(), TaskPriority::Normal);
+    }
+
+    #[test]
+    fn test_deadlock_cycle_detected() {
+        let registry = TaskRegistry::new();
+        let t1 = task(&registry, "t1", TaskPriority::Normal);
+        let t2 = task(&registry, "t2", TaskPriority::Normal);
+        let a = Arc::new(PriorityMutex::new(&registry, "a", ()));
+        let b = Arc::new(PriorityMutex::new(&registry, "b", ()));
+
+        let guard_a = a.lock(&t1).unwrap();
+        assert_eq!(a.lock(&t1).err(), Some(LockError::AlreadyOwned));
+
+        let t2_thread = {
+            let (a, b, t2) = (Arc::clone(&a), Arc::clone(&b), t2.clone());
+            thread::spawn(move || {
+                let _b = b.lock(&t2).unwrap();
+                drop(a.lock(&t2).unwrap());
+            })
+        };
+        wait_until_blocked(&t2);
+
+        assert_eq!(
+            registry.would_deadlock(&t1, b.id()),
+            Ok(Some(vec![t1.id(), t2.id()]))
+        );
+        assert_eq!(
+            b.lock(&t1).err(),
+            Some(LockError::Deadlock(vec![t1.id(), t2.id()]))
+        );
+
+        drop(guard_a);
+        t2_thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_foreign_task_rejected() {
+        let registry = TaskRegistry::new();
+        let other = TaskRegistry::new();
+        // Its id is past the end of `registry`'s task table
+        for _ in 0..3 {
+            task(&other, "filler", TaskPriority::Low);
+        }
+        let stranger = task(&other, "stranger", TaskPriority::Normal);
+        let mutex = PriorityMutex::new(&registry, "m", ());
+
+        assert_eq!(mutex.lock(&stranger).err(), Some(LockError::ForeignTask));
+        assert_eq!(
+            registry.would_deadlock(&stranger, mutex.id()),
+            Err(LockError::ForeignTask)
+        );
+    }
+}
@@ -0,0 +1,399 @@
+//! Preemptive priority scheduler (simulated tick)
+//!
+//! A deterministic, host-side model of a FreeRTOS-style kernel scheduler.
+//! Every call to [`Scheduler::tick`] represents one tick interrupt:
+//!
+//! 1. Delayed tasks whose wake-up tick has arrived become Ready.
+//! 2. The highest-priority Ready task is selected; tasks of equal priority
+//!    share the CPU round-robin, one tick per time slice.
+//! 3. The task body runs once and reports what it wants to do next.
+//!
+//! Because selection happens on every tick, a higher-priority task that
+//! becomes Ready always preempts a lower-priority one at the next tick.
+
+use std::collections::VecDeque;
+
+use crate::{TaskControlBlock, TaskPriority, TaskState};
+
+/// Handle returned by [`Scheduler::spawn`].
+pub type TaskId = usize;
+
+/// Number of distinct priority levels (`Idle` ..= `RealTime`).
+const PRIORITY_LEVELS: usize = TaskPriority::RealTime as usize + 1;
+
+/// What a task wants the scheduler to do after its time slice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskAction {
+    /// Stay Ready and go to the back of its priority level.
+    Yield,
+    /// Block for the given number of ticks (vTaskDelay).
+    Delay(u64),
+    /// Block until another task calls [`Scheduler::unblock`].
+    Block,
+    /// Suspend until [`Scheduler::resume`] is called.
+    Suspend,
+    /// Finish; the task is marked Deleted and never runs again.
+    Exit,
+}
+
+/// Information handed to a task body each time it runs.
+#[derive(Debug)]
+pub struct TaskContext {
+    /// The running task: the body's own id (xTaskGetCurrentTaskHandle).
+    pub id: TaskId,
+    pub tick: u64,
+    stack_used: usize,
+}
+
+impl TaskContext {
+    /// Report how many bytes of stack this time slice used.
+    ///
+    /// The scheduler folds this into `stack_high_water_mark`, which (as in
+    /// FreeRTOS) holds the minimum free stack ever observed.
+    pub fn use_stack(&mut self, bytes: usize) {
+        self.stack_used = self.stack_used.max(bytes);
+    }
+}
+
+/// Task body type.
+pub type TaskBody = Box<dyn FnMut(&mut TaskContext) -> TaskAction>;
+
+struct TaskEntry {
+    tcb: TaskControlBlock,
+    body: TaskBody,
+    wake_tick: Option<u64>,
+}
+
+/// Scheduler errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedulerError {
+    /// No task with this id exists.
+    InvalidTask,
+    /// The task is not in a state that allows this transition.
+    InvalidState(TaskState),
+}
+
+/// A deterministic preemptive-priority scheduler.
+pub struct Scheduler {
+    tasks: Vec<TaskEntry>,
+    ready: [VecDeque<TaskId>; PRIORITY_LEVELS],
+    tick: u64,
+    trace: Vec<Option<TaskId>>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            tasks: Vec::new(),
+            ready: Default::default(),
+            tick: 0,
+            trace: Vec::new(),
+        }
+    }
+
+    /// Add a task in the Ready state (xTaskCreate).
+    pub fn spawn<F>(&mut self, tcb: TaskControlBlock, body: F) -> TaskId
+    where
+        F: FnMut(&mut TaskContext) -> TaskAction + 'static,
+    {
+        let id = self.tasks.len();
+        let state = tcb.state;
+        self.tasks.push(TaskEntry {
+            tcb,
+            body: Box::new(body),
+            wake_tick: None,
+        });
+        match state {
+            TaskState::Ready | TaskState::Running => {
+                self.tasks[id].tcb.state = TaskState::Ready;
+                self.enqueue(id);
+            }
+            // Tasks created Blocked, Suspended or Deleted stay off the ready lists
+            _ => {}
+        }
+        id
+    }
+
+    /// Run one tick. Returns the task that ran, or `None` if the CPU idled.
+    pub fn tick(&mut self) -> Option<TaskId> {
+        self.wake_delayed();
+
+        let id = self.pick_next();
+        self.trace.push(id);
+
+        if let Some(id) = id {
+            let mut ctx = TaskContext {
+                id,
+                tick: self.tick,
+                stack_used: 0,
+            };
+            let entry = &mut self.tasks[id];
+            entry.tcb.state = TaskState::Running;
+            let action = (entry.body)(&mut ctx);
+
+            entry.tcb.run_count += 1;
+            let free = entry.tcb.stack_size.saturating_sub(ctx.stack_used);
+            entry.tcb.stack_high_water_mark = entry.tcb.stack_high_water_mark.min(free);
+
+            self.apply(id, action);
+        }
+
+        self.tick += 1;
+        id
+    }
+
+    /// Run `ticks` ticks back to back.
+    pub fn run_for(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.tick();
+        }
+    }
+
+    /// Move a Blocked task back to Ready (e.g. the event it waited on arrived).
+    pub fn unblock(&mut self, id: TaskId) -> Result<(), SchedulerError> {
+        let state = self.state(id)?;
+        if state != TaskState::Blocked {
+            return Err(SchedulerError::InvalidState(state));
+        }
+        self.tasks[id].wake_tick = None;
+        self.make_ready(id);
+        Ok(())
+    }
+
+    /// Block a Ready task until [`unblock`](Self::unblock) is called.
+    pub fn block(&mut self, id: TaskId) -> Result<(), SchedulerError> {
+        let state = self.state(id)?;
+        if state != TaskState::Ready {
+            return Err(SchedulerError::InvalidState(state));
+        }
+        self.dequeue(id);
+        self.tasks[id].tcb.state = TaskState::Blocked;
+        Ok(())
+    }
+
+    /// Suspend a task (vTaskSuspend). Works from Ready or Blocked.
+    pub fn suspend(&mut self, id: TaskId) -> Result<(), SchedulerError> {
+        let state = self.state(id)?;
+        match state {
+            TaskState::Ready => self.dequeue(id),
+            TaskState::Blocked => self.tasks[id].wake_tick = None,
+            TaskState::Suspended => return Ok(()),
+            _ => return Err(SchedulerError::InvalidState(state)),
+        }
+        self.tasks[id].tcb.state = TaskState::Suspended;
+        Ok(())
+    }
+
+    /// Resume a Suspended task (vTaskResume).
+    pub fn resume(&mut self, id: TaskId) -> Result<(), SchedulerError> {
+        let state = self.state(id)?;
+        if state != TaskState::Suspended {
+            return Err(SchedulerError::InvalidState(state));
+        }
+        self.make_ready(id);
+        Ok(())
+    }
+
+    /// Delete a task (vTaskDelete).
+    pub fn delete(&mut self, id: TaskId) -> Result<(), SchedulerError> {
+        let state = self.state(id)?;
+        if state == TaskState::Ready {
+            self.dequeue(id);
+        }
+        self.tasks[id].wake_tick = None;
+        self.tasks[id].tcb.state = TaskState::Deleted;
+        Ok(())
+    }
+
+    /// Change a task's base and effective priority (vTaskPrioritySet),
+    /// re-queueing it if Ready.
+    pub fn set_priority(
+        &mut self,
+        id: TaskId,
+        priority: TaskPriority,
+    ) -> Result<(), SchedulerError> {
+        let state = self.state(id)?;
+        if state == TaskState::Ready {
+            self.dequeue(id);
+        }
+        self.tasks[id].tcb.base_priority = priority;
+        self.tasks[id].tcb.priority = priority;
+        if state == TaskState::Ready {
+            self.enqueue(id);
+        }
+        Ok(())
+    }
+
+    /// Look up a task's control block.
+    pub fn tcb(&self, id: TaskId) -> Option<&TaskControlBlock> {
+        self.tasks.get(id).map(|entry| &entry.tcb)
+    }
+
+    /// Iterate over all tasks in creation order.
+    pub fn tasks(&self) -> impl Iterator<Item = (TaskId, &TaskControlBlock)> {
+        self.tasks
+            .iter()
+            .enumerate()
+            .map(|(id, entry)| (id, &entry.tcb))
+    }
+
+    /// Number of ticks elapsed.
+    pub fn tick_count(&self) -> u64 {
+        self.tick
+    }
+
+    /// Which task ran on each tick so far (`None` = idle).
+    pub fn trace(&self) -> &[Option<TaskId>] {
+        &self.trace
+    }
+
+    /// Ticks since `id` last ran, or since start if it never ran.
+    ///
+    /// Useful for spotting starvation of low-priority tasks.
+    pub fn ticks_since_run(&self, id: TaskId) -> Option<u64> {
+        self.tasks.get(id)?;
+        let last = self.trace.iter().rposition(|&t| t == Some(id));
+        Some(match last {
+            Some(pos) => self.tick - pos as u64 - 1,
+            None => self.tick,
+        })
+    }
+
+    fn state(&self, id: TaskId) -> Result<TaskState, SchedulerError> {
+        self.tasks
+            .get(id)
+            .map(|entry| entry.tcb.state)
+            .ok_or(SchedulerError::InvalidTask)
+    }
+
+    fn enqueue(&mut self, id: TaskId) {
+        let level = self.tasks[id].tcb.priority as usize;
+        self.ready[level].push_back(id);
+    }
+
+    fn dequeue(&mut self, id: TaskId) {
+        let level = self.tasks[id].tcb.priority as usize;
+        self.ready[level].retain(|&t| t != id);
+    }
+
+    fn make_ready(&mut self, id: TaskId) {
+        self.tasks[id].tcb.state = TaskState::Ready;
+        self.enqueue(id);
+    }
+
+    fn wake_delayed(&mut self) {
+        let now = self.tick;
+        for id in 0..self.tasks.len() {
+            let entry = &self.tasks[id];
+            if entry.tcb.state == TaskState::Blocked && entry.wake_tick.is_some_and(|t| t <= now) {
+                self.tasks[id].wake_tick = None;
+                self.make_ready(id);
+            }
+        }
+    }
+
+    fn pick_next(&mut self) -> Option<TaskId> {
+        self.ready
+            .iter_mut()
+            .rev()
+            .find_map(|queue| queue.pop_front())
+    }
+
+    fn apply(&mut self, id: TaskId, action: TaskAction) {
+        let now = self.tick;
+        let entry = &mut self.tasks[id];
+        match action {
+            TaskAction::Yield | TaskAction::Delay(0) => self.make_ready(id),
+            TaskAction::Delay(ticks) => {
+                entry.tcb.state = TaskState::Blocked;
+                entry.wake_tick = Some(now + ticks);
+            }
+            TaskAction::Block => entry.tcb.state = TaskState::Blocked,
+            TaskAction::Suspend => entry.tcb.state = TaskState::Suspended,
+            TaskAction::Exit => entry.tcb.state = TaskState::Deleted,
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcb(name: &'static str, priority: TaskPriority) -> TaskControlBlock {
+        TaskControlBlock::new(name, priority, 256)
+    }
+
+    #[test]
+    fn test_highest_priority_runs_first() {
+        let mut sched = Scheduler::new();
+        let low = sched.spawn(tcb("low", TaskPriority::Low), |_| TaskAction::Yield);
+        let high = sched.spawn(tcb("high", TaskPriority::High), |ctx| {
+            // The body learns which task it is from its context
+            assert_eq!(ctx.id, 1);
+            TaskAction::Delay(2)
+        });
+
+        sched.run_for(4);
+        assert_eq!(
+            sched.trace(),
+            &[Some(high), Some(low), Some(high), Some(low)]
+        );
+        assert_eq!(sched.tcb(high).unwrap().run_count, 2);
+    }
+
+    #[test]
+    fn test_round_robin_within_priority() {
+        let mut sched = Scheduler::new();
+        let a = sched.spawn(tcb("a", TaskPriority::Normal), |_| TaskAction::Yield);
+        let b = sched.spawn(tcb("b", TaskPriority::Normal), |_| TaskAction::Yield);
+        let idle = sched.spawn(tcb("idle", TaskPriority::Idle), |_| TaskAction::Yield);
+
+        sched.run_for(4);
+        assert_eq!(sched.trace(), &[Some(a), Some(b), Some(a), Some(b)]);
+        assert_eq!(sched.ticks_since_run(idle), Some(4));
+    }
+
+    #[test]
+    fn test_state_transitions() {
+        let mut sched = Scheduler::new();
+        let task = sched.spawn(tcb("t", TaskPriority::Normal), |_| TaskAction::Block);
+
+        sched.tick();
+        assert_eq!(sched.tcb(task).unwrap().state, TaskState::Blocked);
+        assert_eq!(sched.tick(), None);
+
+        sched.suspend(task).unwrap();
+        assert_eq!(
+            sched.unblock(task),
+            Err(SchedulerError::InvalidState(TaskState::Suspended))
+        );
+        sched.resume(task).unwrap();
+        assert_eq!(sched.tick(), Some(task));
+
+        sched.delete(task).unwrap();
+        assert_eq!(sched.tcb(task).unwrap().state, TaskState::Deleted);
+        assert_eq!(sched.unblock(99), Err(SchedulerError::InvalidTask));
+    }
+
+    #[test]
+    fn test_stack_high_water_mark() {
+        let mut sched = Scheduler::new();
+        let mut depth = 0;
+        let task = sched.spawn(tcb("t", TaskPriority::Normal), move |ctx| {
+            depth += 64;
+            ctx.use_stack(depth);
+            TaskAction::Yield
+        });
+
+        sched.run_for(3);
+        let tcb = sched.tcb(task).unwrap();
+        assert_eq!(tcb.run_count, 3);
+        assert_eq!(tcb.stack_high_water_mark, 256 - 192);
+    }
+}
@@ -0,0 +1,417 @@
+//! Software timer service (FreeRTOS timer-task style)
+//!
+//! One [`TimerService`] owns every software timer and keeps the active ones
+//! in an expiry-ordered set. Other threads never touch a timer directly:
+//! they send commands through a [`TimerHandle`], and the service applies
+//! them in order, just like `xTimerStart`/`xTimerReset` post to the FreeRTOS
+//! timer command queue.
+//!
+//! The clock is in ticks (1 tick = 1 ms when run as a daemon). Tests drive
+//! it deterministically with [`TimerService::advance`]; firmware-like code
+//! hands the service to [`TimerService::spawn`] to run it on a real clock.
+
+use std::collections::BTreeSet;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Identifies a timer within its service.
+pub type TimerId = usize;
+
+/// Timer callback, run in the service's context with the expiring timer.
+pub type TimerCallback = Box<dyn FnMut(&TimerHandle) + Send + 'static>;
+
+/// Timer mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerMode {
+    OneShot,
+    Periodic,
+}
+
+/// Commands posted to the timer service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Start(TimerId),
+    Stop(TimerId),
+    Reset(TimerId),
+    ChangePeriod(TimerId, u64),
+    Delete(TimerId),
+    Shutdown,
+}
+
+/// Controls one timer from any thread.
+#[derive(Clone)]
+pub struct TimerHandle {
+    id: TimerId,
+    name: &'static str,
+    commands: Sender<Command>,
+}
+
+impl TimerHandle {
+    pub fn id(&self) -> TimerId {
+        self.id
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Start the timer; it expires one period after the command is applied.
+    /// Starting an active timer restarts it.
+    pub fn start(&self) -> bool {
+        self.post(Command::Start(self.id))
+    }
+
+    /// Stop the timer without firing it.
+    pub fn stop(&self) -> bool {
+        self.post(Command::Stop(self.id))
+    }
+
+    /// Restart the period from now, starting the timer if it was stopped.
+    pub fn reset(&self) -> bool {
+        self.post(Command::Reset(self.id))
+    }
+
+    /// Change the period and (re)start the timer with it.
+    pub fn change_period(&self, period: u64) -> bool {
+        period > 0 && self.post(Command::ChangePeriod(self.id, period))
+    }
+
+    /// Delete the timer; later commands for it are ignored.
+    pub fn delete(&self) -> bool {
+        self.post(Command::Delete(self.id))
+    }
+
+    fn post(&self, command: Command) -> bool {
+        self.commands.send(command).is_ok()
+    }
+}
+
+struct TimerRecord {
+    handle: TimerHandle,
+    period: u64,
+    mode: TimerMode,
+    callback: TimerCallback,
+    /// Key into the expiry set while the timer is active.
+    expiry: Option<(u64, u64)>,
+    fire_count: u64,
+    deleted: bool,
+}
+
+/// The timer daemon: owns timers and fires them in expiry order.
+pub struct TimerService {
+    now: u64,
+    timers: Vec<TimerRecord>,
+    /// (expiry tick, insertion sequence, timer) - the sequence keeps timers
+    /// that expire on the same tick in the order they were scheduled.
+    expiries: BTreeSet<(u64, u64, TimerId)>,
+    next_seq: u64,
+    sender: Sender<Command>,
+    commands: Receiver<Command>,
+}
+
+impl TimerService {
+    pub fn new() -> Self {
+        let (sender, commands) = mpsc::channel();
+        Self {
+            now: 0,
+            timers: Vec::new(),
+            expiries: BTreeSet::new(),
+            next_seq: 0,
+            sender,
+            commands,
+        }
+    }
+
+    /// Create a dormant timer (xTimerCreate). Call `start` on the handle to
+    /// arm it.
+    ///
+    /// # Panics
+    /// If `period` is zero.
+    pub fn create<F>(
+        &mut self,
+        name: &'static str,
+        period: u64,
+        mode: TimerMode,
+        callback: F,
+    ) -> TimerHandle
+    where
+        F: FnMut(&TimerHandle) + Send + 'static,
+    {
+        assert!(period > 0, "timer period must be at least one tick");
+        let handle = TimerHandle {
+            id: self.timers.len(),
+            name,
+            commands: self.sender.clone(),
+        };
+        self.timers.push(TimerRecord {
+            handle: handle.clone(),
+            period,
+            mode,
+            callback: Box::new(callback),
+            expiry: None,
+            fire_count: 0,
+            deleted: false,
+        });
+        handle
+    }
+
+    /// Advance the clock by `ticks`, firing every timer that expires.
+    pub fn advance(&mut self, ticks: u64) {
+        self.advance_to(self.now + ticks);
+    }
+
+    /// Advance the clock to the absolute tick `target`.
+    ///
+    /// Pending commands are applied first. Timers fire in expiry order with
+    /// the clock set to their expiry tick, and commands posted by a callback
+    /// take effect before the next timer fires.
+    pub fn advance_to(&mut self, target: u64) {
+        self.process_commands();
+        while let Some(&(expiry, seq, id)) = self.expiries.first() {
+            if expiry > target {
+                break;
+            }
+            self.expiries.remove(&(expiry, seq, id));
+            self.now = expiry;
+
+            let timer = &mut self.timers[id];
+            timer.expiry = None;
+            timer.fire_count += 1;
+            (timer.callback)(&timer.handle);
+            if timer.mode == TimerMode::Periodic {
+                // Schedule from the previous expiry, not from now, so
+                // periodic timers never drift
+                self.schedule(id, expiry + self.timers[id].period);
+            }
+            self.process_commands();
+        }
+        self.now = self.now.max(target);
+    }
+
+    /// Current tick.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Tick at which the next timer expires, if any is active.
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.expiries.first().map(|&(expiry, _, _)| expiry)
+    }
+
+    /// Tick at which this timer expires, if it is active.
+    pub fn expiry_of(&self, id: TimerId) -> Option<u64> {
+        self.timers.get(id)?.expiry.map(|(expiry, _)| expiry)
+    }
+
+    pub fn is_active(&self, id: TimerId) -> bool {
+        self.expiry_of(id).is_some()
+    }
+
+    /// How many times the timer's callback has run.
+    pub fn fire_count(&self, id: TimerId) -> u64 {
+        self.timers.get(id).map_or(0, |timer| timer.fire_count)
+    }
+
+    pub fn period(&self, id: TimerId) -> Option<u64> {
+        self.timers.get(id).map(|timer| timer.period)
+    }
+
+    /// Run the service on its own thread against the wall clock
+    /// (1 tick = 1 ms), sleeping until the next expiry or command.
+    pub fn spawn(mut self) -> TimerDaemon {
+        let sender = self.sender.clone();
+        let thread = thread::spawn(move || {
+            let start = Instant::now();
+            let base = self.now;
+            let clock = |start: Instant| base + start.elapsed().as_millis() as u64;
+            loop {
+                let wait = match self.next_expiry() {
+                    Some(expiry) => expiry.saturating_sub(clock(start)),
+                    None => u64::MAX,
+                };
+                let wait = Duration::from_millis(wait.min(u32::MAX as u64));
+                match self.commands.recv_timeout(wait) {
+                    Ok(Command::Shutdown) => break,
+                    Ok(command) => {
+                        self.now = self.now.max(clock(start));
+                        self.apply(command);
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                self.advance_to(clock(start));
+            }
+            self
+        });
+        TimerDaemon { thread, sender }
+    }
+
+    fn schedule(&mut self, id: TimerId, expiry: u64) {
+        self.unschedule(id);
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.expiries.insert((expiry, seq, id));
+        self.timers[id].expiry = Some((expiry, seq));
+    }
+
+    fn unschedule(&mut self, id: TimerId) {
+        if let Some((expiry, seq)) = self.timers[id].expiry.take() {
+            self.expiries.remove(&(expiry, seq, id));
+        }
+    }
+
+    fn process_commands(&mut self) {
+        while let Ok(command) = self.commands.try_recv() {
+            self.apply(command);
+        }
+    }
+
+    fn apply(&mut self, command: Command) {
+        let id = match command {
+            Command::Start(id)
+            | Command::Stop(id)
+            | Command::Reset(id)
+            | Command::ChangePeriod(id, _)
+            | Command::Delete(id) => id,
+            // Only meaningful to a running daemon
+            Command::Shutdown => return,
+        };
+        if self.timers.get(id).is_none_or(|timer| timer.deleted) {
+            return;
+        }
+        match command {
+            Command::Start(_) | Command::Reset(_) => {
+                self.schedule(id, self.now + self.timers[id].period);
+            }
+            Command::ChangePeriod(_, period) => {
+                self.timers[id].period = period;
+                self.schedule(id, self.now + period);
+            }
+            Command::Stop(_) => self.unschedule(id),
+            Command::Delete(_) => {
+                self.unschedule(id);
+                self.timers[id].deleted = true;
+            }
+            Command::Shutdown => {}
+        }
+    }
+}
+
+impl Default for TimerService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A [`TimerService`] running on its own thread.
+pub struct TimerDaemon {
+    thread: JoinHandle<TimerService>,
+    sender: Sender<Command>,
+}
+
+impl TimerDaemon {
+    /// Stop the daemon and get the service back for inspection.
+    pub fn shutdown(self) -> TimerService {
+        let _ = self.sender.send(Command::Shutdown);
+        self.thread.join().expect("timer daemon panicked")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_one_shot_and_periodic_fire_on_time() {
+        let mut service = TimerService::new();
+        let one_shot = service.create("once", 5, TimerMode::OneShot, |_| {});
+        let periodic = service.create("tick", 3, TimerMode::Periodic, |_| {});
+        one_shot.start();
+        periodic.start();
+
+        service.advance(2);
+        assert_eq!(service.fire_count(periodic.id()), 0);
+        service.advance(1);
+        assert_eq!(service.fire_count(periodic.id()), 1);
+        assert_eq!(service.next_expiry(), Some(5));
+
+        service.advance(7);
+        assert_eq!(service.now(), 10);
+        assert_eq!(service.fire_count(one_shot.id()), 1);
+        assert!(!service.is_active(one_shot.id()));
+        assert_eq!(service.fire_count(periodic.id()), 3);
+        assert_eq!(service.expiry_of(periodic.id()), Some(12));
+    }
+
+    #[test]
+    fn test_expiry_order_and_callback_commands() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut service = TimerService::new();
+
+        let log = Arc::clone(&order);
+        let b = service.create("b", 4, TimerMode::OneShot, move |t| {
+            log.lock().unwrap().push(t.name())
+        });
+        let log = Arc::clone(&order);
+        let watchdog = service.create("watchdog", 10, TimerMode::Periodic, move |t| {
+            log.lock().unwrap().push(t.name())
+        });
+        // "a" expires first and stops the watchdog from its callback
+        let (log, wd) = (Arc::clone(&order), watchdog.clone());
+        let a = service.create("a", 2, TimerMode::OneShot, move |t| {
+            log.lock().unwrap().push(t.name());
+            wd.stop();
+        });
+        b.start();
+        watchdog.start();
+        a.start();
+
+        service.advance(20);
+        assert_eq!(*order.lock().unwrap(), vec!["a", "b"]);
+        assert_eq!(service.fire_count(watchdog.id()), 0);
+    }
+
+    #[test]
+    fn test_reset_and_change_period_from_other_thread() {
+        let mut service = TimerService::new();
+        let timer = service.create("t", 10, TimerMode::Periodic, |_| {});
+        timer.start();
+        service.advance(8);
+
+        let remote = timer.clone();
+        thread::spawn(move || {
+            remote.reset();
+        })
+        .join()
+        .unwrap();
+        service.advance(9);
+        assert_eq!(service.fire_count(timer.id()), 0);
+        service.advance(1);
+        assert_eq!(service.fire_count(timer.id()), 1);
+
+        timer.change_period(4);
+        service.advance(8);
+        assert_eq!(service.period(timer.id()), Some(4));
+        assert_eq!(service.fire_count(timer.id()), 3);
+
+        timer.delete();
+        timer.start();
+        service.advance(100);
+        assert_eq!(service.fire_count(timer.id()), 3);
+    }
+
+    #[test]
+    fn test_daemon_runs_on_wall_clock() {
+        let mut service = TimerService::new();
+        let timer = service.create("once", 20, TimerMode::OneShot, |_| {});
+        let daemon = service.spawn();
+
+        timer.start();
+        thread::sleep(Duration::from_millis(100));
+        let service = daemon.shutdown();
+        assert_eq!(service.fire_count(timer.id()), 1);
+        assert!(service.now() >= 20);
+    }
+}
@@ -0,0 +1,1453 @@
+//! FAT12/16/32 image backend
+//!
+//! Serves the same operations as [`SimpleFilesystem`](crate::SimpleFilesystem)
+//! (`open`, `list_directory`, `get_info`, reads and writes through
+//! [`FileHandle`]) straight from a raw FAT image, so an ESP built for QEMU can
+//! be inspected on the host without mounting it.
+//!
+//! ```text
+//!   ┌──────────┬──────────┬─────────┬──────────────┬───────────────────────┐
+//!   │ Boot     │ Reserved │ FAT #1  │ Root dir     │ Data region           │
+//!   │ sector   │ sectors  │ FAT #2  │ (FAT12/16)   │ cluster 2, 3, 4, ...  │
+//!   └──────────┴──────────┴─────────┴──────────────┴───────────────────────┘
+//! ```
+//!
+//! The FAT type is decided by the number of data clusters, never by the
+//! type string in the boot sector. Names are matched case-insensitively
+//! against both the long (VFAT) name and the 8.3 short name.
+
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::path::{self, UefiPath, UefiPathBuf};
+use crate::{
+    Backing, CommitTarget, DirEntry, EfiTime, FileAttributes, FileHandle, FileInfo, FileSystemInfo,
+    OpenMode, Status,
+};
+
+const DIR_ENTRY_SIZE: u64 = 32;
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = 0x0F;
+const ENTRY_FREE: u8 = 0x00;
+const ENTRY_DELETED: u8 = 0xE5;
+const LFN_LAST: u8 = 0x40;
+const LFN_CHARS: usize = 13;
+/// Byte offsets of the 13 UCS-2 characters inside a long-name entry
+const LFN_CHAR_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+/// NTRes flags: the base name / extension is stored upper-case but displayed
+/// lower-case
+const NT_LOWER_BASE: u8 = 0x08;
+const NT_LOWER_EXT: u8 = 0x10;
+const FSINFO_LEAD_SIG: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIG: u32 = 0x6141_7272;
+/// Largest cluster count a 28-bit FAT32 entry can address
+const MAX_FAT32_CLUSTERS: u32 = 0x0FFF_FFF5;
+
+/// FAT variant, determined by the cluster count
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    /// Microsoft's FAT specification: fewer than 4085 clusters is FAT12,
+    /// fewer than 65525 is FAT16, anything else FAT32.
+    pub fn from_cluster_count(clusters: u32) -> Self {
+        if clusters < 4085 {
+            FatType::Fat12
+        } else if clusters < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        }
+    }
+
+    /// End-of-chain marker written by this implementation
+    fn end_of_chain(self) -> u32 {
+        match self {
+            FatType::Fat12 => 0xFFF,
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => 0x0FFF_FFFF,
+        }
+    }
+
+    /// Any value at or above this marks the end of a chain
+    fn is_end_of_chain(self, value: u32) -> bool {
+        value >= self.end_of_chain() - 7
+    }
+
+    fn type_string(self) -> &'static [u8; 8] {
+        match self {
+            FatType::Fat12 => b"FAT12   ",
+            FatType::Fat16 => b"FAT16   ",
+            FatType::Fat32 => b"FAT32   ",
+        }
+    }
+}
+
+/// Layout of a FAT volume, decoded from the BIOS parameter block
+#[derive(Debug, Clone, Copy)]
+struct Geometry {
+    bytes_per_sector: u32,
+    sectors_per_cluster: u32,
+    reserved_sectors: u32,
+    fat_count: u32,
+    fat_sectors: u32,
+    root_entries: u32,
+    /// First cluster of the root directory (FAT32 only)
+    root_cluster: u32,
+    /// FSInfo sector (FAT32 only; 0 when absent)
+    fs_info_sector: u32,
+    cluster_count: u32,
+    fat_type: FatType,
+}
+
+impl Geometry {
+    fn parse(boot: &[u8]) -> Result<Self, Status> {
+        if boot[510] != 0x55 || boot[511] != 0xAA {
+            return Err(Status::VOLUME_CORRUPTED);
+        }
+        let bytes_per_sector = u16_at(boot, 11) as u32;
+        let sectors_per_cluster = boot[13] as u32;
+        let reserved_sectors = u16_at(boot, 14) as u32;
+        let fat_count = boot[16] as u32;
+        let root_entries = u16_at(boot, 17) as u32;
+        let total_sectors = match u16_at(boot, 19) {
+            0 => u32_at(boot, 32),
+            small => small as u32,
+        };
+        let fat_sectors = match u16_at(boot, 22) {
+            0 => u32_at(boot, 36),
+            small => small as u32,
+        };
+
+        if !matches!(bytes_per_sector, 512 | 1024 | 2048 | 4096)
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved_sectors == 0
+            || fat_count == 0
+            || fat_sectors == 0
+        {
+            return Err(Status::VOLUME_CORRUPTED);
+        }
+
+        // Every field is untrusted, so the layout must fit in a u32 sector count
+        let root_dir_sectors = (root_entries * 32).div_ceil(bytes_per_sector);
+        let first_data_sector = fat_count
+            .checked_mul(fat_sectors)
+            .and_then(|fats| fats.checked_add(reserved_sectors))
+            .and_then(|sectors| sectors.checked_add(root_dir_sectors))
+            .ok_or(Status::VOLUME_CORRUPTED)?;
+        let data_sectors = total_sectors
+            .checked_sub(first_data_sector)
+            .ok_or(Status::VOLUME_CORRUPTED)?;
+        let cluster_count = data_sectors / sectors_per_cluster;
+        if cluster_count > MAX_FAT32_CLUSTERS {
+            return Err(Status::VOLUME_CORRUPTED);
+        }
+        let fat_type = FatType::from_cluster_count(cluster_count);
+
+        let (root_cluster, fs_info_sector) = if fat_type == FatType::Fat32 {
+            (u32_at(boot, 44), u16_at(boot, 48) as u32)
+        } else {
+            (0, 0)
+        };
+
+        let geometry = Geometry {
+            bytes_per_sector,
+            sectors_per_cluster,
+            reserved_sectors,
+            fat_count,
+            fat_sectors,
+            root_entries,
+            root_cluster,
+            fs_info_sector,
+            cluster_count,
+            fat_type,
+        };
+
+        // The FAT must be able to describe every cluster
+        let fat_bytes = fat_sectors as u64 * bytes_per_sector as u64;
+        let entry_bytes = if fat_type == FatType::Fat32 { 4 } else { 2 };
+        if geometry.fat_entry_offset(cluster_count + 1) + entry_bytes > fat_bytes
+            || (fat_type == FatType::Fat32 && !geometry.is_data_cluster(root_cluster))
+        {
+            return Err(Status::VOLUME_CORRUPTED);
+        }
+        Ok(geometry)
+    }
+
+    fn cluster_size(&self) -> u64 {
+        (self.bytes_per_sector * self.sectors_per_cluster) as u64
+    }
+
+    fn sector_offset(&self, sector: u32) -> u64 {
+        sector as u64 * self.bytes_per_sector as u64
+    }
+
+    fn fat_offset(&self, copy: u32) -> u64 {
+        self.sector_offset(self.reserved_sectors + copy * self.fat_sectors)
+    }
+
+    fn fat_len(&self) -> usize {
+        (self.fat_sectors as u64 * self.bytes_per_sector as u64) as usize
+    }
+
+    /// Fixed root directory region (FAT12/16)
+    fn root_region(&self) -> (u64, u64) {
+        let start = self.fat_offset(self.fat_count);
+        (start, self.root_entries as u64 * DIR_ENTRY_SIZE)
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        let (root_start, root_len) = self.root_region();
+        let data_start = root_start + root_len.next_multiple_of(self.bytes_per_sector as u64);
+        data_start + (cluster as u64 - 2) * self.cluster_size()
+    }
+
+    fn is_data_cluster(&self, cluster: u32) -> bool {
+        (2..self.cluster_count + 2).contains(&cluster)
+    }
+
+    /// Byte offset of a cluster's entry within the FAT
+    fn fat_entry_offset(&self, cluster: u32) -> u64 {
+        let cluster = cluster as u64;
+        match self.fat_type {
+            FatType::Fat12 => cluster + cluster / 2,
+            FatType::Fat16 => cluster * 2,
+            FatType::Fat32 => cluster * 4,
+        }
+    }
+}
+
+/// A directory: the fixed FAT12/16 root region or a cluster chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DirLocation {
+    FixedRoot,
+    Chain(u32),
+}
+
+/// A short directory entry together with its long name
+#[derive(Debug, Clone)]
+struct FatEntry {
+    name: String,
+    raw: [u8; 32],
+    /// Volume offset of the short entry; stable for the life of the file
+    offset: u64,
+}
+
+impl FatEntry {
+    fn attributes(&self) -> u8 {
+        self.raw[11]
+    }
+
+    fn is_directory(&self) -> bool {
+        self.attributes() & ATTR_DIRECTORY != 0
+    }
+
+    fn first_cluster(&self) -> u32 {
+        ((u16_at(&self.raw, 20) as u32) << 16) | u16_at(&self.raw, 26) as u32
+    }
+
+    fn size(&self) -> u32 {
+        u32_at(&self.raw, 28)
+    }
+
+    fn short_name(&self) -> String {
+        short_display_name(&self.raw)
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        path::eq_ignore_case(&self.name, name) || self.short_name().eq_ignore_ascii_case(name)
+    }
+
+    fn file_attributes(&self) -> FileAttributes {
+        FileAttributes(self.attributes() as u64 & FileAttributes::VALID_ATTR)
+    }
+
+    fn location(&self) -> DirLocation {
+        // ".." entries of first-level directories point at cluster 0
+        match self.first_cluster() {
+            0 => DirLocation::FixedRoot,
+            cluster => DirLocation::Chain(cluster),
+        }
+    }
+}
+
+/// Mounted volume state shared by a [`FatFilesystem`] and its handles
+struct FatVolume<D> {
+    device: D,
+    /// Byte offset of the volume within the device (partition start)
+    base: u64,
+    geometry: Geometry,
+    /// Cached copy of the first FAT
+    fat: Vec<u8>,
+    clock: EfiTime,
+}
+
+impl<D: Read + Write + Seek> FatVolume<D> {
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> Result<(), Status> {
+        self.device
+            .seek(SeekFrom::Start(self.base + offset))
+            .and_then(|_| self.device.read_exact(buffer))
+            .map_err(|_| Status::DEVICE_ERROR)
+    }
+
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> Result<(), Status> {
+        self.device
+            .seek(SeekFrom::Start(self.base + offset))
+            .and_then(|_| self.device.write_all(data))
+            .map_err(|_| Status::DEVICE_ERROR)
+    }
+
+    fn fat_entry(&self, cluster: u32) -> u32 {
+        let offset = self.geometry.fat_entry_offset(cluster) as usize;
+        match self.geometry.fat_type {
+            FatType::Fat12 => {
+                let pair = u16_at(&self.fat, offset) as u32;
+                if cluster & 1 == 1 {
+                    pair >> 4
+                } else {
+                    pair & 0xFFF
+                }
+            }
+            FatType::Fat16 => u16_at(&self.fat, offset) as u32,
+            FatType::Fat32 => u32_at(&self.fat, offset) & 0x0FFF_FFFF,
+        }
+    }
+
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) {
+        let offset = self.geometry.fat_entry_offset(cluster) as usize;
+        match self.geometry.fat_type {
+            FatType::Fat12 => {
+                let pair = u16_at(&self.fat, offset);
+                let value = value as u16 & 0xFFF;
+                let pair = if cluster & 1 == 1 {
+                    (pair & 0x000F) | (value << 4)
+                } else {
+                    (pair & 0xF000) | value
+                };
+                self.fat[offset..offset + 2].copy_from_slice(&pair.to_le_bytes());
+            }
+            FatType::Fat16 => {
+                self.fat[offset..offset + 2].copy_from_slice(&(value as u16).to_le_bytes());
+            }
+            FatType::Fat32 => {
+                // The top four bits are reserved and must be preserved
+                let old = u32_at(&self.fat, offset);
+                let value = (old & 0xF000_0000) | (value & 0x0FFF_FFFF);
+                self.fat[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+
+    /// Follow a cluster chain, rejecting out-of-range links and loops.
+    fn chain(&self, start: u32) -> Result<Vec<u32>, Status> {
+        let mut chain = Vec::new();
+        let mut cluster = start;
+        loop {
+            if !self.geometry.is_data_cluster(cluster)
+                || chain.len() as u32 > self.geometry.cluster_count
+            {
+                return Err(Status::VOLUME_CORRUPTED);
+            }
+            chain.push(cluster);
+            let next = self.fat_entry(cluster);
+            if self.geometry.fat_type.is_end_of_chain(next) {
+                return Ok(chain);
+            }
+            cluster = next;
+        }
+    }
+
+    /// Claim `count` free clusters and link them into a chain.
+    fn allocate(&mut self, count: usize) -> Result<Vec<u32>, Status> {
+        let free: Vec<u32> = (2..self.geometry.cluster_count + 2)
+            .filter(|&cluster| self.fat_entry(cluster) == 0)
+            .take(count)
+            .collect();
+        if free.len() < count {
+            return Err(Status::VOLUME_FULL);
+        }
+        for pair in free.windows(2) {
+            self.set_fat_entry(pair[0], pair[1]);
+        }
+        if let Some(&last) = free.last() {
+            self.set_fat_entry(last, self.geometry.fat_type.end_of_chain());
+        }
+        Ok(free)
+    }
+
+    fn free_clusters(&self) -> u32 {
+        (2..self.geometry.cluster_count + 2)
+            .filter(|&cluster| self.fat_entry(cluster) == 0)
+            .count() as u32
+    }
+
+    /// Write the cached FAT to every copy and refresh the FAT32 FSInfo hints.
+    fn write_fat(&mut self) -> Result<(), Status> {
+        let fat = std::mem::take(&mut self.fat);
+        let result = (0..self.geometry.fat_count)
+            .try_for_each(|copy| self.write_at(self.geometry.fat_offset(copy), &fat));
+        self.fat = fat;
+        result?;
+
+        let sector = self.geometry.fs_info_sector;
+        if self.geometry.fat_type == FatType::Fat32 && sector != 0 && sector != 0xFFFF {
+            let offset = self.geometry.sector_offset(sector);
+            let mut info = [0u8; 512];
+            self.read_at(offset, &mut info)?;
+            if u32_at(&info, 0) == FSINFO_LEAD_SIG && u32_at(&info, 484) == FSINFO_STRUCT_SIG {
+                let free = self.free_clusters();
+                info[488..492].copy_from_slice(&free.to_le_bytes());
+                info[492..496].copy_from_slice(&0xFFFF_FFFFu32.to_le_bytes());
+                self.write_at(offset, &info)?;
+            }
+        }
+        self.device.flush().map_err(|_| Status::DEVICE_ERROR)
+    }
+
+    fn root(&self) -> DirLocation {
+        match self.geometry.fat_type {
+            FatType::Fat32 => DirLocation::Chain(self.geometry.root_cluster),
+            _ => DirLocation::FixedRoot,
+        }
+    }
+
+    /// Volume offsets of every 32-byte slot in a directory, in order.
+    fn dir_slots(&self, dir: DirLocation) -> Result<Vec<u64>, Status> {
+        let regions: Vec<(u64, u64)> = match dir {
+            DirLocation::FixedRoot if self.geometry.fat_type != FatType::Fat32 => {
+                vec![self.geometry.root_region()]
+            }
+            DirLocation::FixedRoot => return self.dir_slots(self.root()),
+            DirLocation::Chain(start) => self
+                .chain(start)?
+                .into_iter()
+                .map(|cluster| {
+                    (
+                        self.geometry.cluster_offset(cluster),
+                        self.geometry.cluster_size(),
+                    )
+                })
+                .collect(),
+        };
+        Ok(regions
+            .into_iter()
+            .flat_map(|(start, len)| {
+                (0..len / DIR_ENTRY_SIZE).map(move |i| start + i * DIR_ENTRY_SIZE)
+            })
+            .collect())
+    }
+
+    /// Read a directory, pairing short entries with their long names.
+    /// Volume labels and orphaned long-name entries are skipped.
+    fn read_dir(&mut self, dir: DirLocation) -> Result<Vec<FatEntry>, Status> {
+        let mut entries = Vec::new();
+        // Long-name parts collected so far, in on-disk order (last part first)
+        let mut long_parts: Vec<[u8; 32]> = Vec::new();
+
+        for offset in self.dir_slots(dir)? {
+            let mut raw = [0u8; 32];
+            self.read_at(offset, &mut raw)?;
+            match raw[0] {
+                ENTRY_FREE => break,
+                ENTRY_DELETED => {
+                    long_parts.clear();
+                    continue;
+                }
+                _ => {}
+            }
+            if raw[11] & 0x3F == ATTR_LONG_NAME {
+                if raw[0] & LFN_LAST != 0 {
+                    long_parts.clear();
+                }
+                long_parts.push(raw);
+                continue;
+            }
+            if raw[11] & ATTR_VOLUME_ID != 0 {
+                long_parts.clear();
+                continue;
+            }
+
+            let name =
+                decode_long_name(&long_parts, &raw).unwrap_or_else(|| short_display_name(&raw));
+            long_parts.clear();
+            entries.push(FatEntry { name, raw, offset });
+        }
+        Ok(entries)
+    }
+
+    /// Resolve a path. `Ok(None)` is the root directory.
+    fn lookup(&mut self, path: &UefiPath) -> Result<Option<FatEntry>, Status> {
+        let mut current: Option<FatEntry> = None;
+        for component in path.components() {
+            let dir = match &current {
+                None => self.root(),
+                Some(entry) if entry.is_directory() => entry.location(),
+                Some(_) => return Err(Status::NOT_FOUND),
+            };
+            let found = self
+                .read_dir(dir)?
+                .into_iter()
+                .find(|entry| entry.matches(component))
+                .ok_or(Status::NOT_FOUND)?;
+            // ".." back to the root
+            current = if found.is_directory() && found.first_cluster() == 0 {
+                None
+            } else {
+                Some(found)
+            };
+        }
+        Ok(current)
+    }
+
+    /// Resolve a path that must name a directory.
+    fn lookup_dir(&mut self, path: &UefiPath) -> Result<DirLocation, Status> {
+        match self.lookup(path)? {
+            None => Ok(self.root()),
+            Some(entry) if entry.is_directory() => Ok(entry.location()),
+            Some(_) => Err(Status::NOT_FOUND),
+        }
+    }
+
+    fn read_file(&mut self, entry: &FatEntry) -> Result<Vec<u8>, Status> {
+        let size = entry.size() as usize;
+        let mut data = Vec::with_capacity(size);
+        if entry.first_cluster() != 0 {
+            let cluster_size = self.geometry.cluster_size() as usize;
+            let mut buffer = vec![0u8; cluster_size];
+            for cluster in self.chain(entry.first_cluster())? {
+                if data.len() >= size {
+                    break;
+                }
+                self.read_at(self.geometry.cluster_offset(cluster), &mut buffer)?;
+                let take = cluster_size.min(size - data.len());
+                data.extend_from_slice(&buffer[..take]);
+            }
+        }
+        if data.len() < size {
+            // The chain is shorter than the recorded size
+            return Err(Status::VOLUME_CORRUPTED);
+        }
+        Ok(data)
+    }
+
+    /// Find (or make room for) `count` consecutive free slots in a directory.
+    fn free_slots(&mut self, dir: DirLocation, count: usize) -> Result<Vec<u64>, Status> {
+        loop {
+            let slots = self.dir_slots(dir)?;
+            let mut run = Vec::new();
+            for offset in slots {
+                let mut first = [0u8; 1];
+                self.read_at(offset, &mut first)?;
+                if matches!(first[0], ENTRY_FREE | ENTRY_DELETED) {
+                    run.push(offset);
+                    if run.len() == count {
+                        return Ok(run);
+                    }
+                } else {
+                    run.clear();
+                }
+            }
+
+            // Grow the directory by one zeroed cluster
+            let DirLocation::Chain(start) = self.directory_chain_start(dir) else {
+                return Err(Status::VOLUME_FULL);
+            };
+            let last = *self.chain(start)?.last().unwrap();
+            let new = self.allocate(1)?[0];
+            self.set_fat_entry(last, new);
+            self.zero_cluster(new)?;
+            self.write_fat()?;
+        }
+    }
+
+    fn directory_chain_start(&self, dir: DirLocation) -> DirLocation {
+        match dir {
+            DirLocation::FixedRoot if self.geometry.fat_type == FatType::Fat32 => self.root(),
+            other => other,
+        }
+    }
+
+    fn zero_cluster(&mut self, cluster: u32) -> Result<(), Status> {
+        let zeros = vec![0u8; self.geometry.cluster_size() as usize];
+        self.write_at(self.geometry.cluster_offset(cluster), &zeros)
+    }
+
+    /// Add an entry named `name` to `dir`, with long-name entries when the
+    /// name is not a plain upper-case 8.3 name.
+    fn create_entry(
+        &mut self,
+        dir: DirLocation,
+        name: &str,
+        attributes: u8,
+        first_cluster: u32,
+    ) -> Result<FatEntry, Status> {
+        validate_long_name(name)?;
+        let existing = self.read_dir(dir)?;
+        if existing.iter().any(|entry| entry.matches(name)) {
+            return Err(Status::ACCESS_DENIED);
+        }
+
+        let (short, needs_long_name) = short_name_for(name, &existing);
+        let mut raw = [0u8; 32];
+        raw[..11].copy_from_slice(&short);
+        if raw[0] == ENTRY_DELETED {
+            raw[0] = 0x05;
+        }
+        raw[11] = attributes;
+        let (date, time) = fat_timestamp(&self.clock);
+        raw[14..16].copy_from_slice(&time.to_le_bytes());
+        raw[16..18].copy_from_slice(&date.to_le_bytes());
+        raw[18..20].copy_from_slice(&date.to_le_bytes());
+        raw[22..24].copy_from_slice(&time.to_le_bytes());
+        raw[24..26].copy_from_slice(&date.to_le_bytes());
+        set_first_cluster(&mut raw, first_cluster);
+
+        let mut entries = if needs_long_name {
+            encode_long_name(name, checksum(&short))
+        } else {
+            Vec::new()
+        };
+        entries.push(raw);
+
+        let slots = self.free_slots(dir, entries.len())?;
+        for (offset, entry) in slots.iter().zip(&entries) {
+            self.write_at(*offset, entry)?;
+        }
+        self.device.flush().map_err(|_| Status::DEVICE_ERROR)?;
+        Ok(FatEntry {
+            name: name.to_string(),
+            raw,
+            offset: *slots.last().unwrap(),
+        })
+    }
+}
+
+impl<D: Read + Write + Seek> CommitTarget for FatVolume<D> {
+    /// Rewrite a file's cluster chain, growing or shrinking it to fit.
+    fn commit(&mut self, file_id: u64, data: &[u8]) -> Result<(), Status> {
+        // The directory entry records the size in 32 bits
+        let size = u32::try_from(data.len()).map_err(|_| Status::VOLUME_FULL)?;
+        let mut raw = [0u8; 32];
+        self.read_at(file_id, &mut raw)?;
+        if matches!(raw[0], ENTRY_FREE | ENTRY_DELETED) {
+            return Err(Status::NOT_FOUND);
+        }
+        let entry = FatEntry {
+            name: String::new(),
+            raw,
+            offset: file_id,
+        };
+
+        let cluster_size = self.geometry.cluster_size() as usize;
+        let needed = data.len().div_ceil(cluster_size);
+        let mut chain = match entry.first_cluster() {
+            0 => Vec::new(),
+            start => self.chain(start)?,
+        };
+
+        if needed > chain.len() {
+            let extra = self.allocate(needed - chain.len())?;
+            if let Some(&last) = chain.last() {
+                self.set_fat_entry(last, extra[0]);
+            }
+            chain.extend(extra);
+        } else {
+            for &cluster in &chain[needed..] {
+                self.set_fat_entry(cluster, 0);
+            }
+            chain.truncate(needed);
+            if let Some(&last) = chain.last() {
+                self.set_fat_entry(last, self.geometry.fat_type.end_of_chain());
+            }
+        }
+
+        for (cluster, chunk) in chain.iter().zip(data.chunks(cluster_size)) {
+            let mut buffer = chunk.to_vec();
+            buffer.resize(cluster_size, 0);
+            self.write_at(self.geometry.cluster_offset(*cluster), &buffer)?;
+        }
+
+        let (date, time) = fat_timestamp(&self.clock);
+        raw[11] |= ATTR_ARCHIVE;
+        set_first_cluster(&mut raw, chain.first().copied().unwrap_or(0));
+        raw[18..20].copy_from_slice(&date.to_le_bytes());
+        raw[22..24].copy_from_slice(&time.to_le_bytes());
+        raw[24..26].copy_from_slice(&date.to_le_bytes());
+        raw[28..32].copy_from_slice(&size.to_le_bytes());
+        self.write_at(file_id, &raw)?;
+        self.write_fat()
+    }
+
+    fn max_file_size(&mut self, file_id: u64) -> Result<u64, Status> {
+        let mut raw = [0u8; 32];
+        self.read_at(file_id, &mut raw)?;
+        let entry = FatEntry {
+            name: String::new(),
+            raw,
+            offset: file_id,
+        };
+        let held = match entry.first_cluster() {
+            0 => 0,
+            start => self.chain(start)?.len() as u64,
+        };
+        let room = (held + self.free_clusters() as u64) * self.geometry.cluster_size();
+        Ok(room.min(u32::MAX as u64))
+    }
+}
+
+/// A FAT volume inside a disk image (or any `Read + Write + Seek` device)
+///
+/// Offers the same operations as [`SimpleFilesystem`](crate::SimpleFilesystem).
+/// Directory listings are what firmware sees on disk, so the root has no
+/// `.`/`..` entries while subdirectories do.
+pub struct FatFilesystem<D> {
+    volume: Rc<RefCell<FatVolume<D>>>,
+}
+
+impl FatFilesystem<File> {
+    /// Open a raw image file containing a bare FAT volume (no partition
+    /// table) for reading and writing.
+    pub fn open_image(path: impl AsRef<Path>) -> Result<Self, Status> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|_| Status::NOT_FOUND)?;
+        Self::mount(file, 0)
+    }
+}
+
+impl<D: Read + Write + Seek + 'static> FatFilesystem<D> {
+    /// Mount the FAT volume that starts `offset` bytes into `device`, e.g. a
+    /// partition inside a whole-disk image.
+    pub fn mount(mut device: D, offset: u64) -> Result<Self, Status> {
+        let mut boot = [0u8; 512];
+        device
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| device.read_exact(&mut boot))
+            .map_err(|_| Status::DEVICE_ERROR)?;
+        let geometry = Geometry::parse(&boot)?;
+        // Don't trust the boot sector's FAT size until the device backs it
+        let device_len = device
+            .seek(SeekFrom::End(0))
+            .map_err(|_| Status::DEVICE_ERROR)?;
+        if offset + geometry.fat_offset(0) + geometry.fat_len() as u64 > device_len {
+            return Err(Status::VOLUME_CORRUPTED);
+        }
+
+        let mut volume = FatVolume {
+            device,
+            base: offset,
+            geometry,
+            fat: vec![0u8; geometry.fat_len()],
+            clock: EfiTime::new(2024, 1, 15, 10, 30, 0),
+        };
+        let mut fat = std::mem::take(&mut volume.fat);
+        volume.read_at(geometry.fat_offset(0), &mut fat)?;
+        volume.fat = fat;
+
+        Ok(FatFilesystem {
+            volume: Rc::new(RefCell::new(volume)),
+        })
+    }
+
+    /// Give the device back. Fails (returning `self`) while handles opened
+    /// from this filesystem are still alive.
+    pub fn unmount(self) -> Result<D, Self> {
+        Rc::try_unwrap(self.volume)
+            .map(|volume| volume.into_inner().device)
+            .map_err(|volume| FatFilesystem { volume })
+    }
+
+    pub fn fat_type(&self) -> FatType {
+        self.volume.borrow().geometry.fat_type
+    }
+
+    pub fn cluster_size(&self) -> u64 {
+        self.volume.borrow().geometry.cluster_size()
+    }
+
+    pub fn free_clusters(&self) -> u32 {
+        self.volume.borrow().free_clusters()
+    }
+
+    /// Volume label from the root directory, falling back to the boot sector.
+    pub fn volume_label(&self) -> Result<String, Status> {
+        let mut volume = self.volume.borrow_mut();
+        let root = volume.root();
+        for offset in volume.dir_slots(root)? {
+            let mut raw = [0u8; 32];
+            volume.read_at(offset, &mut raw)?;
+            if raw[0] == ENTRY_FREE {
+                break;
+            }
+            if raw[0] != ENTRY_DELETED
+                && raw[11] & 0x3F != ATTR_LONG_NAME
+                && raw[11] & ATTR_VOLUME_ID != 0
+            {
+                return Ok(String::from_utf8_lossy(&raw[..11]).trim_end().to_string());
+            }
+        }
+        let label_offset = match volume.geometry.fat_type {
+            FatType::Fat32 => 71,
+            _ => 43,
+        };
+        let mut label = [0u8; 11];
+        volume.read_at(label_offset, &mut label)?;
+        Ok(String::from_utf8_lossy(&label).trim_end().to_string())
+    }
+
+    /// EFI_FILE_SYSTEM_INFO: sizes count data clusters only, and the block
+    /// size reported is the cluster size.
+    pub fn get_fs_info(&self) -> Result<FileSystemInfo, Status> {
+        let volume_label = self.volume_label()?;
+        let volume = self.volume.borrow();
+        let cluster_size = volume.geometry.cluster_size();
+        Ok(FileSystemInfo {
+            read_only: false,
+            volume_size: volume.geometry.cluster_count as u64 * cluster_size,
+            free_space: volume.free_clusters() as u64 * cluster_size,
+            block_size: cluster_size as u32,
+            volume_label,
+        })
+    }
+
+    /// Set the time stamped on files created or modified from now on.
+    pub fn set_time(&self, now: EfiTime) {
+        self.volume.borrow_mut().clock = now;
+    }
+
+    /// Open a file or directory with the same `OpenMode` rules as
+    /// [`SimpleFilesystem::open`](crate::SimpleFilesystem).
+    pub fn open(&self, path: &str, mode: OpenMode) -> Result<FileHandle, Status> {
+        let normalized = UefiPathBuf::parse(path)?;
+        let mut volume = self.volume.borrow_mut();
+
+        let entry = match volume.lookup(&normalized) {
+            Ok(None) => return Ok(FileHandle::directory(normalized.as_str())),
+            Ok(Some(entry)) if entry.is_directory() => {
+                return Ok(FileHandle::directory(normalized.as_str()));
+            }
+            Ok(Some(entry)) => entry,
+            Err(status) if status == Status::NOT_FOUND && mode == OpenMode::Create => {
+                let dir = volume.lookup_dir(normalized.parent().unwrap_or(UefiPath::root()))?;
+                let name = normalized.file_name().ok_or(Status::INVALID_PARAMETER)?;
+                volume.create_entry(dir, name, ATTR_ARCHIVE, 0)?
+            }
+            Err(status) => return Err(status),
+        };
+
+        if mode.is_writable() && entry.attributes() & ATTR_READ_ONLY != 0 {
+            return Err(Status::ACCESS_DENIED);
+        }
+        let data = volume.read_file(&entry)?;
+        drop(volume);
+
+        let backing = Backing {
+            target: Rc::clone(&self.volume) as Rc<RefCell<dyn CommitTarget>>,
+            file_id: entry.offset,
+        };
+        Ok(FileHandle::backed(
+            normalized.as_str(),
+            data,
+            entry.file_attributes(),
+            mode,
+            backing,
+        ))
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        UefiPathBuf::parse(path).is_ok_and(|path| self.volume.borrow_mut().lookup(&path).is_ok())
+    }
+
+    pub fn is_directory(&self, path: &str) -> bool {
+        UefiPathBuf::parse(path)
+            .is_ok_and(|path| self.volume.borrow_mut().lookup_dir(&path).is_ok())
+    }
+
+    pub fn list_directory(&self, path: &str) -> Result<Vec<DirEntry>, Status> {
+        let path = UefiPathBuf::parse(path)?;
+        let mut volume = self.volume.borrow_mut();
+        let dir = volume.lookup_dir(&path)?;
+        Ok(volume
+            .read_dir(dir)?
+            .into_iter()
+            .map(|entry| DirEntry {
+                size: entry.size() as u64,
+                attributes: entry.file_attributes(),
+                name: entry.name,
+            })
+            .collect())
+    }
+
+    /// EFI_FILE_INFO for a file or directory. `physical_size` is the space
+    /// taken by the cluster chain.
+    pub fn get_info(&self, path: &str) -> Result<FileInfo, Status> {
+        let path = UefiPathBuf::parse(path)?;
+        let mut volume = self.volume.borrow_mut();
+        let Some(entry) = volume.lookup(&path)? else {
+            return Ok(FileInfo::new("", 0, FileAttributes::new().directory()));
+        };
+
+        let clusters = match entry.first_cluster() {
+            0 => 0,
+            start => volume.chain(start)?.len() as u64,
+        };
+        let mut info = FileInfo::new(&entry.name, entry.size() as u64, entry.file_attributes());
+        info.physical_size = clusters * volume.geometry.cluster_size();
+        info.create_time = efi_time(u16_at(&entry.raw, 16), u16_at(&entry.raw, 14));
+        info.last_access_time = efi_time(u16_at(&entry.raw, 18), 0);
+        info.modification_time = efi_time(u16_at(&entry.raw, 24), u16_at(&entry.raw, 22));
+        Ok(info)
+    }
+
+    /// Create a directory, with its `.` and `..` entries.
+    pub fn mkdir(&self, path: &str) -> Result<(), Status> {
+        let normalized = UefiPathBuf::parse(path)?;
+        let mut volume = self.volume.borrow_mut();
+        let parent = volume.lookup_dir(normalized.parent().unwrap_or(UefiPath::root()))?;
+        let name = normalized.file_name().ok_or(Status::ACCESS_DENIED)?;
+        if volume.lookup(&normalized).is_ok() {
+            return Err(Status::ACCESS_DENIED);
+        }
+
+        let cluster = volume.allocate(1)?[0];
+        let entry = match volume.create_entry(parent, name, ATTR_DIRECTORY, cluster) {
+            Ok(entry) => entry,
+            Err(status) => {
+                volume.set_fat_entry(cluster, 0);
+                return Err(status);
+            }
+        };
+        volume.zero_cluster(cluster)?;
+
+        // ".." of a first-level directory points at cluster 0, even on FAT32
+        let parent_cluster = match parent {
+            DirLocation::Chain(start) if start != volume.geometry.root_cluster => start,
+            _ => 0,
+        };
+        for (index, (dot_name, target)) in [
+            (*b".          ", cluster),
+            (*b"..         ", parent_cluster),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut raw = entry.raw;
+            raw[..11].copy_from_slice(&dot_name);
+            set_first_cluster(&mut raw, target);
+            let offset = volume.geometry.cluster_offset(cluster) + index as u64 * DIR_ENTRY_SIZE;
+            volume.write_at(offset, &raw)?;
+        }
+        volume.write_fat()
+    }
+}
+
+/// Build a blank FAT volume image.
+///
+/// Uses 512-byte sectors and two FATs. Fails with `INVALID_PARAMETER` when
+/// `total_sectors` and `sectors_per_cluster` give a cluster count outside the
+/// range of `fat_type`.
+pub fn format_volume(
+    total_sectors: u32,
+    sectors_per_cluster: u8,
+    fat_type: FatType,
+    label: &str,
+) -> Result<Vec<u8>, Status> {
+    const BYTES_PER_SECTOR: u32 = 512;
+    const FAT_COUNT: u32 = 2;
+    if !sectors_per_cluster.is_power_of_two() {
+        return Err(Status::INVALID_PARAMETER);
+    }
+    let spc = sectors_per_cluster as u32;
+    let (reserved, root_entries) = match fat_type {
+        FatType::Fat12 => (1, 224),
+        FatType::Fat16 => (1, 512),
+        FatType::Fat32 => (32, 0),
+    };
+    let root_dir_sectors = (root_entries * 32u32).div_ceil(BYTES_PER_SECTOR);
+
+    // The FAT size depends on the cluster count, which depends on the FAT size
+    let mut fat_sectors = 1;
+    let clusters = loop {
+        let overhead = reserved + FAT_COUNT * fat_sectors + root_dir_sectors;
+        let clusters = total_sectors
+            .checked_sub(overhead)
+            .ok_or(Status::INVALID_PARAMETER)?
+            / spc;
+        let fat_bytes = match fat_type {
+            FatType::Fat12 => ((clusters + 2) * 3).div_ceil(2),
+            FatType::Fat16 => (clusters + 2) * 2,
+            FatType::Fat32 => (clusters + 2) * 4,
+        };
+        let needed = fat_bytes.div_ceil(BYTES_PER_SECTOR);
+        if needed <= fat_sectors {
+            break clusters;
+        }
+        fat_sectors = needed;
+    };
+    if FatType::from_cluster_count(clusters) != fat_type {
+        return Err(Status::INVALID_PARAMETER);
+    }
+
+    let mut image = vec![0u8; total_sectors as usize * BYTES_PER_SECTOR as usize];
+    let mut label_bytes = [b' '; 11];
+    for (slot, byte) in label_bytes
+        .iter_mut()
+        .zip(label.to_ascii_uppercase().bytes())
+    {
+        *slot = byte;
+    }
+
+    let boot = &mut image[..512];
+    boot[..3].copy_from_slice(match fat_type {
+        FatType::Fat32 => &[0xEB, 0x58, 0x90],
+        _ => &[0xEB, 0x3C, 0x90],
+    });
+    boot[3..11].copy_from_slice(b"MSWIN4.1");
+    boot[11..13].copy_from_slice(&(BYTES_PER_SECTOR as u16).to_le_bytes());
+    boot[13] = sectors_per_cluster;
+    boot[14..16].copy_from_slice(&(reserved as u16).to_le_bytes());
+    boot[16] = FAT_COUNT as u8;
+    boot[17..19].copy_from_slice(&(root_entries as u16).to_le_bytes());
+    if total_sectors < 0x10000 && fat_type != FatType::Fat32 {
+        boot[19..21].copy_from_slice(&(total_sectors as u16).to_le_bytes());
+    } else {
+        boot[32..36].copy_from_slice(&total_sectors.to_le_bytes());
+    }
+    boot[21] = 0xF8; // fixed disk
+    boot[24..26].copy_from_slice(&63u16.to_le_bytes());
+    boot[26..28].copy_from_slice(&255u16.to_le_bytes());
+
+    // Extended boot record: FAT32 moves it behind the extra BPB fields
+    let ebr = if fat_type == FatType::Fat32 {
+        boot[36..40].copy_from_slice(&fat_sectors.to_le_bytes());
+        boot[44..48].copy_from_slice(&2u32.to_le_bytes()); // root cluster
+        boot[48..50].copy_from_slice(&1u16.to_le_bytes()); // FSInfo sector
+        boot[50..52].copy_from_slice(&6u16.to_le_bytes()); // backup boot sector
+        64
+    } else {
+        boot[22..24].copy_from_slice(&(fat_sectors as u16).to_le_bytes());
+        36
+    };
+    boot[ebr] = 0x80; // drive number
+    boot[ebr + 2] = 0x29; // extended boot signature
+    boot[ebr + 3..ebr + 7].copy_from_slice(&0x1234_5678u32.to_le_bytes());
+    boot[ebr + 7..ebr + 18].copy_from_slice(&label_bytes);
+    boot[ebr + 18..ebr + 26].copy_from_slice(fat_type.type_string());
+    boot[510] = 0x55;
+    boot[511] = 0xAA;
+
+    if fat_type == FatType::Fat32 {
+        let info = &mut image[512..1024];
+        info[..4].copy_from_slice(&FSINFO_LEAD_SIG.to_le_bytes());
+        info[484..488].copy_from_slice(&FSINFO_STRUCT_SIG.to_le_bytes());
+        info[488..492].copy_from_slice(&(clusters - 1).to_le_bytes());
+        info[492..496].copy_from_slice(&3u32.to_le_bytes());
+        info[508..512].copy_from_slice(&0xAA55_0000u32.to_le_bytes());
+        // Backup boot sector and FSInfo at sectors 6 and 7
+        image.copy_within(0..1024, 6 * 512);
+    }
+
+    // FAT[0] holds the media byte, FAT[1] an end-of-chain marker; on FAT32
+    // cluster 2 is the root directory
+    let geometry = Geometry::parse(&image[..512])?;
+    let mut volume = FatVolume {
+        device: std::io::Cursor::new(image),
+        base: 0,
+        geometry,
+        fat: vec![0u8; geometry.fat_len()],
+        clock: EfiTime::new(2024, 1, 15, 10, 30, 0),
+    };
+    let eoc = fat_type.end_of_chain();
+    volume.set_fat_entry(0, (eoc & !0xFF) | 0xF8);
+    volume.set_fat_entry(1, eoc);
+    if fat_type == FatType::Fat32 {
+        volume.set_fat_entry(2, eoc);
+    }
+    volume.write_fat()?;
+
+    if !label.is_empty() {
+        let mut raw = [0u8; 32];
+        raw[..11].copy_from_slice(&label_bytes);
+        raw[11] = ATTR_VOLUME_ID;
+        let slot = volume.dir_slots(volume.root())?[0];
+        volume.write_at(slot, &raw)?;
+    }
+    Ok(volume.device.into_inner())
+}
+
+// ============================================
+// Directory entry encoding
+// ============================================
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn set_first_cluster(raw: &mut [u8; 32], cluster: u32) {
+    raw[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+    raw[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+}
+
+/// `NAME.EXT` from the 11-byte short name, honouring the NTRes lower-case
+/// flags Windows uses instead of a long name for names like `readme.txt`.
+fn short_display_name(raw: &[u8; 32]) -> String {
+    let decode = |bytes: &[u8], lower: bool| -> String {
+        let text: String = bytes.iter().map(|&b| b as char).collect();
+        let text = text.trim_end().to_string();
+        if lower {
+            text.to_lowercase()
+        } else {
+            text
+        }
+    };
+    let mut base_bytes = [0u8; 8];
+    base_bytes.copy_from_slice(&raw[..8]);
+    if base_bytes[0] == 0x05 {
+        base_bytes[0] = ENTRY_DELETED;
+    }
+    let base = decode(&base_bytes, raw[12] & NT_LOWER_BASE != 0);
+    let ext = decode(&raw[8..11], raw[12] & NT_LOWER_EXT != 0);
+    if ext.is_empty() {
+        base
+    } else {
+        format!("{}.{}", base, ext)
+    }
+}
+
+/// Checksum of a short name, stored in each of its long-name entries
+fn checksum(short: &[u8; 11]) -> u8 {
+    short
+        .iter()
+        .fold(0u8, |sum, &byte| sum.rotate_right(1).wrapping_add(byte))
+}
+
+/// Reassemble a long name, or `None` if the parts do not belong to `short`.
+fn decode_long_name(parts: &[[u8; 32]], short: &[u8; 32]) -> Option<String> {
+    let count = parts.len();
+    if count == 0 || parts[0][0] & LFN_LAST == 0 {
+        return None;
+    }
+    let mut short_name = [0u8; 11];
+    short_name.copy_from_slice(&short[..11]);
+    let expected = checksum(&short_name);
+
+    let mut units = Vec::with_capacity(count * LFN_CHARS);
+    // On disk the parts run from the last ordinal down to 1
+    for (index, part) in parts.iter().rev().enumerate() {
+        if (part[0] & 0x1F) as usize != index + 1 || part[13] != expected {
+            return None;
+        }
+        units.extend(LFN_CHAR_OFFSETS.iter().map(|&offset| u16_at(part, offset)));
+    }
+    let end = units
+        .iter()
+        .position(|&unit| unit == 0)
+        .unwrap_or(units.len());
+    String::from_utf16(&units[..end]).ok()
+}
+
+/// Long-name entries for `name`, in on-disk order.
+fn encode_long_name(name: &str, checksum: u8) -> Vec<[u8; 32]> {
+    let mut units: Vec<u16> = name.encode_utf16().collect();
+    if !units.len().is_multiple_of(LFN_CHARS) {
+        units.push(0);
+    }
+    units.resize(units.len().next_multiple_of(LFN_CHARS), 0xFFFF);
+
+    let count = units.len() / LFN_CHARS;
+    (0..count)
+        .rev()
+        .map(|index| {
+            let mut raw = [0u8; 32];
+            raw[0] = (index + 1) as u8 | if index + 1 == count { LFN_LAST } else { 0 };
+            raw[11] = ATTR_LONG_NAME;
+            raw[13] = checksum;
+            let chunk = &units[index * LFN_CHARS..(index + 1) * LFN_CHARS];
+            for (&offset, unit) in LFN_CHAR_OFFSETS.iter().zip(chunk) {
+                raw[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+            }
+            raw
+        })
+        .collect()
+}
+
+fn validate_long_name(name: &str) -> Result<(), Status> {
+    let invalid = |c: char| c < ' ' || "\"*/:<>?\\|".contains(c);
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.encode_utf16().count() > 255
+        || name.chars().any(invalid)
+    {
+        return Err(Status::INVALID_PARAMETER);
+    }
+    Ok(())
+}
+
+fn is_short_name_char(c: char) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || "!#$%&'()-@^_`{}~".contains(c)
+}
+
+/// Pick the 8.3 name for a new entry. Returns the name and whether long-name
+/// entries are needed to preserve `name`.
+fn short_name_for(name: &str, existing: &[FatEntry]) -> ([u8; 11], bool) {
+    let (base, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 => (&name[..dot], &name[dot + 1..]),
+        _ => (name, ""),
+    };
+    let exact = (1..=8).contains(&base.len())
+        && ext.len() <= 3
+        && base.chars().chain(ext.chars()).all(is_short_name_char);
+    let pack = |base: &str, ext: &str| {
+        let mut short = [b' '; 11];
+        for (slot, byte) in short[..8].iter_mut().zip(base.bytes()) {
+            *slot = byte;
+        }
+        for (slot, byte) in short[8..].iter_mut().zip(ext.bytes()) {
+            *slot = byte;
+        }
+        short
+    };
+    if exact {
+        return (pack(base, ext), false);
+    }
+
+    // Basis name: upper-case, no spaces or dots, invalid characters as '_'
+    let basis = |part: &str| -> String {
+        part.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| c.to_ascii_uppercase())
+            .map(|c| if is_short_name_char(c) { c } else { '_' })
+            .collect()
+    };
+    let base = basis(base.trim_start_matches('.'));
+    let ext: String = basis(ext).chars().take(3).collect();
+    let taken: Vec<[u8; 11]> = existing
+        .iter()
+        .map(|entry| entry.raw[..11].try_into().unwrap())
+        .collect();
+
+    (1..)
+        .map(|n| {
+            let tail = format!("~{}", n);
+            let keep = base.len().min(8 - tail.len());
+            pack(&format!("{}{}", &base[..keep], tail), &ext)
+        })
+        .find(|candidate| !taken.contains(candidate))
+        .map(|short| (short, true))
+        .unwrap()
+}
+
+/// FAT date and time words for an `EfiTime` (2-second resolution).
+fn fat_timestamp(time: &EfiTime) -> (u16, u16) {
+    let year = time.year.clamp(1980, 2107) - 1980;
+    let date = (year << 9) | ((time.month as u16) << 5) | time.day as u16;
+    let time = ((time.hour as u16) << 11) | ((time.minute as u16) << 5) | (time.second as u16 / 2);
+    (date, time)
+}
+
+/// `EfiTime` for FAT date and time words; a zero date (never recorded,
+/// as mkfs.fat and mtools leave access dates) is the unset time.
+fn efi_time(date: u16, time: u16) -> EfiTime {
+    if date == 0 {
+        return EfiTime::default();
+    }
+    EfiTime::new(
+        1980 + (date >> 9),
+        ((date >> 5) & 0x0F) as u8,
+        (date & 0x1F) as u8,
+        (time >> 11) as u8,
+        ((time >> 5) & 0x3F) as u8,
+        ((time & 0x1F) * 2) as u8,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EfiInfo;
+    use std::io::Cursor;
+
+    fn mount(image: Vec<u8>) -> FatFilesystem<Cursor<Vec<u8>>> {
+        FatFilesystem::mount(Cursor::new(image), 0).unwrap()
+    }
+
+    fn read_all(fs: &FatFilesystem<Cursor<Vec<u8>>>, path: &str) -> Vec<u8> {
+        let mut file = fs.open(path, OpenMode::Read).unwrap();
+        let mut data = vec![0u8; file.size as usize];
+        if !data.is_empty() {
+            file.read(&mut data).unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn test_round_trip_on_every_fat_type() {
+        let layouts = [
+            (2880, 1, FatType::Fat12),
+            (16384, 2, FatType::Fat16),
+            (70000, 1, FatType::Fat32),
+        ];
+        for (sectors, spc, fat_type) in layouts {
+            let fs = mount(format_volume(sectors, spc, fat_type, "ESP").unwrap());
+            assert_eq!(fs.fat_type(), fat_type);
+            assert_eq!(fs.volume_label().unwrap(), "ESP");
+
+            fs.mkdir("\\EFI").unwrap();
+            fs.mkdir("\\EFI\\BOOT").unwrap();
+            let payload: Vec<u8> = (0..3000u32).map(|i| i as u8).collect();
+            let mut file = fs
+                .open("\\EFI\\BOOT\\BOOTX64.EFI", OpenMode::Create)
+                .unwrap();
+            file.write(&payload).unwrap();
+            file.close();
+            let mut file = fs.open("\\startup.nsh", OpenMode::Create).unwrap();
+            file.write(b"fs0:\\EFI\\BOOT\\BOOTX64.EFI").unwrap();
+            drop(file);
+
+            // Remount from the raw bytes to prove everything reached the image
+            let fs = mount(fs.unmount().ok().unwrap().into_inner());
+            assert_eq!(read_all(&fs, "/efi/boot/bootx64.efi"), payload);
+            assert_eq!(
+                read_all(&fs, "\\STARTUP.NSH"),
+                b"fs0:\\EFI\\BOOT\\BOOTX64.EFI"
+            );
+
+            let names: Vec<String> = fs
+                .list_directory("\\EFI\\BOOT")
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect();
+            assert_eq!(names, vec![".", "..", "BOOTX64.EFI"]);
+            assert!(fs.is_directory("\\EFI\\BOOT\\..\\BOOT"));
+            assert!(fs.exists("\\EFI\\BOOT\\..\\..\\startup.nsh"));
+        }
+    }
+
+    #[test]
+    fn test_rewrite_grows_and_frees_clusters() {
+        let fs = mount(format_volume(2880, 1, FatType::Fat12, "").unwrap());
+        let free = fs.free_clusters();
+
+        let mut file = fs.open("\\data.bin", OpenMode::Create).unwrap();
+        file.write(&[0xAB; 5000]).unwrap();
+        file.flush().unwrap();
+        assert_eq!(fs.free_clusters(), free - 10);
+        assert_eq!(fs.get_info("\\data.bin").unwrap().physical_size, 5120);
+
+        // Shrinking the file returns the tail of the chain
+        file.close();
+        let info = fs.get_info("\\data.bin").unwrap();
+        assert!(info.attributes.0 & FileAttributes::ARCHIVE != 0);
+        let mut file = fs.open("\\data.bin", OpenMode::ReadWrite).unwrap();
+        file.data.truncate(100);
+        file.size = 100;
+        file.dirty = true;
+        file.close();
+        assert_eq!(fs.free_clusters(), free - 1);
+        assert_eq!(read_all(&fs, "\\data.bin"), vec![0xAB; 100]);
+
+        // Writes are limited to the clusters held plus the free ones
+        let room = free as usize * 512;
+        let mut file = fs.open("\\data.bin", OpenMode::ReadWrite).unwrap();
+        assert_eq!(file.write(&vec![0; room + 1]), Err(Status::VOLUME_FULL));
+        assert_eq!(file.write(&vec![0; room]), Ok(room));
+        file.close();
+        assert_eq!(fs.free_clusters(), 0);
+    }
+
+    #[test]
+    fn test_unset_access_date() {
+        let fs = mount(format_volume(2880, 1, FatType::Fat12, "").unwrap());
+        fs.open("\\a.txt", OpenMode::Create).unwrap();
+        let mut volume = fs.volume.borrow_mut();
+        let root = volume.root();
+        let offset = volume.read_dir(root).unwrap()[0].offset;
+        volume.write_at(offset + 18, &[0, 0]).unwrap();
+        drop(volume);
+
+        let info = fs.get_info("\\a.txt").unwrap();
+        assert_eq!(info.last_access_time, EfiTime::default());
+        assert_eq!(info.modification_time.year, 2024);
+        assert_eq!(FileInfo::decode(&info.to_bytes()), Ok(info));
+    }
+
+    #[test]
+    fn test_long_and_short_names() {
+        let fs = mount(format_volume(2880, 1, FatType::Fat12, "").unwrap());
+        for name in ["README.TXT", "Long File Name.txt", "Long File Name 2.txt"] {
+            fs.open(&format!("\\{}", name), OpenMode::Create).unwrap();
+        }
+        assert_eq!(
+            fs.open("\\readme.txt", OpenMode::Create).map(|_| ()),
+            Ok(()),
+            "case-insensitive match opens the existing file"
+        );
+
+        let mut volume = fs.volume.borrow_mut();
+        let root = volume.root();
+        let entries = volume.read_dir(root).unwrap();
+        let shorts: Vec<String> = entries.iter().map(FatEntry::short_name).collect();
+        assert_eq!(shorts, vec!["README.TXT", "LONGFI~1.TXT", "LONGFI~2.TXT"]);
+        assert_eq!(entries[2].name, "Long File Name 2.txt");
+        // One short entry for the 8.3 name, 2 + 1 slots for each long name
+        assert_eq!(entries[2].offset, volume.geometry.root_region().0 + 6 * 32);
+        drop(volume);
+
+        assert!(fs.exists("\\LONGFI~2.TXT"));
+        assert_eq!(
+            fs.open("\\bad:name", OpenMode::Create).map(|_| ()),
+            Err(Status::INVALID_PARAMETER)
+        );
+    }
+
+    #[test]
+    fn test_mount_validation() {
+        let mut image = vec![0u8; 512 * 64];
+        let volume = format_volume(2880, 1, FatType::Fat12, "INNER").unwrap();
+        image.extend_from_slice(&volume);
+
+        // Mounting at the partition offset works; the blank sector does not
+        let fs = FatFilesystem::mount(Cursor::new(image.clone()), 512 * 64).unwrap();
+        assert_eq!(fs.volume_label().unwrap(), "INNER");
+        assert_eq!(
+            FatFilesystem::mount(Cursor::new(image), 0).err(),
+            Some(Status::VOLUME_CORRUPTED)
+        );
+
+        // Corrupt FAT sizes are rejected instead of overflowing the layout math
+        let fat32 = format_volume(70000, 1, FatType::Fat32, "").unwrap();
+        for fat_count in [1u8, 2] {
+            let mut corrupt = fat32.clone();
+            corrupt[16] = fat_count;
+            corrupt[36..40].copy_from_slice(&0xFFFF_FFFFu32.to_le_bytes());
+            assert_eq!(
+                FatFilesystem::mount(Cursor::new(corrupt), 0).err(),
+                Some(Status::VOLUME_CORRUPTED)
+            );
+        }
+
+        // A FAT32 layout with too few clusters is rejected at format time
+        assert_eq!(
+            format_volume(2880, 1, FatType::Fat32, ""),
+            Err(Status::INVALID_PARAMETER)
+        );
+    }
+}
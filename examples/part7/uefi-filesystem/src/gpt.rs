@@ -0,0 +1,836 @@
+//! GUID Partition Table parsing and ESP location
+//!
+//! ```text
+//!   LBA 0      Protective MBR (one 0xEE partition covering the disk)
+//!   LBA 1      Primary GPT header ──┐ CRC32 of the header
+//!   LBA 2..33  Partition entries  ◄─┘ CRC32 of the entry array
+//!   ...        Partitions
+//!   LBA -33    Backup partition entries
+//!   LBA -1     Backup GPT header
+//! ```
+//!
+//! [`GptDisk::read`] validates both copies. A damaged primary table is not
+//! fatal as long as the backup is intact, but every problem found is
+//! recorded in [`GptDisk::issues`] so CI can reject images firmware would
+//! have to repair.
+
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::fat::FatFilesystem;
+use crate::Status;
+
+const SIGNATURE: &[u8; 8] = b"EFI PART";
+const REVISION_1_0: u32 = 0x0001_0000;
+const HEADER_SIZE: u32 = 92;
+const ENTRY_SIZE: u32 = 128;
+const ENTRY_COUNT: u32 = 128;
+const PROTECTIVE_MBR_TYPE: u8 = 0xEE;
+/// Partition names are up to 36 UCS-2 characters
+const NAME_UNITS: usize = 36;
+
+pub use uefi_hello::Guid;
+
+// Well-known partition type GUIDs
+pub const EFI_SYSTEM_PARTITION_GUID: Guid = Guid::new(
+    0xC12A7328,
+    0xF81F,
+    0x11D2,
+    [0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B],
+);
+
+pub const BASIC_DATA_PARTITION_GUID: Guid = Guid::new(
+    0xEBD0A0A2,
+    0xB9E5,
+    0x4433,
+    [0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7],
+);
+
+pub const LINUX_FILESYSTEM_GUID: Guid = Guid::new(
+    0x0FC63DAF,
+    0x8483,
+    0x4772,
+    [0x8E, 0x79, 0x3D, 0x69, 0xD8, 0x47, 0x7D, 0xE4],
+);
+
+/// Human-readable name for a partition type GUID
+pub fn partition_type_name(guid: &Guid) -> &'static str {
+    match *guid {
+        EFI_SYSTEM_PARTITION_GUID => "EFI System",
+        BASIC_DATA_PARTITION_GUID => "Basic data",
+        LINUX_FILESYSTEM_GUID => "Linux filesystem",
+        _ => "Unknown",
+    }
+}
+
+/// CRC32 (IEEE 802.3, reflected), as used by GPT headers and entry arrays
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Why a GPT (or one copy of it) could not be used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GptError {
+    Io,
+    NoProtectiveMbr,
+    BadSignature {
+        lba: u64,
+    },
+    BadHeader {
+        lba: u64,
+    },
+    HeaderCrc {
+        lba: u64,
+        stored: u32,
+        computed: u32,
+    },
+    EntriesCrc {
+        lba: u64,
+        stored: u32,
+        computed: u32,
+    },
+    /// The header at `lba` claims to live at `recorded`
+    WrongLocation {
+        lba: u64,
+        recorded: u64,
+    },
+    /// Primary and backup headers describe different tables
+    HeadersDisagree,
+    PartitionOutOfRange {
+        index: usize,
+    },
+    PartitionsOverlap {
+        first: usize,
+        second: usize,
+    },
+    /// Two partitions were given the same entry slot
+    DuplicateIndex {
+        index: usize,
+    },
+    /// Too few blocks (or an unusable block size) to hold a GPT
+    DiskTooSmall {
+        blocks: u64,
+    },
+    NoEsp,
+    /// The ESP was found but its filesystem could not be mounted
+    Filesystem(Status),
+}
+
+impl fmt::Display for GptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GptError::Io => write!(f, "I/O error reading the disk"),
+            GptError::NoProtectiveMbr => write!(f, "no protective MBR (type 0xEE) at LBA 0"),
+            GptError::BadSignature { lba } => write!(f, "no \"EFI PART\" signature at LBA {}", lba),
+            GptError::BadHeader { lba } => write!(f, "malformed GPT header at LBA {}", lba),
+            GptError::HeaderCrc {
+                lba,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "header CRC32 mismatch at LBA {}: stored {:08X}, computed {:08X}",
+                lba, stored, computed
+            ),
+            GptError::EntriesCrc {
+                lba,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "partition entry CRC32 mismatch at LBA {}: stored {:08X}, computed {:08X}",
+                lba, stored, computed
+            ),
+            GptError::WrongLocation { lba, recorded } => write!(
+                f,
+                "header at LBA {} records its own location as LBA {}",
+                lba, recorded
+            ),
+            GptError::HeadersDisagree => write!(f, "primary and backup headers disagree"),
+            GptError::PartitionOutOfRange { index } => {
+                write!(f, "partition {} lies outside the usable LBA range", index)
+            }
+            GptError::PartitionsOverlap { first, second } => {
+                write!(f, "partitions {} and {} overlap", first, second)
+            }
+            GptError::DuplicateIndex { index } => {
+                write!(f, "partition slot {} is used twice", index)
+            }
+            GptError::DiskTooSmall { blocks } => {
+                write!(f, "{} blocks are too few for a GPT", blocks)
+            }
+            GptError::NoEsp => write!(f, "no EFI System Partition"),
+            GptError::Filesystem(status) => {
+                write!(f, "cannot mount the ESP: {}", status.description())
+            }
+        }
+    }
+}
+
+impl From<GptError> for Status {
+    fn from(error: GptError) -> Self {
+        match error {
+            GptError::Io => Status::DEVICE_ERROR,
+            GptError::NoEsp => Status::NOT_FOUND,
+            GptError::Filesystem(status) => status,
+            _ => Status::VOLUME_CORRUPTED,
+        }
+    }
+}
+
+/// A decoded GPT header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GptHeader {
+    pub my_lba: u64,
+    pub alternate_lba: u64,
+    pub first_usable_lba: u64,
+    pub last_usable_lba: u64,
+    pub disk_guid: Guid,
+    pub partition_entry_lba: u64,
+    pub entry_count: u32,
+    pub entry_size: u32,
+    pub entries_crc32: u32,
+}
+
+impl GptHeader {
+    fn parse(block: &[u8], lba: u64) -> Result<Self, GptError> {
+        if &block[..8] != SIGNATURE {
+            return Err(GptError::BadSignature { lba });
+        }
+        let size = u32_at(block, 12) as usize;
+        if size < HEADER_SIZE as usize || size > block.len() {
+            return Err(GptError::BadHeader { lba });
+        }
+
+        let stored = u32_at(block, 16);
+        let mut copy = block[..size].to_vec();
+        copy[16..20].fill(0);
+        let computed = crc32(&copy);
+        if stored != computed {
+            return Err(GptError::HeaderCrc {
+                lba,
+                stored,
+                computed,
+            });
+        }
+
+        let header = GptHeader {
+            my_lba: u64_at(block, 24),
+            alternate_lba: u64_at(block, 32),
+            first_usable_lba: u64_at(block, 40),
+            last_usable_lba: u64_at(block, 48),
+            disk_guid: Guid::from_bytes(block[56..72].try_into().unwrap()),
+            partition_entry_lba: u64_at(block, 72),
+            entry_count: u32_at(block, 80),
+            entry_size: u32_at(block, 84),
+            entries_crc32: u32_at(block, 88),
+        };
+        if header.my_lba != lba {
+            return Err(GptError::WrongLocation {
+                lba,
+                recorded: header.my_lba,
+            });
+        }
+        // Entries are 128 * 2^n bytes; cap the array at 1 MiB
+        if header.entry_size < ENTRY_SIZE
+            || !header.entry_size.is_power_of_two()
+            || header.entry_count as u64 * header.entry_size as u64 > 1 << 20
+        {
+            return Err(GptError::BadHeader { lba });
+        }
+        Ok(header)
+    }
+
+    fn encode(&self, block_size: usize) -> Vec<u8> {
+        let mut block = vec![0u8; block_size];
+        block[..8].copy_from_slice(SIGNATURE);
+        block[8..12].copy_from_slice(&REVISION_1_0.to_le_bytes());
+        block[12..16].copy_from_slice(&HEADER_SIZE.to_le_bytes());
+        block[24..32].copy_from_slice(&self.my_lba.to_le_bytes());
+        block[32..40].copy_from_slice(&self.alternate_lba.to_le_bytes());
+        block[40..48].copy_from_slice(&self.first_usable_lba.to_le_bytes());
+        block[48..56].copy_from_slice(&self.last_usable_lba.to_le_bytes());
+        block[56..72].copy_from_slice(&self.disk_guid.to_bytes());
+        block[72..80].copy_from_slice(&self.partition_entry_lba.to_le_bytes());
+        block[80..84].copy_from_slice(&self.entry_count.to_le_bytes());
+        block[84..88].copy_from_slice(&self.entry_size.to_le_bytes());
+        block[88..92].copy_from_slice(&self.entries_crc32.to_le_bytes());
+        let crc = crc32(&block[..HEADER_SIZE as usize]);
+        block[16..20].copy_from_slice(&crc.to_le_bytes());
+        block
+    }
+
+    fn entries_len(&self) -> usize {
+        self.entry_count as usize * self.entry_size as usize
+    }
+}
+
+/// One used slot of the partition entry array
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GptPartition {
+    /// Slot in the entry array (0-based)
+    pub index: usize,
+    pub type_guid: Guid,
+    pub unique_guid: Guid,
+    pub first_lba: u64,
+    /// Inclusive
+    pub last_lba: u64,
+    pub attributes: u64,
+    pub name: String,
+}
+
+impl GptPartition {
+    pub fn is_esp(&self) -> bool {
+        self.type_guid == EFI_SYSTEM_PARTITION_GUID
+    }
+
+    /// Length in blocks, or `None` for an inverted range
+    pub fn sectors(&self) -> Option<u64> {
+        self.last_lba.checked_sub(self.first_lba)?.checked_add(1)
+    }
+
+    fn parse(index: usize, raw: &[u8]) -> Option<Self> {
+        let type_guid = Guid::from_bytes(raw[..16].try_into().unwrap());
+        if type_guid == Guid::ZERO {
+            return None;
+        }
+        let units: Vec<u16> = raw[56..56 + NAME_UNITS * 2]
+            .as_chunks::<2>()
+            .0
+            .iter()
+            .map(|&pair| u16::from_le_bytes(pair))
+            .take_while(|&unit| unit != 0)
+            .collect();
+        Some(GptPartition {
+            index,
+            type_guid,
+            unique_guid: Guid::from_bytes(raw[16..32].try_into().unwrap()),
+            first_lba: u64_at(raw, 32),
+            last_lba: u64_at(raw, 40),
+            attributes: u64_at(raw, 48),
+            name: String::from_utf16_lossy(&units),
+        })
+    }
+
+    fn encode(&self, raw: &mut [u8]) {
+        raw[..16].copy_from_slice(&self.type_guid.to_bytes());
+        raw[16..32].copy_from_slice(&self.unique_guid.to_bytes());
+        raw[32..40].copy_from_slice(&self.first_lba.to_le_bytes());
+        raw[40..48].copy_from_slice(&self.last_lba.to_le_bytes());
+        raw[48..56].copy_from_slice(&self.attributes.to_le_bytes());
+        for (slot, unit) in raw[56..56 + NAME_UNITS * 2]
+            .as_chunks_mut::<2>()
+            .0
+            .iter_mut()
+            .zip(self.name.encode_utf16())
+        {
+            slot.copy_from_slice(&unit.to_le_bytes());
+        }
+    }
+}
+
+/// A validated partition table
+#[derive(Debug, Clone)]
+pub struct GptDisk {
+    pub block_size: u64,
+    /// The header in use: the primary one unless it was damaged
+    pub header: GptHeader,
+    pub partitions: Vec<GptPartition>,
+    /// Problems that did not prevent reading the table
+    pub issues: Vec<GptError>,
+}
+
+impl GptDisk {
+    /// Read and validate the GPT of a whole-disk image. The block size is
+    /// 512 unless the signature is only found at 4096.
+    pub fn read<D: Read + Seek>(device: &mut D) -> Result<Self, GptError> {
+        let disk_len = device.seek(SeekFrom::End(0)).map_err(|_| GptError::Io)?;
+        let mut signature = [0u8; 8];
+        let block_size = if read_at(device, 4096, &mut signature).is_ok()
+            && &signature == SIGNATURE
+            && !(read_at(device, 512, &mut signature).is_ok() && &signature == SIGNATURE)
+        {
+            4096
+        } else {
+            512
+        };
+        let last_lba = (disk_len / block_size)
+            .checked_sub(1)
+            .ok_or(GptError::NoProtectiveMbr)?;
+
+        let mut mbr = vec![0u8; 512];
+        read_at(device, 0, &mut mbr)?;
+        let protective = mbr[510..512] == [0x55, 0xAA]
+            && (0..4).any(|i| mbr[446 + i * 16 + 4] == PROTECTIVE_MBR_TYPE);
+        if !protective {
+            return Err(GptError::NoProtectiveMbr);
+        }
+
+        // The backup always lives in the last block; a primary pointing
+        // anywhere else is not trusted
+        let primary = load_table(device, block_size, 1).and_then(|table| {
+            if table.0.alternate_lba == last_lba {
+                Ok(table)
+            } else {
+                Err(GptError::BadHeader { lba: 1 })
+            }
+        });
+        let backup = load_table(device, block_size, last_lba);
+
+        let mut issues = Vec::new();
+        let (header, entries) = match (primary, backup) {
+            (Ok(primary), Ok(backup)) => {
+                let (p, b) = (&primary.0, &backup.0);
+                if b.alternate_lba != p.my_lba
+                    || b.disk_guid != p.disk_guid
+                    || b.entries_crc32 != p.entries_crc32
+                    || (b.first_usable_lba, b.last_usable_lba)
+                        != (p.first_usable_lba, p.last_usable_lba)
+                {
+                    issues.push(GptError::HeadersDisagree);
+                }
+                primary
+            }
+            (Ok(primary), Err(error)) => {
+                issues.push(error);
+                primary
+            }
+            (Err(error), Ok(backup)) => {
+                issues.push(error);
+                backup
+            }
+            (Err(error), Err(_)) => return Err(error),
+        };
+
+        let partitions: Vec<GptPartition> = entries
+            .chunks_exact(header.entry_size as usize)
+            .enumerate()
+            .filter_map(|(index, raw)| GptPartition::parse(index, raw))
+            .collect();
+
+        for (n, partition) in partitions.iter().enumerate() {
+            if partition.first_lba > partition.last_lba
+                || partition.first_lba < header.first_usable_lba
+                || partition.last_lba > header.last_usable_lba
+            {
+                issues.push(GptError::PartitionOutOfRange {
+                    index: partition.index,
+                });
+            }
+            for other in &partitions[n + 1..] {
+                if partition.first_lba <= other.last_lba && other.first_lba <= partition.last_lba {
+                    issues.push(GptError::PartitionsOverlap {
+                        first: partition.index,
+                        second: other.index,
+                    });
+                }
+            }
+        }
+
+        Ok(GptDisk {
+            block_size,
+            header,
+            partitions,
+            issues,
+        })
+    }
+
+    /// No damaged copies, overlaps or out-of-range partitions
+    pub fn is_healthy(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// The first EFI System Partition
+    pub fn esp(&self) -> Option<&GptPartition> {
+        self.partitions.iter().find(|partition| partition.is_esp())
+    }
+
+    /// Byte offset of a partition within the disk, or `None` if it does
+    /// not fit in 64 bits
+    pub fn byte_offset(&self, partition: &GptPartition) -> Option<u64> {
+        partition.first_lba.checked_mul(self.block_size)
+    }
+}
+
+/// Read the GPT of a disk image and mount the FAT filesystem on its ESP.
+pub fn mount_esp<D>(mut device: D) -> Result<(GptDisk, FatFilesystem<D>), GptError>
+where
+    D: Read + Write + Seek + 'static,
+{
+    let disk = GptDisk::read(&mut device)?;
+    let esp = disk.esp().ok_or(GptError::NoEsp)?;
+    let offset = disk
+        .byte_offset(esp)
+        .ok_or(GptError::PartitionOutOfRange { index: esp.index })?;
+    let fs = FatFilesystem::mount(device, offset).map_err(GptError::Filesystem)?;
+    Ok((disk, fs))
+}
+
+/// Write a protective MBR and primary and backup GPTs (128 entries of 128
+/// bytes) for `partitions`, each stored at its `index` slot.
+///
+/// `block_size` must be a power of two of at least 512 bytes.
+pub fn write_gpt(
+    disk: &mut [u8],
+    block_size: u64,
+    disk_guid: Guid,
+    partitions: &[GptPartition],
+) -> Result<(), GptError> {
+    let blocks = if block_size >= 512 && block_size.is_power_of_two() {
+        disk.len() as u64 / block_size
+    } else {
+        0
+    };
+    let bs = block_size as usize;
+    let entry_blocks = ((ENTRY_COUNT * ENTRY_SIZE) as u64).div_ceil(block_size.max(1));
+    let first_usable = 2 + entry_blocks;
+    // MBR, both headers and both entry arrays, plus one usable block
+    if blocks < 2 * first_usable + 1 {
+        return Err(GptError::DiskTooSmall { blocks });
+    }
+    let last_lba = blocks - 1;
+    let last_usable = last_lba - entry_blocks - 1;
+
+    let mut entries = vec![0u8; (ENTRY_COUNT * ENTRY_SIZE) as usize];
+    for (n, partition) in partitions.iter().enumerate() {
+        if partition.index >= ENTRY_COUNT as usize
+            || partition.first_lba < first_usable
+            || partition.first_lba > partition.last_lba
+            || partition.last_lba > last_usable
+        {
+            return Err(GptError::PartitionOutOfRange {
+                index: partition.index,
+            });
+        }
+        if partitions[..n]
+            .iter()
+            .any(|earlier| earlier.index == partition.index)
+        {
+            return Err(GptError::DuplicateIndex {
+                index: partition.index,
+            });
+        }
+        let start = partition.index * ENTRY_SIZE as usize;
+        partition.encode(&mut entries[start..start + ENTRY_SIZE as usize]);
+    }
+
+    // Protective MBR: one 0xEE partition from LBA 1 to the end (capped)
+    let mbr = &mut disk[..512];
+    mbr.fill(0);
+    let record = &mut mbr[446..462];
+    record[1..4].copy_from_slice(&[0x00, 0x02, 0x00]); // CHS of LBA 1
+    record[4] = PROTECTIVE_MBR_TYPE;
+    record[5..8].copy_from_slice(&[0xFF, 0xFF, 0xFF]);
+    record[8..12].copy_from_slice(&1u32.to_le_bytes());
+    record[12..16].copy_from_slice(&(last_lba.min(u32::MAX as u64) as u32).to_le_bytes());
+    mbr[510] = 0x55;
+    mbr[511] = 0xAA;
+
+    let primary = GptHeader {
+        my_lba: 1,
+        alternate_lba: last_lba,
+        first_usable_lba: first_usable,
+        last_usable_lba: last_usable,
+        disk_guid,
+        partition_entry_lba: 2,
+        entry_count: ENTRY_COUNT,
+        entry_size: ENTRY_SIZE,
+        entries_crc32: crc32(&entries),
+    };
+    let backup = GptHeader {
+        my_lba: last_lba,
+        alternate_lba: 1,
+        partition_entry_lba: last_usable + 1,
+        ..primary
+    };
+    for header in [primary, backup] {
+        let at = |lba: u64| lba as usize * bs;
+        disk[at(header.my_lba)..at(header.my_lba + 1)].copy_from_slice(&header.encode(bs));
+        let start = at(header.partition_entry_lba);
+        disk[start..start + entries.len()].copy_from_slice(&entries);
+    }
+    Ok(())
+}
+
+/// Read and verify one header and its entry array.
+fn load_table<D: Read + Seek>(
+    device: &mut D,
+    block_size: u64,
+    lba: u64,
+) -> Result<(GptHeader, Vec<u8>), GptError> {
+    let offset = lba
+        .checked_mul(block_size)
+        .ok_or(GptError::BadHeader { lba })?;
+    let mut block = vec![0u8; block_size as usize];
+    read_at(device, offset, &mut block)?;
+    let header = GptHeader::parse(&block, lba)?;
+
+    let entries_offset = header
+        .partition_entry_lba
+        .checked_mul(block_size)
+        .ok_or(GptError::BadHeader { lba })?;
+    let mut entries = vec![0u8; header.entries_len()];
+    read_at(device, entries_offset, &mut entries)?;
+    let computed = crc32(&entries);
+    if computed != header.entries_crc32 {
+        return Err(GptError::EntriesCrc {
+            lba: header.partition_entry_lba,
+            stored: header.entries_crc32,
+            computed,
+        });
+    }
+    Ok((header, entries))
+}
+
+fn read_at<D: Read + Seek>(device: &mut D, offset: u64, buffer: &mut [u8]) -> Result<(), GptError> {
+    device
+        .seek(SeekFrom::Start(offset))
+        .and_then(|_| device.read_exact(buffer))
+        .map_err(|_| GptError::Io)
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fat::{format_volume, FatType};
+    use crate::OpenMode;
+    use std::io::Cursor;
+
+    const DISK_GUID: Guid = Guid::new(0x01234567, 0x89AB, 0xCDEF, [1, 2, 3, 4, 5, 6, 7, 8]);
+
+    /// 4 MiB disk: a FAT12 ESP at LBA 2048 and a Linux partition after it
+    fn sample_disk() -> Vec<u8> {
+        let mut disk = vec![0u8; 8192 * 512];
+        let esp = format_volume(2880, 1, FatType::Fat12, "ESP").unwrap();
+        disk[2048 * 512..2048 * 512 + esp.len()].copy_from_slice(&esp);
+
+        let partitions = [
+            GptPartition {
+                index: 0,
+                type_guid: EFI_SYSTEM_PARTITION_GUID,
+                unique_guid: Guid::new(1, 0, 0, [0; 8]),
+                first_lba: 2048,
+                last_lba: 2048 + 2880 - 1,
+                attributes: 0,
+                name: "EFI System Partition".to_string(),
+            },
+            GptPartition {
+                index: 1,
+                type_guid: LINUX_FILESYSTEM_GUID,
+                unique_guid: Guid::new(2, 0, 0, [0; 8]),
+                first_lba: 6144,
+                last_lba: 8000,
+                attributes: 0,
+                name: "root".to_string(),
+            },
+        ];
+        write_gpt(&mut disk, 512, DISK_GUID, &partitions).unwrap();
+        disk
+    }
+
+    /// Overwrite a field of the header at `lba` and reseal its CRC
+    fn patch_header(disk: &mut [u8], lba: usize, offset: usize, value: &[u8]) {
+        let header = &mut disk[lba * 512..lba * 512 + HEADER_SIZE as usize];
+        header[offset..offset + value.len()].copy_from_slice(value);
+        header[16..20].fill(0);
+        let crc = crc32(header);
+        header[16..20].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    /// Overwrite a field of entry `index` in both tables and reseal them
+    fn patch_entry(disk: &mut [u8], index: usize, offset: usize, value: &[u8]) {
+        for (header_lba, entries_lba) in [(1, 2), (8191, 8191 - 32)] {
+            let entries = entries_lba * 512;
+            let at = entries + index * ENTRY_SIZE as usize + offset;
+            disk[at..at + value.len()].copy_from_slice(value);
+            let crc = crc32(&disk[entries..entries + (ENTRY_COUNT * ENTRY_SIZE) as usize]);
+            patch_header(disk, header_lba, 88, &crc.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_crc32_and_guid_encoding() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(
+            EFI_SYSTEM_PARTITION_GUID.to_string(),
+            "C12A7328-F81F-11D2-BA4B-00A0C93EC93B"
+        );
+        let bytes = EFI_SYSTEM_PARTITION_GUID.to_bytes();
+        assert_eq!(&bytes[..4], &[0x28, 0x73, 0x2A, 0xC1]);
+        assert_eq!(Guid::from_bytes(&bytes), EFI_SYSTEM_PARTITION_GUID);
+    }
+
+    #[test]
+    fn test_read_and_mount_esp() {
+        let disk = sample_disk();
+        let parsed = GptDisk::read(&mut Cursor::new(&disk)).unwrap();
+        assert!(parsed.is_healthy(), "{:?}", parsed.issues);
+        assert_eq!(parsed.header.disk_guid, DISK_GUID);
+        assert_eq!(parsed.header.first_usable_lba, 34);
+        assert_eq!(parsed.header.last_usable_lba, 8191 - 33);
+        assert_eq!(parsed.partitions.len(), 2);
+        assert_eq!(parsed.partitions[1].name, "root");
+        assert_eq!(parsed.esp().unwrap().sectors(), Some(2880));
+
+        let (_, fs) = mount_esp(Cursor::new(disk)).unwrap();
+        fs.open("\\hello.txt", OpenMode::Create)
+            .unwrap()
+            .write(b"hi")
+            .unwrap();
+        assert_eq!(fs.volume_label().unwrap(), "ESP");
+
+        // The write landed inside the partition, not over the GPT
+        let disk = fs.unmount().ok().unwrap().into_inner();
+        assert!(GptDisk::read(&mut Cursor::new(&disk)).unwrap().is_healthy());
+    }
+
+    #[test]
+    fn test_damaged_primary_falls_back_to_backup() {
+        let mut disk = sample_disk();
+        disk[512 + 40] ^= 0xFF; // first_usable_lba of the primary header
+        let parsed = GptDisk::read(&mut Cursor::new(&disk)).unwrap();
+        assert_eq!(parsed.header.my_lba, 8191);
+        assert!(matches!(
+            parsed.issues[..],
+            [GptError::HeaderCrc { lba: 1, .. }]
+        ));
+        assert_eq!(parsed.partitions.len(), 2);
+
+        // With the backup entry array damaged too, nothing is left
+        let backup_entries = (8191 - 32) * 512;
+        disk[backup_entries] ^= 0xFF;
+        assert!(matches!(
+            GptDisk::read(&mut Cursor::new(&disk)),
+            Err(GptError::HeaderCrc { lba: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_header_locations_are_checked() {
+        // A primary that does not point at the last block is not trusted
+        let mut disk = sample_disk();
+        patch_header(&mut disk, 1, 32, &4000u64.to_le_bytes());
+        let parsed = GptDisk::read(&mut Cursor::new(&disk)).unwrap();
+        assert_eq!(parsed.header.my_lba, 8191);
+        assert_eq!(parsed.issues, vec![GptError::BadHeader { lba: 1 }]);
+
+        // Entry arrays whose byte offset overflows are rejected, not read
+        let mut disk = sample_disk();
+        patch_header(&mut disk, 1, 72, &(u64::MAX / 2).to_le_bytes());
+        patch_header(&mut disk, 8191, 72, &(u64::MAX / 2).to_le_bytes());
+        assert_eq!(
+            GptDisk::read(&mut Cursor::new(&disk)).err(),
+            Some(GptError::BadHeader { lba: 1 })
+        );
+    }
+
+    #[test]
+    fn test_large_blocks_reserve_a_whole_entry_block() {
+        // 16 KiB of entries fit in less than one 64 KiB block
+        let mut disk = vec![0u8; 64 * 65536];
+        write_gpt(&mut disk, 65536, DISK_GUID, &[]).unwrap();
+        let header = GptHeader::parse(&disk[65536..2 * 65536], 1).unwrap();
+        assert_eq!(header.partition_entry_lba, 2);
+        assert_eq!(header.first_usable_lba, 3);
+        assert_eq!(header.last_usable_lba, 61);
+
+        let backup = GptHeader::parse(&disk[63 * 65536..], 63).unwrap();
+        assert_eq!(backup.partition_entry_lba, 62);
+    }
+
+    #[test]
+    fn test_sanity_checks() {
+        let mut disk = sample_disk();
+        disk[510] = 0;
+        assert_eq!(
+            GptDisk::read(&mut Cursor::new(&disk)).err(),
+            Some(GptError::NoProtectiveMbr)
+        );
+
+        // Too small for both tables, or a block size that cannot hold a header
+        for (len, block_size) in [
+            (0, 512),
+            (66 * 512, 512),
+            (8192 * 512, 0),
+            (8192 * 512, 100),
+        ] {
+            let mut disk = vec![0u8; len];
+            assert!(matches!(
+                write_gpt(&mut disk, block_size, DISK_GUID, &[]),
+                Err(GptError::DiskTooSmall { .. })
+            ));
+        }
+
+        let mut disk = vec![0u8; 8192 * 512];
+        let overlapping = [(0, 100, 200), (1, 150, 300)].map(|(index, first, last)| GptPartition {
+            index,
+            type_guid: BASIC_DATA_PARTITION_GUID,
+            unique_guid: Guid::ZERO,
+            first_lba: first,
+            last_lba: last,
+            attributes: 0,
+            name: String::new(),
+        });
+        write_gpt(&mut disk, 512, DISK_GUID, &overlapping).unwrap();
+        let parsed = GptDisk::read(&mut Cursor::new(&disk)).unwrap();
+        assert_eq!(
+            parsed.issues,
+            vec![GptError::PartitionsOverlap {
+                first: 0,
+                second: 1
+            }]
+        );
+        assert_eq!(
+            mount_esp(Cursor::new(disk)).err().map(Status::from),
+            Some(Status::NOT_FOUND)
+        );
+
+        // Entries may not share a slot
+        let mut disk = vec![0u8; 8192 * 512];
+        let shared = [(100, 200), (300, 400)].map(|(first, last)| GptPartition {
+            index: 3,
+            type_guid: BASIC_DATA_PARTITION_GUID,
+            unique_guid: Guid::ZERO,
+            first_lba: first,
+            last_lba: last,
+            attributes: 0,
+            name: String::new(),
+        });
+        assert_eq!(
+            write_gpt(&mut disk, 512, DISK_GUID, &shared).err(),
+            Some(GptError::DuplicateIndex { index: 3 })
+        );
+
+        // An inverted or far-away ESP is reported, not computed with
+        let mut disk = sample_disk();
+        patch_entry(&mut disk, 0, 32, &u64::MAX.to_le_bytes());
+        let parsed = GptDisk::read(&mut Cursor::new(&disk)).unwrap();
+        let esp = parsed.esp().unwrap();
+        assert_eq!(esp.sectors(), None);
+        assert_eq!(parsed.byte_offset(esp), None);
+        assert_eq!(
+            parsed.issues,
+            vec![GptError::PartitionOutOfRange { index: 0 }]
+        );
+        assert_eq!(
+            mount_esp(Cursor::new(disk)).err(),
+            Some(GptError::PartitionOutOfRange { index: 0 })
+        );
+    }
+}
@@ -28,8 +28,16 @@
 //! Note: This example demonstrates concepts in a std environment.
 //! Real UEFI filesystem access requires the uefi crate and no_std.
 
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt;
+use std::rc::Rc;
+
+mod fat;
+mod gpt;
+mod path;
+
+use path::{UefiPath, UefiPathBuf};
 
 fn main() {
     println!("=== UEFI Filesystem Concepts ===\n");
@@ -51,6 +59,18 @@ fn main() {
 
     println!("\n--- Simulated Filesystem ---");
     simulated_filesystem();
+
+    if let Some(path) = std::env::args().nth(1) {
+        println!("\n--- Disk Image {} ---", path);
+        inspect_image(&path);
+        return;
+    }
+
+    println!("\n--- FAT Image Backend ---");
+    fat_image_backend();
+
+    println!("\n--- GPT Partition Table ---");
+    gpt_partition_table();
 }
 
 // ============================================
@@ -61,6 +81,7 @@ fn main() {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct FileAttributes(u64);
 
+#[allow(dead_code)]
 impl FileAttributes {
     const READ_ONLY: u64 = 0x0000000000000001;
     const HIDDEN: u64 = 0x0000000000000002;
@@ -149,8 +170,7 @@ fn file_attributes() {
 // ============================================
 
 /// UEFI file open modes
-#[derive(Debug, Clone, Copy)]
-#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum OpenMode {
     Read,
     ReadWrite,
@@ -158,13 +178,26 @@ enum OpenMode {
 }
 
 impl OpenMode {
-    fn to_flags(&self) -> u64 {
+    fn to_flags(self) -> u64 {
         match self {
             OpenMode::Read => 0x0000000000000001,
             OpenMode::ReadWrite => 0x0000000000000003,
             OpenMode::Create => 0x8000000000000000 | 0x0000000000000003,
         }
     }
+
+    /// Parse an `OpenMode` argument. The spec only allows the three
+    /// combinations above; anything else is `INVALID_PARAMETER`.
+    fn from_flags(flags: u64) -> Result<Self, Status> {
+        [OpenMode::Read, OpenMode::ReadWrite, OpenMode::Create]
+            .into_iter()
+            .find(|mode| mode.to_flags() == flags)
+            .ok_or(Status::INVALID_PARAMETER)
+    }
+
+    fn is_writable(self) -> bool {
+        !matches!(self, OpenMode::Read)
+    }
 }
 
 /// Simulated UEFI file status
@@ -173,9 +206,16 @@ struct Status(usize);
 
 impl Status {
     const SUCCESS: Status = Status(0);
+    const INVALID_PARAMETER: Status = Status(2);
+    const UNSUPPORTED: Status = Status(3);
+    const BAD_BUFFER_SIZE: Status = Status(4);
+    const BUFFER_TOO_SMALL: Status = Status(5);
     const NOT_FOUND: Status = Status(14);
+    const ACCESS_DENIED: Status = Status(15);
+    const DEVICE_ERROR: Status = Status(7);
     const WRITE_PROTECTED: Status = Status(8);
-    const VOLUME_FULL: Status = Status(10);
+    const VOLUME_CORRUPTED: Status = Status(10);
+    const VOLUME_FULL: Status = Status(11);
     const END_OF_FILE: Status = Status(31);
 
     fn is_success(&self) -> bool {
@@ -185,9 +225,16 @@ impl Status {
     fn description(&self) -> &'static str {
         match self.0 {
             0 => "Success",
+            2 => "Invalid Parameter",
+            3 => "Unsupported",
+            4 => "Bad Buffer Size",
+            5 => "Buffer Too Small",
+            7 => "Device Error",
             8 => "Write Protected",
-            10 => "Volume Full",
+            10 => "Volume Corrupted",
+            11 => "Volume Full",
             14 => "Not Found",
+            15 => "Access Denied",
             31 => "End of File",
             _ => "Unknown Error",
         }
@@ -195,6 +242,11 @@ impl Status {
 }
 
 /// Simulated file handle
+///
+/// Handles opened through [`SimpleFilesystem::open`] buffer writes and commit
+/// them back to the volume on [`FileHandle::flush`] or [`FileHandle::close`],
+/// like EFI_FILE_PROTOCOL. Handles made with `new`/`with_data` are
+/// standalone and have nothing to commit to.
 struct FileHandle {
     name: String,
     position: u64,
@@ -202,6 +254,25 @@ struct FileHandle {
     data: Vec<u8>,
     attributes: FileAttributes,
     is_open: bool,
+    writable: bool,
+    dirty: bool,
+    backing: Option<Backing>,
+}
+
+/// Storage that open handles commit their data to
+trait CommitTarget {
+    /// Replace the contents of the file identified by `file_id`.
+    fn commit(&mut self, file_id: u64, data: &[u8]) -> Result<(), Status>;
+
+    /// Largest size the file identified by `file_id` can be committed at:
+    /// the space it already occupies plus the volume's free space.
+    fn max_file_size(&mut self, file_id: u64) -> Result<u64, Status>;
+}
+
+/// Where an open handle commits its data
+struct Backing {
+    target: Rc<RefCell<dyn CommitTarget>>,
+    file_id: u64,
 }
 
 impl FileHandle {
@@ -213,6 +284,9 @@ impl FileHandle {
             data: Vec::new(),
             attributes,
             is_open: true,
+            writable: !attributes.is_read_only(),
+            dirty: false,
+            backing: None,
         }
     }
 
@@ -225,9 +299,35 @@ impl FileHandle {
             data,
             attributes: FileAttributes::new(),
             is_open: true,
+            writable: true,
+            dirty: false,
+            backing: None,
         }
     }
 
+    /// A handle on a directory; writes fail with `UNSUPPORTED`.
+    fn directory(name: &str) -> Self {
+        let mut handle = FileHandle::new(name, FileAttributes::new().directory());
+        handle.writable = false;
+        handle
+    }
+
+    /// A handle on a file stored in `backing`, as returned by a filesystem's
+    /// `open`.
+    fn backed(
+        name: &str,
+        data: Vec<u8>,
+        attributes: FileAttributes,
+        mode: OpenMode,
+        backing: Backing,
+    ) -> Self {
+        let mut handle = FileHandle::with_data(name, data);
+        handle.attributes = attributes;
+        handle.writable = mode.is_writable();
+        handle.backing = Some(backing);
+        handle
+    }
+
     fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Status> {
         if !self.is_open {
             return Err(Status::NOT_FOUND);
@@ -252,21 +352,32 @@ impl FileHandle {
             return Err(Status::NOT_FOUND);
         }
 
-        if self.attributes.is_read_only() {
+        if self.attributes.is_directory() {
+            return Err(Status::UNSUPPORTED);
+        }
+
+        if !self.writable {
             return Err(Status::WRITE_PROTECTED);
         }
 
         let start = self.position as usize;
-        let end = start + data.len();
+        let end = start.checked_add(data.len()).ok_or(Status::VOLUME_FULL)?;
 
-        // Extend data if necessary
+        // Extend data if necessary, as far as the volume has room
         if end > self.data.len() {
+            if let Some(backing) = &self.backing {
+                let limit = backing.target.borrow_mut().max_file_size(backing.file_id)?;
+                if end as u64 > limit {
+                    return Err(Status::VOLUME_FULL);
+                }
+            }
             self.data.resize(end, 0);
         }
 
         self.data[start..end].copy_from_slice(data);
         self.position = end as u64;
         self.size = self.data.len() as u64;
+        self.dirty = true;
 
         Ok(data.len())
     }
@@ -284,8 +395,43 @@ impl FileHandle {
         self.position
     }
 
+    /// Commit buffered writes to the volume.
+    ///
+    /// The handle follows its file across renames; if the file was deleted
+    /// in the meantime the data has nowhere to go and `NOT_FOUND` is returned.
+    fn flush(&mut self) -> Result<(), Status> {
+        if !self.is_open {
+            return Err(Status::NOT_FOUND);
+        }
+        let Some(backing) = &self.backing else {
+            return Ok(());
+        };
+        if !self.dirty {
+            return Ok(());
+        }
+
+        backing
+            .target
+            .borrow_mut()
+            .commit(backing.file_id, &self.data)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Flush and close the handle. Like EFI_FILE_PROTOCOL.Close this always
+    /// succeeds; call [`FileHandle::flush`] first to observe commit errors.
     fn close(&mut self) {
+        if self.is_open {
+            let _ = self.flush();
+        }
         self.is_open = false;
+        self.backing = None;
+    }
+}
+
+impl Drop for FileHandle {
+    fn drop(&mut self) {
+        self.close();
     }
 }
 
@@ -460,93 +606,103 @@ fn directory_operations() {
 // Path Handling
 // ============================================
 
-/// UEFI uses backslash as path separator
-fn normalize_path(path: &str) -> String {
-    // Convert forward slashes to backslashes
-    let normalized = path.replace('/', "\\");
-
-    // Remove duplicate separators
-    let mut result = String::new();
-    let mut prev_sep = false;
-
-    for c in normalized.chars() {
-        if c == '\\' {
-            if !prev_sep {
-                result.push(c);
-            }
-            prev_sep = true;
-        } else {
-            result.push(c);
-            prev_sep = false;
-        }
-    }
-
-    result
-}
-
-/// Split path into components
-fn split_path(path: &str) -> Vec<&str> {
-    path.split('\\').filter(|s| !s.is_empty()).collect()
-}
-
-/// Join path components
-fn join_path(components: &[&str]) -> String {
-    if components.is_empty() {
-        "\\".to_string()
-    } else {
-        format!("\\{}", components.join("\\"))
-    }
-}
-
-/// Get parent directory
-fn parent_path(path: &str) -> Option<String> {
-    let components: Vec<&str> = split_path(path);
-    if components.len() <= 1 {
-        None
-    } else {
-        Some(join_path(&components[..components.len() - 1]))
-    }
-}
-
-/// Get filename from path
-fn filename(path: &str) -> Option<&str> {
-    split_path(path).last().copied()
-}
-
 fn path_handling() {
     let paths = [
         "\\EFI\\BOOT\\BOOTX64.EFI",
         "/EFI/BOOT/grub.cfg",
         "\\EFI\\\\ubuntu\\\\shimx64.efi",
+        "\\EFI\\.\\ubuntu\\..\\BOOT\\",
         "\\startup.nsh",
     ];
 
     for path in &paths {
         println!("  Original: {}", path);
-        let normalized = normalize_path(path);
+        let normalized = UefiPathBuf::parse(path).unwrap();
         println!("    Normalized: {}", normalized);
 
-        let components = split_path(&normalized);
+        let components: Vec<&str> = normalized.components().collect();
         println!("    Components: {:?}", components);
 
-        if let Some(parent) = parent_path(&normalized) {
+        if let Some(parent) = normalized.parent() {
             println!("    Parent: {}", parent);
         }
 
-        if let Some(name) = filename(&normalized) {
+        if let Some(name) = normalized.file_name() {
             println!("    Filename: {}", name);
         }
 
         println!();
     }
+
+    // Open on a directory handle resolves relative paths against it
+    let boot = UefiPathBuf::parse("\\EFI\\BOOT").unwrap();
+    for relative in ["grub.cfg", "..\\ubuntu\\shimx64.efi", "\\startup.nsh"] {
+        println!(
+            "  {} + {:?} -> {}",
+            boot,
+            relative,
+            boot.join(relative).unwrap()
+        );
+    }
+
+    // FAT names compare without regard to case
+    let lower = UefiPathBuf::parse("/efi/boot/bootx64.efi").unwrap();
+    let upper = UefiPathBuf::parse("\\EFI\\BOOT\\BOOTX64.EFI").unwrap();
+    println!("  {} == {}: {}", lower, upper, lower == upper);
+    println!(
+        "  {} is below {}: {}",
+        upper,
+        boot,
+        upper.starts_with(&boot)
+    );
+
+    // The CHAR16 string handed to EFI_FILE_PROTOCOL.Open
+    let units = upper.to_ucs2();
+    println!(
+        "  {} as CHAR16: {} units, first {:04X?}",
+        upper,
+        units.len(),
+        &units[..4]
+    );
+
+    println!("\n  Rejected paths:");
+    let long_name = "x".repeat(path::MAX_COMPONENT_LEN + 1);
+    for bad in ["\\EFI\\..\\..", "\\EFI\\boot?.efi", long_name.as_str()] {
+        let shown: String = bad.chars().take(24).collect();
+        match UefiPathBuf::parse(bad) {
+            Ok(path) => println!("    {:24} -> {}", shown, path),
+            Err(e) => println!(
+                "    {:24} -> {} ({})",
+                shown,
+                e,
+                Status::from(e.clone()).description()
+            ),
+        }
+    }
+    let lone_surrogate = [0x5C, 0x61, 0xD800, 0x62, 0];
+    match UefiPathBuf::from_ucs2(&lone_surrogate) {
+        Ok(path) => println!("    {:04X?} -> {}", lone_surrogate, path),
+        Err(e) => println!("    {:04X?} -> {}", lone_surrogate, e),
+    }
 }
 
 // ============================================
 // File Information Structures
 // ============================================
 
-/// UEFI file information
-#[derive(Debug, Clone)]
+/// UEFI file information (EFI_FILE_INFO)
+///
+/// ```text
+///   0  Size              u64   whole structure, including the name
+///   8  FileSize          u64
+///  16  PhysicalSize      u64
+///  24  CreateTime        EFI_TIME
+///  40  LastAccessTime    EFI_TIME
+///  56  ModificationTime  EFI_TIME
+///  72  Attribute         u64
+///  80  FileName          CHAR16[], null-terminated
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct FileInfo {
     size: u64,
     file_size: u64,
@@ -558,8 +714,11 @@ struct FileInfo {
     filename: String,
 }
 
-/// UEFI time structure
-#[derive(Debug, Clone, Copy, Default)]
+/// UEFI time structure (EFI_TIME, 16 bytes)
+///
+/// `time_zone` is the offset from UTC in minutes, or
+/// [`EfiTime::UNSPECIFIED_TIMEZONE`] for local time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 struct EfiTime {
     year: u16,
     month: u8,
@@ -567,9 +726,18 @@ struct EfiTime {
     hour: u8,
     minute: u8,
     second: u8,
+    nanosecond: u32,
+    time_zone: i16,
+    daylight: u8,
 }
 
 impl EfiTime {
+    const SIZE: usize = 16;
+    const UNSPECIFIED_TIMEZONE: i16 = 0x07FF;
+    /// Daylight flags
+    const ADJUST_DAYLIGHT: u8 = 0x01;
+    const IN_DAYLIGHT: u8 = 0x02;
+
     fn new(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Self {
         EfiTime {
             year,
@@ -578,8 +746,89 @@ impl EfiTime {
             hour,
             minute,
             second,
+            nanosecond: 0,
+            time_zone: Self::UNSPECIFIED_TIMEZONE,
+            daylight: 0,
+        }
+    }
+
+    fn with_zone(self, time_zone: i16, daylight: u8) -> Self {
+        EfiTime {
+            time_zone,
+            daylight,
+            ..self
         }
     }
+
+    fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..2].copy_from_slice(&self.year.to_le_bytes());
+        bytes[2] = self.month;
+        bytes[3] = self.day;
+        bytes[4] = self.hour;
+        bytes[5] = self.minute;
+        bytes[6] = self.second;
+        bytes[8..12].copy_from_slice(&self.nanosecond.to_le_bytes());
+        bytes[12..14].copy_from_slice(&self.time_zone.to_le_bytes());
+        bytes[14] = self.daylight;
+        bytes
+    }
+
+    /// Decode an EFI_TIME as stored, without range checks; an all-zero
+    /// time means "unset" in EFI_FILE_INFO.
+    fn decode(bytes: &[u8]) -> Result<Self, Status> {
+        let bytes: &[u8; Self::SIZE] = bytes
+            .get(..Self::SIZE)
+            .and_then(|b| b.try_into().ok())
+            .ok_or(Status::BAD_BUFFER_SIZE)?;
+        Ok(EfiTime {
+            year: u16::from_le_bytes([bytes[0], bytes[1]]),
+            month: bytes[2],
+            day: bytes[3],
+            hour: bytes[4],
+            minute: bytes[5],
+            second: bytes[6],
+            nanosecond: u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            time_zone: i16::from_le_bytes([bytes[12], bytes[13]]),
+            daylight: bytes[14],
+        })
+    }
+
+    /// Decode and range-check an EFI_TIME, as SetTime does.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Status> {
+        let time = Self::decode(bytes)?;
+        if time.is_valid() {
+            Ok(time)
+        } else {
+            Err(Status::INVALID_PARAMETER)
+        }
+    }
+
+    /// All fields zero: no time recorded, and "leave unchanged" for SetInfo
+    fn is_unset(&self) -> bool {
+        *self == EfiTime::default()
+    }
+
+    fn is_valid(&self) -> bool {
+        let leap = self.year.is_multiple_of(4)
+            && (!self.year.is_multiple_of(100) || self.year.is_multiple_of(400));
+        let days_in_month = match self.month {
+            2 if leap => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        };
+        (1900..=9999).contains(&self.year)
+            && (1..=12).contains(&self.month)
+            && (1..=days_in_month).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+            && self.nanosecond < 1_000_000_000
+            && ((-1440..=1440).contains(&self.time_zone)
+                || self.time_zone == Self::UNSPECIFIED_TIMEZONE)
+            && self.daylight & !(Self::ADJUST_DAYLIGHT | Self::IN_DAYLIGHT) == 0
+    }
 }
 
 impl fmt::Display for EfiTime {
@@ -588,17 +837,94 @@ impl fmt::Display for EfiTime {
             f,
             "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
             self.year, self.month, self.day, self.hour, self.minute, self.second
-        )
+        )?;
+        if self.time_zone != Self::UNSPECIFIED_TIMEZONE {
+            let sign = if self.time_zone < 0 { '-' } else { '+' };
+            let minutes = self.time_zone.unsigned_abs();
+            write!(f, " UTC{}{:02}:{:02}", sign, minutes / 60, minutes % 60)?;
+        }
+        if self.daylight & Self::IN_DAYLIGHT != 0 {
+            write!(f, " DST")?;
+        }
+        Ok(())
+    }
+}
+
+/// A variable-length structure returned by EFI_FILE_PROTOCOL.GetInfo
+trait EfiInfo: Sized {
+    /// Bytes needed to encode the structure, including its name
+    fn encoded_size(&self) -> usize;
+
+    /// Encode into `out`, which is exactly `encoded_size()` bytes long.
+    fn encode(&self, out: &mut [u8]);
+
+    fn decode(bytes: &[u8]) -> Result<Self, Status>;
+
+    /// GetInfo's two-call protocol: if `*buffer_size` is too small (the
+    /// first call usually passes 0 and no buffer), return `BUFFER_TOO_SMALL`
+    /// with `*buffer_size` set to the size needed.
+    fn get_info(&self, buffer_size: &mut usize, buffer: Option<&mut [u8]>) -> Status {
+        let needed = self.encoded_size();
+        if *buffer_size < needed {
+            *buffer_size = needed;
+            return Status::BUFFER_TOO_SMALL;
+        }
+        let Some(buffer) = buffer.filter(|buffer| buffer.len() >= needed) else {
+            return Status::INVALID_PARAMETER;
+        };
+        self.encode(&mut buffer[..needed]);
+        *buffer_size = needed;
+        Status::SUCCESS
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0u8; self.encoded_size()];
+        self.encode(&mut bytes);
+        bytes
+    }
+}
+
+/// CHAR16 string with its null terminator
+fn encode_char16(text: &str, out: &mut [u8]) {
+    for (slot, unit) in out
+        .as_chunks_mut::<2>()
+        .0
+        .iter_mut()
+        .zip(text.encode_utf16().chain([0]))
+    {
+        slot.copy_from_slice(&unit.to_le_bytes());
     }
 }
 
+/// Decode a null-terminated CHAR16 string that must end within `bytes`.
+fn decode_char16(bytes: &[u8]) -> Result<String, Status> {
+    let units: Vec<u16> = bytes
+        .as_chunks::<2>()
+        .0
+        .iter()
+        .map(|&pair| u16::from_le_bytes(pair))
+        .collect();
+    let end = units
+        .iter()
+        .position(|&unit| unit == 0)
+        .ok_or(Status::INVALID_PARAMETER)?;
+    String::from_utf16(&units[..end]).map_err(|_| Status::INVALID_PARAMETER)
+}
+
+fn char16_size(text: &str) -> usize {
+    (text.encode_utf16().count() + 1) * 2
+}
+
 impl FileInfo {
+    /// Offset of FileName, i.e. SIZE_OF_EFI_FILE_INFO
+    const HEADER_SIZE: usize = 80;
+
     fn new(filename: &str, file_size: u64, attributes: FileAttributes) -> Self {
         let now = EfiTime::new(2024, 1, 15, 10, 30, 0);
         FileInfo {
-            size: std::mem::size_of::<Self>() as u64,
+            size: (Self::HEADER_SIZE + char16_size(filename)) as u64,
             file_size,
-            physical_size: ((file_size + 4095) / 4096) * 4096, // Round up to cluster
+            physical_size: file_size.div_ceil(4096) * 4096, // Round up to cluster
             create_time: now,
             last_access_time: now,
             modification_time: now,
@@ -608,10 +934,122 @@ impl FileInfo {
     }
 }
 
+impl EfiInfo for FileInfo {
+    fn encoded_size(&self) -> usize {
+        Self::HEADER_SIZE + char16_size(&self.filename)
+    }
+
+    fn encode(&self, out: &mut [u8]) {
+        let size = out.len() as u64;
+        out[0..8].copy_from_slice(&size.to_le_bytes());
+        out[8..16].copy_from_slice(&self.file_size.to_le_bytes());
+        out[16..24].copy_from_slice(&self.physical_size.to_le_bytes());
+        out[24..40].copy_from_slice(&self.create_time.to_bytes());
+        out[40..56].copy_from_slice(&self.last_access_time.to_bytes());
+        out[56..72].copy_from_slice(&self.modification_time.to_bytes());
+        out[72..80].copy_from_slice(&self.attributes.0.to_le_bytes());
+        encode_char16(&self.filename, &mut out[Self::HEADER_SIZE..]);
+    }
+
+    /// `BAD_BUFFER_SIZE` if the buffer is shorter than its Size field says,
+    /// `INVALID_PARAMETER` for an unterminated name. Times are returned as
+    /// stored (possibly zero); only SetInfo range-checks them.
+    fn decode(bytes: &[u8]) -> Result<Self, Status> {
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        if bytes.len() < Self::HEADER_SIZE + 2 {
+            return Err(Status::BAD_BUFFER_SIZE);
+        }
+        let size = u64_at(0);
+        if size > bytes.len() as u64 || size < (Self::HEADER_SIZE + 2) as u64 {
+            return Err(Status::BAD_BUFFER_SIZE);
+        }
+        Ok(FileInfo {
+            size,
+            file_size: u64_at(8),
+            physical_size: u64_at(16),
+            create_time: EfiTime::decode(&bytes[24..40])?,
+            last_access_time: EfiTime::decode(&bytes[40..56])?,
+            modification_time: EfiTime::decode(&bytes[56..72])?,
+            attributes: FileAttributes(u64_at(72)),
+            filename: decode_char16(&bytes[Self::HEADER_SIZE..size as usize])?,
+        })
+    }
+}
+
+/// Volume information (EFI_FILE_SYSTEM_INFO)
+///
+/// ```text
+///   0  Size         u64
+///   8  ReadOnly     BOOLEAN (+7 bytes padding)
+///  16  VolumeSize   u64
+///  24  FreeSpace    u64
+///  32  BlockSize    u32
+///  36  VolumeLabel  CHAR16[], null-terminated
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileSystemInfo {
+    read_only: bool,
+    volume_size: u64,
+    free_space: u64,
+    block_size: u32,
+    volume_label: String,
+}
+
+impl FileSystemInfo {
+    /// Offset of VolumeLabel, i.e. SIZE_OF_EFI_FILE_SYSTEM_INFO
+    const HEADER_SIZE: usize = 36;
+}
+
+impl EfiInfo for FileSystemInfo {
+    fn encoded_size(&self) -> usize {
+        Self::HEADER_SIZE + char16_size(&self.volume_label)
+    }
+
+    fn encode(&self, out: &mut [u8]) {
+        let size = out.len() as u64;
+        out[0..8].copy_from_slice(&size.to_le_bytes());
+        out[8] = self.read_only as u8;
+        out[9..16].fill(0);
+        out[16..24].copy_from_slice(&self.volume_size.to_le_bytes());
+        out[24..32].copy_from_slice(&self.free_space.to_le_bytes());
+        out[32..36].copy_from_slice(&self.block_size.to_le_bytes());
+        encode_char16(&self.volume_label, &mut out[Self::HEADER_SIZE..]);
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, Status> {
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        if bytes.len() < Self::HEADER_SIZE + 2 {
+            return Err(Status::BAD_BUFFER_SIZE);
+        }
+        let size = u64_at(0);
+        if size > bytes.len() as u64 || size < (Self::HEADER_SIZE + 2) as u64 {
+            return Err(Status::BAD_BUFFER_SIZE);
+        }
+        Ok(FileSystemInfo {
+            read_only: bytes[8] != 0,
+            volume_size: u64_at(16),
+            free_space: u64_at(24),
+            block_size: u32::from_le_bytes(bytes[32..36].try_into().unwrap()),
+            volume_label: decode_char16(&bytes[Self::HEADER_SIZE..size as usize])?,
+        })
+    }
+}
+
+/// Hex dump, 16 bytes per line
+fn hex_dump(bytes: &[u8]) {
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02X}", b)).collect();
+        println!("      {:04X}: {}", line * 16, hex.join(" "));
+    }
+}
+
 fn file_information() {
     let file_info = FileInfo::new("BOOTX64.EFI", 102400, FileAttributes::new().read_only());
 
     println!("  File Information:");
+    println!("    Structure Size: {} bytes", file_info.size);
     println!("    Filename: {}", file_info.filename);
     println!("    File Size: {} bytes", file_info.file_size);
     println!("    Physical Size: {} bytes", file_info.physical_size);
@@ -627,100 +1065,506 @@ fn file_information() {
     println!("    Name: {}", dir_info.filename);
     println!("    Attributes: {}", dir_info.attributes);
     println!("    Is Directory: {}", dir_info.attributes.is_directory());
+
+    // GetInfo: ask for the size first, then fetch into a buffer that fits
+    let mut info = file_info;
+    info.modification_time = EfiTime::new(2024, 7, 1, 9, 15, 42)
+        .with_zone(120, EfiTime::ADJUST_DAYLIGHT | EfiTime::IN_DAYLIGHT);
+    let mut buffer_size = 0;
+    let status = info.get_info(&mut buffer_size, None);
+    println!(
+        "\n  GetInfo(EFI_FILE_INFO) with no buffer: {}, need {} bytes",
+        status.description(),
+        buffer_size
+    );
+    let mut buffer = vec![0u8; buffer_size];
+    let status = info.get_info(&mut buffer_size, Some(&mut buffer));
+    println!("  Second call: {}", status.description());
+    hex_dump(&buffer);
+    let decoded = FileInfo::decode(&buffer).unwrap();
+    println!(
+        "    Decoded {:?}, modified {}",
+        decoded.filename, decoded.modification_time
+    );
+    // A zero EFI_TIME is "unset" in EFI_FILE_INFO but not a time SetTime accepts
+    println!(
+        "    Zeroed EFI_TIME: unset {}, SetTime check {}",
+        EfiTime::decode(&[0; EfiTime::SIZE]).unwrap().is_unset(),
+        match EfiTime::from_bytes(&[0; EfiTime::SIZE]) {
+            Ok(_) => "accepted",
+            Err(status) => status.description(),
+        }
+    );
+
+    let fs_info = FileSystemInfo {
+        read_only: false,
+        volume_size: 64 * 1024 * 1024,
+        free_space: 60 * 1024 * 1024,
+        block_size: 512,
+        volume_label: "ESP".to_string(),
+    };
+    println!(
+        "\n  EFI_FILE_SYSTEM_INFO for {:?}: {} bytes",
+        fs_info.volume_label,
+        fs_info.encoded_size()
+    );
+    hex_dump(&fs_info.to_bytes());
+    assert_eq!(FileSystemInfo::decode(&fs_info.to_bytes()), Ok(fs_info));
 }
 
 // ============================================
 // Simulated Filesystem
 // ============================================
 
+/// A file as stored on the volume
+struct StoredFile {
+    /// Stable identity, so open handles survive a rename
+    id: u64,
+    data: Vec<u8>,
+    attributes: FileAttributes,
+    create_time: EfiTime,
+    last_access_time: EfiTime,
+    modification_time: EfiTime,
+}
+
+/// Volume state shared by a [`SimpleFilesystem`] and its open handles
+struct Volume {
+    files: HashMap<UefiPathBuf, StoredFile>,
+    directories: Vec<UefiPathBuf>,
+    next_id: u64,
+    write_protected: bool,
+    /// Timestamp applied to new and modified files
+    clock: EfiTime,
+}
+
+impl Volume {
+    /// Fixed capacity of the simulated volume
+    const VOLUME_SIZE: u64 = 64 * 1024 * 1024;
+    const CLUSTER_SIZE: u64 = 4096;
+
+    fn insert_file(&mut self, path: UefiPathBuf, data: Vec<u8>, attributes: FileAttributes) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        let file = StoredFile {
+            id,
+            data,
+            attributes,
+            create_time: self.clock,
+            last_access_time: self.clock,
+            modification_time: self.clock,
+        };
+        self.files.insert(path, file);
+        id
+    }
+
+    fn is_directory(&self, path: &UefiPath) -> bool {
+        self.directories.iter().any(|dir| dir.as_path() == path)
+    }
+
+    fn exists(&self, path: &UefiPath) -> bool {
+        self.stored_path(path).is_some()
+    }
+
+    /// The key for `path` spelled as it was created
+    fn stored_path(&self, path: &UefiPath) -> Option<&UefiPathBuf> {
+        self.files
+            .get_key_value(path)
+            .map(|(key, _)| key)
+            .or_else(|| self.directories.iter().find(|dir| dir.as_path() == path))
+    }
+
+    /// Is anything stored below the directory `path`?
+    fn has_children(&self, path: &UefiPath) -> bool {
+        self.directories
+            .iter()
+            .chain(self.files.keys())
+            .any(|entry| entry.parent() == Some(path))
+    }
+
+    /// Bytes a file of `len` bytes occupies on the volume
+    fn allocated(len: u64) -> u64 {
+        len.div_ceil(Self::CLUSTER_SIZE) * Self::CLUSTER_SIZE
+    }
+
+    fn free_space(&self) -> u64 {
+        let used: u64 = self
+            .files
+            .values()
+            .map(|file| Self::allocated(file.data.len() as u64))
+            .sum();
+        Self::VOLUME_SIZE.saturating_sub(used)
+    }
+
+    /// Largest size `file` can grow to without filling the volume.
+    fn room_for(&self, file: &StoredFile) -> u64 {
+        Self::allocated(file.data.len() as u64) + self.free_space()
+    }
+
+    /// Check that `path` is free and its parent directory exists.
+    fn check_new_entry(&self, path: &UefiPath) -> Result<(), Status> {
+        if self.write_protected {
+            return Err(Status::WRITE_PROTECTED);
+        }
+        let parent = path.parent().unwrap_or(UefiPath::root());
+        if !self.is_directory(parent) {
+            return Err(Status::NOT_FOUND);
+        }
+        if self.exists(path) {
+            return Err(Status::ACCESS_DENIED);
+        }
+        Ok(())
+    }
+}
+
+impl CommitTarget for Volume {
+    fn commit(&mut self, file_id: u64, data: &[u8]) -> Result<(), Status> {
+        if self.write_protected {
+            return Err(Status::WRITE_PROTECTED);
+        }
+        let now = self.clock;
+        let file = self
+            .files
+            .values_mut()
+            .find(|file| file.id == file_id)
+            .ok_or(Status::NOT_FOUND)?;
+        file.data = data.to_vec();
+        file.modification_time = now;
+        file.attributes = FileAttributes(file.attributes.0 | FileAttributes::ARCHIVE);
+        Ok(())
+    }
+
+    fn max_file_size(&mut self, file_id: u64) -> Result<u64, Status> {
+        let file = self
+            .files
+            .values()
+            .find(|file| file.id == file_id)
+            .ok_or(Status::NOT_FOUND)?;
+        Ok(self.room_for(file))
+    }
+}
+
 /// Simple filesystem simulation
+///
+/// A host-side model of EFI_SIMPLE_FILE_SYSTEM_PROTOCOL: handles returned by
+/// [`SimpleFilesystem::open`] share the volume, so data written through one
+/// handle is visible to later opens once it has been flushed or closed.
 struct SimpleFilesystem {
-    files: HashMap<String, Vec<u8>>,
-    directories: Vec<String>,
+    volume: Rc<RefCell<Volume>>,
 }
 
 impl SimpleFilesystem {
     fn new() -> Self {
-        let mut fs = SimpleFilesystem {
+        let mut volume = Volume {
             files: HashMap::new(),
-            directories: vec![
-                "\\".to_string(),
-                "\\EFI".to_string(),
-                "\\EFI\\BOOT".to_string(),
-            ],
+            directories: ["\\", "\\EFI", "\\EFI\\BOOT"]
+                .iter()
+                .map(|dir| UefiPathBuf::parse(dir).unwrap())
+                .collect(),
+            next_id: 1,
+            write_protected: false,
+            clock: EfiTime::new(2024, 1, 15, 10, 30, 0),
         };
 
         // Add some default files
-        fs.files.insert(
-            "\\EFI\\BOOT\\BOOTX64.EFI".to_string(),
+        volume.insert_file(
+            UefiPathBuf::parse("\\EFI\\BOOT\\BOOTX64.EFI").unwrap(),
             vec![0x4D, 0x5A], // MZ header start
+            FileAttributes::new(),
         );
-        fs.files.insert(
-            "\\startup.nsh".to_string(),
+        volume.insert_file(
+            UefiPathBuf::parse("\\startup.nsh").unwrap(),
             b"echo Hello from UEFI Shell".to_vec(),
+            FileAttributes::new(),
         );
 
-        fs
+        SimpleFilesystem {
+            volume: Rc::new(RefCell::new(volume)),
+        }
     }
 
+    /// Open a file or directory.
+    ///
+    /// - `Read` handles reject writes with `WRITE_PROTECTED`.
+    /// - `ReadWrite` and `Create` are refused with `ACCESS_DENIED` for files
+    ///   with the READ_ONLY attribute, and `WRITE_PROTECTED` on a
+    ///   write-protected volume.
+    /// - `Create` makes an empty file if none exists; its parent directory
+    ///   must exist.
     fn open(&self, path: &str, mode: OpenMode) -> Result<FileHandle, Status> {
-        let normalized = normalize_path(path);
+        let normalized = UefiPathBuf::parse(path)?;
+        let mut volume = self.volume.borrow_mut();
 
-        if let Some(data) = self.files.get(&normalized) {
-            Ok(FileHandle::with_data(&normalized, data.clone()))
-        } else if matches!(mode, OpenMode::Create) {
-            Ok(FileHandle::new(&normalized, FileAttributes::new()))
-        } else {
-            Err(Status::NOT_FOUND)
+        if mode.is_writable() && volume.write_protected {
+            return Err(Status::WRITE_PROTECTED);
+        }
+
+        if volume.is_directory(&normalized) {
+            return Ok(FileHandle::directory(normalized.as_str()));
+        }
+
+        if !volume.files.contains_key(&normalized) {
+            if mode != OpenMode::Create {
+                return Err(Status::NOT_FOUND);
+            }
+            volume.check_new_entry(&normalized)?;
+            volume.insert_file(normalized.clone(), Vec::new(), FileAttributes::new());
         }
+
+        let file = &volume.files[&normalized];
+        if mode.is_writable() && file.attributes.is_read_only() {
+            return Err(Status::ACCESS_DENIED);
+        }
+
+        let backing = Backing {
+            target: Rc::clone(&self.volume) as Rc<RefCell<dyn CommitTarget>>,
+            file_id: file.id,
+        };
+        Ok(FileHandle::backed(
+            normalized.as_str(),
+            file.data.clone(),
+            file.attributes,
+            mode,
+            backing,
+        ))
     }
 
     fn exists(&self, path: &str) -> bool {
-        let normalized = normalize_path(path);
-        self.files.contains_key(&normalized) || self.directories.contains(&normalized)
+        UefiPathBuf::parse(path).is_ok_and(|path| self.volume.borrow().exists(&path))
     }
 
     fn is_directory(&self, path: &str) -> bool {
-        let normalized = normalize_path(path);
-        self.directories.contains(&normalized)
+        UefiPathBuf::parse(path).is_ok_and(|path| self.volume.borrow().is_directory(&path))
     }
 
     fn list_directory(&self, path: &str) -> Result<Vec<DirEntry>, Status> {
-        let normalized = normalize_path(path);
+        let normalized = UefiPathBuf::parse(path)?;
+        let volume = self.volume.borrow();
 
-        if !self.directories.contains(&normalized) {
+        if !volume.is_directory(&normalized) {
             return Err(Status::NOT_FOUND);
         }
 
         let mut entries = vec![DirEntry::directory("."), DirEntry::directory("..")];
+        let is_child = |path: &UefiPath| path.parent() == Some(normalized.as_path());
 
-        let prefix = if normalized == "\\" {
-            "\\".to_string()
-        } else {
-            format!("{}\\", normalized)
+        // Find subdirectories
+        for dir in volume.directories.iter().filter(|dir| is_child(dir)) {
+            entries.push(DirEntry::directory(dir.file_name().unwrap()));
+        }
+
+        // Find files
+        for (file_path, file) in volume.files.iter().filter(|(path, _)| is_child(path)) {
+            let mut entry = DirEntry::file(file_path.file_name().unwrap(), file.data.len() as u64);
+            entry.attributes = file.attributes;
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+
+    /// Create a directory. Fails with `ACCESS_DENIED` if the name is taken
+    /// and `NOT_FOUND` if the parent does not exist.
+    fn mkdir(&self, path: &str) -> Result<(), Status> {
+        let normalized = UefiPathBuf::parse(path)?;
+        let mut volume = self.volume.borrow_mut();
+        volume.check_new_entry(&normalized)?;
+        volume.directories.push(normalized);
+        Ok(())
+    }
+
+    /// Delete a file or an empty directory.
+    ///
+    /// The root, non-empty directories and READ_ONLY files are refused with
+    /// `ACCESS_DENIED`. Handles still open on a deleted file fail to flush.
+    fn delete(&self, path: &str) -> Result<(), Status> {
+        let normalized = UefiPathBuf::parse(path)?;
+        let mut volume = self.volume.borrow_mut();
+        if volume.write_protected {
+            return Err(Status::WRITE_PROTECTED);
+        }
+
+        if let Some(file) = volume.files.get(&normalized) {
+            if file.attributes.is_read_only() {
+                return Err(Status::ACCESS_DENIED);
+            }
+            volume.files.remove(&normalized);
+            return Ok(());
+        }
+
+        if !volume.is_directory(&normalized) {
+            return Err(Status::NOT_FOUND);
+        }
+        if normalized.is_root() || volume.has_children(&normalized) {
+            return Err(Status::ACCESS_DENIED);
+        }
+        volume.directories.retain(|dir| dir != &normalized);
+        Ok(())
+    }
+
+    /// Move a file or directory (with everything below it) to a new path.
+    /// Changing only the case of a name is a rename too.
+    fn rename(&self, from: &str, to: &str) -> Result<(), Status> {
+        let from = UefiPathBuf::parse(from)?;
+        let to = UefiPathBuf::parse(to)?;
+        let mut volume = self.volume.borrow_mut();
+
+        let Some(stored) = volume.stored_path(&from) else {
+            return Err(Status::NOT_FOUND);
         };
+        if from.is_root() || (to.starts_with(&from) && to != from) {
+            return Err(Status::ACCESS_DENIED);
+        }
+        if stored.as_str() == to.as_str() {
+            return Ok(());
+        }
+        if from != to {
+            volume.check_new_entry(&to)?;
+        } else if volume.write_protected {
+            return Err(Status::WRITE_PROTECTED);
+        }
 
-        // Find subdirectories
-        for dir in &self.directories {
-            if dir.starts_with(&prefix) && dir != &normalized {
-                let relative = &dir[prefix.len()..];
-                if !relative.contains('\\') {
-                    entries.push(DirEntry::directory(relative));
-                }
+        // Re-key the entry and everything below it; the new keys are built
+        // first so a path growing past the length limit changes nothing
+        let moved = |path: &UefiPathBuf| match path.strip_prefix(&from) {
+            Some(rest) => to.join(rest),
+            None => Ok(path.clone()),
+        };
+        let directories = volume
+            .directories
+            .iter()
+            .map(moved)
+            .collect::<Result<Vec<_>, _>>()?;
+        let keys = volume
+            .files
+            .keys()
+            .map(|path| Ok((path.clone(), moved(path)?)))
+            .collect::<Result<Vec<_>, path::PathError>>()?;
+        volume.directories = directories;
+        let mut files = std::mem::take(&mut volume.files);
+        volume.files = keys
+            .into_iter()
+            .map(|(old, new)| (new, files.remove(&old).unwrap()))
+            .collect();
+        Ok(())
+    }
+
+    /// EFI_FILE_PROTOCOL.GetInfo for EFI_FILE_INFO.
+    fn get_info(&self, path: &str) -> Result<FileInfo, Status> {
+        let normalized = UefiPathBuf::parse(path)?;
+        let volume = self.volume.borrow();
+        let name = normalized.file_name().unwrap_or("");
+
+        if let Some(file) = volume.files.get(&normalized) {
+            let mut info = FileInfo::new(name, file.data.len() as u64, file.attributes);
+            info.create_time = file.create_time;
+            info.last_access_time = file.last_access_time;
+            info.modification_time = file.modification_time;
+            Ok(info)
+        } else if volume.is_directory(&normalized) {
+            Ok(FileInfo::new(name, 0, FileAttributes::new().directory()))
+        } else {
+            Err(Status::NOT_FOUND)
+        }
+    }
+
+    /// EFI_FILE_PROTOCOL.SetInfo for EFI_FILE_INFO.
+    ///
+    /// A changed `filename` renames the entry (a bare name stays in the same
+    /// directory, a name starting with `\\` is a full path). For files,
+    /// `file_size` truncates or zero-extends the data and the attributes and
+    /// timestamps are stored; a zeroed timestamp is left unchanged, any
+    /// other must pass SetTime's range checks. Directories only support
+    /// renaming, and the DIRECTORY attribute can never be toggled.
+    fn set_info(&self, path: &str, info: &FileInfo) -> Result<(), Status> {
+        let normalized = UefiPathBuf::parse(path)?;
+        if info.attributes.0 & !FileAttributes::VALID_ATTR != 0 {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        let times = [
+            info.create_time,
+            info.last_access_time,
+            info.modification_time,
+        ];
+        if times
+            .iter()
+            .any(|time| !time.is_unset() && !time.is_valid())
+        {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        if self.volume.borrow().write_protected {
+            return Err(Status::WRITE_PROTECTED);
+        }
+
+        let current = self.get_info(normalized.as_str())?;
+        if current.attributes.is_directory() != info.attributes.is_directory() {
+            return Err(Status::ACCESS_DENIED);
+        }
+        if current.attributes.is_directory() && info.file_size != 0 {
+            return Err(Status::ACCESS_DENIED);
+        }
+        if current.attributes.is_read_only() && info.file_size != current.file_size {
+            return Err(Status::ACCESS_DENIED);
+        }
+        if info.file_size > current.file_size {
+            let volume = self.volume.borrow();
+            let room = volume
+                .files
+                .get(&normalized)
+                .map_or(0, |file| volume.room_for(file));
+            if info.file_size > room {
+                return Err(Status::VOLUME_FULL);
             }
         }
 
-        // Find files
-        for (file_path, data) in &self.files {
-            if file_path.starts_with(&prefix) {
-                let relative = &file_path[prefix.len()..];
-                if !relative.contains('\\') {
-                    entries.push(DirEntry::file(relative, data.len() as u64));
+        let target = normalized
+            .parent()
+            .unwrap_or(UefiPath::root())
+            .join(&info.filename)?;
+        if target.as_str() != normalized.as_str() {
+            self.rename(normalized.as_str(), target.as_str())?;
+        }
+
+        let mut volume = self.volume.borrow_mut();
+        if let Some(file) = volume.files.get_mut(&target) {
+            file.data.resize(info.file_size as usize, 0);
+            file.attributes = info.attributes;
+            let stored = [
+                &mut file.create_time,
+                &mut file.last_access_time,
+                &mut file.modification_time,
+            ];
+            for (slot, time) in stored.into_iter().zip(times) {
+                if !time.is_unset() {
+                    *slot = time;
                 }
             }
         }
+        Ok(())
+    }
 
-        Ok(entries)
+    /// EFI_FILE_PROTOCOL.GetInfo for EFI_FILE_SYSTEM_INFO. The simulated
+    /// volume has a fixed capacity; used space is the sum of file sizes
+    /// rounded up to 4 KiB clusters.
+    fn get_fs_info(&self) -> FileSystemInfo {
+        let volume = self.volume.borrow();
+        FileSystemInfo {
+            read_only: volume.write_protected,
+            volume_size: Volume::VOLUME_SIZE,
+            free_space: volume.free_space(),
+            block_size: Volume::CLUSTER_SIZE as u32,
+            volume_label: "SIMULATED".to_string(),
+        }
+    }
+
+    /// Simulate a write-protected medium (a locked SD card, a read-only
+    /// image). Every mutating operation then fails with `WRITE_PROTECTED`.
+    fn set_write_protected(&self, write_protected: bool) {
+        self.volume.borrow_mut().write_protected = write_protected;
+    }
+
+    /// Set the time stamped on files created or modified from now on.
+    fn set_time(&self, now: EfiTime) {
+        self.volume.borrow_mut().clock = now;
     }
 }
 
@@ -784,6 +1628,303 @@ fn simulated_filesystem() {
         Ok(_) => println!("    Unexpected success"),
         Err(e) => println!("    Error: {}", e.description()),
     }
+
+    writable_filesystem(&fs);
+}
+
+fn print_status(operation: &str, result: Result<(), Status>) {
+    let status = result.err().unwrap_or(Status::SUCCESS);
+    let marker = if status.is_success() { "ok" } else { "!!" };
+    println!("    [{}] {}: {}", marker, operation, status.description());
+}
+
+fn writable_filesystem(fs: &SimpleFilesystem) {
+    println!("\n  Writing through handles:");
+    fs.set_time(EfiTime::new(2024, 3, 1, 8, 0, 0));
+
+    let mode = OpenMode::from_flags(0x8000000000000003).unwrap();
+    let mut config = fs.open("\\EFI\\BOOT\\grub.cfg", mode).unwrap();
+    config.write(b"set timeout=5\n").unwrap();
+    println!(
+        "    Before close, grub.cfg holds {} bytes",
+        fs.get_info("\\EFI\\BOOT\\grub.cfg").unwrap().file_size
+    );
+    config.close();
+    let info = fs.get_info("\\EFI\\BOOT\\grub.cfg").unwrap();
+    println!(
+        "    After close:  {} bytes, modified {}, {}",
+        info.file_size, info.modification_time, info.attributes
+    );
+
+    let mut reader = fs.open("\\startup.nsh", OpenMode::Read).unwrap();
+    print_status("write via Read handle", reader.write(b"x").map(|_| ()));
+    print_status("open with flags 0x2", OpenMode::from_flags(0x2).map(|_| ()));
+
+    println!("\n  Directory and metadata operations:");
+    print_status("mkdir \\EFI\\ubuntu", fs.mkdir("\\EFI\\ubuntu"));
+    print_status("mkdir \\EFI\\ubuntu again", fs.mkdir("\\EFI\\ubuntu"));
+    print_status(
+        "rename grub.cfg into \\EFI\\ubuntu",
+        fs.rename("\\EFI\\BOOT\\grub.cfg", "\\EFI\\ubuntu\\grub.cfg"),
+    );
+    print_status("delete non-empty \\EFI\\ubuntu", fs.delete("\\EFI\\ubuntu"));
+
+    let mut info = fs.get_info("\\EFI\\ubuntu\\grub.cfg").unwrap();
+    info.file_size = 3;
+    info.attributes = info.attributes.read_only();
+    print_status(
+        "set_info size=3 READ_ONLY",
+        fs.set_info("\\EFI\\ubuntu\\grub.cfg", &info),
+    );
+    print_status(
+        "open READ_ONLY file for write",
+        fs.open("\\EFI\\ubuntu\\grub.cfg", OpenMode::ReadWrite)
+            .map(|_| ()),
+    );
+
+    fs.set_write_protected(true);
+    print_status("mkdir on write-protected volume", fs.mkdir("\\tmp"));
+    fs.set_write_protected(false);
+
+    println!("\n  \\EFI\\ubuntu directory contents:");
+    for entry in fs.list_directory("\\EFI\\ubuntu").unwrap() {
+        println!(
+            "    {:12} {:>4} bytes  {}",
+            entry.name, entry.size, entry.attributes
+        );
+    }
+
+    let info = fs.get_fs_info();
+    println!(
+        "\n  Volume {:?}: {} KiB free of {} KiB",
+        info.volume_label,
+        info.free_space / 1024,
+        info.volume_size / 1024
+    );
+}
+
+// ============================================
+// FAT Image Backend
+// ============================================
+
+/// Print a directory tree, depth first, skipping `.` and `..`.
+fn print_tree<D>(fs: &fat::FatFilesystem<D>, path: &str, depth: usize)
+where
+    D: std::io::Read + std::io::Write + std::io::Seek + 'static,
+{
+    let Ok(entries) = fs.list_directory(path) else {
+        return;
+    };
+    for entry in entries {
+        if entry.name == "." || entry.name == ".." {
+            continue;
+        }
+        let child = format!("{}\\{}", path.trim_end_matches('\\'), entry.name);
+        if entry.attributes.is_directory() {
+            println!("    {}{}\\", "  ".repeat(depth), entry.name);
+            print_tree(fs, &child, depth + 1);
+        } else {
+            println!(
+                "    {}{} ({} bytes)",
+                "  ".repeat(depth),
+                entry.name,
+                entry.size
+            );
+        }
+    }
+}
+
+fn print_volume_summary<D>(fs: &fat::FatFilesystem<D>)
+where
+    D: std::io::Read + std::io::Write + std::io::Seek + 'static,
+{
+    println!(
+        "  {:?} volume {:?}, {} free clusters of {} bytes",
+        fs.fat_type(),
+        fs.volume_label().unwrap_or_default(),
+        fs.free_clusters(),
+        fs.cluster_size()
+    );
+    if let Ok(info) = fs.get_fs_info() {
+        println!(
+            "  EFI_FILE_SYSTEM_INFO: {} of {} bytes free ({} byte encoding)",
+            info.free_space,
+            info.volume_size,
+            info.encoded_size()
+        );
+    }
+    print_tree(fs, "\\", 0);
+}
+
+/// Inspect the image given on the command line (`cargo run -- disk.img`):
+/// a whole disk with a GPT, or a bare FAT volume.
+fn inspect_image(path: &str) {
+    let file = match std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+    {
+        Ok(file) => file,
+        Err(e) => {
+            println!("  {}: {}", path, e);
+            return;
+        }
+    };
+    match gpt::mount_esp(file) {
+        Ok((disk, fs)) => {
+            print_partition_table(&disk);
+            println!();
+            print_volume_summary(&fs);
+        }
+        Err(gpt::GptError::NoProtectiveMbr) => match fat::FatFilesystem::open_image(path) {
+            Ok(fs) => print_volume_summary(&fs),
+            Err(e) => println!("  Neither a GPT disk nor a FAT volume: {}", e.description()),
+        },
+        Err(e) => println!("  {}", e),
+    }
+}
+
+/// Build an ESP image in memory and read it back.
+fn fat_image_backend() {
+    // 16 MiB with 2 KiB clusters: FAT16
+    let image = fat::format_volume(32768, 4, fat::FatType::Fat16, "EFI SYSTEM").unwrap();
+    let fs = fat::FatFilesystem::mount(std::io::Cursor::new(image), 0).unwrap();
+    fs.set_time(EfiTime::new(2024, 5, 20, 14, 2, 30));
+    println!("  Formatted a blank image:");
+    print_volume_summary(&fs);
+
+    fs.mkdir("\\EFI").unwrap();
+    fs.mkdir("\\EFI\\BOOT").unwrap();
+    let mut loader = fs
+        .open("\\EFI\\BOOT\\BOOTX64.EFI", OpenMode::Create)
+        .unwrap();
+    let mut pe = vec![0u8; 10_000];
+    pe[..2].copy_from_slice(b"MZ");
+    loader.write(&pe).unwrap();
+    loader.close();
+    let mut script = fs.open("\\startup.nsh", OpenMode::Create).unwrap();
+    script.write(b"fs0:\\EFI\\BOOT\\BOOTX64.EFI").unwrap();
+    script.close();
+
+    // Remount from the raw bytes, as firmware would see them
+    let image = fs.unmount().ok().unwrap().into_inner();
+    let fs = fat::FatFilesystem::mount(std::io::Cursor::new(image), 0).unwrap();
+    println!("\n  Image contents after remount:");
+    print_tree(&fs, "\\", 0);
+
+    for path in [
+        "\\efi\\boot",
+        "\\EFI\\BOOT\\..\\..\\startup.nsh",
+        "\\missing",
+    ] {
+        println!(
+            "    {} - exists: {}, directory: {}",
+            path,
+            fs.exists(path),
+            fs.is_directory(path)
+        );
+    }
+
+    let info = fs.get_info("/efi/boot/bootx64.efi").unwrap();
+    println!(
+        "\n  {}: {} bytes on disk as {} bytes, modified {}, {}",
+        info.filename, info.file_size, info.physical_size, info.modification_time, info.attributes
+    );
+    let mut reader = fs.open("\\STARTUP.NSH", OpenMode::Read).unwrap();
+    let mut buffer = [0u8; 64];
+    let read = reader.read(&mut buffer).unwrap();
+    println!(
+        "  startup.nsh (found case-insensitively): {}",
+        String::from_utf8_lossy(&buffer[..read])
+    );
+}
+
+// ============================================
+// GPT Partition Table
+// ============================================
+
+fn print_partition_table(disk: &gpt::GptDisk) {
+    println!(
+        "  Disk {} ({}-byte blocks, usable LBA {}..={})",
+        disk.header.disk_guid,
+        disk.block_size,
+        disk.header.first_usable_lba,
+        disk.header.last_usable_lba
+    );
+    for partition in &disk.partitions {
+        println!(
+            "    #{} {:>6}..={:<6} {:>6} KiB  {:16} {:?}",
+            partition.index,
+            partition.first_lba,
+            partition.last_lba,
+            partition.sectors().unwrap_or(0) * disk.block_size / 1024,
+            gpt::partition_type_name(&partition.type_guid),
+            partition.name
+        );
+    }
+    if disk.is_healthy() {
+        println!("    Primary and backup tables verified");
+    }
+    for issue in &disk.issues {
+        println!("    warning: {}", issue);
+    }
+}
+
+fn gpt_partition_table() {
+    // 20 MiB disk: a 16 MiB ESP at the customary 1 MiB offset, then Linux
+    let mut disk = vec![0u8; 40960 * 512];
+    let esp = fat::format_volume(32768, 4, fat::FatType::Fat16, "ESP").unwrap();
+    disk[2048 * 512..][..esp.len()].copy_from_slice(&esp);
+    let partition = |index, type_guid, first_lba, last_lba, name: &str| gpt::GptPartition {
+        index,
+        type_guid,
+        unique_guid: gpt::Guid::new(0x5EED_0000 + index as u32, 0, 0, [0; 8]),
+        first_lba,
+        last_lba,
+        attributes: 0,
+        name: name.to_string(),
+    };
+    let partitions = [
+        partition(
+            0,
+            gpt::EFI_SYSTEM_PARTITION_GUID,
+            2048,
+            34815,
+            "EFI System Partition",
+        ),
+        partition(1, gpt::LINUX_FILESYSTEM_GUID, 34816, 40926, "root"),
+    ];
+    let disk_guid = gpt::Guid::new(0xD15C0000, 0x1234, 0x5678, [0x9A, 0xBC, 0, 0, 0, 0, 0, 1]);
+    gpt::write_gpt(&mut disk, 512, disk_guid, &partitions).unwrap();
+
+    let (table, fs) = gpt::mount_esp(std::io::Cursor::new(disk)).unwrap();
+    print_partition_table(&table);
+    println!(
+        "\n  ESP at byte offset {}:",
+        table.byte_offset(table.esp().unwrap()).unwrap()
+    );
+    fs.mkdir("\\EFI").unwrap();
+    fs.mkdir("\\EFI\\BOOT").unwrap();
+    let mut loader = fs
+        .open("\\EFI\\BOOT\\BOOTX64.EFI", OpenMode::Create)
+        .unwrap();
+    loader.write(b"MZ").unwrap();
+    loader.close();
+    print_volume_summary(&fs);
+
+    // Damage the primary header: the backup keeps the disk readable
+    let mut disk = fs.unmount().ok().unwrap().into_inner();
+    disk[512 + 48] ^= 0x01;
+    println!("\n  After corrupting the primary header:");
+    match gpt::GptDisk::read(&mut std::io::Cursor::new(&disk)) {
+        Ok(table) => {
+            println!("    read from LBA {}", table.header.my_lba);
+            for issue in &table.issues {
+                println!("    warning: {}", issue);
+            }
+        }
+        Err(e) => println!("    unreadable: {}", e),
+    }
 }
 
 #[cfg(test)]
@@ -816,18 +1957,46 @@ mod tests {
 
     #[test]
     fn test_path_operations() {
-        assert_eq!(normalize_path("/EFI/BOOT"), "\\EFI\\BOOT");
-        assert_eq!(normalize_path("\\\\EFI\\\\BOOT"), "\\EFI\\BOOT");
+        let parse = |path| UefiPathBuf::parse(path).unwrap();
+        assert_eq!(parse("/EFI/BOOT").as_str(), "\\EFI\\BOOT");
+        assert_eq!(parse("\\\\EFI\\\\BOOT").as_str(), "\\EFI\\BOOT");
 
-        let components = split_path("\\EFI\\BOOT\\file.efi");
+        let path = parse("\\EFI\\BOOT\\file.efi");
+        let components: Vec<&str> = path.components().collect();
         assert_eq!(components, vec!["EFI", "BOOT", "file.efi"]);
 
+        assert_eq!(path.parent().unwrap().as_str(), "\\EFI\\BOOT");
+
+        assert_eq!(path.file_name(), Some("file.efi"));
+    }
+
+    #[test]
+    fn test_case_insensitive_lookup() {
+        let fs = SimpleFilesystem::new();
+        assert!(fs.exists("\\efi\\boot\\bootx64.efi"));
+        assert!(fs.is_directory("/Efi/Boot/"));
         assert_eq!(
-            parent_path("\\EFI\\BOOT\\file.efi"),
-            Some("\\EFI\\BOOT".to_string())
+            fs.open("\\EFI\\BOOT\\..\\..\\..", OpenMode::Read)
+                .map(|_| ()),
+            Err(Status::NOT_FOUND)
         );
-
-        assert_eq!(filename("\\EFI\\BOOT\\file.efi"), Some("file.efi"));
+        assert_eq!(fs.mkdir("\\EFI\\a:b"), Err(Status::INVALID_PARAMETER));
+        assert_eq!(fs.mkdir("\\efi\\BOOT"), Err(Status::ACCESS_DENIED));
+
+        // Opening with other casing reaches the same file and keeps its name
+        let mut file = fs.open("\\STARTUP.NSH", OpenMode::ReadWrite).unwrap();
+        file.write(b"!").unwrap();
+        file.close();
+        let entries = fs.list_directory("\\").unwrap();
+        assert!(entries.iter().any(|entry| entry.name == "startup.nsh"));
+
+        // A case-only rename changes the stored spelling
+        fs.rename("\\efi", "\\efi").unwrap();
+        let entries = fs.list_directory("\\").unwrap();
+        assert!(entries.iter().any(|entry| entry.name == "efi"));
+        assert!(fs.exists("\\efi\\BOOT\\BOOTX64.EFI"));
+        let info = fs.get_info("\\EFI\\BOOT\\BOOTX64.EFI").unwrap();
+        assert_eq!(info.filename, "BOOTX64.EFI");
     }
 
     #[test]
@@ -839,4 +2008,252 @@ mod tests {
         assert!(fs.is_directory("\\EFI\\BOOT"));
         assert!(!fs.is_directory("\\startup.nsh"));
     }
+
+    #[test]
+    fn test_writes_commit_on_flush_and_close() {
+        let fs = SimpleFilesystem::new();
+        let mut writer = fs.open("/EFI/BOOT/new.txt", OpenMode::Create).unwrap();
+        writer.write(b"hello").unwrap();
+
+        // Buffered until the handle commits
+        assert_eq!(fs.get_info("\\EFI\\BOOT\\new.txt").unwrap().file_size, 0);
+        writer.flush().unwrap();
+        assert_eq!(fs.get_info("\\EFI\\BOOT\\new.txt").unwrap().file_size, 5);
+
+        writer.write(b", world").unwrap();
+        drop(writer);
+
+        let mut reader = fs.open("\\EFI\\BOOT\\new.txt", OpenMode::Read).unwrap();
+        let mut buffer = [0u8; 32];
+        let read = reader.read(&mut buffer).unwrap();
+        assert_eq!(&buffer[..read], b"hello, world");
+
+        // Missing parent directory
+        assert_eq!(
+            fs.open("\\nope\\file.txt", OpenMode::Create).err(),
+            Some(Status::NOT_FOUND)
+        );
+    }
+
+    #[test]
+    fn test_open_mode_semantics() {
+        let fs = SimpleFilesystem::new();
+        let mut reader = fs.open("\\startup.nsh", OpenMode::Read).unwrap();
+        assert_eq!(reader.write(b"x"), Err(Status::WRITE_PROTECTED));
+
+        let mut dir = fs.open("\\EFI", OpenMode::ReadWrite).unwrap();
+        assert_eq!(dir.write(b"x"), Err(Status::UNSUPPORTED));
+
+        let mut info = fs.get_info("\\startup.nsh").unwrap();
+        info.attributes = info.attributes.read_only();
+        fs.set_info("\\startup.nsh", &info).unwrap();
+        assert_eq!(
+            fs.open("\\startup.nsh", OpenMode::ReadWrite).err(),
+            Some(Status::ACCESS_DENIED)
+        );
+
+        fs.set_write_protected(true);
+        assert_eq!(
+            fs.open("\\other.txt", OpenMode::Create).err(),
+            Some(Status::WRITE_PROTECTED)
+        );
+
+        assert_eq!(OpenMode::from_flags(0x1), Ok(OpenMode::Read));
+        assert_eq!(
+            OpenMode::from_flags(0x8000000000000001),
+            Err(Status::INVALID_PARAMETER)
+        );
+    }
+
+    #[test]
+    fn test_mkdir_rename_delete() {
+        let fs = SimpleFilesystem::new();
+        fs.mkdir("\\EFI\\tools").unwrap();
+        assert_eq!(fs.mkdir("\\EFI\\tools"), Err(Status::ACCESS_DENIED));
+        assert_eq!(fs.mkdir("\\a\\b"), Err(Status::NOT_FOUND));
+
+        let mut handle = fs
+            .open("\\EFI\\tools\\shell.efi", OpenMode::Create)
+            .unwrap();
+        handle.write(b"MZ").unwrap();
+
+        // The open handle follows its file when a parent directory moves
+        fs.rename("\\EFI\\tools", "\\EFI\\BOOT\\tools").unwrap();
+        handle.close();
+        assert_eq!(
+            fs.get_info("\\EFI\\BOOT\\tools\\shell.efi")
+                .unwrap()
+                .file_size,
+            2
+        );
+        assert!(!fs.exists("\\EFI\\tools"));
+        assert_eq!(
+            fs.rename("\\EFI", "\\EFI\\BOOT\\inner"),
+            Err(Status::ACCESS_DENIED)
+        );
+
+        assert_eq!(fs.delete("\\EFI\\BOOT\\tools"), Err(Status::ACCESS_DENIED));
+        fs.delete("\\EFI\\BOOT\\tools\\shell.efi").unwrap();
+        fs.delete("\\EFI\\BOOT\\tools").unwrap();
+        assert_eq!(fs.delete("\\"), Err(Status::ACCESS_DENIED));
+
+        // Writing to a deleted file has nowhere to commit
+        let mut orphan = fs.open("\\startup.nsh", OpenMode::ReadWrite).unwrap();
+        fs.delete("\\startup.nsh").unwrap();
+        orphan.write(b"x").unwrap();
+        assert_eq!(orphan.flush(), Err(Status::NOT_FOUND));
+    }
+
+    #[test]
+    fn test_set_info() {
+        let fs = SimpleFilesystem::new();
+        let mut info = fs.get_info("\\startup.nsh").unwrap();
+        info.file_size = 4;
+        info.filename = "boot.nsh".to_string();
+        info.modification_time = EfiTime::new(2030, 6, 1, 0, 0, 0);
+        fs.set_info("\\startup.nsh", &info).unwrap();
+
+        assert!(!fs.exists("\\startup.nsh"));
+        let updated = fs.get_info("\\boot.nsh").unwrap();
+        assert_eq!(updated.file_size, 4);
+        assert_eq!(updated.modification_time.year, 2030);
+
+        // Zeroed times are left alone, out-of-range ones rejected
+        info.modification_time = EfiTime::default();
+        fs.set_info("\\boot.nsh", &info).unwrap();
+        assert_eq!(
+            fs.get_info("\\boot.nsh").unwrap().modification_time.year,
+            2030
+        );
+        info.last_access_time.month = 13;
+        assert_eq!(
+            fs.set_info("\\boot.nsh", &info),
+            Err(Status::INVALID_PARAMETER)
+        );
+        info.last_access_time.month = 12;
+
+        info.attributes = FileAttributes(0x40);
+        assert_eq!(
+            fs.set_info("\\boot.nsh", &info),
+            Err(Status::INVALID_PARAMETER)
+        );
+        info.attributes = FileAttributes::new().directory();
+        assert_eq!(fs.set_info("\\boot.nsh", &info), Err(Status::ACCESS_DENIED));
+
+        // Growing past the free space fails instead of allocating
+        info.attributes = FileAttributes::new();
+        info.file_size = u64::MAX;
+        assert_eq!(fs.set_info("\\boot.nsh", &info), Err(Status::VOLUME_FULL));
+        let room = fs.get_fs_info().free_space + 4096;
+        info.file_size = room;
+        fs.set_info("\\boot.nsh", &info).unwrap();
+        assert_eq!(fs.get_fs_info().free_space, 0);
+        info.file_size = room + 1;
+        assert_eq!(fs.set_info("\\boot.nsh", &info), Err(Status::VOLUME_FULL));
+    }
+
+    #[test]
+    fn test_write_checks_free_space() {
+        let fs = SimpleFilesystem::new();
+        let mut file = fs.open("\\big.bin", OpenMode::Create).unwrap();
+        let free = fs.get_fs_info().free_space as usize;
+        assert_eq!(file.write(&vec![0; free + 1]), Err(Status::VOLUME_FULL));
+        assert_eq!(file.write(&vec![0; free]), Ok(free));
+        file.close();
+        assert_eq!(fs.get_fs_info().free_space, 0);
+    }
+
+    #[test]
+    fn test_efi_time_bytes() {
+        let time = EfiTime::new(2024, 2, 29, 23, 59, 58).with_zone(-300, EfiTime::ADJUST_DAYLIGHT);
+        let bytes = time.to_bytes();
+        assert_eq!(
+            bytes,
+            [0xE8, 0x07, 2, 29, 23, 59, 58, 0, 0, 0, 0, 0, 0xD4, 0xFE, 1, 0]
+        );
+        assert_eq!(EfiTime::from_bytes(&bytes), Ok(time));
+        assert_eq!(time.to_string(), "2024-02-29 23:59:58 UTC-05:00");
+
+        // 2023 is not a leap year, and month 13 does not exist
+        let mut invalid = bytes;
+        invalid[0..2].copy_from_slice(&2023u16.to_le_bytes());
+        assert_eq!(
+            EfiTime::from_bytes(&invalid),
+            Err(Status::INVALID_PARAMETER)
+        );
+        let mut invalid = bytes;
+        invalid[2] = 13;
+        assert_eq!(
+            EfiTime::from_bytes(&invalid),
+            Err(Status::INVALID_PARAMETER)
+        );
+        assert_eq!(
+            EfiTime::from_bytes(&bytes[..15]),
+            Err(Status::BAD_BUFFER_SIZE)
+        );
+    }
+
+    #[test]
+    fn test_file_info_layout() {
+        let fs = SimpleFilesystem::new();
+        let info = fs.get_info("\\startup.nsh").unwrap();
+
+        // Two-call protocol
+        let mut buffer_size = 0;
+        assert_eq!(
+            info.get_info(&mut buffer_size, None),
+            Status::BUFFER_TOO_SMALL
+        );
+        assert_eq!(buffer_size, 80 + 2 * ("startup.nsh".len() + 1));
+        assert_eq!(
+            info.get_info(&mut buffer_size, None),
+            Status::INVALID_PARAMETER
+        );
+        let mut buffer = vec![0xAAu8; buffer_size + 8];
+        let mut oversized = buffer.len();
+        assert_eq!(
+            info.get_info(&mut oversized, Some(&mut buffer)),
+            Status::SUCCESS
+        );
+        assert_eq!(oversized, buffer_size);
+
+        assert_eq!(&buffer[0..8], &(buffer_size as u64).to_le_bytes());
+        assert_eq!(&buffer[8..16], &info.file_size.to_le_bytes());
+        assert_eq!(&buffer[24..40], &info.create_time.to_bytes());
+        assert_eq!(&buffer[72..80], &info.attributes.0.to_le_bytes());
+        assert_eq!(&buffer[80..84], &[b's', 0, b't', 0]);
+        assert_eq!(&buffer[buffer_size - 2..buffer_size], &[0, 0]);
+        assert_eq!(&buffer[buffer_size..], &[0xAA; 8]);
+        assert_eq!(FileInfo::decode(&buffer), Ok(info.clone()));
+
+        // Unset (zero) times decode as-is
+        let mut unset = info;
+        unset.last_access_time = EfiTime::default();
+        assert_eq!(FileInfo::decode(&unset.to_bytes()), Ok(unset));
+
+        // Size larger than the buffer, then an unterminated name
+        assert_eq!(
+            FileInfo::decode(&buffer[..buffer_size - 1]),
+            Err(Status::BAD_BUFFER_SIZE)
+        );
+        buffer[buffer_size - 2] = b'!';
+        assert_eq!(FileInfo::decode(&buffer), Err(Status::INVALID_PARAMETER));
+    }
+
+    #[test]
+    fn test_file_system_info_layout() {
+        let fs = SimpleFilesystem::new();
+        fs.set_write_protected(true);
+        let info = fs.get_fs_info();
+        let bytes = info.to_bytes();
+
+        assert_eq!(bytes.len(), 36 + 2 * ("SIMULATED".len() + 1));
+        assert_eq!(&bytes[0..8], &(bytes.len() as u64).to_le_bytes());
+        assert_eq!(&bytes[8..16], &[1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&bytes[16..24], &info.volume_size.to_le_bytes());
+        assert_eq!(&bytes[24..32], &info.free_space.to_le_bytes());
+        assert_eq!(&bytes[32..36], &4096u32.to_le_bytes());
+        assert_eq!(&bytes[36..38], &[b'S', 0]);
+        assert_eq!(FileSystemInfo::decode(&bytes), Ok(info));
+    }
 }
@@ -0,0 +1,477 @@
+//! UEFI file paths
+//!
+//! EFI_FILE_PROTOCOL.Open takes a null-terminated CHAR16 path whose
+//! components are separated by `\`. A path starting with `\` is absolute;
+//! anything else is relative to the directory the handle was opened on.
+//! `.` and `..` are resolved by the filesystem, and names compare without
+//! regard to case, as FAT does.
+//!
+//! [`UefiPathBuf`] holds a path in normal form, and [`UefiPath`] borrows one
+//! (the same split as `PathBuf`/`Path`):
+//!
+//! ```text
+//!   "/EFI//Boot/./../BOOT/BOOTX64.EFI"   as typed
+//!   "\EFI\BOOT\BOOTX64.EFI"              normal form
+//! ```
+//!
+//! In normal form a path starts with a single `\`, has no empty, `.` or `..`
+//! components and no trailing separator; the root is `\`. Equality and
+//! hashing fold case, so a `HashMap<UefiPathBuf, _>` finds `\efi\boot` under
+//! `\EFI\BOOT` while still remembering how the name was spelled.
+
+use std::borrow::Borrow;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+use crate::Status;
+
+/// Longest component, in CHAR16 units (the VFAT long-name limit)
+pub const MAX_COMPONENT_LEN: usize = 255;
+
+/// Longest normalized path, in CHAR16 units without the terminator
+pub const MAX_PATH_LEN: usize = 4096;
+
+/// Characters FAT long names and the UEFI shell refuse inside a component
+const RESERVED_CHARS: &[char] = &['"', '*', ':', '<', '>', '?', '|'];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+    /// The normalized path is longer than [`MAX_PATH_LEN`].
+    TooLong { len: usize },
+    /// A component is longer than [`MAX_COMPONENT_LEN`].
+    ComponentTooLong { len: usize },
+    /// A control character or one of `" * : < > ? |`.
+    InvalidCharacter(char),
+    /// `..` would climb above the root.
+    AboveRoot,
+    /// A lone high or low surrogate in a CHAR16 string.
+    UnpairedSurrogate { index: usize, unit: u16 },
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathError::TooLong { len } => {
+                write!(f, "path is {} CHAR16s long, limit is {}", len, MAX_PATH_LEN)
+            }
+            PathError::ComponentTooLong { len } => write!(
+                f,
+                "component is {} CHAR16s long, limit is {}",
+                len, MAX_COMPONENT_LEN
+            ),
+            PathError::InvalidCharacter(c) => write!(f, "invalid character {:?}", c),
+            PathError::AboveRoot => write!(f, "'..' above the root directory"),
+            PathError::UnpairedSurrogate { index, unit } => {
+                write!(f, "unpaired surrogate 0x{:04X} at index {}", unit, index)
+            }
+        }
+    }
+}
+
+impl From<PathError> for Status {
+    fn from(error: PathError) -> Self {
+        match error {
+            PathError::AboveRoot => Status::NOT_FOUND,
+            _ => Status::INVALID_PARAMETER,
+        }
+    }
+}
+
+/// Encode as a null-terminated CHAR16 string.
+///
+/// Characters outside the Basic Multilingual Plane become surrogate pairs,
+/// so [`decode_ucs2`] gives back exactly the same string.
+pub fn encode_ucs2(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain([0]).collect()
+}
+
+/// Decode a CHAR16 string, stopping at the first null (or the end of the
+/// slice). Surrogate pairs are combined; a surrogate without its partner
+/// has no `char` to map to and is an error rather than being replaced.
+pub fn decode_ucs2(units: &[u16]) -> Result<String, PathError> {
+    let end = units
+        .iter()
+        .position(|&unit| unit == 0)
+        .unwrap_or(units.len());
+    let mut text = String::with_capacity(end);
+    let mut index = 0;
+    for decoded in char::decode_utf16(units[..end].iter().copied()) {
+        match decoded {
+            Ok(c) => {
+                text.push(c);
+                index += c.len_utf16();
+            }
+            Err(error) => {
+                return Err(PathError::UnpairedSurrogate {
+                    index,
+                    unit: error.unpaired_surrogate(),
+                })
+            }
+        }
+    }
+    Ok(text)
+}
+
+/// Upper-case one character the way a firmware upcase table does: one
+/// character in, one out. Characters whose upper case is longer (such as
+/// 'ß' -> "SS") are left alone.
+fn upcase(c: char) -> char {
+    let mut upper = c.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(u), None) => u,
+        _ => c,
+    }
+}
+
+/// Case-insensitive comparison of two names, character by character
+pub fn eq_ignore_case(a: &str, b: &str) -> bool {
+    a.chars().map(upcase).eq(b.chars().map(upcase))
+}
+
+fn check_component(component: &str) -> Result<(), PathError> {
+    if let Some(c) = component
+        .chars()
+        .find(|&c| c.is_control() || RESERVED_CHARS.contains(&c))
+    {
+        return Err(PathError::InvalidCharacter(c));
+    }
+    let len = component.encode_utf16().count();
+    if len > MAX_COMPONENT_LEN {
+        return Err(PathError::ComponentTooLong { len });
+    }
+    Ok(())
+}
+
+/// A borrowed path in normal form
+#[repr(transparent)]
+pub struct UefiPath {
+    inner: str,
+}
+
+impl UefiPath {
+    /// Callers guarantee `path` is already in normal form.
+    fn from_normalized(path: &str) -> &UefiPath {
+        // SAFETY: UefiPath is a repr(transparent) wrapper around str
+        unsafe { &*(path as *const str as *const UefiPath) }
+    }
+
+    pub fn root() -> &'static UefiPath {
+        UefiPath::from_normalized("\\")
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.inner
+    }
+
+    pub fn is_root(&self) -> bool {
+        &self.inner == "\\"
+    }
+
+    /// Names from the root down; empty for the root itself.
+    pub fn components(&self) -> impl Iterator<Item = &str> {
+        self.inner.split('\\').filter(|c| !c.is_empty())
+    }
+
+    pub fn file_name(&self) -> Option<&str> {
+        self.components().last()
+    }
+
+    /// The containing directory, or `None` for the root.
+    pub fn parent(&self) -> Option<&UefiPath> {
+        match self.inner.rfind('\\')? {
+            _ if self.is_root() => None,
+            0 => Some(UefiPath::root()),
+            index => Some(UefiPath::from_normalized(&self.inner[..index])),
+        }
+    }
+
+    /// Resolve `path` the way Open does on a handle for this directory: an
+    /// absolute path replaces it, a relative one is appended, `/` counts as
+    /// `\`, and `.` and `..` are resolved.
+    pub fn join(&self, path: &str) -> Result<UefiPathBuf, PathError> {
+        let path = path.replace('/', "\\");
+        let mut components: Vec<&str> = if path.starts_with('\\') {
+            Vec::new()
+        } else {
+            self.components().collect()
+        };
+        for component in path.split('\\') {
+            match component {
+                "" | "." => {}
+                ".." => {
+                    components.pop().ok_or(PathError::AboveRoot)?;
+                }
+                name => {
+                    check_component(name)?;
+                    components.push(name);
+                }
+            }
+        }
+
+        let normalized = format!("\\{}", components.join("\\"));
+        let len = normalized.encode_utf16().count();
+        if len > MAX_PATH_LEN {
+            return Err(PathError::TooLong { len });
+        }
+        Ok(UefiPathBuf { inner: normalized })
+    }
+
+    /// Is `self` equal to `base` or somewhere below it? Compares whole
+    /// components, ignoring case.
+    pub fn starts_with(&self, base: &UefiPath) -> bool {
+        self.strip_prefix(base).is_some()
+    }
+
+    /// The part of `self` below `base`, without a leading separator (empty
+    /// when the two are equal).
+    pub fn strip_prefix(&self, base: &UefiPath) -> Option<&str> {
+        let mut rest = self.components();
+        for expected in base.components() {
+            if !eq_ignore_case(rest.next()?, expected) {
+                return None;
+            }
+        }
+        let skipped = base.components().count();
+        let offset = self
+            .inner
+            .match_indices('\\')
+            .nth(skipped)
+            .map_or(self.inner.len(), |(index, _)| index + 1);
+        Some(&self.inner[offset..])
+    }
+
+    /// The CHAR16 string to hand to EFI_FILE_PROTOCOL.Open.
+    pub fn to_ucs2(&self) -> Vec<u16> {
+        encode_ucs2(&self.inner)
+    }
+}
+
+impl PartialEq for UefiPath {
+    fn eq(&self, other: &Self) -> bool {
+        eq_ignore_case(&self.inner, &other.inner)
+    }
+}
+
+impl Eq for UefiPath {}
+
+impl Hash for UefiPath {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for c in self.inner.chars().map(upcase) {
+            state.write_u32(c as u32);
+        }
+        state.write_u8(0xFF);
+    }
+}
+
+impl fmt::Debug for UefiPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl fmt::Display for UefiPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.inner)
+    }
+}
+
+impl ToOwned for UefiPath {
+    type Owned = UefiPathBuf;
+
+    fn to_owned(&self) -> UefiPathBuf {
+        UefiPathBuf {
+            inner: self.inner.to_string(),
+        }
+    }
+}
+
+/// An owned path in normal form
+#[derive(Clone)]
+pub struct UefiPathBuf {
+    inner: String,
+}
+
+impl UefiPathBuf {
+    /// Normalize a path relative to the root.
+    pub fn parse(path: &str) -> Result<Self, PathError> {
+        UefiPath::root().join(path)
+    }
+
+    /// Decode and normalize a CHAR16 path.
+    pub fn from_ucs2(units: &[u16]) -> Result<Self, PathError> {
+        Self::parse(&decode_ucs2(units)?)
+    }
+
+    pub fn as_path(&self) -> &UefiPath {
+        UefiPath::from_normalized(&self.inner)
+    }
+}
+
+impl Deref for UefiPathBuf {
+    type Target = UefiPath;
+
+    fn deref(&self) -> &UefiPath {
+        self.as_path()
+    }
+}
+
+impl Borrow<UefiPath> for UefiPathBuf {
+    fn borrow(&self) -> &UefiPath {
+        self.as_path()
+    }
+}
+
+impl PartialEq for UefiPathBuf {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_path() == other.as_path()
+    }
+}
+
+impl Eq for UefiPathBuf {}
+
+impl Hash for UefiPathBuf {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_path().hash(state);
+    }
+}
+
+impl fmt::Debug for UefiPathBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_path(), f)
+    }
+}
+
+impl fmt::Display for UefiPathBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_path(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn parse(path: &str) -> String {
+        UefiPathBuf::parse(path).unwrap().as_str().to_string()
+    }
+
+    #[test]
+    fn test_normalization() {
+        assert_eq!(parse(""), "\\");
+        assert_eq!(parse("/EFI//BOOT/"), "\\EFI\\BOOT");
+        assert_eq!(
+            parse("\\EFI\\.\\ubuntu\\..\\BOOT\\BOOTX64.EFI"),
+            "\\EFI\\BOOT\\BOOTX64.EFI"
+        );
+        assert_eq!(parse("EFI\\.."), "\\");
+        assert_eq!(
+            UefiPathBuf::parse("\\EFI\\..\\.."),
+            Err(PathError::AboveRoot)
+        );
+
+        let boot = UefiPathBuf::parse("\\EFI\\BOOT").unwrap();
+        assert_eq!(
+            boot.join("..\\ubuntu\\grub.cfg").unwrap().as_str(),
+            "\\EFI\\ubuntu\\grub.cfg"
+        );
+        assert_eq!(
+            boot.join("\\startup.nsh").unwrap().as_str(),
+            "\\startup.nsh"
+        );
+        assert_eq!(boot.parent().unwrap().as_str(), "\\EFI");
+        assert_eq!(boot.parent().unwrap().parent(), Some(UefiPath::root()));
+        assert_eq!(UefiPath::root().parent(), None);
+        assert_eq!(boot.file_name(), Some("BOOT"));
+        assert_eq!(boot.components().collect::<Vec<_>>(), ["EFI", "BOOT"]);
+    }
+
+    #[test]
+    fn test_limits_and_characters() {
+        assert_eq!(
+            UefiPathBuf::parse("\\EFI\\bo?t"),
+            Err(PathError::InvalidCharacter('?'))
+        );
+        assert_eq!(
+            UefiPathBuf::parse("\\a\tb"),
+            Err(PathError::InvalidCharacter('\t'))
+        );
+
+        let longest = "x".repeat(MAX_COMPONENT_LEN);
+        assert!(UefiPathBuf::parse(&longest).is_ok());
+        assert_eq!(
+            UefiPathBuf::parse(&format!("{}x", longest)),
+            Err(PathError::ComponentTooLong {
+                len: MAX_COMPONENT_LEN + 1
+            })
+        );
+
+        let deep = format!("\\{}", vec!["abcdefg"; MAX_PATH_LEN / 8].join("\\"));
+        assert_eq!(deep.len(), MAX_PATH_LEN);
+        assert!(UefiPathBuf::parse(&deep).is_ok());
+        assert_eq!(
+            UefiPathBuf::parse(&format!("{}\\ab", deep)),
+            Err(PathError::TooLong {
+                len: MAX_PATH_LEN + 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let upper = UefiPathBuf::parse("\\EFI\\BOOT\\BOOTX64.EFI").unwrap();
+        let lower = UefiPathBuf::parse("/efi/boot/bootx64.efi").unwrap();
+        assert_eq!(upper, lower);
+        assert_ne!(upper.as_str(), lower.as_str());
+
+        let mut map = HashMap::new();
+        map.insert(upper.clone(), 1);
+        assert_eq!(map.get(lower.as_path()), Some(&1));
+
+        let efi = UefiPathBuf::parse("\\efi").unwrap();
+        assert!(upper.starts_with(&efi));
+        assert_eq!(upper.strip_prefix(&efi), Some("BOOT\\BOOTX64.EFI"));
+        assert_eq!(upper.strip_prefix(&upper), Some(""));
+        assert_eq!(
+            upper.strip_prefix(UefiPath::root()),
+            Some("EFI\\BOOT\\BOOTX64.EFI")
+        );
+        assert!(!UefiPathBuf::parse("\\EFIX").unwrap().starts_with(&efi));
+
+        // Upcasing maps one character to one character
+        assert!(eq_ignore_case("grüße", "GRÜßE"));
+        assert!(!eq_ignore_case("ß", "SS"));
+        assert!(!eq_ignore_case("ﬁle", "FILE"));
+    }
+
+    #[test]
+    fn test_ucs2_round_trip() {
+        let path = UefiPathBuf::parse("\\Grüße\\日本\\😀.txt").unwrap();
+        let units = path.to_ucs2();
+        assert_eq!(units.last(), Some(&0));
+        assert_eq!(&units[..3], &[0x5C, 0x47, 0x72]);
+        assert_eq!(
+            UefiPathBuf::from_ucs2(&units).unwrap().as_str(),
+            path.as_str()
+        );
+
+        let lone_high = [0x5C, 0x61, 0xD83D, 0x62, 0];
+        assert_eq!(
+            decode_ucs2(&lone_high),
+            Err(PathError::UnpairedSurrogate {
+                index: 2,
+                unit: 0xD83D
+            })
+        );
+        let lone_low = [0x5C, 0xDE00];
+        assert_eq!(
+            UefiPathBuf::from_ucs2(&lone_low).map(|_| ()),
+            Err(PathError::UnpairedSurrogate {
+                index: 1,
+                unit: 0xDE00
+            })
+        );
+        assert_eq!(decode_ucs2(&[0x41, 0, 0x42]).unwrap(), "A");
+    }
+}
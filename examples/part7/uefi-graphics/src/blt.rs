@@ -0,0 +1,498 @@
+//! EFI_GRAPHICS_OUTPUT_PROTOCOL.Blt
+//!
+//! Moves rectangles of pixels between the framebuffer ("video") and a
+//! caller's array of [`BltPixel`]s, converting between the BGRX layout of
+//! `BltPixel` and whatever the current mode's pixel format is.
+//!
+//! ```text
+//!   VideoFill          buffer[0] ──────────────► video (dst, w x h)
+//!   VideoToBltBuffer   video (src) ────────────► buffer (dst, delta)
+//!   BufferToVideo      buffer (src, delta) ────► video (dst)
+//!   VideoToVideo       video (src) ────────────► video (dst), may overlap
+//! ```
+//!
+//! `delta` is the length of one buffer row in bytes, so a sub-rectangle of
+//! a larger image can be transferred; 0 means the buffer is exactly `width`
+//! pixels wide.
+
+use crate::{BltOperation, BltPixel, GraphicsOutput, ModeInfo, PixelBitmask, PixelFormat, Status};
+
+const BLT_PIXEL_SIZE: usize = 4;
+
+impl TryFrom<u32> for BltOperation {
+    type Error = Status;
+
+    /// EFI_GRAPHICS_OUTPUT_BLT_OPERATION values; anything else is
+    /// `INVALID_PARAMETER`, as Blt reports it.
+    fn try_from(value: u32) -> Result<Self, Status> {
+        match value {
+            0 => Ok(BltOperation::VideoFill),
+            1 => Ok(BltOperation::VideoToBltBuffer),
+            2 => Ok(BltOperation::BufferToVideo),
+            3 => Ok(BltOperation::VideoToVideo),
+            _ => Err(Status::INVALID_PARAMETER),
+        }
+    }
+}
+
+impl PixelBitmask {
+    /// Each color mask is one non-empty run of bits and no two masks
+    /// (including reserved) overlap.
+    pub fn is_valid(&self) -> bool {
+        let contiguous = |mask: u32| {
+            let run = mask >> mask.trailing_zeros();
+            mask != 0 && run & run.wrapping_add(1) == 0
+        };
+        let masks = [
+            self.red_mask,
+            self.green_mask,
+            self.blue_mask,
+            self.reserved_mask,
+        ];
+        let total_bits: u32 = masks.iter().map(|mask| mask.count_ones()).sum();
+        let union = masks.iter().fold(0, |union, mask| union | mask);
+        contiguous(self.red_mask)
+            && contiguous(self.green_mask)
+            && contiguous(self.blue_mask)
+            && total_bits == union.count_ones()
+    }
+}
+
+/// Scale an 8-bit channel into `mask`, keeping the most significant bits.
+fn pack_channel(value: u8, mask: u32) -> u32 {
+    let bits = mask.count_ones();
+    let scaled = if bits >= 8 {
+        (value as u32) << (bits - 8)
+    } else {
+        value as u32 >> (8 - bits)
+    };
+    (scaled << mask.trailing_zeros()) & mask
+}
+
+/// Widen the channel under `mask` to 8 bits, replicating narrow channels so
+/// that full intensity stays 0xFF.
+pub(crate) fn unpack_channel(raw: u32, mask: u32) -> u8 {
+    let bits = mask.count_ones();
+    let value = (raw & mask) >> mask.trailing_zeros();
+    if bits >= 8 {
+        return (value >> (bits - 8)) as u8;
+    }
+    let mut widened = 0u32;
+    let mut filled = 0;
+    while filled < 8 {
+        widened = (widened << bits) | value;
+        filled += bits;
+    }
+    (widened >> (filled - 8)) as u8
+}
+
+impl BltPixel {
+    /// The framebuffer value for this pixel in `mode`'s format. BltOnly
+    /// modes keep the BltPixel layout.
+    pub fn to_raw(self, mode: &ModeInfo) -> Result<u32, Status> {
+        let [b, g, r] = [self.blue as u32, self.green as u32, self.red as u32];
+        match mode.pixel_format {
+            PixelFormat::RedGreenBlueReserved8BitPerColor => Ok(r | (g << 8) | (b << 16)),
+            PixelFormat::BlueGreenRedReserved8BitPerColor | PixelFormat::BltOnly => {
+                Ok(b | (g << 8) | (r << 16))
+            }
+            PixelFormat::BitMask => {
+                let mask = mode.valid_bitmask()?;
+                Ok(pack_channel(self.red, mask.red_mask)
+                    | pack_channel(self.green, mask.green_mask)
+                    | pack_channel(self.blue, mask.blue_mask))
+            }
+        }
+    }
+
+    pub fn from_raw(raw: u32, mode: &ModeInfo) -> Result<Self, Status> {
+        let byte = |shift: u32| (raw >> shift) as u8;
+        match mode.pixel_format {
+            PixelFormat::RedGreenBlueReserved8BitPerColor => {
+                Ok(BltPixel::new(byte(0), byte(8), byte(16)))
+            }
+            PixelFormat::BlueGreenRedReserved8BitPerColor | PixelFormat::BltOnly => {
+                Ok(BltPixel::new(byte(16), byte(8), byte(0)))
+            }
+            PixelFormat::BitMask => {
+                let mask = mode.valid_bitmask()?;
+                Ok(BltPixel::new(
+                    unpack_channel(raw, mask.red_mask),
+                    unpack_channel(raw, mask.green_mask),
+                    unpack_channel(raw, mask.blue_mask),
+                ))
+            }
+        }
+    }
+}
+
+impl ModeInfo {
+    /// The bitmask of a BitMask mode; a missing or malformed one makes the
+    /// mode unusable.
+    fn valid_bitmask(&self) -> Result<PixelBitmask, Status> {
+        self.pixel_bitmask
+            .filter(PixelBitmask::is_valid)
+            .ok_or(Status::UNSUPPORTED)
+    }
+}
+
+/// A `width` x `height` rectangle at (`x`, `y`) inside a buffer whose rows
+/// are `row_pixels` long; returns the index of its first pixel.
+fn check_buffer_rect(
+    len: usize,
+    (x, y): (u32, u32),
+    width: u32,
+    height: u32,
+    row_pixels: usize,
+) -> Result<usize, Status> {
+    let (x, y) = (x as usize, y as usize);
+    let row_end = x
+        .checked_add(width as usize)
+        .filter(|&row_end| row_end <= row_pixels)
+        .ok_or(Status::INVALID_PARAMETER)?;
+    (height as usize)
+        .checked_sub(1)
+        .and_then(|rows| y.checked_add(rows))
+        .and_then(|last_row| last_row.checked_mul(row_pixels))
+        .and_then(|last_start| last_start.checked_add(row_end))
+        .filter(|&end| end <= len)
+        .ok_or(Status::INVALID_PARAMETER)?;
+    // The first row starts before the last one ends, so this cannot overflow
+    Ok(y * row_pixels + x)
+}
+
+impl GraphicsOutput {
+    /// Blt(): transfer a `width` x `height` rectangle.
+    ///
+    /// `src` and `dst` are (x, y) pairs in the framebuffer or the buffer,
+    /// depending on `op`; `delta` is the buffer row length in bytes (0 for
+    /// `width * 4`). A rectangle that leaves the screen or the buffer, an
+    /// empty rectangle, a missing fill pixel or a `delta` that is not a
+    /// whole number of pixels is `INVALID_PARAMETER`, and nothing is drawn.
+    #[allow(clippy::too_many_arguments)]
+    pub fn blt(
+        &mut self,
+        op: BltOperation,
+        buffer: &mut [BltPixel],
+        src: (u32, u32),
+        dst: (u32, u32),
+        width: u32,
+        height: u32,
+        delta: usize,
+    ) -> Result<(), Status> {
+        if width == 0 || height == 0 {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        let delta = if delta == 0 {
+            width as usize * BLT_PIXEL_SIZE
+        } else {
+            delta
+        };
+        if delta % BLT_PIXEL_SIZE != 0 {
+            return Err(Status::INVALID_PARAMETER);
+        }
+        let row_pixels = delta / BLT_PIXEL_SIZE;
+        let mode = self.current_mode_info().clone();
+        let stride = mode.pixels_per_scan_line as usize;
+        let on_screen = |(x, y): (u32, u32)| {
+            x as u64 + width as u64 <= mode.horizontal_resolution as u64
+                && y as u64 + height as u64 <= mode.vertical_resolution as u64
+        };
+        let video_index = |(x, y): (u32, u32), row: u32| (y + row) as usize * stride + x as usize;
+
+        match op {
+            BltOperation::VideoFill => {
+                let fill = buffer.first().ok_or(Status::INVALID_PARAMETER)?;
+                if !on_screen(dst) {
+                    return Err(Status::INVALID_PARAMETER);
+                }
+                let raw = fill.to_raw(&mode)?;
+                for row in 0..height {
+                    let start = video_index(dst, row);
+                    self.framebuffer[start..start + width as usize].fill(raw);
+                }
+            }
+            BltOperation::VideoToBltBuffer => {
+                if !on_screen(src) {
+                    return Err(Status::INVALID_PARAMETER);
+                }
+                let first = check_buffer_rect(buffer.len(), dst, width, height, row_pixels)?;
+                for row in 0..height {
+                    let video = video_index(src, row);
+                    let start = first + row as usize * row_pixels;
+                    for (pixel, &raw) in buffer[start..start + width as usize]
+                        .iter_mut()
+                        .zip(&self.framebuffer[video..video + width as usize])
+                    {
+                        *pixel = BltPixel::from_raw(raw, &mode)?;
+                    }
+                }
+            }
+            BltOperation::BufferToVideo => {
+                if !on_screen(dst) {
+                    return Err(Status::INVALID_PARAMETER);
+                }
+                let first = check_buffer_rect(buffer.len(), src, width, height, row_pixels)?;
+                for row in 0..height {
+                    let video = video_index(dst, row);
+                    let start = first + row as usize * row_pixels;
+                    for (raw, pixel) in self.framebuffer[video..video + width as usize]
+                        .iter_mut()
+                        .zip(&buffer[start..start + width as usize])
+                    {
+                        *raw = pixel.to_raw(&mode)?;
+                    }
+                }
+            }
+            BltOperation::VideoToVideo => {
+                if !on_screen(src) || !on_screen(dst) {
+                    return Err(Status::INVALID_PARAMETER);
+                }
+                // Copy rows away from the overlap: bottom-up when moving
+                // down, so no source row is overwritten before it is read.
+                // Within a row copy_within already behaves like memmove.
+                let copy_row = |framebuffer: &mut Vec<u32>, row: u32| {
+                    let from = video_index(src, row);
+                    framebuffer.copy_within(from..from + width as usize, video_index(dst, row));
+                };
+                if dst.1 > src.1 {
+                    (0..height)
+                        .rev()
+                        .for_each(|row| copy_row(&mut self.framebuffer, row));
+                } else {
+                    (0..height).for_each(|row| copy_row(&mut self.framebuffer, row));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gop(mode: ModeInfo) -> GraphicsOutput {
+        let mut gop = GraphicsOutput::with_modes(vec![mode]);
+        gop.set_mode(0).unwrap();
+        gop
+    }
+
+    fn bgr(width: u32, height: u32) -> ModeInfo {
+        ModeInfo::new(width, height, PixelFormat::BlueGreenRedReserved8BitPerColor)
+    }
+
+    /// Read the whole screen back through VideoToBltBuffer.
+    fn screen(gop: &mut GraphicsOutput) -> Vec<BltPixel> {
+        let mode = gop.current_mode_info().clone();
+        let (width, height) = (mode.horizontal_resolution, mode.vertical_resolution);
+        let mut pixels = vec![BltPixel::default(); (width * height) as usize];
+        gop.blt(
+            BltOperation::VideoToBltBuffer,
+            &mut pixels,
+            (0, 0),
+            (0, 0),
+            width,
+            height,
+            0,
+        )
+        .unwrap();
+        pixels
+    }
+
+    #[test]
+    fn test_fill_and_buffer_transfers_with_delta() {
+        // Padded scan lines: stride 12 for an 8-pixel-wide mode
+        let mut gop = gop(bgr(8, 6).with_stride(12));
+        let orange = BltPixel::new(255, 128, 0);
+        gop.blt(
+            BltOperation::VideoFill,
+            &mut [orange],
+            (0, 0),
+            (1, 1),
+            3,
+            2,
+            0,
+        )
+        .unwrap();
+        assert_eq!(gop.framebuffer[12 + 1], 0x00FF8000);
+        assert_eq!(gop.framebuffer[2 * 12 + 3], 0x00FF8000);
+        assert_eq!(gop.framebuffer[2 * 12 + 4], 0);
+
+        // Upload the 2x2 block at (1, 1) of a 4x3 image (delta 16 bytes)
+        let image: Vec<BltPixel> = (0..12).map(|i| BltPixel::new(i * 10, 0, 0)).collect();
+        let mut source = image.clone();
+        gop.blt(
+            BltOperation::BufferToVideo,
+            &mut source,
+            (1, 1),
+            (5, 3),
+            2,
+            2,
+            16,
+        )
+        .unwrap();
+        let pixels = screen(&mut gop);
+        assert_eq!(pixels[3 * 8 + 5], image[5]);
+        assert_eq!(pixels[3 * 8 + 6], image[6]);
+        assert_eq!(pixels[4 * 8 + 5], image[9]);
+        assert_eq!(pixels[4 * 8 + 6], image[10]);
+
+        // Download into the middle of a larger buffer, leaving the rest alone
+        let marker = BltPixel::new(1, 2, 3);
+        let mut target = vec![marker; 5 * 4];
+        gop.blt(
+            BltOperation::VideoToBltBuffer,
+            &mut target,
+            (1, 1),
+            (1, 1),
+            3,
+            2,
+            20,
+        )
+        .unwrap();
+        assert_eq!(target[5 + 1], orange);
+        assert_eq!(target[2 * 5 + 3], orange);
+        assert_eq!(target[5 + 4], marker);
+        assert_eq!(target[0], marker);
+    }
+
+    #[test]
+    fn test_overlapping_video_to_video() {
+        let (width, height) = (10, 10);
+        let mut gop = gop(bgr(width, height));
+        let mut pattern: Vec<BltPixel> = (0..width * height)
+            .map(|i| BltPixel::new(i as u8, (i / width) as u8, 7))
+            .collect();
+        gop.blt(
+            BltOperation::BufferToVideo,
+            &mut pattern,
+            (0, 0),
+            (0, 0),
+            width,
+            height,
+            0,
+        )
+        .unwrap();
+
+        // Move a 6x6 block by (+2, +3), (-3, -1) and (+1, 0): every result
+        // must equal what a copy through a temporary buffer would give
+        for (src, dst) in [((1, 1), (3, 4)), ((4, 3), (1, 2)), ((2, 2), (3, 2))] {
+            let before = screen(&mut gop);
+            gop.blt(BltOperation::VideoToVideo, &mut [], src, dst, 6, 6, 0)
+                .unwrap();
+            let after = screen(&mut gop);
+            for y in 0..height {
+                for x in 0..width {
+                    let inside = (dst.0..dst.0 + 6).contains(&x) && (dst.1..dst.1 + 6).contains(&y);
+                    let expected = if inside {
+                        before[((y - dst.1 + src.1) * width + x - dst.0 + src.0) as usize]
+                    } else {
+                        before[(y * width + x) as usize]
+                    };
+                    assert_eq!(after[(y * width + x) as usize], expected, "{:?}", (x, y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        let mut gop = gop(bgr(8, 8));
+        let mut buffer = vec![BltPixel::default(); 16];
+        let before = gop.framebuffer.clone();
+        let cases = [
+            (BltOperation::VideoFill, (0, 0), (6, 0), 3, 1, 0),
+            (BltOperation::VideoFill, (0, 0), (0, 0), 0, 1, 0),
+            (BltOperation::BufferToVideo, (0, 0), (0, 0), 4, 4, 15),
+            (BltOperation::BufferToVideo, (1, 0), (0, 0), 4, 4, 16),
+            (BltOperation::BufferToVideo, (0, 0), (0, 0), 4, 5, 16),
+            (BltOperation::VideoToBltBuffer, (5, 5), (0, 0), 4, 4, 0),
+            (BltOperation::VideoToVideo, (0, 0), (0, 7), 2, 2, 0),
+        ];
+        for (op, src, dst, width, height, delta) in cases {
+            assert_eq!(
+                gop.blt(op, &mut buffer, src, dst, width, height, delta),
+                Err(Status::INVALID_PARAMETER),
+                "{:?}",
+                (op, src, dst, width, height, delta)
+            );
+        }
+        assert_eq!(
+            gop.blt(BltOperation::VideoFill, &mut [], (0, 0), (0, 0), 1, 1, 0),
+            Err(Status::INVALID_PARAMETER)
+        );
+        assert_eq!(BltOperation::try_from(4), Err(Status::INVALID_PARAMETER));
+        assert_eq!(gop.framebuffer, before);
+
+        // Offsets past usize are rejected rather than wrapping
+        for (pos, width, height, row_pixels) in [
+            ((0, 1), 1, 1, usize::MAX),
+            ((0, u32::MAX), 1, 2, usize::MAX),
+            ((0, 0), 1, 0, 1),
+        ] {
+            assert_eq!(
+                check_buffer_rect(usize::MAX, pos, width, height, row_pixels),
+                Err(Status::INVALID_PARAMETER)
+            );
+        }
+    }
+
+    #[test]
+    fn test_pixel_format_conversion() {
+        let pixel = BltPixel::new(0x12, 0x34, 0x56);
+        let raw = |format, mask| {
+            let mut mode = bgr(1, 1);
+            mode.pixel_format = format;
+            mode.pixel_bitmask = mask;
+            pixel.to_raw(&mode)
+        };
+        let rgb = PixelFormat::RedGreenBlueReserved8BitPerColor;
+        let bgr_format = PixelFormat::BlueGreenRedReserved8BitPerColor;
+        assert_eq!(raw(rgb, None), Ok(0x00563412));
+        assert_eq!(raw(bgr_format, None), Ok(0x00123456));
+        assert_eq!(raw(PixelFormat::BltOnly, None), Ok(0x00123456));
+        // The 8-bit masks reproduce the two fixed layouts
+        let bitmask = PixelFormat::BitMask;
+        assert_eq!(
+            raw(bitmask, Some(PixelBitmask::rgb888())),
+            raw(bgr_format, None)
+        );
+        assert_eq!(raw(bitmask, Some(PixelBitmask::bgr888())), raw(rgb, None));
+        assert_eq!(raw(bitmask, None), Err(Status::UNSUPPORTED));
+
+        // Narrow and wide channels round-trip full intensity and black
+        let rgb565 = PixelBitmask {
+            red_mask: 0xF800,
+            green_mask: 0x07E0,
+            blue_mask: 0x001F,
+            reserved_mask: 0,
+        };
+        for mask in [rgb565, PixelBitmask::x2r10g10b10()] {
+            let mode = ModeInfo::with_bitmask(1, 1, mask);
+            for pixel in [BltPixel::new(255, 255, 255), BltPixel::new(0, 0, 0)] {
+                let raw = pixel.to_raw(&mode).unwrap();
+                assert_eq!(BltPixel::from_raw(raw, &mode), Ok(pixel));
+            }
+        }
+        let mode = ModeInfo::with_bitmask(1, 1, rgb565);
+        assert_eq!(BltPixel::new(255, 0, 0).to_raw(&mode), Ok(0xF800));
+        let back = BltPixel::from_raw(
+            BltPixel::new(0x80, 0x40, 0x20).to_raw(&mode).unwrap(),
+            &mode,
+        );
+        assert_eq!(back, Ok(BltPixel::new(0x84, 0x41, 0x21)));
+
+        let overlapping = PixelBitmask {
+            green_mask: 0x00FF8000,
+            ..PixelBitmask::rgb888()
+        };
+        assert!(!overlapping.is_valid());
+        let split = PixelBitmask {
+            blue_mask: 0x000000F1,
+            ..PixelBitmask::rgb888()
+        };
+        assert!(!split.is_valid());
+        assert!(PixelBitmask::rgb888().is_valid());
+    }
+}
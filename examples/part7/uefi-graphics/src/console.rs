@@ -0,0 +1,168 @@
+//! Text console on a framebuffer
+//!
+//! A character grid drawn with a bitmap [`Font`], as a graphical stand-in for
+//! EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL. Text wraps at the right edge, and
+//! writing past the last row scrolls the pixels up by one line.
+//!
+//! ```text
+//!   ┌────────────────────────────┐
+//!   │ BootManager v1.0           │  row 0
+//!   │ > Linux                    │
+//!   │   Windows Boot Manager     │
+//!   │ _                          │  cursor
+//!   └────────────────────────────┘
+//!     columns = width / font width, rows = height / font height
+//! ```
+
+use std::fmt;
+
+use crate::font::Font;
+use crate::{Canvas, Color};
+
+pub struct TextConsole {
+    canvas: Canvas,
+    font: Font,
+    columns: u32,
+    rows: u32,
+    cursor_column: u32,
+    cursor_row: u32,
+    foreground: Color,
+    background: Color,
+    /// Characters on screen, row by row, for reading the console back
+    cells: Vec<char>,
+    lines_scrolled: u64,
+}
+
+impl TextConsole {
+    /// A console covering the whole canvas, cleared to black.
+    pub fn new(canvas: Canvas, font: Font) -> Self {
+        let columns = canvas.width / font.width();
+        let rows = canvas.height / font.height();
+        let mut console = TextConsole {
+            canvas,
+            font,
+            columns,
+            rows,
+            cursor_column: 0,
+            cursor_row: 0,
+            foreground: Color::new(170, 170, 170),
+            background: Color::black(),
+            cells: vec![' '; (columns * rows) as usize],
+            lines_scrolled: 0,
+        };
+        console.clear();
+        console
+    }
+
+    pub fn columns(&self) -> u32 {
+        self.columns
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.rows
+    }
+
+    /// Lines that have scrolled off the top since the console was created.
+    pub fn lines_scrolled(&self) -> u64 {
+        self.lines_scrolled
+    }
+
+    pub fn canvas(&self) -> &Canvas {
+        &self.canvas
+    }
+
+    /// Colors for text written from now on.
+    pub fn set_colors(&mut self, foreground: Color, background: Color) {
+        self.foreground = foreground;
+        self.background = background;
+    }
+
+    /// Fill with the background color and home the cursor.
+    pub fn clear(&mut self) {
+        self.canvas.clear(self.background);
+        self.cells.fill(' ');
+        self.cursor_column = 0;
+        self.cursor_row = 0;
+    }
+
+    /// Cursor position; the column equals `columns()` when the last
+    /// character written filled the row.
+    pub fn cursor(&self) -> (u32, u32) {
+        (self.cursor_column, self.cursor_row)
+    }
+
+    /// Move the cursor, clamped to the grid.
+    pub fn set_cursor(&mut self, column: u32, row: u32) {
+        self.cursor_column = column.min(self.columns.saturating_sub(1));
+        self.cursor_row = row.min(self.rows.saturating_sub(1));
+    }
+
+    /// Text of one row, without trailing spaces.
+    pub fn line(&self, row: u32) -> String {
+        let start = (row * self.columns) as usize;
+        let line: String = self.cells[start..start + self.columns as usize]
+            .iter()
+            .collect();
+        line.trim_end().to_string()
+    }
+
+    /// Write one character. `\n` starts a new line, `\r` returns to the
+    /// first column, `\t` advances to the next multiple of 8 and backspace
+    /// moves back one cell.
+    pub fn put_char(&mut self, c: char) {
+        if self.columns == 0 || self.rows == 0 {
+            return;
+        }
+        match c {
+            '\n' => self.new_line(),
+            '\r' => self.cursor_column = 0,
+            '\t' => {
+                let spaces = (8 - self.cursor_column % 8).min(self.columns - self.cursor_column);
+                for _ in 0..spaces {
+                    self.put_char(' ');
+                }
+            }
+            '\u{8}' => self.cursor_column = self.cursor_column.saturating_sub(1),
+            c => {
+                // Wrap only once there is something to put on the next line
+                if self.cursor_column == self.columns {
+                    self.new_line();
+                }
+                let (column, row) = (self.cursor_column, self.cursor_row);
+                self.cells[(row * self.columns + column) as usize] = c;
+                self.canvas.draw_char(
+                    (column * self.font.width()) as i32,
+                    (row * self.font.height()) as i32,
+                    c,
+                    &self.font,
+                    self.foreground,
+                    Some(self.background),
+                );
+                self.cursor_column += 1;
+            }
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.cursor_column = 0;
+        if self.cursor_row + 1 < self.rows {
+            self.cursor_row += 1;
+            return;
+        }
+
+        // Scroll: move the pixels and the cells up one row, blank the last
+        self.canvas.scroll_up(self.font.height(), self.background);
+        let columns = self.columns as usize;
+        self.cells.copy_within(columns.., 0);
+        let last_row = self.cells.len() - columns;
+        self.cells[last_row..].fill(' ');
+        self.lines_scrolled += 1;
+    }
+}
+
+impl fmt::Write for TextConsole {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        text.chars().for_each(|c| self.put_char(c));
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+//! Dirty-rectangle presentation for [`DoubleBuffer`](crate::DoubleBuffer)
+//!
+//! Copying a whole frame into a GOP framebuffer is slow (it is usually
+//! uncached memory behind a PCI BAR), while an animated boot screen only
+//! changes a few small areas per frame. Drawing through the double buffer
+//! records the rectangles it touched, and `present()` copies just those
+//! from the back buffer to the front buffer.
+//!
+//! ```text
+//!   fill_rect / set_pixel / clear_back ──► DirtyRegion ──► present()
+//!                                          (coalesced)     copies rows
+//!                                                          back ──► front
+//! ```
+//!
+//! Two rectangles are merged when copying their bounding box costs no more
+//! than copying both, and the list is capped at [`MAX_DIRTY_RECTS`] by
+//! merging the cheapest pair, so bookkeeping never outgrows the copies.
+
+use crate::{DoubleBuffer, Rect};
+
+pub const MAX_DIRTY_RECTS: usize = 16;
+
+const BYTES_PER_PIXEL: u64 = 4;
+
+/// The areas of the back buffer that differ from the front buffer.
+#[derive(Debug, Clone, Default)]
+pub struct DirtyRegion {
+    rects: Vec<Rect>,
+}
+
+/// What one `present()` copied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    pub rects: usize,
+    pub pixels: u64,
+    pub bytes_copied: u64,
+}
+
+/// Totals over every `present()` of a [`DoubleBuffer`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PresentStats {
+    pub frames: u64,
+    pub bytes_copied: u64,
+    pub last_frame: FrameStats,
+}
+
+impl PresentStats {
+    pub fn average_bytes_per_frame(&self) -> u64 {
+        self.bytes_copied.checked_div(self.frames).unwrap_or(0)
+    }
+}
+
+/// Pixels wasted by copying `a.union(b)` instead of `a` and `b` separately;
+/// negative when the two overlap enough that merging saves copies.
+fn merge_cost(a: &Rect, b: &Rect) -> i64 {
+    a.union(b).area() as i64 - a.area() as i64 - b.area() as i64
+}
+
+impl DirtyRegion {
+    pub fn new() -> Self {
+        DirtyRegion::default()
+    }
+
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects
+    }
+
+    pub fn clear(&mut self) {
+        self.rects.clear();
+    }
+
+    /// Record `rect`, merging it with every rectangle it is cheap to
+    /// combine with. A merge grows the rectangle, which can make further
+    /// merges worthwhile, so this repeats until nothing changes.
+    pub fn add(&mut self, rect: Rect) {
+        if rect.is_empty() {
+            return;
+        }
+        let mut rect = rect;
+        loop {
+            let before = self.rects.len();
+            let mut i = 0;
+            while i < self.rects.len() {
+                if merge_cost(&rect, &self.rects[i]) <= 0 {
+                    rect = rect.union(&self.rects.swap_remove(i));
+                } else {
+                    i += 1;
+                }
+            }
+            if self.rects.len() == before {
+                break;
+            }
+        }
+        self.rects.push(rect);
+
+        if self.rects.len() > MAX_DIRTY_RECTS {
+            let mut cheapest = (0, 1);
+            for i in 0..self.rects.len() {
+                for j in i + 1..self.rects.len() {
+                    let cost = merge_cost(&self.rects[i], &self.rects[j]);
+                    if cost < merge_cost(&self.rects[cheapest.0], &self.rects[cheapest.1]) {
+                        cheapest = (i, j);
+                    }
+                }
+            }
+            // Remove the higher index first so the lower one stays valid
+            let second = self.rects.swap_remove(cheapest.1);
+            let first = self.rects.swap_remove(cheapest.0);
+            self.add(first.union(&second));
+        }
+    }
+}
+
+impl DoubleBuffer {
+    pub fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    /// Note that the back buffer changed inside `rect`; parts outside the
+    /// buffer are ignored.
+    pub fn mark_dirty(&mut self, rect: Rect) {
+        let rect = rect.intersect(&self.bounds());
+        self.dirty.add(rect);
+    }
+
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: u32) {
+        if self.bounds().contains(x, y) {
+            self.back[(y as u32 * self.width + x as u32) as usize] = color;
+            self.mark_dirty(Rect::new(x, y, 1, 1));
+        }
+    }
+
+    /// Fill the part of `rect` inside the back buffer and mark it dirty.
+    pub fn fill_rect(&mut self, rect: Rect, color: u32) {
+        let rect = rect.intersect(&self.bounds());
+        if rect.is_empty() {
+            return;
+        }
+        let width = self.width as usize;
+        for y in rect.y..rect.bottom() {
+            let start = y as usize * width + rect.x as usize;
+            self.back[start..start + rect.width as usize].fill(color);
+        }
+        self.mark_dirty(rect);
+    }
+
+    /// Copy every dirty rectangle from the back buffer to the front buffer
+    /// and start a new frame. Everything outside them is already identical
+    /// in both buffers, so the front buffer ends up equal to the back one.
+    pub fn present(&mut self) -> FrameStats {
+        let width = self.width as usize;
+        let mut frame = FrameStats::default();
+        for rect in self.dirty.rects() {
+            for y in rect.y..rect.bottom() {
+                let start = y as usize * width + rect.x as usize;
+                let end = start + rect.width as usize;
+                self.front[start..end].copy_from_slice(&self.back[start..end]);
+            }
+            frame.rects += 1;
+            frame.pixels += rect.area();
+        }
+        frame.bytes_copied = frame.pixels * BYTES_PER_PIXEL;
+        self.dirty.clear();
+
+        self.stats.frames += 1;
+        self.stats.bytes_copied += frame.bytes_copied;
+        self.stats.last_frame = frame;
+        frame
+    }
+
+    pub fn present_stats(&self) -> PresentStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coalescing() {
+        let mut region = DirtyRegion::new();
+        region.add(Rect::new(0, 0, 10, 10));
+        // Contained and empty rectangles change nothing
+        region.add(Rect::new(2, 2, 4, 4));
+        region.add(Rect::new(50, 50, 0, 10));
+        assert_eq!(region.rects(), &[Rect::new(0, 0, 10, 10)]);
+
+        // Adjacent rectangles merge exactly; a distant one stays separate
+        region.add(Rect::new(10, 0, 5, 10));
+        region.add(Rect::new(100, 100, 4, 4));
+        assert_eq!(
+            region.rects(),
+            &[Rect::new(0, 0, 15, 10), Rect::new(100, 100, 4, 4)]
+        );
+
+        // A rectangle bridging both pulls them into one once it is cheap
+        region.add(Rect::new(0, 0, 104, 104));
+        assert_eq!(region.rects(), &[Rect::new(0, 0, 104, 104)]);
+
+        // A cross shape would waste most of its bounding box
+        let mut cross = DirtyRegion::new();
+        cross.add(Rect::new(0, 45, 100, 10));
+        cross.add(Rect::new(45, 0, 10, 100));
+        assert_eq!(cross.rects().len(), 2);
+    }
+
+    #[test]
+    fn test_rect_count_is_capped() {
+        let mut region = DirtyRegion::new();
+        for i in 0..40 {
+            region.add(Rect::new(i * 20, (i % 3) * 20, 2, 2));
+        }
+        assert!(region.rects().len() <= MAX_DIRTY_RECTS);
+        for i in 0..40 {
+            let (x, y) = (i * 20, (i % 3) * 20);
+            assert!(region.rects().iter().any(|rect| rect.contains(x, y)));
+        }
+    }
+
+    #[test]
+    fn test_present_copies_only_dirty_regions() {
+        let mut db = DoubleBuffer::new(32, 16);
+        db.clear_back(0x00102040);
+        let full = db.present();
+        assert_eq!(full.bytes_copied, 32 * 16 * 4);
+        assert_eq!(db.front_buffer(), &vec![0x00102040; 32 * 16][..]);
+
+        // A sprite moving 2 pixels right: erase the old position, draw the
+        // new one. The overlap merges into a single 6x4 copy.
+        db.fill_rect(Rect::new(4, 4, 4, 4), 0x00FF0000);
+        db.present();
+        db.fill_rect(Rect::new(4, 4, 4, 4), 0x00102040);
+        db.fill_rect(Rect::new(6, 4, 4, 4), 0x00FF0000);
+        db.set_pixel(40, 0, 0x00FFFFFF);
+        let frame = db.present();
+        assert_eq!(
+            frame,
+            FrameStats {
+                rects: 1,
+                pixels: 24,
+                bytes_copied: 96
+            }
+        );
+        assert_eq!(db.front_buffer()[4 * 32 + 4], 0x00102040);
+        assert_eq!(db.front_buffer()[4 * 32 + 9], 0x00FF0000);
+
+        // Nothing drawn, nothing copied
+        assert_eq!(db.present(), FrameStats::default());
+
+        let stats = db.present_stats();
+        assert_eq!(stats.frames, 4);
+        assert_eq!(stats.bytes_copied, 32 * 16 * 4 + 64 + 96);
+        assert_eq!(stats.last_frame, FrameStats::default());
+        assert_eq!(stats.average_bytes_per_frame(), (2048 + 160) / 4);
+    }
+}
@@ -0,0 +1,470 @@
+//! Bitmap fonts
+//!
+//! Glyphs are stored one bit per pixel, most significant bit leftmost, each
+//! row padded to a whole byte. A built-in 8x16 font in the style of the VGA
+//! ROM font covers printable ASCII; PC Screen Font files (the `.psf` fonts
+//! Linux consoles use) can be loaded for anything else.
+//!
+//! ```text
+//!   PSF1  36 04 | mode | charsize            glyphs are 8 x charsize
+//!   PSF2  72 B5 4A 86 | version | headersize | flags | length
+//!         | charsize | height | width                (little-endian u32s)
+//! ```
+//!
+//! Both formats may end with a Unicode table mapping each glyph to the
+//! characters it draws; without one, glyph `n` draws code point `n`.
+
+use std::collections::HashMap;
+use std::fmt;
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE512: u8 = 0x01;
+const PSF1_MODEHASTAB: u8 = 0x02;
+const PSF1_SEPARATOR: u16 = 0xFFFF;
+const PSF1_STARTSEQ: u16 = 0xFFFE;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+const PSF2_HEADER_SIZE: usize = 32;
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+const PSF2_SEPARATOR: u8 = 0xFF;
+const PSF2_STARTSEQ: u8 = 0xFE;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FontError {
+    /// Neither the PSF1 nor the PSF2 magic number.
+    BadMagic,
+    /// The file ends before the header, glyphs or Unicode table do.
+    Truncated,
+    /// Zero-sized glyphs, or a glyph size that disagrees with the dimensions.
+    BadDimensions,
+    /// A Unicode table entry that is not a valid character.
+    BadUnicodeTable,
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontError::BadMagic => write!(f, "not a PSF1 or PSF2 font"),
+            FontError::Truncated => write!(f, "font file is truncated"),
+            FontError::BadDimensions => write!(f, "invalid glyph dimensions"),
+            FontError::BadUnicodeTable => write!(f, "invalid Unicode table"),
+        }
+    }
+}
+
+/// A fixed-width bitmap font
+#[derive(Debug, Clone)]
+pub struct Font {
+    width: u32,
+    height: u32,
+    glyph_count: usize,
+    /// All glyphs back to back, `bytes_per_glyph()` each
+    bitmaps: Vec<u8>,
+    /// Characters to glyph indices; empty means "glyph n is code point n"
+    unicode: HashMap<char, usize>,
+}
+
+impl Font {
+    /// The built-in 8x16 font: printable ASCII, plus a box drawn for
+    /// everything else.
+    pub fn vga8x16() -> Self {
+        let mut bitmaps: Vec<u8> = VGA_8X16.iter().flatten().copied().collect();
+        bitmaps.extend_from_slice(&REPLACEMENT_8X16);
+        let mut unicode: HashMap<char, usize> = (' '..='~').zip(0..).collect();
+        unicode.insert(char::REPLACEMENT_CHARACTER, VGA_8X16.len());
+        Font {
+            width: 8,
+            height: 16,
+            glyph_count: VGA_8X16.len() + 1,
+            bitmaps,
+            unicode,
+        }
+    }
+
+    /// Load a PSF1 or PSF2 font.
+    pub fn from_psf(bytes: &[u8]) -> Result<Self, FontError> {
+        if bytes.starts_with(&PSF2_MAGIC) {
+            Self::from_psf2(bytes)
+        } else if bytes.starts_with(&PSF1_MAGIC) {
+            Self::from_psf1(bytes)
+        } else {
+            Err(FontError::BadMagic)
+        }
+    }
+
+    fn from_psf1(bytes: &[u8]) -> Result<Self, FontError> {
+        let [_, _, mode, charsize, ..] = *bytes else {
+            return Err(FontError::Truncated);
+        };
+        if charsize == 0 {
+            return Err(FontError::BadDimensions);
+        }
+        let glyph_count = if mode & PSF1_MODE512 != 0 { 512 } else { 256 };
+        let glyphs_end = 4 + glyph_count * charsize as usize;
+        let bitmaps = bytes.get(4..glyphs_end).ok_or(FontError::Truncated)?;
+
+        let mut unicode = HashMap::new();
+        if mode & PSF1_MODEHASTAB != 0 {
+            let table: Vec<u16> = bytes[glyphs_end..]
+                .as_chunks::<2>()
+                .0
+                .iter()
+                .map(|&pair| u16::from_le_bytes(pair))
+                .collect();
+            let mut entries = table.split(|&unit| unit == PSF1_SEPARATOR);
+            for glyph in 0..glyph_count {
+                let entry = entries.next().ok_or(FontError::Truncated)?;
+                // Characters before the first sequence each map to this glyph
+                for &unit in entry.iter().take_while(|&&unit| unit != PSF1_STARTSEQ) {
+                    let c = char::from_u32(unit as u32).ok_or(FontError::BadUnicodeTable)?;
+                    unicode.entry(c).or_insert(glyph);
+                }
+            }
+        }
+
+        Ok(Font {
+            width: 8,
+            height: charsize as u32,
+            glyph_count,
+            bitmaps: bitmaps.to_vec(),
+            unicode,
+        })
+    }
+
+    fn from_psf2(bytes: &[u8]) -> Result<Self, FontError> {
+        let field = |index: usize| -> Result<u32, FontError> {
+            let offset = 4 + index * 4;
+            bytes
+                .get(offset..offset + 4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .ok_or(FontError::Truncated)
+        };
+        let header_size = field(1)? as usize;
+        let flags = field(2)?;
+        let glyph_count = field(3)? as usize;
+        let charsize = field(4)? as usize;
+        let height = field(5)?;
+        let width = field(6)?;
+
+        if header_size < PSF2_HEADER_SIZE
+            || width == 0
+            || height == 0
+            || charsize != width.div_ceil(8) as usize * height as usize
+        {
+            return Err(FontError::BadDimensions);
+        }
+        let glyphs_end = glyph_count
+            .checked_mul(charsize)
+            .and_then(|size| size.checked_add(header_size))
+            .ok_or(FontError::BadDimensions)?;
+        let bitmaps = bytes
+            .get(header_size..glyphs_end)
+            .ok_or(FontError::Truncated)?;
+
+        let mut unicode = HashMap::new();
+        if flags & PSF2_HAS_UNICODE_TABLE != 0 {
+            let mut entries = bytes[glyphs_end..].split(|&byte| byte == PSF2_SEPARATOR);
+            for glyph in 0..glyph_count {
+                let entry = entries.next().ok_or(FontError::Truncated)?;
+                let singles = entry.split(|&byte| byte == PSF2_STARTSEQ).next().unwrap();
+                let text = std::str::from_utf8(singles).map_err(|_| FontError::BadUnicodeTable)?;
+                for c in text.chars() {
+                    unicode.entry(c).or_insert(glyph);
+                }
+            }
+        }
+
+        Ok(Font {
+            width,
+            height,
+            glyph_count,
+            bitmaps: bitmaps.to_vec(),
+            unicode,
+        })
+    }
+
+    /// Encode as PSF2, with a Unicode table when the font has one.
+    pub fn to_psf2(&self) -> Vec<u8> {
+        let flags = if self.unicode.is_empty() {
+            0
+        } else {
+            PSF2_HAS_UNICODE_TABLE
+        };
+        let mut bytes = PSF2_MAGIC.to_vec();
+        for value in [
+            0,
+            PSF2_HEADER_SIZE as u32,
+            flags,
+            self.glyph_count as u32,
+            self.bytes_per_glyph() as u32,
+            self.height,
+            self.width,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.bitmaps);
+
+        if !self.unicode.is_empty() {
+            let mut chars: Vec<Vec<char>> = vec![Vec::new(); self.glyph_count];
+            for (&c, &glyph) in &self.unicode {
+                chars[glyph].push(c);
+            }
+            for mut glyph_chars in chars {
+                glyph_chars.sort_unstable();
+                for c in glyph_chars {
+                    let mut utf8 = [0u8; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+                }
+                bytes.push(PSF2_SEPARATOR);
+            }
+        }
+        bytes
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn glyph_count(&self) -> usize {
+        self.glyph_count
+    }
+
+    pub fn bytes_per_row(&self) -> usize {
+        self.width.div_ceil(8) as usize
+    }
+
+    fn bytes_per_glyph(&self) -> usize {
+        self.bytes_per_row() * self.height as usize
+    }
+
+    /// The bitmap for `c`, if the font has one.
+    pub fn glyph(&self, c: char) -> Option<&[u8]> {
+        let index = if self.unicode.is_empty() {
+            c as usize
+        } else {
+            *self.unicode.get(&c)?
+        };
+        if index >= self.glyph_count {
+            return None;
+        }
+        let size = self.bytes_per_glyph();
+        Some(&self.bitmaps[index * size..(index + 1) * size])
+    }
+
+    /// The bitmap for `c`, falling back to U+FFFD and then `?`.
+    pub fn glyph_or_replacement(&self, c: char) -> Option<&[u8]> {
+        self.glyph(c)
+            .or_else(|| self.glyph(char::REPLACEMENT_CHARACTER))
+            .or_else(|| self.glyph('?'))
+    }
+
+    /// Is pixel (`x`, `y`) of `glyph` set?
+    pub fn pixel(&self, glyph: &[u8], x: u32, y: u32) -> bool {
+        let byte = glyph[y as usize * self.bytes_per_row() + (x / 8) as usize];
+        byte & (0x80 >> (x % 8)) != 0
+    }
+
+    /// Width in pixels of the longest line of `text`.
+    pub fn text_width(&self, text: &str) -> u32 {
+        text.lines()
+            .map(|line| line.chars().count() as u32 * self.width)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Hollow box drawn for characters the built-in font lacks
+const REPLACEMENT_8X16: [u8; 16] = [
+    0x00, 0x00, 0xFE, 0x82, 0x82, 0x82, 0x82, 0x82, 0x82, 0x82, 0x82, 0xFE, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Glyphs for U+0020 to U+007E
+#[rustfmt::skip]
+const VGA_8X16: [[u8; 16]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x18, 0x3C, 0x3C, 0x3C, 0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00], // '!'
+    [0x00, 0x66, 0x66, 0x66, 0x24, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x00, 0x00, 0x00, 0x6C, 0x6C, 0xFE, 0x6C, 0x6C, 0x6C, 0xFE, 0x6C, 0x6C, 0x00, 0x00, 0x00, 0x00], // '#'
+    [0x18, 0x18, 0x7C, 0xC6, 0xC2, 0xC0, 0x7C, 0x06, 0x06, 0x86, 0xC6, 0x7C, 0x18, 0x18, 0x00, 0x00], // '$'
+    [0x00, 0x00, 0x00, 0x00, 0xC2, 0xC6, 0x0C, 0x18, 0x30, 0x60, 0xC6, 0x86, 0x00, 0x00, 0x00, 0x00], // '%'
+    [0x00, 0x00, 0x38, 0x6C, 0x6C, 0x38, 0x76, 0xDC, 0xCC, 0xCC, 0xCC, 0x76, 0x00, 0x00, 0x00, 0x00], // '&'
+    [0x00, 0x30, 0x30, 0x30, 0x60, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '\''
+    [0x00, 0x00, 0x0C, 0x18, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x18, 0x0C, 0x00, 0x00, 0x00, 0x00], // '('
+    [0x00, 0x00, 0x30, 0x18, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x18, 0x30, 0x00, 0x00, 0x00, 0x00], // ')'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '*'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x7E, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x18, 0x30, 0x00, 0x00, 0x00], // ','
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFE, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00], // '.'
+    [0x00, 0x00, 0x00, 0x00, 0x02, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xC0, 0x80, 0x00, 0x00, 0x00, 0x00], // '/'
+    [0x00, 0x00, 0x38, 0x6C, 0xC6, 0xC6, 0xD6, 0xD6, 0xC6, 0xC6, 0x6C, 0x38, 0x00, 0x00, 0x00, 0x00], // '0'
+    [0x00, 0x00, 0x18, 0x38, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x7E, 0x00, 0x00, 0x00, 0x00], // '1'
+    [0x00, 0x00, 0x7C, 0xC6, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xC0, 0xC6, 0xFE, 0x00, 0x00, 0x00, 0x00], // '2'
+    [0x00, 0x00, 0x7C, 0xC6, 0x06, 0x06, 0x3C, 0x06, 0x06, 0x06, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00], // '3'
+    [0x00, 0x00, 0x0C, 0x1C, 0x3C, 0x6C, 0xCC, 0xFE, 0x0C, 0x0C, 0x0C, 0x1E, 0x00, 0x00, 0x00, 0x00], // '4'
+    [0x00, 0x00, 0xFE, 0xC0, 0xC0, 0xC0, 0xFC, 0x06, 0x06, 0x06, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00], // '5'
+    [0x00, 0x00, 0x38, 0x60, 0xC0, 0xC0, 0xFC, 0xC6, 0xC6, 0xC6, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00], // '6'
+    [0x00, 0x00, 0xFE, 0xC6, 0x06, 0x06, 0x0C, 0x18, 0x30, 0x30, 0x30, 0x30, 0x00, 0x00, 0x00, 0x00], // '7'
+    [0x00, 0x00, 0x7C, 0xC6, 0xC6, 0xC6, 0x7C, 0xC6, 0xC6, 0xC6, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00], // '8'
+    [0x00, 0x00, 0x7C, 0xC6, 0xC6, 0xC6, 0x7E, 0x06, 0x06, 0x06, 0x0C, 0x78, 0x00, 0x00, 0x00, 0x00], // '9'
+    [0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // ':'
+    [0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x18, 0x18, 0x30, 0x00, 0x00, 0x00, 0x00], // ';'
+    [0x00, 0x00, 0x00, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x30, 0x18, 0x0C, 0x06, 0x00, 0x00, 0x00, 0x00], // '<'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x7E, 0x00, 0x00, 0x7E, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '='
+    [0x00, 0x00, 0x00, 0x60, 0x30, 0x18, 0x0C, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x00, 0x00, 0x00, 0x00], // '>'
+    [0x00, 0x00, 0x7C, 0xC6, 0xC6, 0x0C, 0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00], // '?'
+    [0x00, 0x00, 0x00, 0x7C, 0xC6, 0xC6, 0xDE, 0xDE, 0xDE, 0xDC, 0xC0, 0x7C, 0x00, 0x00, 0x00, 0x00], // '@'
+    [0x00, 0x00, 0x10, 0x38, 0x6C, 0xC6, 0xC6, 0xFE, 0xC6, 0xC6, 0xC6, 0xC6, 0x00, 0x00, 0x00, 0x00], // 'A'
+    [0x00, 0x00, 0xFC, 0x66, 0x66, 0x66, 0x7C, 0x66, 0x66, 0x66, 0x66, 0xFC, 0x00, 0x00, 0x00, 0x00], // 'B'
+    [0x00, 0x00, 0x3C, 0x66, 0xC2, 0xC0, 0xC0, 0xC0, 0xC0, 0xC2, 0x66, 0x3C, 0x00, 0x00, 0x00, 0x00], // 'C'
+    [0x00, 0x00, 0xF8, 0x6C, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x6C, 0xF8, 0x00, 0x00, 0x00, 0x00], // 'D'
+    [0x00, 0x00, 0xFE, 0x66, 0x62, 0x68, 0x78, 0x68, 0x60, 0x62, 0x66, 0xFE, 0x00, 0x00, 0x00, 0x00], // 'E'
+    [0x00, 0x00, 0xFE, 0x66, 0x62, 0x68, 0x78, 0x68, 0x60, 0x60, 0x60, 0xF0, 0x00, 0x00, 0x00, 0x00], // 'F'
+    [0x00, 0x00, 0x3C, 0x66, 0xC2, 0xC0, 0xC0, 0xDE, 0xC6, 0xC6, 0x66, 0x3A, 0x00, 0x00, 0x00, 0x00], // 'G'
+    [0x00, 0x00, 0xC6, 0xC6, 0xC6, 0xC6, 0xFE, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0x00, 0x00, 0x00, 0x00], // 'H'
+    [0x00, 0x00, 0x3C, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, 0x00, 0x00, 0x00, 0x00], // 'I'
+    [0x00, 0x00, 0x1E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0xCC, 0xCC, 0xCC, 0x78, 0x00, 0x00, 0x00, 0x00], // 'J'
+    [0x00, 0x00, 0xE6, 0x66, 0x66, 0x6C, 0x78, 0x78, 0x6C, 0x66, 0x66, 0xE6, 0x00, 0x00, 0x00, 0x00], // 'K'
+    [0x00, 0x00, 0xF0, 0x60, 0x60, 0x60, 0x60, 0x60, 0x60, 0x62, 0x66, 0xFE, 0x00, 0x00, 0x00, 0x00], // 'L'
+    [0x00, 0x00, 0xC6, 0xEE, 0xFE, 0xFE, 0xD6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0x00, 0x00, 0x00, 0x00], // 'M'
+    [0x00, 0x00, 0xC6, 0xE6, 0xF6, 0xFE, 0xDE, 0xCE, 0xC6, 0xC6, 0xC6, 0xC6, 0x00, 0x00, 0x00, 0x00], // 'N'
+    [0x00, 0x00, 0x7C, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00], // 'O'
+    [0x00, 0x00, 0xFC, 0x66, 0x66, 0x66, 0x7C, 0x60, 0x60, 0x60, 0x60, 0xF0, 0x00, 0x00, 0x00, 0x00], // 'P'
+    [0x00, 0x00, 0x7C, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xD6, 0xDE, 0x7C, 0x0C, 0x0E, 0x00, 0x00], // 'Q'
+    [0x00, 0x00, 0xFC, 0x66, 0x66, 0x66, 0x7C, 0x6C, 0x66, 0x66, 0x66, 0xE6, 0x00, 0x00, 0x00, 0x00], // 'R'
+    [0x00, 0x00, 0x7C, 0xC6, 0xC6, 0x60, 0x38, 0x0C, 0x06, 0xC6, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00], // 'S'
+    [0x00, 0x00, 0x7E, 0x7E, 0x5A, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, 0x00, 0x00, 0x00, 0x00], // 'T'
+    [0x00, 0x00, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00], // 'U'
+    [0x00, 0x00, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0x6C, 0x38, 0x10, 0x00, 0x00, 0x00, 0x00], // 'V'
+    [0x00, 0x00, 0xC6, 0xC6, 0xC6, 0xC6, 0xD6, 0xD6, 0xD6, 0xFE, 0xEE, 0x6C, 0x00, 0x00, 0x00, 0x00], // 'W'
+    [0x00, 0x00, 0xC6, 0xC6, 0x6C, 0x7C, 0x38, 0x38, 0x7C, 0x6C, 0xC6, 0xC6, 0x00, 0x00, 0x00, 0x00], // 'X'
+    [0x00, 0x00, 0x66, 0x66, 0x66, 0x66, 0x3C, 0x18, 0x18, 0x18, 0x18, 0x3C, 0x00, 0x00, 0x00, 0x00], // 'Y'
+    [0x00, 0x00, 0xFE, 0xC6, 0x86, 0x0C, 0x18, 0x30, 0x60, 0xC2, 0xC6, 0xFE, 0x00, 0x00, 0x00, 0x00], // 'Z'
+    [0x00, 0x00, 0x3C, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x3C, 0x00, 0x00, 0x00, 0x00], // '['
+    [0x00, 0x00, 0x00, 0x80, 0xC0, 0xE0, 0x70, 0x38, 0x1C, 0x0E, 0x06, 0x02, 0x00, 0x00, 0x00, 0x00], // '\\'
+    [0x00, 0x00, 0x3C, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x3C, 0x00, 0x00, 0x00, 0x00], // ']'
+    [0x10, 0x38, 0x6C, 0xC6, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x00], // '_'
+    [0x30, 0x30, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x78, 0x0C, 0x7C, 0xCC, 0xCC, 0xCC, 0x76, 0x00, 0x00, 0x00, 0x00], // 'a'
+    [0x00, 0x00, 0xE0, 0x60, 0x60, 0x78, 0x6C, 0x66, 0x66, 0x66, 0x66, 0x7C, 0x00, 0x00, 0x00, 0x00], // 'b'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x7C, 0xC6, 0xC0, 0xC0, 0xC0, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00], // 'c'
+    [0x00, 0x00, 0x1C, 0x0C, 0x0C, 0x3C, 0x6C, 0xCC, 0xCC, 0xCC, 0xCC, 0x76, 0x00, 0x00, 0x00, 0x00], // 'd'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x7C, 0xC6, 0xFE, 0xC0, 0xC0, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00], // 'e'
+    [0x00, 0x00, 0x38, 0x6C, 0x64, 0x60, 0xF0, 0x60, 0x60, 0x60, 0x60, 0xF0, 0x00, 0x00, 0x00, 0x00], // 'f'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x76, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0x7C, 0x0C, 0xCC, 0x78, 0x00], // 'g'
+    [0x00, 0x00, 0xE0, 0x60, 0x60, 0x6C, 0x76, 0x66, 0x66, 0x66, 0x66, 0xE6, 0x00, 0x00, 0x00, 0x00], // 'h'
+    [0x00, 0x00, 0x18, 0x18, 0x00, 0x38, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, 0x00, 0x00, 0x00, 0x00], // 'i'
+    [0x00, 0x00, 0x06, 0x06, 0x00, 0x0E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x06, 0x66, 0x66, 0x3C, 0x00], // 'j'
+    [0x00, 0x00, 0xE0, 0x60, 0x60, 0x66, 0x6C, 0x78, 0x78, 0x6C, 0x66, 0xE6, 0x00, 0x00, 0x00, 0x00], // 'k'
+    [0x00, 0x00, 0x38, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, 0x00, 0x00, 0x00, 0x00], // 'l'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0xEC, 0xFE, 0xD6, 0xD6, 0xD6, 0xD6, 0xC6, 0x00, 0x00, 0x00, 0x00], // 'm'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0xDC, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x00, 0x00, 0x00, 0x00], // 'n'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x7C, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00], // 'o'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0xDC, 0x66, 0x66, 0x66, 0x66, 0x66, 0x7C, 0x60, 0x60, 0xF0, 0x00], // 'p'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x76, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0x7C, 0x0C, 0x0C, 0x1E, 0x00], // 'q'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0xDC, 0x76, 0x66, 0x60, 0x60, 0x60, 0xF0, 0x00, 0x00, 0x00, 0x00], // 'r'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x7C, 0xC6, 0x60, 0x38, 0x0C, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00], // 's'
+    [0x00, 0x00, 0x10, 0x30, 0x30, 0xFC, 0x30, 0x30, 0x30, 0x30, 0x36, 0x1C, 0x00, 0x00, 0x00, 0x00], // 't'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0x76, 0x00, 0x00, 0x00, 0x00], // 'u'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x66, 0x66, 0x66, 0x66, 0x66, 0x3C, 0x18, 0x00, 0x00, 0x00, 0x00], // 'v'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0xC6, 0xC6, 0xD6, 0xD6, 0xD6, 0xFE, 0x6C, 0x00, 0x00, 0x00, 0x00], // 'w'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0xC6, 0x6C, 0x38, 0x38, 0x38, 0x6C, 0xC6, 0x00, 0x00, 0x00, 0x00], // 'x'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0x7E, 0x06, 0x0C, 0xF8, 0x00], // 'y'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0xFE, 0xCC, 0x18, 0x30, 0x60, 0xC6, 0xFE, 0x00, 0x00, 0x00, 0x00], // 'z'
+    [0x00, 0x00, 0x0E, 0x18, 0x18, 0x18, 0x70, 0x18, 0x18, 0x18, 0x18, 0x0E, 0x00, 0x00, 0x00, 0x00], // '{'
+    [0x00, 0x00, 0x18, 0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00], // '|'
+    [0x00, 0x00, 0x70, 0x18, 0x18, 0x18, 0x0E, 0x18, 0x18, 0x18, 0x18, 0x70, 0x00, 0x00, 0x00, 0x00], // '}'
+    [0x00, 0x76, 0xDC, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '~'
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(font: &Font, c: char) -> Vec<String> {
+        let glyph = font.glyph(c).unwrap();
+        (0..font.height())
+            .map(|y| {
+                (0..font.width())
+                    .map(|x| if font.pixel(glyph, x, y) { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_builtin_font() {
+        let font = Font::vga8x16();
+        assert_eq!((font.width(), font.height()), (8, 16));
+        let a = render(&font, 'A');
+        assert_eq!(a[2], "...#....");
+        assert_eq!(a[7], "#######.");
+        assert_eq!(font.glyph('é'), None);
+        assert_eq!(font.glyph_or_replacement('é'), Some(&REPLACEMENT_8X16[..]));
+    }
+
+    #[test]
+    fn test_psf2_round_trip() {
+        let font = Font::vga8x16();
+        let bytes = font.to_psf2();
+        assert_eq!(&bytes[..4], &PSF2_MAGIC);
+        assert_eq!(bytes.len(), 32 + 96 * 16 + 96 * 2 + 2);
+
+        let loaded = Font::from_psf(&bytes).unwrap();
+        assert_eq!(loaded.glyph_count(), 96);
+        for c in [' ', 'A', 'z', '~', char::REPLACEMENT_CHARACTER] {
+            assert_eq!(loaded.glyph(c), font.glyph(c));
+        }
+
+        assert_eq!(
+            Font::from_psf(&bytes[..100]).map(|_| ()),
+            Err(FontError::Truncated)
+        );
+        let mut bad = bytes.clone();
+        bad[24] = 15; // charsize no longer matches 8x16
+        assert_eq!(
+            Font::from_psf(&bad).map(|_| ()),
+            Err(FontError::BadDimensions)
+        );
+        assert_eq!(
+            Font::from_psf(b"BM...").map(|_| ()),
+            Err(FontError::BadMagic)
+        );
+    }
+
+    #[test]
+    fn test_psf1_unicode_table() {
+        // 256 glyphs of 8x4; glyph 1 draws 'x' and 'X', glyph 2 draws 'é'
+        let mut bytes = vec![0x36, 0x04, PSF1_MODEHASTAB, 4];
+        let mut bitmaps = vec![0u8; 256 * 4];
+        bitmaps[4..8].copy_from_slice(&[0x81, 0x42, 0x24, 0x18]);
+        bitmaps[8..12].copy_from_slice(&[0x10, 0x7C, 0x7C, 0x00]);
+        bytes.extend_from_slice(&bitmaps);
+        for glyph in 0..256u16 {
+            let chars: &[u16] = match glyph {
+                1 => &[0x78, 0x58, PSF1_STARTSEQ, 0x65, 0x301],
+                2 => &[0xE9],
+                _ => &[],
+            };
+            for unit in chars.iter().chain([&PSF1_SEPARATOR]) {
+                bytes.extend_from_slice(&unit.to_le_bytes());
+            }
+        }
+
+        let font = Font::from_psf(&bytes).unwrap();
+        assert_eq!(
+            (font.width(), font.height(), font.glyph_count()),
+            (8, 4, 256)
+        );
+        assert_eq!(font.glyph('x'), Some(&[0x81, 0x42, 0x24, 0x18][..]));
+        assert_eq!(font.glyph('X'), font.glyph('x'));
+        assert_eq!(font.glyph('é'), Some(&[0x10, 0x7C, 0x7C, 0x00][..]));
+        // 'e' only appears inside a combining sequence
+        assert_eq!(font.glyph('e'), None);
+    }
+}
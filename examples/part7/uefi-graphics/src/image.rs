@@ -0,0 +1,652 @@
+//! Decoded images and alpha blending
+//!
+//! BMP and PNG files decode to an [`Image`]: row-major [`BltPixel`]s, ready
+//! for `Blt(BufferToVideo)`, with straight (not premultiplied) alpha in
+//! each pixel's `reserved` byte. Blt() copies that byte untouched;
+//! `Canvas::draw_image` blends with it.
+//!
+//! ```text
+//!   BMP   "BM" | file size | reserved | pixel data offset    14 bytes
+//!         DIB header: CORE (12 bytes) or INFO (40) .. V5 (124)
+//!         [bitfield masks] [palette]
+//!         pixel rows, bottom-up unless the height is negative,
+//!         each padded to 4 bytes, or an RLE8/RLE4 stream
+//! ```
+//!
+//! PNG decoding lives in `png.rs`, PPM in `snapshot.rs`.
+
+use std::fmt;
+
+use crate::blt::unpack_channel;
+use crate::png;
+use crate::raster::Rect;
+use crate::zlib::InflateError;
+use crate::{BltPixel, Canvas, Color, PixelBitmask};
+
+/// Larger than any framebuffer we drive; bigger images are rejected before
+/// anything is allocated for them.
+pub(crate) const MAX_PIXELS: u64 = 1 << 26;
+
+const BMP_SIGNATURE: [u8; 2] = *b"BM";
+const BMP_FILE_HEADER_SIZE: usize = 14;
+const BMP_CORE_HEADER_SIZE: usize = 12;
+const BMP_INFO_HEADER_SIZE: usize = 40;
+
+const BI_RGB: u32 = 0;
+const BI_RLE8: u32 = 1;
+const BI_RLE4: u32 = 2;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageError {
+    /// Not the signature of any format we read.
+    UnknownFormat,
+    /// The file ends before its headers or pixel data do.
+    Truncated,
+    /// A valid file using a feature this decoder does not implement.
+    Unsupported(&'static str),
+    /// A header or pixel data that breaks the format's rules.
+    Malformed(&'static str),
+    /// A PNG chunk whose CRC-32 does not match.
+    BadCrc,
+    /// PNG image data that does not decompress.
+    Inflate(InflateError),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::UnknownFormat => write!(f, "not a BMP, PNG or PPM file"),
+            ImageError::Truncated => write!(f, "image file is truncated"),
+            ImageError::Unsupported(what) => write!(f, "unsupported: {}", what),
+            ImageError::Malformed(what) => write!(f, "malformed image: {}", what),
+            ImageError::BadCrc => write!(f, "PNG chunk CRC mismatch"),
+            ImageError::Inflate(error) => write!(f, "PNG image data: {}", error),
+        }
+    }
+}
+
+impl From<InflateError> for ImageError {
+    fn from(error: InflateError) -> Self {
+        ImageError::Inflate(error)
+    }
+}
+
+impl BltPixel {
+    /// A pixel carrying `alpha` (255 = opaque) in its reserved byte.
+    pub fn with_alpha(red: u8, green: u8, blue: u8, alpha: u8) -> Self {
+        BltPixel {
+            reserved: alpha,
+            ..BltPixel::new(red, green, blue)
+        }
+    }
+
+    pub fn alpha(self) -> u8 {
+        self.reserved
+    }
+}
+
+/// A decoded image
+#[derive(Debug, Clone)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<BltPixel>,
+}
+
+impl Image {
+    /// Decode a BMP, PNG or binary PPM file, told apart by its signature.
+    pub fn decode(data: &[u8]) -> Result<Self, ImageError> {
+        if data.starts_with(&BMP_SIGNATURE) {
+            Image::from_bmp(data)
+        } else if data.starts_with(&png::SIGNATURE) {
+            Image::from_png(data)
+        } else if data.starts_with(b"P6") {
+            Image::from_ppm(data)
+        } else {
+            Err(ImageError::UnknownFormat)
+        }
+    }
+
+    pub(crate) fn new(width: u32, height: u32, pixels: Vec<BltPixel>) -> Self {
+        debug_assert_eq!(pixels.len(), (width * height) as usize);
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// All pixels, row by row.
+    pub fn pixels(&self) -> &[BltPixel] {
+        &self.pixels
+    }
+
+    /// All pixels, row by row; a `Blt()` buffer with delta `width * 4`.
+    pub fn pixels_mut(&mut self) -> &mut [BltPixel] {
+        &mut self.pixels
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Option<BltPixel> {
+        if x < self.width && y < self.height {
+            Some(self.pixels[(y * self.width + x) as usize])
+        } else {
+            None
+        }
+    }
+
+    /// True when every pixel has alpha 255, so Blt() alone draws it right.
+    pub fn is_opaque(&self) -> bool {
+        self.pixels.iter().all(|pixel| pixel.alpha() == 255)
+    }
+
+    /// Decode a Windows bitmap: 1, 4, 8, 16, 24 or 32 bits per pixel,
+    /// uncompressed, bitfields or RLE8/RLE4. Pixels an RLE stream skips
+    /// are left transparent.
+    pub fn from_bmp(data: &[u8]) -> Result<Self, ImageError> {
+        if !data.starts_with(&BMP_SIGNATURE) {
+            return Err(ImageError::UnknownFormat);
+        }
+        let pixel_offset = read_u32(data, 10)? as usize;
+        let header_size = read_u32(data, BMP_FILE_HEADER_SIZE)? as usize;
+        let header = data
+            .get(BMP_FILE_HEADER_SIZE..BMP_FILE_HEADER_SIZE + header_size)
+            .ok_or(ImageError::Truncated)?;
+
+        let (width, height, planes, bits, compression, colors_used) = match header_size {
+            BMP_CORE_HEADER_SIZE => (
+                read_u16(header, 4)? as i32,
+                read_u16(header, 6)? as i32,
+                read_u16(header, 8)?,
+                read_u16(header, 10)?,
+                BI_RGB,
+                0,
+            ),
+            40 | 52 | 56 | 108 | 124 => (
+                read_u32(header, 4)? as i32,
+                read_u32(header, 8)? as i32,
+                read_u16(header, 12)?,
+                read_u16(header, 14)?,
+                read_u32(header, 16)?,
+                read_u32(header, 32)?,
+            ),
+            _ => return Err(ImageError::Unsupported("BMP header version")),
+        };
+        if planes != 1 || width <= 0 || height == 0 || height == i32::MIN {
+            return Err(ImageError::Malformed("BMP dimensions"));
+        }
+        // A negative height stores rows top-down
+        let top_down = height < 0;
+        let (width, height) = (width as u32, height.unsigned_abs());
+        check_dimensions(width, height)?;
+
+        // Color masks sit right after the 40-byte INFO fields: inside
+        // the V2..V5 headers, or appended to a plain INFO header
+        let has_bitfields = matches!(compression, BI_BITFIELDS | BI_ALPHABITFIELDS);
+        let mut palette_offset = BMP_FILE_HEADER_SIZE + header_size;
+        let mut masks = match bits {
+            16 => PixelBitmask {
+                red_mask: 0x7C00,
+                green_mask: 0x03E0,
+                blue_mask: 0x001F,
+                reserved_mask: 0,
+            },
+            _ => PixelBitmask {
+                reserved_mask: 0,
+                ..PixelBitmask::rgb888()
+            },
+        };
+        if has_bitfields {
+            let masks_offset = BMP_FILE_HEADER_SIZE + BMP_INFO_HEADER_SIZE;
+            let has_alpha = compression == BI_ALPHABITFIELDS || header_size >= 56;
+            masks = PixelBitmask {
+                red_mask: read_u32(data, masks_offset)?,
+                green_mask: read_u32(data, masks_offset + 4)?,
+                blue_mask: read_u32(data, masks_offset + 8)?,
+                reserved_mask: if has_alpha {
+                    read_u32(data, masks_offset + 12)?
+                } else {
+                    0
+                },
+            };
+            if !masks.is_valid() {
+                return Err(ImageError::Malformed("BMP color masks"));
+            }
+            if header_size == BMP_INFO_HEADER_SIZE {
+                palette_offset += if has_alpha { 16 } else { 12 };
+            }
+        }
+
+        let palette = if bits <= 8 {
+            let entry_size = if header_size == BMP_CORE_HEADER_SIZE {
+                3
+            } else {
+                4
+            };
+            let count = match colors_used {
+                0 => 1 << bits,
+                count => count.min(256) as usize,
+            };
+            data.get(palette_offset..palette_offset + count * entry_size)
+                .ok_or(ImageError::Truncated)?
+                .chunks_exact(entry_size)
+                .map(|entry| BltPixel::with_alpha(entry[2], entry[1], entry[0], 255))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let pixel_data = data.get(pixel_offset..).ok_or(ImageError::Truncated)?;
+        let pixels = match (compression, bits) {
+            (BI_RGB, 1 | 4 | 8 | 16 | 24 | 32) | (BI_BITFIELDS | BI_ALPHABITFIELDS, 16 | 32) => {
+                let bmp = BmpRows {
+                    width,
+                    height,
+                    bits,
+                    top_down,
+                    palette,
+                    masks,
+                };
+                bmp.decode(pixel_data)?
+            }
+            (BI_RLE8, 8) | (BI_RLE4, 4) if top_down => {
+                return Err(ImageError::Malformed("top-down RLE bitmap"))
+            }
+            (BI_RLE8, 8) | (BI_RLE4, 4) => decode_rle(pixel_data, width, height, bits, &palette)?,
+            _ => return Err(ImageError::Unsupported("BMP compression")),
+        };
+        Ok(Image::new(width, height, pixels))
+    }
+}
+
+/// Zero-sized images are malformed; oversized ones are refused.
+pub(crate) fn check_dimensions(width: u32, height: u32) -> Result<(), ImageError> {
+    if width == 0 || height == 0 {
+        return Err(ImageError::Malformed("zero width or height"));
+    }
+    if width as u64 * height as u64 > MAX_PIXELS {
+        return Err(ImageError::Unsupported("image too large"));
+    }
+    Ok(())
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, ImageError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or(ImageError::Truncated)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, ImageError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or(ImageError::Truncated)
+}
+
+fn palette_entry(palette: &[BltPixel], index: u8) -> Result<BltPixel, ImageError> {
+    palette
+        .get(index as usize)
+        .copied()
+        .ok_or(ImageError::Malformed("palette index out of range"))
+}
+
+/// Layout of uncompressed BMP pixel rows
+struct BmpRows {
+    width: u32,
+    height: u32,
+    bits: u16,
+    top_down: bool,
+    palette: Vec<BltPixel>,
+    masks: PixelBitmask,
+}
+
+impl BmpRows {
+    fn decode(&self, data: &[u8]) -> Result<Vec<BltPixel>, ImageError> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let bits = self.bits as usize;
+        let stride = (width * bits).div_ceil(32) * 4;
+        let rows = data.get(..stride * height).ok_or(ImageError::Truncated)?;
+
+        let mut pixels = vec![BltPixel::default(); width * height];
+        for (file_row, row) in rows.chunks_exact(stride).enumerate() {
+            let y = if self.top_down {
+                file_row
+            } else {
+                height - 1 - file_row
+            };
+            for x in 0..width {
+                pixels[y * width + x] = self.pixel(row, x)?;
+            }
+        }
+        Ok(pixels)
+    }
+
+    fn pixel(&self, row: &[u8], x: usize) -> Result<BltPixel, ImageError> {
+        let bits = self.bits as usize;
+        match bits {
+            1 | 4 | 8 => {
+                let bit = x * bits;
+                let index = (row[bit / 8] >> (8 - bits - bit % 8)) & ((1u16 << bits) - 1) as u8;
+                palette_entry(&self.palette, index)
+            }
+            24 => Ok(BltPixel::with_alpha(
+                row[x * 3 + 2],
+                row[x * 3 + 1],
+                row[x * 3],
+                255,
+            )),
+            16 => Ok(self.masked(u16::from_le_bytes([row[x * 2], row[x * 2 + 1]]) as u32)),
+            _ => {
+                let bytes = &row[x * 4..x * 4 + 4];
+                Ok(self.masked(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])))
+            }
+        }
+    }
+
+    /// A 16 or 32-bit pixel; with no alpha mask it is opaque.
+    fn masked(&self, raw: u32) -> BltPixel {
+        let masks = &self.masks;
+        let alpha = match masks.reserved_mask {
+            0 => 255,
+            mask => unpack_channel(raw, mask),
+        };
+        BltPixel::with_alpha(
+            unpack_channel(raw, masks.red_mask),
+            unpack_channel(raw, masks.green_mask),
+            unpack_channel(raw, masks.blue_mask),
+            alpha,
+        )
+    }
+}
+
+/// Run-length encoded 8 or 4-bit data. Pairs of bytes are either a run
+/// (count > 0: repeat the index, or for RLE4 alternate its two nibbles) or
+/// an escape: 0 end of line, 1 end of bitmap, 2 move by (dx, dy), or
+/// n >= 3 literal indices padded to a 16-bit boundary.
+fn decode_rle(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    bits: u16,
+    palette: &[BltPixel],
+) -> Result<Vec<BltPixel>, ImageError> {
+    let (width, height) = (width as usize, height as usize);
+    let mut pixels = vec![BltPixel::default(); width * height];
+    let nibble = |byte: u8, i: usize| match (bits, i % 2) {
+        (8, _) => byte,
+        (_, 0) => byte >> 4,
+        _ => byte & 0x0F,
+    };
+    // `y` counts rows from the bottom of the image; pixels past the right
+    // or top edge are dropped
+    let mut put = |x: usize, y: usize, index: u8| {
+        if x < width && y < height {
+            pixels[(height - 1 - y) * width + x] = palette_entry(palette, index)?;
+        }
+        Ok::<(), ImageError>(())
+    };
+
+    let (mut x, mut y) = (0usize, 0usize);
+    let mut position = 0;
+    loop {
+        let pair = data
+            .get(position..position + 2)
+            .ok_or(ImageError::Truncated)?;
+        position += 2;
+        match (pair[0], pair[1]) {
+            (0, 0) => {
+                x = 0;
+                y += 1;
+            }
+            (0, 1) => break,
+            (0, 2) => {
+                let delta = data
+                    .get(position..position + 2)
+                    .ok_or(ImageError::Truncated)?;
+                position += 2;
+                x += delta[0] as usize;
+                y += delta[1] as usize;
+            }
+            (0, count) => {
+                let count = count as usize;
+                let length = if bits == 8 { count } else { count.div_ceil(2) };
+                let literal = data
+                    .get(position..position + length)
+                    .ok_or(ImageError::Truncated)?;
+                for i in 0..count {
+                    let byte = if bits == 8 {
+                        literal[i]
+                    } else {
+                        literal[i / 2]
+                    };
+                    put(x, y, nibble(byte, i))?;
+                    x += 1;
+                }
+                position += length + length % 2;
+            }
+            (count, value) => {
+                for i in 0..count as usize {
+                    put(x, y, nibble(value, i))?;
+                    x += 1;
+                }
+            }
+        }
+    }
+    Ok(pixels)
+}
+
+/// Mix one channel: `alpha` of `source` over `destination`, rounded.
+fn blend(source: u8, destination: u8, alpha: u8) -> u8 {
+    let alpha = alpha as u32;
+    ((source as u32 * alpha + destination as u32 * (255 - alpha) + 127) / 255) as u8
+}
+
+impl Canvas {
+    /// Draw `image` with its top-left corner at (`x`, `y`), blending each
+    /// pixel over the canvas by its alpha, inside the clip rectangle.
+    pub fn draw_image(&mut self, x: i32, y: i32, image: &Image) {
+        let area = Rect::new(x, y, image.width, image.height).intersect(&self.clip);
+        for row in area.y..area.bottom() {
+            for column in area.x..area.right() {
+                let source =
+                    image.pixels[((row - y) as u32 * image.width + (column - x) as u32) as usize];
+                let offset = (row as u32 * self.stride + column as u32) as usize;
+                let color = match source.alpha() {
+                    0 => continue,
+                    255 => Color::new(source.red, source.green, source.blue),
+                    alpha => {
+                        let under = Color::from_pixel(self.pixels[offset], self.format);
+                        Color::new(
+                            blend(source.red, under.red, alpha),
+                            blend(source.green, under.green, alpha),
+                            blend(source.blue, under.blue, alpha),
+                        )
+                    }
+                };
+                self.pixels[offset] = color.to_pixel(self.format);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PixelFormat;
+
+    /// A BMP file around `dib` (header, masks, palette) and `pixels`.
+    fn bmp(dib: &[u8], pixels: &[u8]) -> Vec<u8> {
+        let offset = (BMP_FILE_HEADER_SIZE + dib.len()) as u32;
+        let mut file = b"BM".to_vec();
+        file.extend_from_slice(&(offset + pixels.len() as u32).to_le_bytes());
+        file.extend_from_slice(&[0; 4]);
+        file.extend_from_slice(&offset.to_le_bytes());
+        file.extend_from_slice(dib);
+        file.extend_from_slice(pixels);
+        file
+    }
+
+    /// A BITMAPINFOHEADER.
+    fn info_header(width: i32, height: i32, bits: u16, compression: u32, colors: u32) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&40u32.to_le_bytes());
+        header.extend_from_slice(&width.to_le_bytes());
+        header.extend_from_slice(&height.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&bits.to_le_bytes());
+        header.extend_from_slice(&compression.to_le_bytes());
+        header.extend_from_slice(&[0; 12]);
+        header.extend_from_slice(&colors.to_le_bytes());
+        header.extend_from_slice(&[0; 4]);
+        header
+    }
+
+    #[test]
+    fn test_uncompressed_bmp() {
+        // 24-bit, 2x2, bottom-up: rows are padded from 6 to 8 bytes
+        let pixels = [
+            0, 0, 255, 0, 255, 0, 0, 0, // bottom: red, green
+            255, 0, 0, 255, 255, 255, 0, 0, // top: blue, white
+        ];
+        let image = Image::decode(&bmp(&info_header(2, 2, 24, BI_RGB, 0), &pixels)).unwrap();
+        assert_eq!(
+            image.pixel(0, 0),
+            Some(BltPixel::with_alpha(0, 0, 255, 255))
+        );
+        assert_eq!(
+            image.pixel(1, 0),
+            Some(BltPixel::with_alpha(255, 255, 255, 255))
+        );
+        assert_eq!(
+            image.pixel(0, 1),
+            Some(BltPixel::with_alpha(255, 0, 0, 255))
+        );
+        assert!(image.is_opaque());
+
+        // 1-bit, top-down, 10 pixels wide with a two-entry palette
+        let mut dib = info_header(10, -1, 1, BI_RGB, 2);
+        dib.extend_from_slice(&[0, 0, 0, 0, 0x10, 0x20, 0x30, 0]);
+        let image = Image::from_bmp(&bmp(&dib, &[0b1010_0000, 0b0100_0000, 0, 0])).unwrap();
+        let lit: Vec<u32> = (0..10)
+            .filter(|&x| image.pixel(x, 0).unwrap().red == 0x30)
+            .collect();
+        assert_eq!(lit, [0, 2, 9]);
+
+        // 32-bit ARGB with an alpha mask, 16-bit RGB565 via BI_BITFIELDS
+        let mut dib = info_header(1, 1, 32, BI_ALPHABITFIELDS, 0);
+        for mask in [0x00FF0000u32, 0x0000FF00, 0x000000FF, 0xFF000000] {
+            dib.extend_from_slice(&mask.to_le_bytes());
+        }
+        let image = Image::from_bmp(&bmp(&dib, &0x80112233u32.to_le_bytes())).unwrap();
+        assert_eq!(
+            image.pixel(0, 0),
+            Some(BltPixel::with_alpha(0x11, 0x22, 0x33, 0x80))
+        );
+        let mut dib = info_header(1, 1, 16, BI_BITFIELDS, 0);
+        for mask in [0xF800u32, 0x07E0, 0x001F] {
+            dib.extend_from_slice(&mask.to_le_bytes());
+        }
+        let image = Image::from_bmp(&bmp(&dib, &[0x1F, 0xF8, 0, 0])).unwrap();
+        assert_eq!(
+            image.pixel(0, 0),
+            Some(BltPixel::with_alpha(255, 0, 255, 255))
+        );
+
+        let file = bmp(&info_header(4, 4, 24, BI_RGB, 0), &[0; 40]);
+        assert_eq!(Image::from_bmp(&file).unwrap_err(), ImageError::Truncated);
+        let file = bmp(&info_header(1, 1, 24, 4, 0), &[0; 4]);
+        assert_eq!(
+            Image::from_bmp(&file).unwrap_err(),
+            ImageError::Unsupported("BMP compression")
+        );
+    }
+
+    #[test]
+    fn test_rle_bmp() {
+        let palette = [0, 0, 0, 0, 0, 0, 255, 0, 0, 255, 0, 0, 255, 0, 0, 0];
+        // RLE8, 6x3: bottom row a run and a literal, then skip to the top
+        // row's fourth pixel
+        let mut dib = info_header(6, 3, 8, BI_RLE8, 4);
+        dib.extend_from_slice(&palette);
+        let stream = [
+            3, 1, // 3 x red
+            0, 3, 2, 3, 0, 0, // literal green, blue, black (+ pad)
+            0, 0, // end of line
+            0, 2, 3, 1, // move right 3, up 1
+            2, 2, // 2 x green
+            0, 1, // end of bitmap
+        ];
+        let image = Image::from_bmp(&bmp(&dib, &stream)).unwrap();
+        let red = BltPixel::with_alpha(255, 0, 0, 255);
+        let row = |y| {
+            (0..6)
+                .map(|x| image.pixel(x, y).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(row(2)[..3], [red; 3]);
+        assert_eq!(row(2)[3].green, 255);
+        assert_eq!(row(2)[4].blue, 255);
+        assert_eq!(row(2)[5].alpha(), 255);
+        assert!(row(1).iter().all(|pixel| pixel.alpha() == 0));
+        assert_eq!(row(0)[..3], [BltPixel::default(); 3]);
+        assert_eq!(
+            (row(0)[3].green, row(0)[4].green, row(0)[5].alpha()),
+            (255, 255, 0)
+        );
+
+        // RLE4, 5x1: a run alternating nibbles, a 3-nibble literal
+        let mut dib = info_header(5, 1, 4, BI_RLE4, 4);
+        dib.extend_from_slice(&palette);
+        let image = Image::from_bmp(&bmp(&dib, &[2, 0x12, 0, 3, 0x31, 0x20, 0, 1])).unwrap();
+        let greens: Vec<u8> = (0..5).map(|x| image.pixel(x, 0).unwrap().green).collect();
+        assert_eq!(greens, [0, 255, 0, 0, 255]);
+
+        let mut dib = info_header(2, 1, 8, BI_RLE8, 4);
+        dib.extend_from_slice(&palette);
+        assert_eq!(
+            Image::from_bmp(&bmp(&dib, &[2, 9, 0, 1])).unwrap_err(),
+            ImageError::Malformed("palette index out of range")
+        );
+        assert_eq!(
+            Image::from_bmp(&bmp(&dib, &[2, 1])).unwrap_err(),
+            ImageError::Truncated
+        );
+    }
+
+    #[test]
+    fn test_draw_image_blends_and_clips() {
+        let mut canvas = Canvas::new(4, 2, PixelFormat::BlueGreenRedReserved8BitPerColor);
+        canvas.clear(Color::new(0, 0, 200));
+        let image = Image::new(
+            3,
+            1,
+            vec![
+                BltPixel::with_alpha(255, 255, 255, 255),
+                BltPixel::with_alpha(255, 0, 0, 128),
+                BltPixel::with_alpha(255, 255, 255, 0),
+            ],
+        );
+        canvas.set_clip(Rect::new(0, 0, 3, 2));
+        canvas.draw_image(-1, 1, &image);
+        canvas.draw_image(1, 0, &image);
+
+        let pixel = |x, y| {
+            let color = canvas.get_pixel(x, y).unwrap();
+            (color.red, color.green, color.blue)
+        };
+        assert_eq!(pixel(0, 1), (128, 0, 100));
+        assert_eq!(pixel(1, 1), (0, 0, 200));
+        assert_eq!(pixel(1, 0), (255, 255, 255));
+        assert_eq!(pixel(2, 0), (128, 0, 100));
+        // Outside the clip
+        assert_eq!(pixel(3, 0), (0, 0, 200));
+    }
+}
@@ -27,6 +27,24 @@
 //! Real UEFI graphics requires the uefi crate and no_std.
 
 use std::fmt;
+use std::fs;
+
+mod blt;
+mod console;
+mod dirty;
+mod font;
+mod image;
+mod png;
+mod raster;
+mod snapshot;
+mod zlib;
+
+use console::TextConsole;
+use dirty::{DirtyRegion, PresentStats};
+use font::Font;
+use image::Image;
+use raster::Rect;
+use snapshot::check_golden;
 
 fn main() {
     println!("=== UEFI Graphics Concepts ===\n");
@@ -43,11 +61,20 @@ fn main() {
     println!("\n--- Drawing Primitives ---");
     drawing_primitives();
 
+    println!("\n--- Text Rendering ---");
+    text_rendering();
+
     println!("\n--- BLT Operations ---");
     blt_operations();
 
+    println!("\n--- Image Decoding ---");
+    image_decoding();
+
     println!("\n--- Double Buffering ---");
     double_buffering();
+
+    println!("\n--- Frame Export ---");
+    frame_export();
 }
 
 // ============================================
@@ -55,6 +82,7 @@ fn main() {
 // ============================================
 
 /// UEFI pixel format types
+#[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum PixelFormat {
     /// Red-Green-Blue-Reserved 8-bit per color
@@ -87,6 +115,7 @@ struct PixelBitmask {
     reserved_mask: u32,
 }
 
+#[allow(dead_code)]
 impl PixelBitmask {
     /// Standard RGB888 mask
     fn rgb888() -> Self {
@@ -107,6 +136,16 @@ impl PixelBitmask {
             reserved_mask: 0xFF000000,
         }
     }
+
+    /// 10 bits per color (30-bit deep color)
+    fn x2r10g10b10() -> Self {
+        PixelBitmask {
+            red_mask: 0x3FF00000,
+            green_mask: 0x000FFC00,
+            blue_mask: 0x000003FF,
+            reserved_mask: 0xC0000000,
+        }
+    }
 }
 
 /// Color representation
@@ -149,16 +188,16 @@ impl Color {
     }
 
     /// Convert to 32-bit pixel value based on format
-    fn to_pixel(&self, format: PixelFormat) -> u32 {
+    fn to_pixel(self, format: PixelFormat) -> u32 {
         match format {
             PixelFormat::RedGreenBlueReserved8BitPerColor => {
-                ((self.red as u32) << 0)
+                (self.red as u32)
                     | ((self.green as u32) << 8)
                     | ((self.blue as u32) << 16)
                     | ((self.reserved as u32) << 24)
             }
             PixelFormat::BlueGreenRedReserved8BitPerColor => {
-                ((self.blue as u32) << 0)
+                (self.blue as u32)
                     | ((self.green as u32) << 8)
                     | ((self.red as u32) << 16)
                     | ((self.reserved as u32) << 24)
@@ -213,6 +252,7 @@ fn pixel_formats() {
 // ============================================
 
 /// Graphics mode information
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 struct ModeInfo {
     version: u32,
@@ -235,6 +275,19 @@ impl ModeInfo {
         }
     }
 
+    fn with_bitmask(width: u32, height: u32, bitmask: PixelBitmask) -> Self {
+        ModeInfo {
+            pixel_bitmask: Some(bitmask),
+            ..ModeInfo::new(width, height, PixelFormat::BitMask)
+        }
+    }
+
+    /// Scan lines padded to `pixels_per_scan_line` pixels
+    fn with_stride(mut self, pixels_per_scan_line: u32) -> Self {
+        self.pixels_per_scan_line = pixels_per_scan_line.max(self.horizontal_resolution);
+        self
+    }
+
     fn framebuffer_size(&self) -> usize {
         (self.pixels_per_scan_line * self.vertical_resolution * 4) as usize
     }
@@ -262,14 +315,17 @@ struct GraphicsOutput {
 
 impl GraphicsOutput {
     fn new() -> Self {
-        let modes = vec![
+        GraphicsOutput::with_modes(vec![
             ModeInfo::new(640, 480, PixelFormat::BlueGreenRedReserved8BitPerColor),
             ModeInfo::new(800, 600, PixelFormat::BlueGreenRedReserved8BitPerColor),
             ModeInfo::new(1024, 768, PixelFormat::BlueGreenRedReserved8BitPerColor),
             ModeInfo::new(1280, 720, PixelFormat::BlueGreenRedReserved8BitPerColor),
             ModeInfo::new(1920, 1080, PixelFormat::BlueGreenRedReserved8BitPerColor),
-        ];
+        ])
+    }
 
+    /// A GOP offering `modes`, starting in mode 0.
+    fn with_modes(modes: Vec<ModeInfo>) -> Self {
         let default_mode = &modes[0];
         let fb_size =
             (default_mode.pixels_per_scan_line * default_mode.vertical_resolution) as usize;
@@ -396,12 +452,18 @@ fn framebuffer_operations() {
 // ============================================
 
 /// Simple framebuffer wrapper for drawing
+///
+/// Drawing is clipped to `clip` (the whole canvas unless narrowed with
+/// `set_clip`); `clear` and `scroll_up` always act on the whole canvas.
+/// Lines, circles, polygons, flood fill and the other shapes live in
+/// [`raster`].
 struct Canvas {
     width: u32,
     height: u32,
     stride: u32,
     format: PixelFormat,
     pixels: Vec<u32>,
+    clip: Rect,
 }
 
 impl Canvas {
@@ -412,6 +474,7 @@ impl Canvas {
             stride: width,
             format,
             pixels: vec![0; (width * height) as usize],
+            clip: Rect::new(0, 0, width, height),
         }
     }
 
@@ -422,8 +485,7 @@ impl Canvas {
 
     fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
         if x < self.width && y < self.height {
-            let offset = (y * self.stride + x) as usize;
-            self.pixels[offset] = color.to_pixel(self.format);
+            self.plot(x as i32, y as i32, color.to_pixel(self.format));
         }
     }
 
@@ -437,26 +499,30 @@ impl Canvas {
     }
 
     fn draw_hline(&mut self, x: u32, y: u32, length: u32, color: Color) {
-        let pixel = color.to_pixel(self.format);
-        for i in 0..length {
-            if x + i < self.width && y < self.height {
-                let offset = (y * self.stride + x + i) as usize;
-                self.pixels[offset] = pixel;
-            }
+        let end = x.saturating_add(length).min(self.width);
+        if x < end && y < self.height {
+            self.span(
+                x as i32,
+                end as i32 - 1,
+                y as i32,
+                color.to_pixel(self.format),
+            );
         }
     }
 
     fn draw_vline(&mut self, x: u32, y: u32, length: u32, color: Color) {
         let pixel = color.to_pixel(self.format);
-        for i in 0..length {
-            if x < self.width && y + i < self.height {
-                let offset = ((y + i) * self.stride + x) as usize;
-                self.pixels[offset] = pixel;
+        if x < self.width {
+            for row in y..y.saturating_add(length).min(self.height) {
+                self.plot(x as i32, row as i32, pixel);
             }
         }
     }
 
     fn draw_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: Color) {
+        if width == 0 || height == 0 {
+            return;
+        }
         self.draw_hline(x, y, width, color);
         self.draw_hline(x, y + height - 1, width, color);
         self.draw_vline(x, y, height, color);
@@ -464,27 +530,101 @@ impl Canvas {
     }
 
     fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: Color) {
-        let pixel = color.to_pixel(self.format);
-        for row in 0..height {
-            if y + row >= self.height {
-                break;
+        for row in y..y.saturating_add(height).min(self.height) {
+            self.draw_hline(x, row, width, color);
+        }
+    }
+
+    /// Draw one character with its top-left corner at (`x`, `y`), clipped
+    /// to the canvas. With no background, unset glyph pixels are left as
+    /// they were.
+    fn draw_char(
+        &mut self,
+        x: i32,
+        y: i32,
+        c: char,
+        font: &Font,
+        foreground: Color,
+        background: Option<Color>,
+    ) {
+        let Some(glyph) = font.glyph_or_replacement(c) else {
+            return;
+        };
+        for row in 0..font.height() {
+            let py = y + row as i32;
+            if py < 0 || py >= self.height as i32 {
+                continue;
+            }
+            for col in 0..font.width() {
+                let px = x + col as i32;
+                if px < 0 || px >= self.width as i32 {
+                    continue;
+                }
+                let color = if font.pixel(glyph, col, row) {
+                    foreground
+                } else if let Some(background) = background {
+                    background
+                } else {
+                    continue;
+                };
+                self.set_pixel(px as u32, py as u32, color);
             }
-            let row_offset = ((y + row) * self.stride + x) as usize;
-            for col in 0..width {
-                if x + col >= self.width {
+        }
+    }
+
+    /// Draw a string starting at (`x`, `y`); `\n` moves down one line and
+    /// back to `x`. Characters off the canvas are clipped, not wrapped.
+    fn draw_text(
+        &mut self,
+        x: i32,
+        y: i32,
+        text: &str,
+        font: &Font,
+        foreground: Color,
+        background: Option<Color>,
+    ) {
+        for (line_number, line) in text.split('\n').enumerate() {
+            let line_y = y + (line_number as u32 * font.height()) as i32;
+            for (column, c) in line.chars().enumerate() {
+                let char_x = x + (column as u32 * font.width()) as i32;
+                if char_x >= self.width as i32 {
                     break;
                 }
-                self.pixels[row_offset + col as usize] = pixel;
+                self.draw_char(char_x, line_y, c, font, foreground, background);
             }
         }
     }
 
+    /// Move the whole canvas up by `rows` pixel rows, filling the rows
+    /// uncovered at the bottom with `fill`.
+    fn scroll_up(&mut self, rows: u32, fill: Color) {
+        let rows = rows.min(self.height);
+        let shift = (rows * self.stride) as usize;
+        self.pixels.copy_within(shift.., 0);
+        let uncovered = self.pixels.len() - shift;
+        self.pixels[uncovered..].fill(fill.to_pixel(self.format));
+    }
+
     /// Count non-zero pixels (for verification)
     fn count_filled_pixels(&self) -> usize {
         self.pixels.iter().filter(|&&p| p != 0).count()
     }
 }
 
+/// Lines, circles, ellipses, polygons and a rounded frame on a 48x20 canvas
+fn splash_canvas() -> Canvas {
+    let mut splash = Canvas::new(48, 20, PixelFormat::BlueGreenRedReserved8BitPerColor);
+    splash.draw_rounded_rect(0, 0, 48, 20, 5, Color::white());
+    splash.draw_line(3, 17, 14, 3, Color::white());
+    splash.draw_circle(20, 9, 5, Color::white());
+    splash.fill_circle(20, 9, 2, Color::white());
+    splash.draw_ellipse(34, 6, 8, 3, Color::white());
+    splash.fill_ellipse(34, 6, 4, 1, Color::white());
+    splash.draw_polygon(&[(28, 16), (36, 11), (44, 16)], Color::white());
+    splash.fill_polygon(&[(32, 15), (36, 13), (40, 15)], Color::white());
+    splash
+}
+
 fn drawing_primitives() {
     let mut canvas = Canvas::new(320, 200, PixelFormat::BlueGreenRedReserved8BitPerColor);
 
@@ -513,27 +653,173 @@ fn drawing_primitives() {
 
     let filled = canvas.count_filled_pixels();
     println!("\n  Total filled pixels: {}", filled);
+
+    // Splash-screen shapes on a small canvas, shown one character per pixel
+    let splash = splash_canvas();
+    println!("\n  Lines, circles, ellipses, polygons and a rounded frame:");
+    print_region(&splash, 0, 0, 48, 20);
+
+    // Flood fill the ring between the circle and its filled centre
+    let mut filled = splash_canvas();
+    filled.flood_fill(20, 6, Color::white());
+    println!(
+        "  Flood fill inside the circle outline lit {} more pixels",
+        filled.count_filled_pixels() - splash.count_filled_pixels()
+    );
+
+    // A progress bar: everything drawn inside the clip stays inside it
+    let mut bar = Canvas::new(48, 6, PixelFormat::BlueGreenRedReserved8BitPerColor);
+    bar.draw_rounded_rect(0, 0, 48, 6, 2, Color::white());
+    bar.set_clip(Rect::new(2, 2, 44, 2).intersect(&Rect::new(0, 0, 30, 6)));
+    bar.fill_rounded_rect(-10, -10, 100, 30, 2, Color::green());
+    println!(
+        "\n  Progress bar clipped to {:?} (empty clip: {}):",
+        bar.clip(),
+        bar.clip().is_empty()
+    );
+    bar.reset_clip();
+    print_region(&bar, 0, 0, 48, bar.bounds().height);
+}
+
+// ============================================
+// Text Rendering
+// ============================================
+
+/// Print a canvas region as ASCII art, one character per pixel
+fn print_region(canvas: &Canvas, x: u32, y: u32, width: u32, height: u32) {
+    for row in y..y + height {
+        let line: String = (x..x + width)
+            .map(|col| match canvas.get_pixel(col, row) {
+                Some(color) if color.red > 128 || color.green > 128 || color.blue > 128 => '#',
+                Some(_) => '.',
+                None => ' ',
+            })
+            .collect();
+        println!("    {}", line);
+    }
+}
+
+fn text_rendering() {
+    let font = Font::vga8x16();
+    let mut canvas = Canvas::new(320, 200, PixelFormat::BlueGreenRedReserved8BitPerColor);
+    canvas.clear(Color::black());
+
+    canvas.draw_text(4, 4, "UEFI", &font, Color::white(), None);
+    println!(
+        "  Built-in {}x{} font, {} glyphs; \"UEFI\" is {} pixels wide:",
+        font.width(),
+        font.height(),
+        font.glyph_count(),
+        font.text_width("UEFI")
+    );
+    print_region(&canvas, 4, 6, 32, 11);
+
+    // Text running off the right edge is clipped
+    let before = canvas.count_filled_pixels();
+    canvas.draw_text(
+        300,
+        40,
+        "clipped",
+        &font,
+        Color::green(),
+        Some(Color::blue()),
+    );
+    println!(
+        "  \"clipped\" at x=300 keeps {} of {} pixels",
+        canvas.count_filled_pixels() - before,
+        font.text_width("clipped") * font.height()
+    );
+
+    // A PSF2 round trip, as if loaded from \EFI\fonts\ter-16n.psf
+    let psf = font.to_psf2();
+    let loaded = Font::from_psf(&psf).unwrap();
+    println!(
+        "  PSF2 image: {} bytes, reloaded with {} glyphs",
+        psf.len(),
+        loaded.glyph_count()
+    );
+    if let Err(e) = Font::from_psf(&psf[..64]) {
+        println!("  Truncated PSF2: {}", e);
+    }
+
+    // Boot menu on a small console: 30x5 characters
+    let mut console = TextConsole::new(
+        Canvas::new(240, 80, PixelFormat::BlueGreenRedReserved8BitPerColor),
+        loaded,
+    );
+    use std::fmt::Write;
+    console.set_colors(Color::white(), Color::blue());
+    writeln!(console, "BootManager v1.0").unwrap();
+    console.set_colors(Color::new(170, 170, 170), Color::black());
+    for entry in [
+        "Linux",
+        "Windows Boot Manager",
+        "UEFI Shell",
+        "Firmware Setup",
+    ] {
+        writeln!(console, "  {}", entry).unwrap();
+    }
+    write!(console, "Boot in 5s\tEnter: select").unwrap();
+
+    // The header has scrolled away; mark the first entry, now on row 0
+    console.set_cursor(0, 0);
+    console.put_char('>');
+
+    println!(
+        "\n  {}x{} console after {} line(s) scrolled, cursor at {:?}:",
+        console.columns(),
+        console.rows(),
+        console.lines_scrolled(),
+        console.cursor()
+    );
+    for row in 0..console.rows() {
+        println!("    |{:30}|", console.line(row));
+    }
+    println!(
+        "  {} lit pixels on the console framebuffer",
+        console.canvas().count_filled_pixels()
+    );
 }
 
 // ============================================
 // BLT Operations
 // ============================================
 
-/// BLT operation types (Block Transfer)
-#[derive(Debug, Clone, Copy)]
+/// Simulated EFI_STATUS returned by Blt()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Status(usize);
+
+impl Status {
+    const INVALID_PARAMETER: Status = Status(2);
+    const UNSUPPORTED: Status = Status(3);
+
+    fn description(&self) -> &'static str {
+        match self.0 {
+            0 => "Success",
+            2 => "Invalid Parameter",
+            3 => "Unsupported",
+            _ => "Unknown Error",
+        }
+    }
+}
+
+/// BLT operation types (Block Transfer), with their
+/// EFI_GRAPHICS_OUTPUT_BLT_OPERATION values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
 enum BltOperation {
-    /// Write data from buffer to video
-    VideoFill,
-    /// Copy from buffer to video
-    BufferToVideo,
+    /// Fill a video rectangle with the first buffer pixel
+    VideoFill = 0,
     /// Copy from video to buffer
-    VideoToBuffer,
+    VideoToBltBuffer = 1,
+    /// Copy from buffer to video
+    BufferToVideo = 2,
     /// Copy within video memory
-    VideoToVideo,
+    VideoToVideo = 3,
 }
 
 /// BLT pixel (BGRX format)
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 struct BltPixel {
     blue: u8,
     green: u8,
@@ -559,91 +845,292 @@ impl BltPixel {
 fn blt_operations() {
     println!("  BLT (Block Transfer) Operations:");
     println!("    - VideoFill: Fill area with single color");
+    println!("    - VideoToBltBuffer: Copy screen to buffer");
     println!("    - BufferToVideo: Copy buffer to screen");
-    println!("    - VideoToBuffer: Copy screen to buffer");
     println!("    - VideoToVideo: Copy within screen");
 
-    // Simulate a framebuffer
-    let width = 100u32;
-    let height = 100u32;
-    let mut framebuffer = vec![BltPixel::default(); (width * height) as usize];
+    // A 100x100 BGRX mode whose scan lines are padded to 128 pixels, and a
+    // 30-bit BitMask mode
+    let mut gop = GraphicsOutput::with_modes(vec![
+        ModeInfo::new(100, 100, PixelFormat::BlueGreenRedReserved8BitPerColor).with_stride(128),
+        ModeInfo::with_bitmask(64, 64, PixelBitmask::x2r10g10b10()),
+    ]);
+    gop.set_mode(0).unwrap();
+    println!("\n  Mode: {}", gop.current_mode_info());
 
     // VideoFill - fill a rectangle
-    println!("\n  Simulating VideoFill:");
-    let fill_color = BltPixel::new(0, 128, 255); // Orange
-    let dest_x = 10u32;
-    let dest_y = 10u32;
-    let fill_width = 30u32;
-    let fill_height = 20u32;
-
-    for row in 0..fill_height {
-        let offset = ((dest_y + row) * width + dest_x) as usize;
-        for col in 0..fill_width {
-            framebuffer[offset + col as usize] = fill_color;
-        }
-    }
+    let orange = BltPixel::from_color(Color::new(255, 128, 0));
+    gop.blt(
+        BltOperation::VideoFill,
+        &mut [orange],
+        (0, 0),
+        (10, 10),
+        30,
+        20,
+        0,
+    )
+    .unwrap();
+    println!("\n  VideoFill: 30x20 at (10, 10) with orange");
+
+    // BufferToVideo - upload the middle 4x4 of an 8x8 gradient; delta is
+    // the width of the whole image in bytes
+    let mut image: Vec<BltPixel> = (0..64)
+        .map(|i| BltPixel::new((i % 8 * 32) as u8, (i / 8 * 32) as u8, 0))
+        .collect();
+    gop.blt(
+        BltOperation::BufferToVideo,
+        &mut image,
+        (2, 2),
+        (50, 50),
+        4,
+        4,
+        8 * 4,
+    )
+    .unwrap();
+    println!("  BufferToVideo: 4x4 from (2, 2) of an 8x8 image (delta 32) to (50, 50)");
+
+    // VideoToVideo - overlapping move: slide the orange block down-right
+    gop.blt(
+        BltOperation::VideoToVideo,
+        &mut [],
+        (10, 10),
+        (15, 14),
+        30,
+        20,
+        0,
+    )
+    .unwrap();
+    println!("  VideoToVideo: 30x20 from (10, 10) to (15, 14), overlapping");
+
+    // VideoToBltBuffer - read back a region spanning both
+    let mut readback = vec![BltPixel::default(); 100 * 100];
+    gop.blt(
+        BltOperation::VideoToBltBuffer,
+        &mut readback,
+        (0, 0),
+        (0, 0),
+        100,
+        100,
+        0,
+    )
+    .unwrap();
+    let filled = readback
+        .iter()
+        .filter(|p| p.red != 0 || p.green != 0 || p.blue != 0)
+        .count();
     println!(
-        "    Filled {}x{} area at ({}, {}) with orange",
-        fill_width, fill_height, dest_x, dest_y
+        "  VideoToBltBuffer: read back 100x100, {} non-black pixels",
+        filled
+    );
+    println!(
+        "    (51, 51) = {:?}, raw framebuffer word = {:#010X}",
+        readback[51 * 100 + 51],
+        gop.framebuffer[51 * 128 + 51]
     );
 
-    // BufferToVideo - copy from source buffer
-    println!("\n  Simulating BufferToVideo:");
-    let src_buffer: Vec<BltPixel> = (0..16)
-        .map(|i| BltPixel::new((i * 16) as u8, 0, 0))
-        .collect();
-
-    let dest_x = 50u32;
-    let dest_y = 50u32;
-    for (i, pixel) in src_buffer.iter().enumerate() {
-        let x = dest_x + (i % 4) as u32;
-        let y = dest_y + (i / 4) as u32;
-        let offset = (y * width + x) as usize;
-        framebuffer[offset] = *pixel;
+    // Parameter validation
+    println!("\n  Rejected calls:");
+    let rejected = [
+        (
+            "off-screen fill",
+            BltOperation::VideoFill,
+            (0, 0),
+            (90, 90),
+            20,
+            20,
+            0,
+        ),
+        (
+            "zero width",
+            BltOperation::VideoToVideo,
+            (0, 0),
+            (1, 1),
+            0,
+            5,
+            0,
+        ),
+        (
+            "delta not whole pixels",
+            BltOperation::BufferToVideo,
+            (0, 0),
+            (0, 0),
+            4,
+            4,
+            30,
+        ),
+        (
+            "rectangle past buffer end",
+            BltOperation::BufferToVideo,
+            (4, 6),
+            (0, 0),
+            4,
+            4,
+            32,
+        ),
+    ];
+    for (what, op, src, dst, width, height, delta) in rejected {
+        let status = gop
+            .blt(op, &mut image, src, dst, width, height, delta)
+            .unwrap_err();
+        println!("    {:<26} -> {}", what, status.description());
     }
+    match BltOperation::try_from(7) {
+        Ok(op) => println!("    operation 7 -> {:?}", op),
+        Err(status) => println!("    {:<26} -> {}", "operation 7", status.description()),
+    }
+
+    // The same pixel in a BitMask mode
+    gop.set_mode(1).unwrap();
+    println!("\n  Mode: {}", gop.current_mode_info());
+    gop.blt(
+        BltOperation::VideoFill,
+        &mut [orange],
+        (0, 0),
+        (0, 0),
+        1,
+        1,
+        0,
+    )
+    .unwrap();
+    let mut back = [BltPixel::default()];
+    gop.blt(
+        BltOperation::VideoToBltBuffer,
+        &mut back,
+        (0, 0),
+        (0, 0),
+        1,
+        1,
+        0,
+    )
+    .unwrap();
     println!(
-        "    Copied 4x4 gradient pattern to ({}, {})",
-        dest_x, dest_y
+        "    orange -> {:#010X} -> {:?}",
+        gop.framebuffer[0], back[0]
     );
+}
 
-    // VideoToVideo - copy within framebuffer
-    println!("\n  Simulating VideoToVideo:");
-    let src_x = 10u32;
-    let src_y = 10u32;
-    let copy_width = 15u32;
-    let copy_height = 10u32;
-    let new_dest_x = 60u32;
-    let new_dest_y = 20u32;
-
-    // Copy to temp buffer first (avoid overlap issues)
-    let mut temp = vec![BltPixel::default(); (copy_width * copy_height) as usize];
-    for row in 0..copy_height {
-        let src_offset = ((src_y + row) * width + src_x) as usize;
-        let temp_offset = (row * copy_width) as usize;
-        for col in 0..copy_width {
-            temp[temp_offset + col as usize] = framebuffer[src_offset + col as usize];
-        }
-    }
+// ============================================
+// Image Decoding
+// ============================================
+
+/// A 16x16 boot logo: a blue disc with a white centre and an antialiased
+/// edge, RGBA, every row using a different filter
+const BOOT_LOGO_PNG: &[u8] = &[
+    0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x10, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1F, 0xF3, 0xFF,
+    0x61, 0x00, 0x00, 0x00, 0xF6, 0x49, 0x44, 0x41, 0x54, 0x78, 0xDA, 0x63, 0xD0, 0xA8, 0xF8, 0xCF,
+    0x80, 0x05, 0x0B, 0x00, 0x71, 0x00, 0x10, 0x37, 0x40, 0x71, 0x00, 0x54, 0x0C, 0x43, 0x2D, 0x23,
+    0x88, 0x40, 0x02, 0x02, 0x40, 0xDC, 0x0F, 0xC4, 0x09, 0x0C, 0xD8, 0xC1, 0x02, 0x20, 0x2E, 0x04,
+    0xE2, 0x0F, 0x30, 0x01, 0x26, 0x24, 0x49, 0x03, 0x20, 0xBE, 0x8F, 0x47, 0x33, 0x03, 0x54, 0xEE,
+    0x3E, 0x54, 0x2D, 0x18, 0x30, 0x8B, 0xD8, 0x34, 0xC0, 0x6C, 0x3E, 0x0F, 0xA5, 0x09, 0x01, 0x0E,
+    0x20, 0x8E, 0x00, 0xE2, 0x99, 0x40, 0xFC, 0x83, 0x05, 0x2A, 0xD8, 0x4F, 0xA4, 0x66, 0x74, 0xAF,
+    0x26, 0xC2, 0x02, 0xEC, 0x3F, 0x3A, 0x5E, 0x78, 0xE4, 0xFF, 0xFF, 0x4F, 0xDF, 0x21, 0x18, 0xC4,
+    0xC6, 0xA6, 0x06, 0xA4, 0x17, 0x14, 0x88, 0x01, 0x40, 0x93, 0xD6, 0x23, 0x1B, 0x5F, 0xE9, 0xC3,
+    0xC0, 0x10, 0x67, 0x8D, 0x6A, 0xE5, 0xA2, 0xA3, 0x0C, 0x0C, 0xED, 0x5B, 0x30, 0x5C, 0x12, 0xC8,
+    0x84, 0x1C, 0x20, 0x70, 0x51, 0x63, 0x4C, 0x37, 0x63, 0x13, 0x03, 0xE9, 0x05, 0x05, 0xA2, 0x03,
+    0x90, 0xE1, 0x80, 0x2C, 0x9A, 0x0A, 0xE4, 0xB1, 0xB3, 0xA0, 0xAA, 0xFC, 0xF5, 0x87, 0x81, 0x61,
+    0xF6, 0x41, 0x0C, 0x03, 0x0E, 0x82, 0x94, 0x5D, 0x40, 0x17, 0x5D, 0x7F, 0x16, 0xD3, 0x0B, 0x20,
+    0x31, 0x2C, 0xE0, 0x02, 0x55, 0x02, 0x11, 0x64, 0xD2, 0x7C, 0x02, 0x09, 0x08, 0x57, 0xAA, 0x4C,
+    0x84, 0xA5, 0x44, 0x94, 0xE4, 0x49, 0x04, 0xF8, 0x00, 0xD5, 0x03, 0x4F, 0x89, 0x3F, 0x80, 0x78,
+    0x27, 0x34, 0x85, 0x71, 0x10, 0xA1, 0xD9, 0x11, 0x88, 0x1F, 0x80, 0x38, 0x2C, 0x28, 0x01, 0xC2,
+    0xC0, 0xA0, 0x48, 0x6A, 0x66, 0x62, 0xA0, 0x34, 0x3B, 0x03, 0x00, 0x91, 0x2B, 0xBF, 0x56, 0x1A,
+    0xA7, 0xEB, 0xB4, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82,
+];
+
+/// A 9x7 check mark as an RLE8 bitmap. Only the green pixels are encoded;
+/// the rest is skipped with delta escapes and stays transparent
+#[rustfmt::skip]
+const CHECK_MARK_BMP: &[u8] = &[
+    // BITMAPFILEHEADER: "BM", file size 122, reserved, pixel data at 62
+    b'B', b'M', 122, 0, 0, 0, 0, 0, 0, 0, 62, 0, 0, 0,
+    // BITMAPINFOHEADER: 40 bytes, 9x7, 1 plane, 8 bpp, BI_RLE8, 60 bytes
+    // of pixel data, 72 DPI, 2 colors
+    40, 0, 0, 0, 9, 0, 0, 0, 7, 0, 0, 0, 1, 0, 8, 0, 1, 0, 0, 0, 60, 0, 0, 0,
+    0x13, 0x0B, 0, 0, 0x13, 0x0B, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0,
+    // Palette (blue, green, red, 0): unused, green
+    0, 0, 0, 0, 80, 200, 40, 0,
+    // Rows bottom-up: (count, index) runs, 00 02 dx dy moves, 00 00 ends a
+    // row, 00 01 ends the bitmap
+    0, 2, 2, 0, 2, 1, 0, 0,
+    0, 2, 1, 0, 4, 1, 0, 0,
+    2, 1, 0, 2, 2, 0, 2, 1, 0, 0,
+    1, 1, 0, 2, 4, 0, 2, 1, 0, 0,
+    0, 2, 6, 0, 2, 1, 0, 0,
+    0, 2, 7, 0, 2, 1, 0, 0,
+    0, 2, 8, 0, 1, 1, 0, 1,
+];
+
+/// The logo and the check mark blended over dark gray
+fn blended_images_canvas(logo: &Image, check: &Image) -> Canvas {
+    let mut canvas = Canvas::new(40, 16, PixelFormat::BlueGreenRedReserved8BitPerColor);
+    canvas.clear(Color::new(60, 60, 60));
+    canvas.draw_image(2, 0, logo);
+    canvas.draw_image(24, 5, check);
+    canvas
+}
 
-    // Copy from temp to destination
-    for row in 0..copy_height {
-        let dest_offset = ((new_dest_y + row) * width + new_dest_x) as usize;
-        let temp_offset = (row * copy_width) as usize;
-        for col in 0..copy_width {
-            framebuffer[dest_offset + col as usize] = temp[temp_offset + col as usize];
+fn image_decoding() {
+    let mut logo = match Image::decode(BOOT_LOGO_PNG) {
+        Ok(image) => image,
+        Err(error) => {
+            println!("  Logo failed to decode: {}", error);
+            return;
         }
-    }
+    };
+    println!(
+        "  Decoded {}-byte PNG: {}x{}, opaque: {}",
+        BOOT_LOGO_PNG.len(),
+        logo.width(),
+        logo.height(),
+        logo.is_opaque()
+    );
+    let check = Image::decode(CHECK_MARK_BMP).unwrap();
     println!(
-        "    Copied {}x{} from ({}, {}) to ({}, {})",
-        copy_width, copy_height, src_x, src_y, new_dest_x, new_dest_y
+        "  Decoded {}-byte RLE8 BMP: {}x{}, opaque: {}",
+        CHECK_MARK_BMP.len(),
+        check.width(),
+        check.height(),
+        check.is_opaque()
     );
 
-    // Count non-black pixels
-    let filled = framebuffer
-        .iter()
-        .filter(|p| p.red != 0 || p.green != 0 || p.blue != 0)
-        .count();
-    println!("\n  Total non-black pixels: {}", filled);
+    // Blt() copies pixels as they are, alpha and all: fine for a black
+    // screen, which is all the firmware shows before the logo
+    let mut gop = GraphicsOutput::new();
+    let mode = gop.current_mode_info().clone();
+    let center = (
+        (mode.horizontal_resolution - logo.width()) / 2,
+        (mode.vertical_resolution - logo.height()) / 2,
+    );
+    let (width, height) = (logo.width(), logo.height());
+    gop.blt(
+        BltOperation::BufferToVideo,
+        logo.pixels_mut(),
+        (0, 0),
+        center,
+        width,
+        height,
+        0,
+    )
+    .unwrap();
+    println!("  Blt() the logo to {:?} of the {} screen", center, mode);
+
+    // Over anything else, blend: the edge pixels mix with the background
+    let canvas = blended_images_canvas(&logo, &check);
+    let edge = canvas.get_pixel(2 + 3, 1).unwrap();
+    println!(
+        "  Edge pixel over gray: ({}, {}, {}) for alpha {}",
+        edge.red,
+        edge.green,
+        edge.blue,
+        logo.pixel(3, 1).unwrap().alpha()
+    );
+    println!("\n  Logo and check mark blended onto a canvas:");
+    print_region(&canvas, 0, 0, 40, 16);
+
+    let corrupt = &BOOT_LOGO_PNG[..BOOT_LOGO_PNG.len() / 2];
+    if let Err(error) = Image::decode(corrupt) {
+        println!("\n  Truncated PNG: {}", error);
+    }
 }
 
 // ============================================
@@ -651,11 +1138,17 @@ fn blt_operations() {
 // ============================================
 
 /// Double buffer for flicker-free rendering
+///
+/// The front buffer stands in for the GOP framebuffer. Drawing goes to the
+/// back buffer and `present()` copies only the dirty rectangles across
+/// (see the `dirty` module).
 struct DoubleBuffer {
     front: Vec<u32>,
     back: Vec<u32>,
     width: u32,
     height: u32,
+    dirty: DirtyRegion,
+    stats: PresentStats,
 }
 
 impl DoubleBuffer {
@@ -666,19 +1159,19 @@ impl DoubleBuffer {
             back: vec![0; size],
             width,
             height,
+            dirty: DirtyRegion::new(),
+            stats: PresentStats::default(),
         }
     }
 
-    /// Get the back buffer for drawing
+    /// Get the back buffer for drawing. The writes cannot be tracked, so
+    /// the whole surface is presented next time; use `fill_rect` or
+    /// `mark_dirty` to keep the copy small.
     fn back_buffer(&mut self) -> &mut [u32] {
+        self.dirty.add(self.bounds());
         &mut self.back
     }
 
-    /// Swap front and back buffers
-    fn swap(&mut self) {
-        std::mem::swap(&mut self.front, &mut self.back);
-    }
-
     /// Get the front buffer (for display)
     fn front_buffer(&self) -> &[u32] {
         &self.front
@@ -687,72 +1180,157 @@ impl DoubleBuffer {
     /// Clear the back buffer
     fn clear_back(&mut self, color: u32) {
         self.back.fill(color);
+        self.dirty.add(self.bounds());
     }
 }
 
 fn double_buffering() {
     let mut db = DoubleBuffer::new(320, 200);
+    let full_frame = db.width as u64 * db.height as u64 * 4;
 
     println!("  Double buffering simulation:");
     println!("    Buffer size: {}x{}", db.width, db.height);
 
-    // Frame 1: Draw to back buffer
-    db.clear_back(0x00000000); // Black
-    {
-        let back = db.back_buffer();
-        // Draw a simple pattern
-        for y in 50..100 {
-            for x in 50..150 {
-                let offset = y * 320 + x;
-                back[offset as usize] = 0x00FF0000; // Red in BGRX
-            }
-        }
-    }
-    println!("    Frame 1: Drew red rectangle to back buffer");
-
-    // Swap
-    db.swap();
-    println!("    Swapped buffers - frame 1 now visible");
+    // Frame 0: the first frame has to be copied in full
+    let background = 0x00102040;
+    db.clear_back(background);
+    let frame = db.present();
+    println!(
+        "    Frame 0: cleared, {} rect(s), {} bytes copied",
+        frame.rects, frame.bytes_copied
+    );
 
-    // Frame 2: Draw different content
-    db.clear_back(0x00000000);
-    {
-        let back = db.back_buffer();
-        for y in 100..150 {
-            for x in 100..200 {
-                let offset = y * 320 + x;
-                back[offset as usize] = 0x0000FF00; // Green in BGRX
-            }
-        }
+    // Animate a 16x16 sprite moving right and a blinking cursor: each frame
+    // erases the old sprite, draws the new one and toggles the cursor
+    let mut sprite = Rect::new(40, 90, 16, 16);
+    db.fill_rect(sprite, 0x00FF8000);
+    db.present();
+    for frame_number in 1..=5 {
+        db.fill_rect(sprite, background);
+        sprite.x += 6;
+        db.fill_rect(sprite, 0x00FF8000);
+        let cursor = if frame_number % 2 == 1 {
+            0x00FFFFFF
+        } else {
+            background
+        };
+        db.fill_rect(Rect::new(300, 180, 8, 2), cursor);
+        // A single twinkling star
+        db.set_pixel(10 + frame_number * 50, 20, 0x00FFFFFF);
+
+        let frame = db.present();
+        println!(
+            "    Frame {}: {} rect(s), {} pixels, {} bytes copied ({:.1}% of a full frame)",
+            frame_number,
+            frame.rects,
+            frame.pixels,
+            frame.bytes_copied,
+            frame.bytes_copied as f64 * 100.0 / full_frame as f64
+        );
     }
-    println!("    Frame 2: Drew green rectangle to back buffer");
 
-    // Check front buffer still has frame 1
-    let front_red_count = db
-        .front_buffer()
-        .iter()
-        .filter(|&&p| p == 0x00FF0000)
-        .count();
-    println!("    Front buffer red pixels (frame 1): {}", front_red_count);
+    let stats = db.present_stats();
+    println!(
+        "    {} presents, {} bytes total, {} bytes/frame on average (full frame: {})",
+        stats.frames,
+        stats.bytes_copied,
+        stats.average_bytes_per_frame(),
+        full_frame
+    );
 
-    // Swap again
-    db.swap();
-    println!("    Swapped buffers - frame 2 now visible");
+    // Raw access to the back buffer cannot be tracked
+    db.back_buffer()[0] = background;
+    let frame = db.present();
+    println!(
+        "    Raw back_buffer() write: {} bytes copied (whole surface)",
+        frame.bytes_copied
+    );
 
-    let front_green_count = db
+    let sprite_pixels = db
         .front_buffer()
         .iter()
-        .filter(|&&p| p == 0x0000FF00)
+        .filter(|&&p| p == 0x00FF8000)
         .count();
-    println!(
-        "    Front buffer green pixels (frame 2): {}",
-        front_green_count
-    );
+    println!("    Sprite pixels on screen: {}", sprite_pixels);
 
     println!("\n  Benefits of double buffering:");
     println!("    - No screen tearing");
     println!("    - Smooth animations");
     println!("    - Draw complex scenes without visible artifacts");
+    println!("    - Dirty rectangles: only changed regions reach the framebuffer");
+}
+
+// ============================================
+// Frame Export
+// ============================================
+
+/// A frame shown through a DoubleBuffer: a rectangle at `x` on dark blue
+fn presented_frame(x: u32, rect_color: u32) -> DoubleBuffer {
+    let mut db = DoubleBuffer::new(320, 200);
+    db.clear_back(0x00102040);
+    db.fill_rect(Rect::new(x as i32, 50, 100, 50), rect_color);
+    db.present();
+    db
+}
+
+fn frame_export() {
+    let frame = presented_frame(50, 0x00FF0000).front_image();
+    let ppm = frame.to_ppm();
+    let png = frame.to_png();
+    println!(
+        "  Front buffer {}x{} as PPM: {} bytes, as PNG: {} bytes",
+        frame.width(),
+        frame.height(),
+        ppm.len(),
+        png.len()
+    );
+
+    let splash = splash_canvas().to_image().to_png();
+    println!("  Splash canvas as PNG: {} bytes", splash.len());
+
+    let dir = std::env::temp_dir().join("uefi-graphics-frames");
+    let saved = fs::create_dir_all(&dir)
+        .and_then(|()| fs::write(dir.join("frame.ppm"), &ppm))
+        .and_then(|()| fs::write(dir.join("frame.png"), &png))
+        .and_then(|()| fs::write(dir.join("splash.png"), &splash));
+    match saved {
+        Ok(()) => println!(
+            "  Saved frame.ppm, frame.png and splash.png to {}",
+            dir.display()
+        ),
+        Err(error) => {
+            println!("  Could not save frames: {}", error);
+            return;
+        }
+    }
+
+    // Golden-image checks with a tolerance of 2 per channel. A missing
+    // golden fails until it is recorded, then frames are compared with it
+    println!("\n  Golden checks against golden.png (tolerance 2):");
+    let golden = dir.join("golden.png");
+    let _ = fs::remove_file(&golden);
+    let report = |label: &str, image: &Image| match check_golden(image, &golden, 2) {
+        Ok(outcome) => println!("    {:<15} {:?}", label, outcome),
+        Err(error) => println!("    {:<15} {}", label, error),
+    };
+    report("no golden", &frame);
+    if fs::write(&golden, frame.to_png()).is_ok() {
+        println!("    {:<15} golden.png", "recorded");
+    }
+    let checks = [
+        ("same frame", frame),
+        (
+            "red off by 1",
+            presented_frame(50, 0x00FE0000).front_image(),
+        ),
+        (
+            "moved 4 pixels",
+            presented_frame(54, 0x00FF0000).front_image(),
+        ),
+    ];
+    for (label, image) in &checks {
+        report(label, image);
+    }
 }
 
 #[cfg(test)]
@@ -794,13 +1372,115 @@ mod tests {
     }
 
     #[test]
-    fn test_double_buffer_swap() {
+    fn test_double_buffer_present() {
         let mut db = DoubleBuffer::new(10, 10);
 
         db.back_buffer()[0] = 0xDEADBEEF;
         assert_eq!(db.front_buffer()[0], 0);
 
-        db.swap();
+        db.present();
         assert_eq!(db.front_buffer()[0], 0xDEADBEEF);
     }
+
+    #[test]
+    fn test_draw_text_clipping() {
+        let font = Font::vga8x16();
+        let glyph = font.glyph('A').unwrap();
+        let mut canvas = Canvas::new(12, 20, PixelFormat::BlueGreenRedReserved8BitPerColor);
+
+        // 'A' drawn 4 pixels off the left edge keeps its right half
+        canvas.draw_text(-4, 2, "AA", &font, Color::white(), None);
+        for y in 0..16 {
+            for x in 0..8 {
+                let lit = canvas.get_pixel(x, y + 2).unwrap().red == 255;
+                let expected = if x < 4 {
+                    font.pixel(glyph, x + 4, y)
+                } else {
+                    font.pixel(glyph, x - 4, y)
+                };
+                assert_eq!(lit, expected, "pixel ({}, {})", x, y);
+            }
+        }
+
+        // A background fills the whole visible cell, clipped at the bottom
+        canvas.clear(Color::black());
+        canvas.draw_char(8, 10, ' ', &font, Color::white(), Some(Color::blue()));
+        assert_eq!(canvas.count_filled_pixels(), 4 * 10);
+    }
+
+    #[test]
+    fn test_console_wrap_and_scroll() {
+        use std::fmt::Write;
+
+        // 4 columns, 2 rows
+        let canvas = Canvas::new(32, 32, PixelFormat::BlueGreenRedReserved8BitPerColor);
+        let mut console = TextConsole::new(canvas, Font::vga8x16());
+        write!(console, "abcdef").unwrap();
+        assert_eq!(console.line(0), "abcd");
+        assert_eq!(console.line(1), "ef");
+        assert_eq!(console.cursor(), (2, 1));
+
+        // Filling the last cell does not scroll until more text arrives,
+        // and a tab there has nowhere to go
+        write!(console, "gh\t").unwrap();
+        assert_eq!(console.lines_scrolled(), 0);
+        write!(console, "x\ny").unwrap();
+        assert_eq!(console.lines_scrolled(), 2);
+        assert_eq!(console.line(0), "x");
+        assert_eq!(console.line(1), "y");
+
+        // The pixels scrolled with the cells
+        let mut expected = Canvas::new(32, 32, PixelFormat::BlueGreenRedReserved8BitPerColor);
+        let font = Font::vga8x16();
+        let gray = Color::new(170, 170, 170);
+        expected.draw_text(0, 0, "x\ny", &font, gray, Some(Color::black()));
+        assert_eq!(console.canvas().pixels, expected.pixels);
+    }
+
+    #[test]
+    fn test_demo_images_decode() {
+        let logo = Image::decode(BOOT_LOGO_PNG).unwrap();
+        assert_eq!(
+            logo.pixel(8, 8),
+            Some(BltPixel::with_alpha(255, 255, 255, 255))
+        );
+        assert_eq!(logo.pixel(0, 0).unwrap().alpha(), 0);
+        assert_eq!(logo.pixel(8, 0).unwrap().alpha(), 128);
+
+        let check = Image::decode(CHECK_MARK_BMP).unwrap();
+        let green = BltPixel::with_alpha(40, 200, 80, 255);
+        assert_eq!(check.pixel(8, 0), Some(green));
+        assert_eq!(check.pixel(0, 3), Some(green));
+        assert_eq!(check.pixel(0, 0).unwrap().alpha(), 0);
+    }
+
+    #[test]
+    fn test_golden_frames() {
+        let golden = |name: &str| {
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("golden")
+                .join(name)
+        };
+        let logo = Image::decode(BOOT_LOGO_PNG).unwrap();
+        let check = Image::decode(CHECK_MARK_BMP).unwrap();
+        // Blending rounds, so allow off-by-one channels there
+        let frames = [
+            ("splash.png", splash_canvas().to_image(), 0),
+            (
+                "blended_images.png",
+                blended_images_canvas(&logo, &check).to_image(),
+                1,
+            ),
+            (
+                "double_buffer.png",
+                presented_frame(50, 0x00FF0000).front_image(),
+                0,
+            ),
+        ];
+        for (name, frame, tolerance) in &frames {
+            if let Err(error) = check_golden(frame, &golden(name), *tolerance) {
+                panic!("{}", error);
+            }
+        }
+    }
 }
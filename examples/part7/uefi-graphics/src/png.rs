@@ -0,0 +1,658 @@
+//! PNG decoding and encoding
+//!
+//! ```text
+//!   89 50 4E 47 0D 0A 1A 0A                              signature
+//!   length (BE) | type | data | CRC-32 of type + data    each chunk
+//!   IHDR  [PLTE]  [tRNS]  IDAT ...  IEND
+//! ```
+//!
+//! The IDAT chunks together hold one zlib stream of scanlines, each led by
+//! a filter type byte. Every color type and bit depth is decoded, with tRNS
+//! transparency and Adam7 interlacing; 16-bit samples keep their high byte.
+//! Other ancillary chunks (gamma, color profiles, text) are ignored.
+
+use crate::image::{check_dimensions, Image, ImageError};
+use crate::zlib;
+use crate::BltPixel;
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+/// Adam7 passes: (first column, first row, column step, row step)
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// CRC-32 (ISO 3309) as PNG chunks use it.
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorType {
+    Gray,
+    Rgb,
+    Indexed,
+    GrayAlpha,
+    Rgba,
+}
+
+impl ColorType {
+    fn channels(self) -> usize {
+        match self {
+            ColorType::Gray | ColorType::Indexed => 1,
+            ColorType::GrayAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+}
+
+/// The tRNS color that is fully transparent, in sample values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TransparentColor {
+    None,
+    Gray(u16),
+    Rgb(u16, u16, u16),
+}
+
+/// IHDR
+struct Header {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: ColorType,
+    interlaced: bool,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Result<Self, ImageError> {
+        let [w0, w1, w2, w3, h0, h1, h2, h3, bit_depth, color_type, compression, filter, interlace] =
+            *data
+        else {
+            return Err(ImageError::Malformed("IHDR length"));
+        };
+        let color_type = match (color_type, bit_depth) {
+            (0, 1 | 2 | 4 | 8 | 16) => ColorType::Gray,
+            (2, 8 | 16) => ColorType::Rgb,
+            (3, 1 | 2 | 4 | 8) => ColorType::Indexed,
+            (4, 8 | 16) => ColorType::GrayAlpha,
+            (6, 8 | 16) => ColorType::Rgba,
+            _ => return Err(ImageError::Malformed("color type and bit depth")),
+        };
+        if compression != 0 || filter != 0 || interlace > 1 {
+            return Err(ImageError::Malformed("IHDR method"));
+        }
+        let width = u32::from_be_bytes([w0, w1, w2, w3]);
+        let height = u32::from_be_bytes([h0, h1, h2, h3]);
+        check_dimensions(width, height)?;
+        Ok(Header {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlaced: interlace == 1,
+        })
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth as usize
+    }
+
+    /// Bytes in one scanline of `width` pixels, without the filter byte.
+    fn row_bytes(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    /// Each pass as (first column, first row, column step, row step,
+    /// width, height), skipping passes with no pixels.
+    fn passes(&self) -> Vec<(usize, usize, usize, usize, usize, usize)> {
+        let passes: &[_] = if self.interlaced {
+            &ADAM7
+        } else {
+            &[(0, 0, 1, 1)]
+        };
+        let (width, height) = (self.width as usize, self.height as usize);
+        passes
+            .iter()
+            .map(|&(x0, y0, dx, dy)| {
+                let count = |size: usize, start: usize, step: usize| {
+                    size.saturating_sub(start).div_ceil(step)
+                };
+                (x0, y0, dx, dy, count(width, x0, dx), count(height, y0, dy))
+            })
+            .filter(|pass| pass.4 > 0 && pass.5 > 0)
+            .collect()
+    }
+
+    /// Pixel `x` of an unfiltered scanline.
+    fn pixel(
+        &self,
+        row: &[u8],
+        x: usize,
+        palette: &[BltPixel],
+        transparent: TransparentColor,
+    ) -> Result<BltPixel, ImageError> {
+        let depth = self.bit_depth as usize;
+        let channels = self.color_type.channels();
+        let sample = |channel: usize| -> u16 {
+            let index = x * channels + channel;
+            match depth {
+                16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+                8 => row[index] as u16,
+                _ => {
+                    // Packed samples, leftmost in the high bits
+                    let bit = index * depth;
+                    (row[bit / 8] >> (8 - depth - bit % 8)) as u16 & ((1 << depth) - 1)
+                }
+            }
+        };
+        let scale = |value: u16| -> u8 {
+            match depth {
+                16 => (value >> 8) as u8,
+                8 => value as u8,
+                _ => (value as u32 * 255 / ((1 << depth) - 1)) as u8,
+            }
+        };
+
+        Ok(match self.color_type {
+            ColorType::Gray => {
+                let gray = sample(0);
+                let alpha = if transparent == TransparentColor::Gray(gray) {
+                    0
+                } else {
+                    255
+                };
+                let gray = scale(gray);
+                BltPixel::with_alpha(gray, gray, gray, alpha)
+            }
+            ColorType::Rgb => {
+                let (red, green, blue) = (sample(0), sample(1), sample(2));
+                let alpha = if transparent == TransparentColor::Rgb(red, green, blue) {
+                    0
+                } else {
+                    255
+                };
+                BltPixel::with_alpha(scale(red), scale(green), scale(blue), alpha)
+            }
+            ColorType::Indexed => *palette
+                .get(sample(0) as usize)
+                .ok_or(ImageError::Malformed("palette index out of range"))?,
+            ColorType::GrayAlpha => {
+                let gray = scale(sample(0));
+                BltPixel::with_alpha(gray, gray, gray, scale(sample(1)))
+            }
+            ColorType::Rgba => BltPixel::with_alpha(
+                scale(sample(0)),
+                scale(sample(1)),
+                scale(sample(2)),
+                scale(sample(3)),
+            ),
+        })
+    }
+}
+
+/// What filter type `filter` predicts for a byte from the bytes to its
+/// left, above, and above-left; `None` for an unknown type.
+fn predict(filter: u8, left: u8, up: u8, up_left: u8) -> Option<u8> {
+    match filter {
+        0 => Some(0),
+        1 => Some(left),
+        2 => Some(up),
+        3 => Some(((left as u16 + up as u16) / 2) as u8),
+        4 => Some(paeth(left, up, up_left)),
+        _ => None,
+    }
+}
+
+/// Undo one scanline's filter in place; `bpp` is the byte distance to the
+/// corresponding byte of the pixel on the left (at least 1).
+fn unfilter(filter: u8, row: &mut [u8], previous: &[u8], bpp: usize) -> Result<(), ImageError> {
+    for i in 0..row.len() {
+        let left = if i >= bpp { row[i - bpp] } else { 0 };
+        let up_left = if i >= bpp { previous[i - bpp] } else { 0 };
+        let predictor = predict(filter, left, previous[i], up_left)
+            .ok_or(ImageError::Malformed("unknown filter type"))?;
+        row[i] = row[i].wrapping_add(predictor);
+    }
+    Ok(())
+}
+
+/// Append `row` filtered with `filter`, preceded by the filter type byte.
+fn filter_row(filter: u8, row: &[u8], previous: &[u8], bpp: usize, out: &mut Vec<u8>) {
+    out.push(filter);
+    for i in 0..row.len() {
+        let left = if i >= bpp { row[i - bpp] } else { 0 };
+        let up_left = if i >= bpp { previous[i - bpp] } else { 0 };
+        let predictor = predict(filter, left, previous[i], up_left).expect("known filter type");
+        out.push(row[i].wrapping_sub(predictor));
+    }
+}
+
+/// Whichever neighbour is closest to left + up - up_left.
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance = |value: u8| (estimate - value as i16).abs();
+    if distance(left) <= distance(up) && distance(left) <= distance(up_left) {
+        left
+    } else if distance(up) <= distance(up_left) {
+        up
+    } else {
+        up_left
+    }
+}
+
+impl Image {
+    /// Decode a PNG file.
+    pub fn from_png(data: &[u8]) -> Result<Self, ImageError> {
+        let mut rest = data
+            .strip_prefix(&SIGNATURE)
+            .ok_or(ImageError::UnknownFormat)?;
+        let mut header = None;
+        let mut palette = Vec::new();
+        let mut transparent = TransparentColor::None;
+        let mut image_data = Vec::new();
+
+        loop {
+            let length = rest
+                .get(..4)
+                .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                .ok_or(ImageError::Truncated)? as usize;
+            let chunk = rest.get(4..8 + length).ok_or(ImageError::Truncated)?;
+            let crc = rest
+                .get(8 + length..12 + length)
+                .ok_or(ImageError::Truncated)?;
+            if crc32(chunk).to_be_bytes() != crc {
+                return Err(ImageError::BadCrc);
+            }
+            rest = &rest[12 + length..];
+
+            let (kind, body) = chunk.split_at(4);
+            let Some(ihdr) = &header else {
+                if kind != b"IHDR" {
+                    return Err(ImageError::Malformed("first chunk is not IHDR"));
+                }
+                header = Some(Header::parse(body)?);
+                continue;
+            };
+            match kind {
+                b"IHDR" => return Err(ImageError::Malformed("duplicate IHDR")),
+                b"PLTE" => {
+                    if body.is_empty() || body.len() % 3 != 0 || body.len() > 256 * 3 {
+                        return Err(ImageError::Malformed("PLTE length"));
+                    }
+                    palette = body
+                        .as_chunks::<3>()
+                        .0
+                        .iter()
+                        .map(|&[red, green, blue]| BltPixel::with_alpha(red, green, blue, 255))
+                        .collect();
+                }
+                b"tRNS" => {
+                    let value = |i: usize| u16::from_be_bytes([body[i], body[i + 1]]);
+                    match (ihdr.color_type, body.len()) {
+                        (ColorType::Indexed, length) if length <= palette.len() => {
+                            for (entry, &alpha) in palette.iter_mut().zip(body) {
+                                entry.reserved = alpha;
+                            }
+                        }
+                        (ColorType::Gray, 2) => transparent = TransparentColor::Gray(value(0)),
+                        (ColorType::Rgb, 6) => {
+                            transparent = TransparentColor::Rgb(value(0), value(2), value(4))
+                        }
+                        _ => return Err(ImageError::Malformed("tRNS")),
+                    }
+                }
+                b"IDAT" => image_data.extend_from_slice(body),
+                b"IEND" => break,
+                // Bit 5 of the first byte clear: critical, and not skippable
+                _ if kind[0] & 0x20 == 0 => {
+                    return Err(ImageError::Unsupported("unknown critical PNG chunk"))
+                }
+                _ => {}
+            }
+        }
+
+        let header = header.ok_or(ImageError::Malformed("missing IHDR"))?;
+        if header.color_type == ColorType::Indexed && palette.is_empty() {
+            return Err(ImageError::Malformed("missing PLTE"));
+        }
+        let passes = header.passes();
+        let expected: usize = passes
+            .iter()
+            .map(|pass| pass.5 * (1 + header.row_bytes(pass.4)))
+            .sum();
+        let raw = zlib::decompress(&image_data, expected)?;
+        if raw.len() != expected {
+            return Err(ImageError::Truncated);
+        }
+
+        let (width, height) = (header.width as usize, header.height as usize);
+        let bpp = header.bits_per_pixel().div_ceil(8);
+        let mut pixels = vec![BltPixel::default(); width * height];
+        let mut scanlines = raw.as_slice();
+        for (x0, y0, dx, dy, pass_width, pass_height) in passes {
+            let row_bytes = header.row_bytes(pass_width);
+            let mut previous = vec![0; row_bytes];
+            let mut row = vec![0; row_bytes];
+            for pass_row in 0..pass_height {
+                let (line, next) = scanlines.split_at(1 + row_bytes);
+                scanlines = next;
+                row.copy_from_slice(&line[1..]);
+                unfilter(line[0], &mut row, &previous, bpp)?;
+                let y = y0 + pass_row * dy;
+                for pass_column in 0..pass_width {
+                    let x = x0 + pass_column * dx;
+                    pixels[y * width + x] =
+                        header.pixel(&row, pass_column, &palette, transparent)?;
+                }
+                std::mem::swap(&mut previous, &mut row);
+            }
+        }
+        Ok(Image::new(header.width, header.height, pixels))
+    }
+
+    /// Encode as an 8-bit PNG: RGB when every pixel is opaque, RGBA
+    /// otherwise. Each row gets the filter whose output has the smallest
+    /// sum of absolute values, the usual heuristic for picking one.
+    pub fn to_png(&self) -> Vec<u8> {
+        let opaque = self.is_opaque();
+        let bpp = if opaque { 3 } else { 4 };
+        let row_bytes = self.width() as usize * bpp;
+
+        let mut scanlines = Vec::with_capacity((row_bytes + 1) * self.height() as usize);
+        let mut previous = vec![0; row_bytes];
+        let mut row = Vec::with_capacity(row_bytes);
+        let mut candidate = Vec::with_capacity(row_bytes + 1);
+        let mut best = Vec::with_capacity(row_bytes + 1);
+        for y in 0..self.height() {
+            row.clear();
+            for x in 0..self.width() {
+                let pixel = self.pixel(x, y).expect("inside the image");
+                row.extend_from_slice(&[pixel.red, pixel.green, pixel.blue, pixel.alpha()][..bpp]);
+            }
+            let mut best_cost = u64::MAX;
+            for filter in 0..5 {
+                candidate.clear();
+                filter_row(filter, &row, &previous, bpp, &mut candidate);
+                let cost = candidate[1..]
+                    .iter()
+                    .map(|&byte| (byte as i8).unsigned_abs() as u64)
+                    .sum();
+                if cost < best_cost {
+                    best_cost = cost;
+                    std::mem::swap(&mut best, &mut candidate);
+                }
+            }
+            scanlines.extend_from_slice(&best);
+            std::mem::swap(&mut previous, &mut row);
+        }
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&self.width().to_be_bytes());
+        ihdr.extend_from_slice(&self.height().to_be_bytes());
+        ihdr.extend_from_slice(&[8, if opaque { 2 } else { 6 }, 0, 0, 0]);
+
+        let mut file = SIGNATURE.to_vec();
+        write_chunk(&mut file, b"IHDR", &ihdr);
+        write_chunk(&mut file, b"IDAT", &zlib::compress(&scanlines));
+        write_chunk(&mut file, b"IEND", &[]);
+        file
+    }
+}
+
+fn write_chunk(file: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    file.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    let start = file.len();
+    file.extend_from_slice(kind);
+    file.extend_from_slice(body);
+    let crc = crc32(&file[start..]);
+    file.extend_from_slice(&crc.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zlib::adler32;
+
+    /// A zlib stream of stored blocks.
+    fn zlib_stored(data: &[u8]) -> Vec<u8> {
+        let mut stream = vec![0x78, 0x01];
+        let blocks: Vec<&[u8]> = data.chunks(0xFFFF).collect();
+        for (i, block) in blocks.iter().enumerate() {
+            stream.push((i + 1 == blocks.len()) as u8);
+            stream.extend_from_slice(&(block.len() as u16).to_le_bytes());
+            stream.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+            stream.extend_from_slice(block);
+        }
+        stream.extend_from_slice(&adler32(data).to_be_bytes());
+        stream
+    }
+
+    /// Filter `rows` of a non-interlaced image, cycling through all five
+    /// filter types.
+    fn filter_rows(rows: &[Vec<u8>], bpp: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut previous = vec![0; rows[0].len()];
+        for (y, row) in rows.iter().enumerate() {
+            filter_row((y % 5) as u8, row, &previous, bpp, &mut out);
+            previous = row.clone();
+        }
+        out
+    }
+
+    fn png(
+        width: u32,
+        height: u32,
+        depth: u8,
+        color: u8,
+        interlace: u8,
+        extra: &[(&[u8; 4], Vec<u8>)],
+        scanlines: &[u8],
+    ) -> Vec<u8> {
+        let mut file = SIGNATURE.to_vec();
+        let mut ihdr = width.to_be_bytes().to_vec();
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[depth, color, 0, 0, interlace]);
+        write_chunk(&mut file, b"IHDR", &ihdr);
+        for (kind, body) in extra {
+            write_chunk(&mut file, kind, body);
+        }
+        // Split the image data across two IDAT chunks
+        let data = zlib_stored(scanlines);
+        let (first, second) = data.split_at(data.len() / 2);
+        write_chunk(&mut file, b"IDAT", first);
+        write_chunk(&mut file, b"IDAT", second);
+        write_chunk(&mut file, b"IEND", &[]);
+        file
+    }
+
+    #[test]
+    fn test_truecolor_with_filters() {
+        // RGBA8, 7x6, a gradient so every filter has something to predict
+        let rows: Vec<Vec<u8>> = (0..6u8)
+            .map(|y| {
+                (0..7u8)
+                    .flat_map(|x| [x * 37, y * 41, x * y * 8, 255 - x * y])
+                    .collect()
+            })
+            .collect();
+        let file = png(
+            7,
+            6,
+            8,
+            6,
+            0,
+            &[(b"gAMA", vec![0, 0, 0xB1, 0x8F])],
+            &filter_rows(&rows, 4),
+        );
+        let image = Image::decode(&file).unwrap();
+        assert_eq!((image.width(), image.height()), (7, 6));
+        for y in 0..6u8 {
+            for x in 0..7u8 {
+                let expected = BltPixel::with_alpha(x * 37, y * 41, x * y * 8, 255 - x * y);
+                assert_eq!(
+                    image.pixel(x as u32, y as u32),
+                    Some(expected),
+                    "{:?}",
+                    (x, y)
+                );
+            }
+        }
+
+        // RGB16 with a tRNS color: high bytes kept, exact match transparent
+        let row = vec![
+            0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 0x12, 0x34, 0x56, 0x78, 0x9A, 0xBD,
+        ];
+        let trns = (b"tRNS", vec![0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC]);
+        let image =
+            Image::from_png(&png(2, 1, 16, 2, 0, &[trns], &filter_rows(&[row], 6))).unwrap();
+        assert_eq!(
+            image.pixel(0, 0),
+            Some(BltPixel::with_alpha(0x12, 0x56, 0x9A, 0))
+        );
+        assert_eq!(
+            image.pixel(1, 0),
+            Some(BltPixel::with_alpha(0x12, 0x56, 0x9A, 255))
+        );
+    }
+
+    #[test]
+    fn test_palette_gray_and_interlace() {
+        // 2-bit palette, 5 pixels: 0 1 2 3 1, with tRNS for entries 0 and 1
+        let palette = (
+            b"PLTE",
+            vec![10, 20, 30, 40, 50, 60, 70, 80, 90, 255, 255, 255],
+        );
+        let trns = (b"tRNS", vec![0, 128]);
+        let scanline = [0, 0b00_01_10_11, 0b01_000000];
+        let image = Image::from_png(&png(5, 1, 2, 3, 0, &[palette, trns], &scanline)).unwrap();
+        let row: Vec<BltPixel> = (0..5).map(|x| image.pixel(x, 0).unwrap()).collect();
+        assert_eq!(row[0], BltPixel::with_alpha(10, 20, 30, 0));
+        assert_eq!(row[1], BltPixel::with_alpha(40, 50, 60, 128));
+        assert_eq!(row[3], BltPixel::with_alpha(255, 255, 255, 255));
+        assert_eq!(row[4], row[1]);
+
+        // 4-bit gray scales 0..15 to 0..255; gray+alpha 8-bit
+        let image = Image::from_png(&png(2, 1, 4, 0, 0, &[], &[0, 0xF5])).unwrap();
+        assert_eq!(
+            image.pixel(0, 0),
+            Some(BltPixel::with_alpha(255, 255, 255, 255))
+        );
+        assert_eq!(
+            image.pixel(1, 0),
+            Some(BltPixel::with_alpha(85, 85, 85, 255))
+        );
+        let image = Image::from_png(&png(1, 1, 8, 4, 0, &[], &[0, 200, 100])).unwrap();
+        assert_eq!(
+            image.pixel(0, 0),
+            Some(BltPixel::with_alpha(200, 200, 200, 100))
+        );
+
+        // Adam7, 8-bit gray, 10x9: each pixel's value is its index. Build
+        // the passes' scanlines with filter 0
+        let (width, height) = (10usize, 9usize);
+        let mut scanlines = Vec::new();
+        for (x0, y0, dx, dy) in ADAM7 {
+            let columns: Vec<usize> = (x0..width).step_by(dx).collect();
+            for y in (y0..height).step_by(dy) {
+                if columns.is_empty() {
+                    break;
+                }
+                scanlines.push(0);
+                scanlines.extend(columns.iter().map(|&x| (y * width + x) as u8));
+            }
+        }
+        let image = Image::from_png(&png(10, 9, 8, 0, 1, &[], &scanlines)).unwrap();
+        for y in 0..height {
+            for x in 0..width {
+                let value = (y * width + x) as u8;
+                assert_eq!(image.pixel(x as u32, y as u32).unwrap().red, value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let pixels: Vec<BltPixel> = (0..40 * 30u32)
+            .map(|i| BltPixel::with_alpha((i % 40 * 6) as u8, (i / 40 * 8) as u8, 90, 255))
+            .collect();
+        let mut image = Image::new(40, 30, pixels);
+        let file = image.to_png();
+        assert_eq!(file[25], 2, "opaque images are written as RGB");
+        let decoded = Image::from_png(&file).unwrap();
+        assert_eq!(decoded.pixels(), image.pixels());
+
+        image.pixels_mut()[7] = BltPixel::with_alpha(1, 2, 3, 4);
+        let file = image.to_png();
+        assert_eq!(file[25], 6);
+        assert_eq!(Image::decode(&file).unwrap().pixel(7, 0), image.pixel(7, 0));
+    }
+
+    #[test]
+    fn test_malformed_png() {
+        let good = png(1, 1, 8, 0, 0, &[], &[0, 7]);
+        assert!(Image::from_png(&good).is_ok());
+
+        let mut bad_crc = good.clone();
+        bad_crc[20] ^= 0xFF;
+        assert_eq!(Image::from_png(&bad_crc).unwrap_err(), ImageError::BadCrc);
+        assert_eq!(
+            Image::from_png(&good[..good.len() - 20]).unwrap_err(),
+            ImageError::Truncated
+        );
+        assert_eq!(
+            Image::from_png(&png(1, 1, 8, 3, 0, &[], &[0, 0])).unwrap_err(),
+            ImageError::Malformed("missing PLTE")
+        );
+        assert_eq!(
+            Image::from_png(&png(1, 1, 8, 0, 0, &[], &[5, 0])).unwrap_err(),
+            ImageError::Malformed("unknown filter type")
+        );
+        assert_eq!(
+            Image::from_png(&png(1, 1, 8, 0, 0, &[(b"ABCD", vec![])], &[0, 0])).unwrap_err(),
+            ImageError::Unsupported("unknown critical PNG chunk")
+        );
+        assert_eq!(
+            Image::from_png(&png(1, 1, 3, 2, 0, &[], &[0, 0])).unwrap_err(),
+            ImageError::Malformed("color type and bit depth")
+        );
+        // More image data than the header describes
+        assert_eq!(
+            Image::from_png(&png(1, 1, 8, 0, 0, &[], &[0, 0, 0])).unwrap_err(),
+            ImageError::Inflate(zlib::InflateError::TooLarge)
+        );
+        assert_eq!(
+            Image::from_png(&png(1, 1, 8, 0, 0, &[], &[0])).unwrap_err(),
+            ImageError::Truncated
+        );
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+}
@@ -0,0 +1,615 @@
+//! Rasterizer for [`Canvas`](crate::Canvas)
+//!
+//! Integer-only, aliased primitives: every pixel is either fully drawn or
+//! untouched, which keeps output identical across firmware and host and
+//! makes it easy to compare in tests.
+//!
+//! - Lines: Bresenham, endpoints included.
+//! - Circles and ellipses: the midpoint algorithms, outline or filled.
+//! - Polygons: scanline fill sampling pixel centres with the even-odd rule,
+//!   so polygons sharing an edge never overwrite each other's pixels.
+//! - Rounded rectangles: a circle split into four corners.
+//! - Flood fill: 4-connected, with an explicit stack of spans rather than
+//!   recursion, so a large region cannot overflow the (small) UEFI stack.
+//!
+//! Everything, including the older `draw_hline`/`fill_rect` family and text,
+//! is clipped to the canvas' clip rectangle. Coordinates are signed so
+//! shapes may hang off any edge.
+
+use crate::{Canvas, Color};
+
+/// An axis-aligned rectangle; `x + width` and `y + height` are exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    /// The overlap of two rectangles, empty if they do not touch.
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if right <= x || bottom <= y {
+            return Rect::default();
+        }
+        Rect::new(x, y, (right - x) as u32, (bottom - y) as u32)
+    }
+
+    /// The smallest rectangle covering both; an empty side is ignored.
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+        Rect::new(x, y, (right - x) as u32, (bottom - y) as u32)
+    }
+
+    pub fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+}
+
+impl Canvas {
+    pub fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    /// Restrict drawing to `rect` (intersected with the canvas).
+    pub fn set_clip(&mut self, rect: Rect) {
+        self.clip = rect.intersect(&self.bounds());
+    }
+
+    pub fn reset_clip(&mut self) {
+        self.clip = self.bounds();
+    }
+
+    pub fn clip(&self) -> Rect {
+        self.clip
+    }
+
+    /// Write one raw pixel if it lies inside the clip rectangle.
+    pub(crate) fn plot(&mut self, x: i32, y: i32, pixel: u32) {
+        if self.clip.contains(x, y) {
+            self.pixels[(y as u32 * self.stride + x as u32) as usize] = pixel;
+        }
+    }
+
+    /// Fill pixels `x0..=x1` of row `y`, clipped.
+    pub(crate) fn span(&mut self, x0: i32, x1: i32, y: i32, pixel: u32) {
+        if y < self.clip.y || y >= self.clip.bottom() {
+            return;
+        }
+        let start = x0.min(x1).max(self.clip.x);
+        let end = x0.max(x1).min(self.clip.right() - 1);
+        if start > end {
+            return;
+        }
+        let row = (y as u32 * self.stride) as usize;
+        self.pixels[row + start as usize..=row + end as usize].fill(pixel);
+    }
+
+    /// Bresenham line from (`x0`, `y0`) to (`x1`, `y1`), both ends included.
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: Color) {
+        let pixel = color.to_pixel(self.format);
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let step_x = if x0 < x1 { 1 } else { -1 };
+        let step_y = if y0 < y1 { 1 } else { -1 };
+        let (mut x, mut y) = (x0, y0);
+        let mut error = dx + dy;
+        loop {
+            self.plot(x, y, pixel);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Closed outline through `points`.
+    pub fn draw_polygon(&mut self, points: &[(i32, i32)], color: Color) {
+        for (i, &(x0, y0)) in points.iter().enumerate() {
+            let (x1, y1) = points[(i + 1) % points.len()];
+            self.draw_line(x0, y0, x1, y1, color);
+        }
+    }
+
+    /// Scanline fill of the polygon through `points` (even-odd rule).
+    ///
+    /// A pixel is filled when its centre is inside, so a shared edge
+    /// belongs to exactly one of the two polygons on either side of it.
+    pub fn fill_polygon(&mut self, points: &[(i32, i32)], color: Color) {
+        if points.len() < 3 {
+            return;
+        }
+        let pixel = color.to_pixel(self.format);
+        let top = points.iter().map(|p| p.1).min().unwrap().max(self.clip.y);
+        let bottom = points
+            .iter()
+            .map(|p| p.1)
+            .max()
+            .unwrap()
+            .min(self.clip.bottom());
+
+        let mut crossings = Vec::new();
+        for y in top..bottom {
+            let centre = y as f64 + 0.5;
+            crossings.clear();
+            for (i, &(x0, y0)) in points.iter().enumerate() {
+                let (x1, y1) = points[(i + 1) % points.len()];
+                // Half-open in y, so a vertex is counted once
+                if (y0 as f64 <= centre) != (y1 as f64 <= centre) {
+                    let t = (centre - y0 as f64) / (y1 - y0) as f64;
+                    crossings.push(x0 as f64 + t * (x1 - x0) as f64);
+                }
+            }
+            crossings.sort_by(|a, b| a.total_cmp(b));
+            for pair in crossings.chunks(2) {
+                if let [left, right] = *pair {
+                    // Pixels whose centres x + 0.5 lie in [left, right)
+                    let start = (left - 0.5).ceil() as i32;
+                    let end = (right - 0.5).ceil() as i32 - 1;
+                    if start <= end {
+                        self.span(start, end, y, pixel);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Midpoint circle outline of radius `radius` around (`cx`, `cy`).
+    pub fn draw_circle(&mut self, cx: i32, cy: i32, radius: u32, color: Color) {
+        self.stretched_circle(cx, cy, cx, cy, radius, color, false);
+    }
+
+    pub fn fill_circle(&mut self, cx: i32, cy: i32, radius: u32, color: Color) {
+        self.stretched_circle(cx, cy, cx, cy, radius, color, true);
+    }
+
+    /// Rectangle outline with corners rounded to `radius` (clamped to half
+    /// the shorter side).
+    pub fn draw_rounded_rect(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        radius: u32,
+        color: Color,
+    ) {
+        self.rounded_rect(Rect::new(x, y, width, height), radius, color, false);
+    }
+
+    pub fn fill_rounded_rect(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        radius: u32,
+        color: Color,
+    ) {
+        self.rounded_rect(Rect::new(x, y, width, height), radius, color, true);
+    }
+
+    fn rounded_rect(&mut self, rect: Rect, radius: u32, color: Color, filled: bool) {
+        if rect.is_empty() {
+            return;
+        }
+        let radius = radius.min((rect.width - 1) / 2).min((rect.height - 1) / 2);
+        let r = radius as i32;
+        let (left, top) = (rect.x + r, rect.y + r);
+        let (right, bottom) = (rect.right() - 1 - r, rect.bottom() - 1 - r);
+        self.stretched_circle(left, top, right, bottom, radius, color, filled);
+
+        let pixel = color.to_pixel(self.format);
+        if filled {
+            for y in top + 1..bottom {
+                self.span(rect.x, rect.right() - 1, y, pixel);
+            }
+        } else {
+            self.span(left, right, rect.y, pixel);
+            self.span(left, right, rect.bottom() - 1, pixel);
+            for y in top..=bottom {
+                self.plot(rect.x, y, pixel);
+                self.plot(rect.right() - 1, y, pixel);
+            }
+        }
+    }
+
+    /// A midpoint circle whose four quadrants are centred on the corners
+    /// of (`left`, `top`)–(`right`, `bottom`): a plain circle when the two
+    /// corners coincide, the rounded corners of a rectangle otherwise.
+    #[allow(clippy::too_many_arguments)]
+    fn stretched_circle(
+        &mut self,
+        left: i32,
+        top: i32,
+        right: i32,
+        bottom: i32,
+        radius: u32,
+        color: Color,
+        filled: bool,
+    ) {
+        let pixel = color.to_pixel(self.format);
+        let mut x = radius as i32;
+        let mut y = 0;
+        let mut decision = 1 - x;
+        while x >= y {
+            if filled {
+                self.span(left - x, right + x, top - y, pixel);
+                self.span(left - x, right + x, bottom + y, pixel);
+                self.span(left - y, right + y, top - x, pixel);
+                self.span(left - y, right + y, bottom + x, pixel);
+            } else {
+                for (dx, dy) in [(x, y), (y, x)] {
+                    self.plot(right + dx, bottom + dy, pixel);
+                    self.plot(left - dx, bottom + dy, pixel);
+                    self.plot(right + dx, top - dy, pixel);
+                    self.plot(left - dx, top - dy, pixel);
+                }
+            }
+            y += 1;
+            if decision < 0 {
+                decision += 2 * y + 1;
+            } else {
+                x -= 1;
+                decision += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    /// Midpoint ellipse outline with radii `rx` and `ry`.
+    pub fn draw_ellipse(&mut self, cx: i32, cy: i32, rx: u32, ry: u32, color: Color) {
+        let pixel = color.to_pixel(self.format);
+        for_each_ellipse_point(rx, ry, |x, y| {
+            self.plot(cx + x, cy + y, pixel);
+            self.plot(cx - x, cy + y, pixel);
+            self.plot(cx + x, cy - y, pixel);
+            self.plot(cx - x, cy - y, pixel);
+        });
+    }
+
+    pub fn fill_ellipse(&mut self, cx: i32, cy: i32, rx: u32, ry: u32, color: Color) {
+        let pixel = color.to_pixel(self.format);
+        for_each_ellipse_point(rx, ry, |x, y| {
+            self.span(cx - x, cx + x, cy + y, pixel);
+            self.span(cx - x, cx + x, cy - y, pixel);
+        });
+    }
+
+    /// Fill the 4-connected region of pixels matching the one at (`x`, `y`)
+    /// with `color`, staying inside the clip rectangle.
+    pub fn flood_fill(&mut self, x: i32, y: i32, color: Color) {
+        if !self.clip.contains(x, y) {
+            return;
+        }
+        let pixel = color.to_pixel(self.format);
+        let stride = self.stride;
+        let index = |x: i32, y: i32| (y as u32 * stride + x as u32) as usize;
+        let target = self.pixels[index(x, y)];
+        if target == pixel {
+            return;
+        }
+
+        let clip = self.clip;
+        let mut seeds = vec![(x, y)];
+        while let Some((x, y)) = seeds.pop() {
+            if self.pixels[index(x, y)] != target {
+                continue; // already filled through another seed
+            }
+            // Widen the seed into the whole run on its row
+            let mut left = x;
+            while left > clip.x && self.pixels[index(left - 1, y)] == target {
+                left -= 1;
+            }
+            let mut right = x;
+            while right + 1 < clip.right() && self.pixels[index(right + 1, y)] == target {
+                right += 1;
+            }
+            self.span(left, right, y, pixel);
+
+            // One seed per run of matching pixels touching the span above and below
+            for row in [y - 1, y + 1] {
+                if row < clip.y || row >= clip.bottom() {
+                    continue;
+                }
+                let mut in_run = false;
+                for column in left..=right {
+                    let matches = self.pixels[index(column, row)] == target;
+                    if matches && !in_run {
+                        seeds.push((column, row));
+                    }
+                    in_run = matches;
+                }
+            }
+        }
+    }
+}
+
+/// Points of the first quadrant of an ellipse centred on the origin,
+/// region 1 (slope above -1) then region 2, in integer arithmetic.
+fn for_each_ellipse_point(rx: u32, ry: u32, mut point: impl FnMut(i32, i32)) {
+    if rx == 0 || ry == 0 {
+        // Degenerate: a horizontal or vertical line
+        for x in 0..=rx {
+            for y in 0..=ry {
+                if x == rx || y == ry {
+                    point(x as i32, y as i32);
+                }
+            }
+        }
+        return;
+    }
+    let (rx, ry) = (rx as i64, ry as i64);
+    let (rx2, ry2) = (rx * rx, ry * ry);
+    let (mut x, mut y) = (0i64, ry);
+
+    // Region 1: step x, decide on y. The decision variable is scaled by 4
+    // to stay integral.
+    let mut decision = 4 * ry2 - 4 * rx2 * ry + rx2;
+    while ry2 * x <= rx2 * y {
+        point(x as i32, y as i32);
+        if decision < 0 {
+            decision += 4 * ry2 * (2 * x + 3);
+        } else {
+            decision += 4 * ry2 * (2 * x + 3) - 8 * rx2 * (y - 1);
+            y -= 1;
+        }
+        x += 1;
+    }
+
+    // Region 2: step y, decide on x
+    let mut decision =
+        ry2 * (2 * x + 1) * (2 * x + 1) + 4 * rx2 * (y - 1) * (y - 1) - 4 * rx2 * ry2;
+    while y >= 0 {
+        point(x as i32, y as i32);
+        if decision > 0 {
+            decision += 4 * rx2 * (3 - 2 * y);
+        } else {
+            decision += 8 * ry2 * (x + 1) + 4 * rx2 * (3 - 2 * y);
+            x += 1;
+        }
+        y -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PixelFormat;
+
+    fn canvas(width: u32, height: u32) -> Canvas {
+        Canvas::new(width, height, PixelFormat::BlueGreenRedReserved8BitPerColor)
+    }
+
+    fn lit(canvas: &Canvas) -> Vec<(u32, u32)> {
+        let mut points = Vec::new();
+        for y in 0..canvas.height {
+            for x in 0..canvas.width {
+                if canvas.get_pixel(x, y).unwrap().red != 0 {
+                    points.push((x, y));
+                }
+            }
+        }
+        points
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut c = canvas(10, 10);
+        c.draw_line(1, 1, 7, 4, Color::white());
+        assert_eq!(
+            lit(&c),
+            [(1, 1), (2, 2), (3, 2), (4, 3), (5, 3), (6, 4), (7, 4)]
+        );
+
+        // Shallow lines have one pixel per column, steep ones one per row
+        let mut reversed = canvas(10, 10);
+        reversed.draw_line(7, 4, 1, 1, Color::white());
+        assert_eq!(lit(&reversed).len(), 7);
+        let mut steep = canvas(10, 10);
+        steep.draw_line(2, 9, 4, 0, Color::white());
+        let points = lit(&steep);
+        assert_eq!(points.len(), 10);
+        assert!((0..10).all(|y| points.iter().filter(|p| p.1 == y).count() == 1));
+    }
+
+    #[test]
+    fn test_circles_and_ellipses() {
+        let mut c = canvas(21, 21);
+        c.draw_circle(10, 10, 0, Color::white());
+        assert_eq!(lit(&c), [(10, 10)]);
+
+        c.clear(Color::black());
+        c.draw_circle(10, 10, 5, Color::white());
+        let points = lit(&c);
+        assert!(points.contains(&(15, 10)) && points.contains(&(10, 5)));
+        for &(x, y) in &points {
+            // Mirror images are present in all eight octants
+            let (dx, dy) = (x as i32 - 10, y as i32 - 10);
+            assert!(points.contains(&((10 - dx) as u32, (10 + dy) as u32)));
+            assert!(points.contains(&((10 + dy) as u32, (10 + dx) as u32)));
+            let distance = ((dx * dx + dy * dy) as f64).sqrt();
+            assert!((distance - 5.0).abs() < 0.75, "{:?}", (x, y));
+        }
+
+        // A filled circle covers its outline
+        let mut filled = canvas(21, 21);
+        filled.fill_circle(10, 10, 5, Color::white());
+        let inside = lit(&filled);
+        assert!(points.iter().all(|p| inside.contains(p)));
+        assert_eq!(inside.len(), 97);
+
+        let mut ellipse = canvas(21, 21);
+        ellipse.draw_ellipse(10, 10, 8, 3, Color::white());
+        let points = lit(&ellipse);
+        for extreme in [(2, 10), (18, 10), (10, 7), (10, 13)] {
+            assert!(points.contains(&extreme), "{:?}", extreme);
+        }
+        assert!(points
+            .iter()
+            .all(|&(x, y)| (7..=13).contains(&y) && (2..=18).contains(&x)));
+        let mut filled = canvas(21, 21);
+        filled.fill_ellipse(10, 10, 8, 3, Color::white());
+        let inside = lit(&filled);
+        assert!(points.iter().all(|p| inside.contains(p)));
+    }
+
+    #[test]
+    fn test_polygons() {
+        // An axis-aligned square fills exactly like fill_rect
+        let mut polygon = canvas(16, 16);
+        polygon.fill_polygon(&[(2, 3), (9, 3), (9, 8), (2, 8)], Color::white());
+        let mut rect = canvas(16, 16);
+        rect.fill_rect(2, 3, 7, 5, Color::white());
+        assert_eq!(lit(&polygon), lit(&rect));
+
+        // Two triangles sharing a diagonal tile the square with no overlap
+        let first = [(2, 3), (9, 3), (2, 8)];
+        let second = [(9, 3), (9, 8), (2, 8)];
+        let mut a = canvas(16, 16);
+        a.fill_polygon(&first, Color::white());
+        let mut b = canvas(16, 16);
+        b.fill_polygon(&second, Color::white());
+        let (a, b) = (lit(&a), lit(&b));
+        assert!(a.iter().all(|p| !b.contains(p)));
+        assert_eq!(a.len() + b.len(), 35);
+
+        // Concave shapes and self-intersections follow even-odd
+        let mut star = canvas(16, 16);
+        star.fill_polygon(
+            &[(0, 4), (12, 4), (2, 12), (6, 0), (10, 12)],
+            Color::white(),
+        );
+        assert!(lit(&star).contains(&(6, 3)));
+        assert!(!lit(&star).contains(&(6, 7))); // centre of the pentagram
+    }
+
+    #[test]
+    fn test_rounded_rect_and_clip() {
+        let mut round = canvas(16, 16);
+        round.fill_rounded_rect(1, 1, 12, 8, 0, Color::white());
+        let mut square = canvas(16, 16);
+        square.fill_rect(1, 1, 12, 8, Color::white());
+        assert_eq!(lit(&round), lit(&square));
+
+        round.clear(Color::black());
+        round.fill_rounded_rect(1, 1, 12, 8, 3, Color::white());
+        let filled = lit(&round);
+        assert!(!filled.contains(&(1, 1)) && filled.contains(&(4, 1)));
+        assert!(filled.contains(&(1, 4)) && filled.contains(&(12, 5)));
+        round.clear(Color::black());
+        round.draw_rounded_rect(1, 1, 12, 8, 3, Color::white());
+        let outline = lit(&round);
+        assert!(outline.iter().all(|p| filled.contains(p)));
+        assert!(!outline.contains(&(6, 4)));
+
+        // Nothing lands outside the clip rectangle, whatever the primitive
+        let mut c = canvas(16, 16);
+        c.set_clip(Rect::new(4, 4, 6, 6));
+        c.fill_circle(8, 8, 20, Color::white());
+        c.draw_line(-5, 0, 20, 15, Color::white());
+        c.fill_polygon(&[(-10, -10), (30, 0), (0, 30)], Color::white());
+        c.fill_rect(0, 0, 16, 16, Color::white());
+        c.draw_hline(0, 5, 16, Color::white());
+        c.set_pixel(0, 0, Color::white());
+        let points = lit(&c);
+        assert_eq!(points.len(), 36);
+        assert!(points
+            .iter()
+            .all(|&(x, y)| c.clip().contains(x as i32, y as i32)));
+
+        c.set_clip(Rect::new(20, 20, 5, 5));
+        assert!(c.clip().is_empty());
+        c.reset_clip();
+        assert_eq!(c.clip(), c.bounds());
+    }
+
+    #[test]
+    fn test_flood_fill() {
+        let only = |c: &Canvas, channel: fn(Color) -> u8| {
+            let mut points = Vec::new();
+            for y in 0..c.height {
+                for x in 0..c.width {
+                    let pixel = c.get_pixel(x, y).unwrap();
+                    if pixel.red == 0 && channel(pixel) == 255 {
+                        points.push((x, y));
+                    }
+                }
+            }
+            points
+        };
+
+        // Filling inside a closed outline stops at the outline
+        let mut c = canvas(16, 16);
+        c.draw_polygon(
+            &[(2, 2), (12, 2), (12, 12), (7, 6), (2, 12)],
+            Color::white(),
+        );
+        let border = lit(&c);
+        c.flood_fill(4, 4, Color::blue());
+        let inside = only(&c, |p| p.blue);
+        assert!(inside.contains(&(3, 9)) && inside.contains(&(11, 9)));
+        assert!(!inside.contains(&(7, 11)), "below the notch is outside");
+        assert!(!inside.contains(&(0, 0)) && !inside.contains(&(15, 15)));
+        assert_eq!(lit(&c), border, "the outline itself is untouched");
+
+        // Filling outside reaches every remaining pixel, around the notch
+        c.flood_fill(0, 0, Color::green());
+        let outside = only(&c, |p| p.green);
+        assert_eq!(outside.len() + inside.len() + border.len(), 16 * 16);
+
+        // A large fill is clipped and does not recurse
+        let mut big = canvas(640, 480);
+        big.set_clip(Rect::new(10, 10, 600, 400));
+        big.flood_fill(320, 240, Color::white());
+        assert_eq!(lit(&big).len(), 600 * 400);
+        big.flood_fill(0, 0, Color::red()); // seed outside the clip
+        assert_eq!(lit(&big).len(), 600 * 400);
+    }
+}
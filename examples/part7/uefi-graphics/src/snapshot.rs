@@ -0,0 +1,513 @@
+//! Frame capture and golden-image comparison
+//!
+//! Rendering is tested the way UI code usually is: capture a frame as an
+//! [`Image`], and compare it with a reviewed "golden" copy checked in next
+//! to the tests. Both PPM and PNG are written; PPM needs no decoder at all,
+//! PNG keeps the goldens small.
+//!
+//! ```text
+//!   Canvas::to_image ──────────┐
+//!   DoubleBuffer::front_image ─┴─► Image ──► check_golden(path, tolerance)
+//!                                                │ mismatch
+//!                                                ▼
+//!                                  <name>.actual.png   <name>.diff.png
+//! ```
+//!
+//! Running the tests with `UPDATE_GOLDEN=1` rewrites the goldens instead
+//! of comparing against them. Without it a missing golden is an error, so
+//! a golden that was deleted or never committed cannot pass silently.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::image::{check_dimensions, Image, ImageError};
+use crate::{BltPixel, Canvas, Color, DoubleBuffer, PixelFormat};
+
+/// Set (to anything) to rewrite golden files from the current output
+pub const UPDATE_GOLDEN_VAR: &str = "UPDATE_GOLDEN";
+
+fn opaque(color: Color) -> BltPixel {
+    BltPixel::with_alpha(color.red, color.green, color.blue, 255)
+}
+
+impl Canvas {
+    /// The canvas as an opaque image.
+    pub fn to_image(&self) -> Image {
+        let mut pixels = Vec::with_capacity((self.width * self.height) as usize);
+        for y in 0..self.height {
+            let row = (y * self.stride) as usize;
+            for &raw in &self.pixels[row..row + self.width as usize] {
+                pixels.push(opaque(Color::from_pixel(raw, self.format)));
+            }
+        }
+        Image::new(self.width, self.height, pixels)
+    }
+}
+
+impl DoubleBuffer {
+    /// What is on screen: the front buffer, which holds BGRX pixels.
+    pub fn front_image(&self) -> Image {
+        let format = PixelFormat::BlueGreenRedReserved8BitPerColor;
+        let pixels = self
+            .front_buffer()
+            .iter()
+            .map(|&raw| opaque(Color::from_pixel(raw, format)))
+            .collect();
+        Image::new(self.width, self.height, pixels)
+    }
+}
+
+impl Image {
+    /// Binary PPM (P6). Alpha is dropped.
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut file = format!("P6\n{} {}\n255\n", self.width(), self.height()).into_bytes();
+        for pixel in self.pixels() {
+            file.extend_from_slice(&[pixel.red, pixel.green, pixel.blue]);
+        }
+        file
+    }
+
+    /// Read a binary PPM with a maximum sample value of at most 255.
+    pub fn from_ppm(data: &[u8]) -> Result<Self, ImageError> {
+        let mut position = 0;
+        let mut token = || -> Result<&[u8], ImageError> {
+            // Whitespace and '#' comments may separate header fields
+            loop {
+                match data.get(position) {
+                    Some(byte) if byte.is_ascii_whitespace() => position += 1,
+                    Some(b'#') => {
+                        while data.get(position).is_some_and(|&byte| byte != b'\n') {
+                            position += 1;
+                        }
+                    }
+                    Some(_) => break,
+                    None => return Err(ImageError::Truncated),
+                }
+            }
+            let start = position;
+            while data
+                .get(position)
+                .is_some_and(|byte| !byte.is_ascii_whitespace())
+            {
+                position += 1;
+            }
+            Ok(&data[start..position])
+        };
+        if token()? != b"P6" {
+            return Err(ImageError::UnknownFormat);
+        }
+        let mut number = || -> Result<u32, ImageError> {
+            std::str::from_utf8(token()?)
+                .ok()
+                .and_then(|text| text.parse().ok())
+                .ok_or(ImageError::Malformed("PPM header"))
+        };
+        let (width, height, max_value) = (number()?, number()?, number()?);
+        check_dimensions(width, height)?;
+        match max_value {
+            0 => return Err(ImageError::Malformed("PPM maximum value")),
+            256.. => return Err(ImageError::Unsupported("16-bit PPM")),
+            _ => {}
+        }
+
+        // Exactly one whitespace byte separates the header from the samples
+        let samples = data
+            .get(position + 1..)
+            .and_then(|rest| rest.get(..(width * height * 3) as usize))
+            .ok_or(ImageError::Truncated)?;
+        let scale = |sample: u8| (sample.min(max_value as u8) as u32 * 255 / max_value) as u8;
+        let pixels = samples
+            .as_chunks::<3>()
+            .0
+            .iter()
+            .map(|&[red, green, blue]| {
+                BltPixel::with_alpha(scale(red), scale(green), scale(blue), 255)
+            })
+            .collect();
+        Ok(Image::new(width, height, pixels))
+    }
+
+    /// Compare with `expected`, allowing every channel (alpha included) to
+    /// be off by up to `tolerance`.
+    pub fn compare(&self, expected: &Image, tolerance: u8) -> Result<(), Mismatch> {
+        let size = |image: &Image| (image.width(), image.height());
+        if size(self) != size(expected) {
+            return Err(Mismatch::Size {
+                actual: size(self),
+                expected: size(expected),
+            });
+        }
+
+        let mut count = 0;
+        let mut max_difference = 0;
+        let mut diff = Vec::with_capacity(self.pixels().len());
+        for (&actual, &wanted) in self.pixels().iter().zip(expected.pixels()) {
+            let difference = [
+                actual.red.abs_diff(wanted.red),
+                actual.green.abs_diff(wanted.green),
+                actual.blue.abs_diff(wanted.blue),
+                actual.alpha().abs_diff(wanted.alpha()),
+            ]
+            .into_iter()
+            .max()
+            .unwrap_or(0);
+            max_difference = max_difference.max(difference);
+            diff.push(if difference > tolerance {
+                count += 1;
+                BltPixel::with_alpha(255, 0, 0, 255)
+            } else {
+                // A dimmed gray copy of the golden, for context
+                let luma =
+                    (wanted.red as u32 * 77 + wanted.green as u32 * 150 + wanted.blue as u32 * 29)
+                        >> 8;
+                let gray = (luma / 3) as u8;
+                BltPixel::with_alpha(gray, gray, gray, 255)
+            });
+        }
+        if count == 0 {
+            return Ok(());
+        }
+        Err(Mismatch::Pixels {
+            count,
+            max_difference,
+            diff: Image::new(self.width(), self.height(), diff),
+        })
+    }
+}
+
+/// How a frame differs from its golden image
+#[derive(Debug, Clone)]
+pub enum Mismatch {
+    Size {
+        actual: (u32, u32),
+        expected: (u32, u32),
+    },
+    /// `count` pixels are off by more than the tolerance. `diff` shows
+    /// them in red over a dimmed copy of the golden.
+    Pixels {
+        count: usize,
+        max_difference: u8,
+        diff: Image,
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Size { actual, expected } => write!(
+                f,
+                "frame is {}x{}, golden is {}x{}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            Mismatch::Pixels {
+                count,
+                max_difference,
+                diff,
+            } => write!(
+                f,
+                "{} of {} pixels differ, by up to {}",
+                count,
+                diff.pixels().len(),
+                max_difference
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GoldenOutcome {
+    Matched,
+    /// UPDATE_GOLDEN was set, so the frame became the golden.
+    Written,
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    /// No golden at `golden`, and UPDATE_GOLDEN was not set to record one
+    Missing {
+        golden: PathBuf,
+    },
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Decode {
+        path: PathBuf,
+        error: ImageError,
+    },
+    /// The frame was saved to `actual`, and the diff image to `diff` when
+    /// the sizes agree.
+    Mismatch {
+        golden: PathBuf,
+        mismatch: Mismatch,
+        actual: PathBuf,
+        diff: Option<PathBuf>,
+    },
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GoldenError::Missing { golden } => write!(
+                f,
+                "{}: no golden image (rerun with {}=1 to record it)",
+                golden.display(),
+                UPDATE_GOLDEN_VAR
+            ),
+            GoldenError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            GoldenError::Decode { path, error } => write!(f, "{}: {}", path.display(), error),
+            GoldenError::Mismatch {
+                golden,
+                mismatch,
+                actual,
+                diff,
+            } => {
+                write!(
+                    f,
+                    "{}: {}; frame saved to {}",
+                    golden.display(),
+                    mismatch,
+                    actual.display()
+                )?;
+                if let Some(diff) = diff {
+                    write!(f, ", diff to {}", diff.display())?;
+                }
+                write!(f, " (rerun with {}=1 to accept)", UPDATE_GOLDEN_VAR)
+            }
+        }
+    }
+}
+
+/// Compare `image` with the golden file at `path`: PPM if it has a `.ppm`
+/// extension, PNG otherwise. While `UPDATE_GOLDEN` is set the golden is
+/// (re)written from `image`; otherwise a missing golden is an error. On a
+/// mismatch the frame is saved beside the golden as `<stem>.actual.<ext>`,
+/// with a `<stem>.diff.<ext>` when the sizes agree.
+pub fn check_golden(
+    image: &Image,
+    path: &Path,
+    tolerance: u8,
+) -> Result<GoldenOutcome, GoldenError> {
+    let update = std::env::var_os(UPDATE_GOLDEN_VAR).is_some();
+    check_golden_with(image, path, tolerance, update)
+}
+
+fn check_golden_with(
+    image: &Image,
+    path: &Path,
+    tolerance: u8,
+    update: bool,
+) -> Result<GoldenOutcome, GoldenError> {
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |error| GoldenError::Io { path, error }
+    };
+    let actual_path = sibling(path, "actual");
+    let diff_path = sibling(path, "diff");
+
+    let golden = match fs::read(path) {
+        Ok(data) if !update => data,
+        Ok(_) => return write_golden(image, path),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            return if update {
+                write_golden(image, path)
+            } else {
+                Err(GoldenError::Missing {
+                    golden: path.to_path_buf(),
+                })
+            };
+        }
+        Err(error) => return Err(io_error(path)(error)),
+    };
+    let expected = Image::decode(&golden).map_err(|error| GoldenError::Decode {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    match image.compare(&expected, tolerance) {
+        Ok(()) => {
+            // Leftovers from an earlier failure would only mislead
+            let _ = fs::remove_file(&actual_path);
+            let _ = fs::remove_file(&diff_path);
+            Ok(GoldenOutcome::Matched)
+        }
+        Err(mismatch) => {
+            fs::write(&actual_path, encode_for(path, image)).map_err(io_error(&actual_path))?;
+            let diff = match &mismatch {
+                Mismatch::Pixels { diff, .. } => {
+                    fs::write(&diff_path, encode_for(path, diff)).map_err(io_error(&diff_path))?;
+                    Some(diff_path)
+                }
+                Mismatch::Size { .. } => None,
+            };
+            Err(GoldenError::Mismatch {
+                golden: path.to_path_buf(),
+                mismatch,
+                actual: actual_path,
+                diff,
+            })
+        }
+    }
+}
+
+fn write_golden(image: &Image, path: &Path) -> Result<GoldenOutcome, GoldenError> {
+    let io_error = |error| GoldenError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory).map_err(io_error)?;
+    }
+    fs::write(path, encode_for(path, image)).map_err(io_error)?;
+    Ok(GoldenOutcome::Written)
+}
+
+fn encode_for(path: &Path, image: &Image) -> Vec<u8> {
+    let is_ppm = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("ppm"));
+    if is_ppm {
+        image.to_ppm()
+    } else {
+        image.to_png()
+    }
+}
+
+/// `dir/name.png` -> `dir/name.<tag>.png`
+fn sibling(path: &Path, tag: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(extension) => {
+            path.with_file_name(format!("{}.{}.{}", stem, tag, extension.to_string_lossy()))
+        }
+        None => path.with_file_name(format!("{}.{}", stem, tag)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkerboard(width: u32, height: u32) -> Image {
+        let pixels = (0..width * height)
+            .map(|i| {
+                let on = (i % width + i / width).is_multiple_of(2);
+                let value = if on { 200 } else { 40 };
+                BltPixel::with_alpha(value, value / 2, 255 - value, 255)
+            })
+            .collect();
+        Image::new(width, height, pixels)
+    }
+
+    /// A fresh directory under the system temp dir.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("uefi-graphics-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_ppm_round_trip() {
+        let image = checkerboard(5, 3);
+        let ppm = image.to_ppm();
+        assert!(ppm.starts_with(b"P6\n5 3\n255\n"));
+        assert_eq!(Image::decode(&ppm).unwrap().pixels(), image.pixels());
+
+        // Comments, odd spacing and a maximum value below 255
+        let mut file = b"P6 # written by hand\n2\t1\n# max\n15\n".to_vec();
+        file.extend_from_slice(&[15, 0, 5, 0, 15, 0]);
+        let image = Image::from_ppm(&file).unwrap();
+        assert_eq!(
+            image.pixel(0, 0),
+            Some(BltPixel::with_alpha(255, 0, 85, 255))
+        );
+        assert_eq!(
+            image.pixel(1, 0),
+            Some(BltPixel::with_alpha(0, 255, 0, 255))
+        );
+        assert_eq!(
+            Image::from_ppm(&file[..file.len() - 1]).unwrap_err(),
+            ImageError::Truncated
+        );
+        assert_eq!(
+            Image::from_ppm(b"P6 1 1 65535\n\0\0\0\0\0\0").unwrap_err(),
+            ImageError::Unsupported("16-bit PPM")
+        );
+    }
+
+    #[test]
+    fn test_compare_tolerance_and_diff() {
+        let expected = checkerboard(4, 4);
+        let mut actual = expected.clone();
+        actual.pixels_mut()[5].red += 3;
+        actual.pixels_mut()[10].blue -= 9;
+
+        assert!(actual.compare(&expected, 9).is_ok());
+        match actual.compare(&expected, 3) {
+            Err(Mismatch::Pixels {
+                count,
+                max_difference,
+                diff,
+            }) => {
+                assert_eq!((count, max_difference), (1, 9));
+                assert_eq!(diff.pixel(2, 2), Some(BltPixel::with_alpha(255, 0, 0, 255)));
+                assert_ne!(diff.pixel(1, 1), diff.pixel(2, 2));
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(
+            checkerboard(4, 3).compare(&expected, 255),
+            Err(Mismatch::Size {
+                actual: (4, 3),
+                expected: (4, 4)
+            })
+        ));
+    }
+
+    #[test]
+    fn test_golden_workflow() {
+        let dir = scratch_dir("golden");
+        let golden = dir.join("board.png");
+        let image = checkerboard(6, 4);
+        let check = |image: &Image, update| check_golden_with(image, &golden, 0, update);
+
+        // A missing golden fails unless it is being recorded
+        assert!(matches!(
+            check(&image, false),
+            Err(GoldenError::Missing { .. })
+        ));
+        assert!(!golden.exists());
+        assert_eq!(check(&image, true).unwrap(), GoldenOutcome::Written);
+        assert_eq!(check(&image, false).unwrap(), GoldenOutcome::Matched);
+
+        let mut changed = image.clone();
+        changed.pixels_mut()[0] = BltPixel::with_alpha(0, 0, 0, 255);
+        let error = check(&changed, false).unwrap_err();
+        assert!(
+            error.to_string().contains("1 of 24 pixels differ"),
+            "{}",
+            error
+        );
+        let actual = Image::decode(&fs::read(dir.join("board.actual.png")).unwrap()).unwrap();
+        assert_eq!(actual.pixels(), changed.pixels());
+        let diff = Image::decode(&fs::read(dir.join("board.diff.png")).unwrap()).unwrap();
+        assert_eq!(diff.pixel(0, 0), Some(BltPixel::with_alpha(255, 0, 0, 255)));
+
+        // Accepting the change, then matching again, clears the leftovers
+        assert_eq!(check(&changed, true).unwrap(), GoldenOutcome::Written);
+        assert_eq!(check(&changed, false).unwrap(), GoldenOutcome::Matched);
+        assert!(!dir.join("board.diff.png").exists());
+
+        let ppm = dir.join("board.ppm");
+        assert_eq!(
+            check_golden_with(&image, &ppm, 0, true).unwrap(),
+            GoldenOutcome::Written
+        );
+        assert!(fs::read(&ppm).unwrap().starts_with(b"P6"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
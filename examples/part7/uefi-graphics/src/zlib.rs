@@ -0,0 +1,597 @@
+//! zlib / DEFLATE (RFC 1950, RFC 1951)
+//!
+//! A small canonical-Huffman inflater in the style of zlib's `puff.c`, and
+//! a fixed-Huffman compressor for writing PNGs. Both use nothing beyond
+//! `Vec`, so they move into a `no_std` + `alloc` loader unchanged.
+//!
+//! ```text
+//!   zlib stream   CMF | FLG | deflate blocks ... | Adler-32 (big-endian)
+//!   block         BFINAL (1 bit) | BTYPE (2 bits) | data
+//!                 BTYPE 00 stored, 01 fixed Huffman, 10 dynamic Huffman
+//! ```
+
+use std::fmt;
+
+const MAX_BITS: usize = 15;
+
+/// Base lengths and extra bits for length symbols 257..=285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Base distances and extra bits for distance symbols 0..=29
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Order in which dynamic blocks send the code length code lengths
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InflateError {
+    /// The input ends inside a block or before the checksum.
+    Truncated,
+    /// Not a deflate-compressed zlib stream, or one needing a preset dictionary.
+    BadHeader,
+    /// Block type 11.
+    BadBlockType,
+    /// A stored block whose LEN and NLEN disagree.
+    BadStoredLength,
+    /// An over-subscribed or incomplete Huffman code, or an invalid symbol.
+    BadCode,
+    /// A back-reference before the start of the output.
+    BadDistance,
+    /// The output would exceed the caller's limit.
+    TooLarge,
+    /// The Adler-32 of the output does not match the stream.
+    BadChecksum,
+}
+
+impl fmt::Display for InflateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InflateError::Truncated => write!(f, "compressed data is truncated"),
+            InflateError::BadHeader => write!(f, "invalid zlib header"),
+            InflateError::BadBlockType => write!(f, "invalid deflate block type"),
+            InflateError::BadStoredLength => write!(f, "stored block length mismatch"),
+            InflateError::BadCode => write!(f, "invalid Huffman code"),
+            InflateError::BadDistance => write!(f, "distance too far back"),
+            InflateError::TooLarge => write!(f, "decompressed data too large"),
+            InflateError::BadChecksum => write!(f, "Adler-32 mismatch"),
+        }
+    }
+}
+
+/// Adler-32 checksum as used by zlib.
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    // 5552 is the most bytes that can be summed before `b` may overflow
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+/// Decompress a zlib stream, producing at most `limit` bytes.
+pub fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, InflateError> {
+    let [cmf, flg, ..] = *data else {
+        return Err(InflateError::Truncated);
+    };
+    let method = cmf & 0x0F;
+    let window_bits = (cmf >> 4) + 8;
+    let preset_dictionary = flg & 0x20 != 0;
+    if method != 8
+        || window_bits > 15
+        || preset_dictionary
+        || !(((cmf as u16) << 8) | flg as u16).is_multiple_of(31)
+    {
+        return Err(InflateError::BadHeader);
+    }
+
+    let mut reader = BitReader::new(&data[2..]);
+    let output = inflate_from(&mut reader, limit)?;
+    let trailer = reader.remaining().get(..4).ok_or(InflateError::Truncated)?;
+    if u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]) != adler32(&output) {
+        return Err(InflateError::BadChecksum);
+    }
+    Ok(output)
+}
+
+fn inflate_from(reader: &mut BitReader, limit: usize) -> Result<Vec<u8>, InflateError> {
+    let mut output = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => stored_block(reader, &mut output, limit)?,
+            1 => {
+                let (literals, distances) = fixed_codes();
+                codes(reader, &mut output, limit, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(reader)?;
+                codes(reader, &mut output, limit, &literals, &distances)?;
+            }
+            _ => return Err(InflateError::BadBlockType),
+        }
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+/// LSB-first bit input over a byte slice
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            position: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    /// The next `n` (at most 16) bits, first bit in the lowest position.
+    fn bits(&mut self, n: u32) -> Result<u32, InflateError> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or(InflateError::Truncated)?;
+            self.position += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1 << n) - 1);
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Drop the bits left in the current byte.
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+
+    /// Whole bytes after the current one.
+    fn remaining(&self) -> &'a [u8] {
+        &self.data[self.position..]
+    }
+}
+
+/// A canonical Huffman code: how many codes of each length, and the
+/// symbols ordered by code
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// Build from per-symbol code lengths (0 = unused). Incomplete codes are
+    /// allowed, since a distance code may have a single symbol.
+    fn new(lengths: &[u8]) -> Result<Self, InflateError> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(InflateError::BadCode);
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for length in 1..=MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; offsets[MAX_BITS + 1] as usize];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    /// Read one code bit by bit. Canonical codes of each length are
+    /// consecutive, so a code is found once it falls below the first code
+    /// of the next length.
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, InflateError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= reader.bits(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(InflateError::BadCode)
+    }
+}
+
+fn stored_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<(), InflateError> {
+    reader.align();
+    let header = reader.remaining().get(..4).ok_or(InflateError::Truncated)?;
+    let length = u16::from_le_bytes([header[0], header[1]]);
+    let complement = u16::from_le_bytes([header[2], header[3]]);
+    if length != !complement {
+        return Err(InflateError::BadStoredLength);
+    }
+    let data = reader
+        .remaining()
+        .get(4..4 + length as usize)
+        .ok_or(InflateError::Truncated)?;
+    if output.len() + data.len() > limit {
+        return Err(InflateError::TooLarge);
+    }
+    output.extend_from_slice(data);
+    reader.position += 4 + length as usize;
+    Ok(())
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    let literals = Huffman::new(&lengths).expect("fixed literal code");
+    let distances = Huffman::new(&[5; 30]).expect("fixed distance code");
+    (literals, distances)
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), InflateError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(InflateError::BadCode);
+    }
+
+    let mut code_length_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_length_lengths)?;
+
+    // Literal/length and distance lengths form one sequence, and repeats
+    // may cross from one into the other
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = code_lengths.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = index
+                    .checked_sub(1)
+                    .map(|i| lengths[i])
+                    .ok_or(InflateError::BadCode)?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        let run = lengths
+            .get_mut(index..index + repeat)
+            .ok_or(InflateError::BadCode)?;
+        run.fill(value);
+        index += repeat;
+    }
+    if lengths[256] == 0 {
+        // No end-of-block code
+        return Err(InflateError::BadCode);
+    }
+
+    let literals = Huffman::new(&lengths[..literal_count])?;
+    let distances = Huffman::new(&lengths[literal_count..])?;
+    Ok((literals, distances))
+}
+
+/// Decode literals and back-references until end-of-block.
+fn codes(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    limit: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), InflateError> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 => {
+                if output.len() == limit {
+                    return Err(InflateError::TooLarge);
+                }
+                output.push(symbol as u8);
+            }
+            256 => return Ok(()),
+            257..=285 => {
+                let index = symbol - 257;
+                let length =
+                    LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+                let index = distances.decode(reader)? as usize;
+                if index >= DIST_BASE.len() {
+                    return Err(InflateError::BadCode);
+                }
+                let distance =
+                    DIST_BASE[index] as usize + reader.bits(DIST_EXTRA[index] as u32)? as usize;
+                if distance > output.len() {
+                    return Err(InflateError::BadDistance);
+                }
+                if output.len() + length > limit {
+                    return Err(InflateError::TooLarge);
+                }
+                // Byte by byte: the copy may overlap its own output
+                let start = output.len() - distance;
+                for i in 0..length {
+                    output.push(output[start + i]);
+                }
+            }
+            _ => return Err(InflateError::BadCode),
+        }
+    }
+}
+
+/// Compress `data` into a zlib stream: greedy LZ77 matching over a 32 KiB
+/// window, coded as one fixed-Huffman block. Far from zlib's ratio, but
+/// framebuffer captures are mostly runs and repeated rows, which this
+/// catches.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    const WINDOW: usize = 32 * 1024;
+    const MIN_MATCH: usize = 3;
+    const MAX_MATCH: usize = 258;
+    const MAX_CHAIN: usize = 64;
+    const HASH_BITS: u32 = 15;
+
+    let mut writer = BitWriter::default();
+    // Deflate with a 32 KiB window, no dictionary, "fastest" level hint
+    writer.bytes.extend_from_slice(&[0x78, 0x01]);
+    writer.bits(1, 1);
+    writer.bits(1, 2);
+
+    let hash = |i: usize| {
+        let key = u32::from_le_bytes([data[i], data[i + 1], data[i + 2], 0]);
+        (key.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    };
+    // Most recent position for each hash, and for each position the
+    // previous one with the same hash
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; data.len()];
+
+    let mut i = 0;
+    while i < data.len() {
+        let (mut best_length, mut best_distance) = (0, 0);
+        if i + MIN_MATCH <= data.len() {
+            let max_length = MAX_MATCH.min(data.len() - i);
+            let mut candidate = head[hash(i)];
+            let mut chain = 0;
+            while candidate != usize::MAX && i - candidate <= WINDOW && chain < MAX_CHAIN {
+                let length = data[candidate..]
+                    .iter()
+                    .zip(&data[i..i + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best_length {
+                    (best_length, best_distance) = (length, i - candidate);
+                    if length == max_length {
+                        break;
+                    }
+                }
+                candidate = previous[candidate];
+                chain += 1;
+            }
+        }
+
+        let step = if best_length >= MIN_MATCH {
+            writer.length(best_length);
+            writer.distance(best_distance);
+            best_length
+        } else {
+            writer.literal(data[i] as u16);
+            1
+        };
+        let hashable = data.len().saturating_sub(MIN_MATCH - 1);
+        for (position, link) in previous
+            .iter_mut()
+            .enumerate()
+            .take(hashable.min(i + step))
+            .skip(i)
+        {
+            let h = hash(position);
+            *link = head[h];
+            head[h] = position;
+        }
+        i += step;
+    }
+    writer.literal(256);
+
+    let mut stream = writer.finish();
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+/// LSB-first bit output
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn bits(&mut self, value: u32, n: u32) {
+        self.buffer |= value << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// A Huffman code, which deflate sends most significant bit first.
+    fn code(&mut self, code: u32, length: u32) {
+        self.bits(code.reverse_bits() >> (32 - length), length);
+    }
+
+    /// A literal/length symbol in the fixed code.
+    fn literal(&mut self, symbol: u16) {
+        let symbol = symbol as u32;
+        match symbol {
+            0..=143 => self.code(0x30 + symbol, 8),
+            144..=255 => self.code(0x190 + symbol - 144, 9),
+            256..=279 => self.code(symbol - 256, 7),
+            _ => self.code(0xC0 + symbol - 280, 8),
+        }
+    }
+
+    fn length(&mut self, length: usize) {
+        let index = LENGTH_BASE
+            .iter()
+            .rposition(|&base| base as usize <= length)
+            .expect("match length of at least 3");
+        self.literal(257 + index as u16);
+        self.bits(
+            (length - LENGTH_BASE[index] as usize) as u32,
+            LENGTH_EXTRA[index] as u32,
+        );
+    }
+
+    fn distance(&mut self, distance: usize) {
+        let index = DIST_BASE
+            .iter()
+            .rposition(|&base| base as usize <= distance)
+            .expect("distance of at least 1");
+        self.code(index as u32, 5);
+        self.bits(
+            (distance - DIST_BASE[index] as usize) as u32,
+            DIST_EXTRA[index] as u32,
+        );
+    }
+
+    /// Pad the last byte with zero bits.
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A raw deflate stream, without the zlib header and checksum.
+    fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>, InflateError> {
+        inflate_from(&mut BitReader::new(data), limit)
+    }
+
+    #[test]
+    fn test_stored_and_fixed_blocks() {
+        // Two stored blocks, then a final fixed-Huffman block ("!!!!!" as a
+        // literal and a length-4 match at distance 1)
+        let mut stream = vec![0x78, 0x01];
+        stream.extend_from_slice(&[0x00, 0x03, 0x00, 0xFC, 0xFF, b'a', b'b', b'c']);
+        stream.extend_from_slice(&[0x00, 0x00, 0x00, 0xFF, 0xFF]);
+        stream.extend_from_slice(&[0x53, 0x54, 0x04, 0x02, 0x00]);
+        stream.extend_from_slice(&adler32(b"abc!!!!!").to_be_bytes());
+        assert_eq!(decompress(&stream, 100).unwrap(), b"abc!!!!!");
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+
+        assert_eq!(decompress(&stream, 7), Err(InflateError::TooLarge));
+        let last = stream.len() - 1;
+        stream[last] ^= 1;
+        assert_eq!(decompress(&stream, 100), Err(InflateError::BadChecksum));
+        assert_eq!(
+            decompress(&stream[..stream.len() - 6], 100),
+            Err(InflateError::Truncated)
+        );
+        assert_eq!(decompress(&[0x78, 0x02], 100), Err(InflateError::BadHeader));
+        assert_eq!(inflate(&[0x07], 100), Err(InflateError::BadBlockType));
+        assert_eq!(
+            inflate(&[0x01, 0x03, 0x00, 0xFC, 0xFE], 100),
+            Err(InflateError::BadStoredLength)
+        );
+    }
+
+    #[test]
+    fn test_dynamic_block() {
+        // zlib.compress(text, 9) of the text below: one dynamic block
+        let text = b"GraphicsOutput.Blt() moves rectangles between video memory \
+and a buffer; GraphicsOutput.SetMode() changes the resolution; \
+GraphicsOutput.QueryMode() describes each mode.";
+        let compressed = DYNAMIC_STREAM;
+        assert_eq!(compressed[2] >> 1 & 0b11, 0b10);
+        assert_eq!(decompress(compressed, 1024).unwrap(), text);
+        // A match reaching before the start of the output
+        assert_eq!(
+            inflate(&[0x03, 0x02, 0x00], 100),
+            Err(InflateError::BadDistance)
+        );
+    }
+
+    #[test]
+    fn test_compress_round_trip() {
+        let runs = [0u8; 100_000];
+        let text = b"GraphicsOutput.Blt() GraphicsOutput.SetMode() GraphicsOutput.QueryMode()";
+        let noise: Vec<u8> = (0..70_000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        for data in [&[][..], b"a", text, &runs, &noise] {
+            let compressed = compress(data);
+            assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+        }
+        // Long runs become chains of 258-byte matches
+        assert!(compress(&runs).len() < 1000);
+    }
+
+    const DYNAMIC_STREAM: &[u8] = &[
+        0x78, 0xDA, 0x65, 0xCE, 0x3B, 0x0E, 0xC2, 0x30, 0x10, 0x84, 0xE1, 0xAB, 0x6C, 0x09, 0x4D,
+        0x2E, 0x40, 0x47, 0x93, 0x0A, 0x45, 0x88, 0x13, 0xF8, 0x31, 0x89, 0x2D, 0xD9, 0xDE, 0x68,
+        0xBD, 0x0E, 0xCA, 0xED, 0xD9, 0x82, 0x06, 0xD1, 0x4D, 0xF3, 0x7F, 0x9A, 0x59, 0xDC, 0x9E,
+        0x72, 0xE8, 0xCB, 0xD0, 0x7D, 0xE8, 0x74, 0x2F, 0x7A, 0xB9, 0x52, 0xE5, 0x03, 0x9D, 0x04,
+        0x41, 0x5D, 0xDB, 0x8A, 0x4D, 0x0F, 0x7D, 0x03, 0x8D, 0x8E, 0x1C, 0xC1, 0x54, 0x51, 0x59,
+        0x4E, 0x72, 0x2D, 0x92, 0x23, 0x3F, 0xD6, 0x15, 0x72, 0xA3, 0xF9, 0x17, 0x7A, 0x41, 0x1F,
+        0x1C, 0x61, 0x58, 0x48, 0x86, 0x98, 0xA1, 0x09, 0x46, 0x76, 0x2E, 0x43, 0x33, 0xB7, 0xBF,
+        0xE0, 0x39, 0x20, 0xE7, 0x37, 0x89, 0xE8, 0x41, 0xB2, 0xB7, 0x08, 0x2E, 0x24, 0xBB, 0x13,
+        0x31, 0x7D, 0x00, 0xC2, 0xBF, 0x3E, 0x28,
+    ];
+}
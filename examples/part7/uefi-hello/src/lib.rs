@@ -0,0 +1,63 @@
+//! Types shared by the part7 UEFI examples
+//!
+//! The `uefi-hello` binary introduces these; other examples (the GPT parser
+//! in `uefi-filesystem`) depend on this library so there is one definition.
+
+use std::fmt;
+
+/// GUID - Globally Unique Identifier
+///
+/// Same layout as `EFI_GUID`. On disk (GPT headers and entries, variable
+/// stores) the first three fields are little-endian and `data4` is stored
+/// as-is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Guid {
+    data1: u32,
+    data2: u16,
+    data3: u16,
+    data4: [u8; 8],
+}
+
+impl Guid {
+    pub const fn new(data1: u32, data2: u16, data3: u16, data4: [u8; 8]) -> Self {
+        Guid {
+            data1,
+            data2,
+            data3,
+            data4,
+        }
+    }
+
+    pub const ZERO: Guid = Guid::new(0, 0, 0, [0; 8]);
+
+    pub fn from_bytes(bytes: &[u8; 16]) -> Self {
+        let mut data4 = [0u8; 8];
+        data4.copy_from_slice(&bytes[8..]);
+        Guid {
+            data1: u32::from_le_bytes(bytes[..4].try_into().unwrap()),
+            data2: u16::from_le_bytes([bytes[4], bytes[5]]),
+            data3: u16::from_le_bytes([bytes[6], bytes[7]]),
+            data4,
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[..4].copy_from_slice(&self.data1.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.data2.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.data3.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.data4);
+        bytes
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let d = &self.data4;
+        write!(
+            f,
+            "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+            self.data1, self.data2, self.data3, d[0], d[1], d[2], d[3], d[4], d[5], d[6], d[7]
+        )
+    }
+}
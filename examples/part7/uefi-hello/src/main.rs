@@ -45,6 +45,8 @@
 
 use std::collections::HashMap;
 
+use uefi_hello::Guid;
+
 fn main() {
     println!("=== UEFI Programming Concepts ===\n");
 
@@ -82,26 +84,6 @@ type UefiUintn = usize; // Platform-dependent size
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct Handle(usize);
 
-/// GUID - Globally Unique Identifier
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-struct Guid {
-    data1: u32,
-    data2: u16,
-    data3: u16,
-    data4: [u8; 8],
-}
-
-impl Guid {
-    const fn new(data1: u32, data2: u16, data3: u16, data4: [u8; 8]) -> Self {
-        Guid {
-            data1,
-            data2,
-            data3,
-            data4,
-        }
-    }
-}
-
 // Well-known GUIDs
 const EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID: Guid = Guid::new(
     0x387477c2,
@@ -129,18 +111,9 @@ fn uefi_types() {
     );
 
     println!("\n  GUID Examples:");
-    println!("    SimpleTextOutput: {:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
-        EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID.data1,
-        EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID.data2,
-        EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID.data3,
-        EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID.data4[0],
-        EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID.data4[1],
-        EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID.data4[2],
-        EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID.data4[3],
-        EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID.data4[4],
-        EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID.data4[5],
-        EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID.data4[6],
-        EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID.data4[7],
+    println!(
+        "    SimpleTextOutput: {}",
+        EFI_SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID
     );
 }
 
@@ -0,0 +1,291 @@
+//! Per-architecture QEMU setup
+//!
+//! Each guest architecture has its own QEMU binary, machine types and CPU
+//! models:
+//!
+//! ```text
+//!   x86_64   qemu-system-x86_64   pc / q35 / microvm   qemu64
+//!   aarch64  qemu-system-aarch64  virt                 cortex-a72
+//!   riscv64  qemu-system-riscv64  virt                 rv64
+//! ```
+//!
+//! KVM only runs guests of the host's own architecture, and only when
+//! `/dev/kvm` can be opened for reading and writing; anything else runs
+//! under TCG, QEMU's emulator. With KVM the CPU model is `host`.
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::path::Path;
+
+use crate::{Arch, MachineType, QemuCommandBuilder, QemuConfig};
+
+const KVM_DEVICE: &str = "/dev/kvm";
+
+/// QEMU accelerators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Accel {
+    /// Hardware virtualization through Linux KVM
+    Kvm,
+    /// QEMU's portable dynamic translator
+    Tcg,
+}
+
+impl fmt::Display for Accel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Accel::Kvm => write!(f, "kvm"),
+            Accel::Tcg => write!(f, "tcg"),
+        }
+    }
+}
+
+/// What the machine running QEMU offers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Host {
+    /// `None` for architectures with no UEFI guest support here
+    pub arch: Option<Arch>,
+    /// `/dev/kvm` is present and usable by this user
+    pub kvm: bool,
+}
+
+impl Host {
+    pub fn detect() -> Self {
+        Host {
+            arch: Arch::host(),
+            kvm: kvm_usable(Path::new(KVM_DEVICE)),
+        }
+    }
+}
+
+/// KVM needs read-write access, usually through the `kvm` group; merely
+/// existing is not enough.
+fn kvm_usable(device: &Path) -> bool {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(device)
+        .is_ok()
+}
+
+/// QEMU configuration errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The machine type does not exist for the architecture
+    UnsupportedMachine { machine: MachineType, arch: Arch },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::UnsupportedMachine { machine, arch } => {
+                write!(f, "machine type {} is not available for {}", machine, arch)
+            }
+        }
+    }
+}
+
+impl Arch {
+    /// The architecture this program was built for.
+    pub fn host() -> Option<Arch> {
+        if cfg!(target_arch = "x86_64") {
+            Some(Arch::X86_64)
+        } else if cfg!(target_arch = "aarch64") {
+            Some(Arch::Aarch64)
+        } else if cfg!(target_arch = "riscv64") {
+            Some(Arch::Riscv64)
+        } else {
+            None
+        }
+    }
+
+    pub fn qemu_binary(self) -> &'static str {
+        match self {
+            Arch::X86_64 => "qemu-system-x86_64",
+            Arch::Aarch64 => "qemu-system-aarch64",
+            Arch::Riscv64 => "qemu-system-riscv64",
+        }
+    }
+
+    pub fn default_machine(self) -> MachineType {
+        match self {
+            Arch::X86_64 => MachineType::Q35,
+            Arch::Aarch64 | Arch::Riscv64 => MachineType::Virt,
+        }
+    }
+
+    /// The CPU model to emulate under TCG.
+    pub fn tcg_cpu(self) -> &'static str {
+        match self {
+            Arch::X86_64 => "qemu64",
+            Arch::Aarch64 => "cortex-a72",
+            Arch::Riscv64 => "rv64",
+        }
+    }
+}
+
+impl MachineType {
+    pub fn supports(self, arch: Arch) -> bool {
+        match self {
+            MachineType::Pc | MachineType::Q35 | MachineType::Microvm => arch == Arch::X86_64,
+            MachineType::Virt => arch != Arch::X86_64,
+        }
+    }
+}
+
+impl QemuConfig {
+    /// The defaults for `arch`: its usual machine type, KVM when possible.
+    pub fn for_arch(arch: Arch) -> Self {
+        QemuConfig {
+            arch,
+            machine: arch.default_machine(),
+            ..Default::default()
+        }
+    }
+
+    /// KVM if it is enabled, the guest matches the host and `/dev/kvm` is
+    /// usable; TCG otherwise.
+    pub fn accelerator(&self, host: &Host) -> Accel {
+        if self.enable_kvm && host.arch == Some(self.arch) && host.kvm {
+            Accel::Kvm
+        } else {
+            Accel::Tcg
+        }
+    }
+
+    /// The configured CPU model, else `host` under KVM and the
+    /// architecture's TCG model otherwise.
+    pub fn cpu_model(&self, accel: Accel) -> String {
+        match (&self.cpu, accel) {
+            (Some(cpu), _) => cpu.clone(),
+            (None, Accel::Kvm) => "host".to_string(),
+            (None, Accel::Tcg) => self.arch.tcg_cpu().to_string(),
+        }
+    }
+
+    /// The `-machine` value. aarch64 `virt` defaults to GICv2, which KVM
+    /// hosts with only a GICv3 cannot provide; `max` picks the best one
+    /// the accelerator supports.
+    pub fn machine_option(&self) -> String {
+        match (self.arch, self.machine) {
+            (Arch::Aarch64, MachineType::Virt) => "virt,gic-version=max".to_string(),
+            (_, machine) => machine.to_string(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.machine.supports(self.arch) {
+            Ok(())
+        } else {
+            Err(ConfigError::UnsupportedMachine {
+                machine: self.machine,
+                arch: self.arch,
+            })
+        }
+    }
+}
+
+impl QemuCommandBuilder {
+    /// Start a command for `config`'s architecture with its machine, CPU,
+    /// accelerator and other options.
+    pub fn from_config(config: &QemuConfig, host: &Host) -> Result<Self, ConfigError> {
+        let mut builder = QemuCommandBuilder::for_arch(config.arch);
+        builder.args = config.to_args(host)?.into_iter().map(Into::into).collect();
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accelerator_selection() {
+        let host = |arch, kvm| Host { arch, kvm };
+        let x86 = QemuConfig::for_arch(Arch::X86_64);
+        let arm = QemuConfig::for_arch(Arch::Aarch64);
+
+        assert_eq!(x86.accelerator(&host(Some(Arch::X86_64), true)), Accel::Kvm);
+        // No usable /dev/kvm, or a foreign guest: TCG
+        assert_eq!(
+            x86.accelerator(&host(Some(Arch::X86_64), false)),
+            Accel::Tcg
+        );
+        assert_eq!(arm.accelerator(&host(Some(Arch::X86_64), true)), Accel::Tcg);
+        assert_eq!(
+            arm.accelerator(&host(Some(Arch::Aarch64), true)),
+            Accel::Kvm
+        );
+        assert_eq!(arm.accelerator(&host(None, true)), Accel::Tcg);
+        let no_kvm = QemuConfig {
+            enable_kvm: false,
+            ..QemuConfig::for_arch(Arch::X86_64)
+        };
+        assert_eq!(
+            no_kvm.accelerator(&host(Some(Arch::X86_64), true)),
+            Accel::Tcg
+        );
+
+        assert!(!kvm_usable(Path::new("/nonexistent/kvm")));
+    }
+
+    #[test]
+    fn test_per_arch_command_lines() {
+        let x86_host = Host {
+            arch: Some(Arch::X86_64),
+            kvm: true,
+        };
+        let argv = QemuCommandBuilder::from_config(&QemuConfig::for_arch(Arch::Aarch64), &x86_host)
+            .unwrap()
+            .esp_dir("/tmp/esp")
+            .build();
+        assert_eq!(
+            argv,
+            [
+                "qemu-system-aarch64",
+                "-machine",
+                "virt,gic-version=max",
+                "-accel",
+                "tcg",
+                "-cpu",
+                "cortex-a72",
+                "-m",
+                "256M",
+                "-smp",
+                "1",
+                "-serial",
+                "stdio",
+                "-drive",
+                "if=none,id=drive0,format=raw,file=fat:rw:/tmp/esp",
+                "-device",
+                "virtio-blk-pci,drive=drive0",
+            ]
+        );
+
+        let riscv = QemuConfig::for_arch(Arch::Riscv64);
+        let args = riscv.to_args(&x86_host).unwrap();
+        assert_eq!(
+            args[..7],
+            ["-machine", "virt", "-accel", "tcg", "-cpu", "rv64", "-m"]
+        );
+
+        let x86 = QemuConfig::for_arch(Arch::X86_64)
+            .to_args(&x86_host)
+            .unwrap();
+        assert_eq!(
+            x86[..6],
+            ["-machine", "q35", "-accel", "kvm", "-cpu", "host"]
+        );
+
+        let wrong = QemuConfig {
+            machine: MachineType::Q35,
+            ..QemuConfig::for_arch(Arch::Riscv64)
+        };
+        assert_eq!(
+            QemuCommandBuilder::from_config(&wrong, &x86_host).err(),
+            Some(ConfigError::UnsupportedMachine {
+                machine: MachineType::Q35,
+                arch: Arch::Riscv64
+            })
+        );
+    }
+}
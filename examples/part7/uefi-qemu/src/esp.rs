@@ -0,0 +1,378 @@
+//! Staging an EFI System Partition directory for QEMU
+//!
+//! QEMU's `fat:rw:<dir>` drive presents a host directory as a FAT disk, so
+//! an ESP is just a directory laid out the way the firmware expects. The
+//! stager turns a list of [`EspEntry`]s into such a directory:
+//!
+//! ```text
+//!   EspEntry::boot_loader(X86_64, app)   ──►  <root>/EFI/BOOT/BOOTX64.EFI
+//!   EspEntry::driver("/EFI/drivers/..")  ──►  <root>/EFI/drivers/...
+//!   EspEntry::startup_script("...")      ──►  <root>/startup.nsh
+//! ```
+//!
+//! Everything is checked before anything is written: paths must be
+//! storable on FAT and unique ignoring case, and EFI binaries must pass the
+//! PE/COFF checks in [`pe`](crate::pe). The directory is removed when the
+//! returned [`StagedEsp`] is dropped.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::ovmf::create_run_dir;
+use crate::pe::{self, PeError};
+use crate::{Arch, EspEntry, EspEntryType};
+
+/// Where a staged file's content comes from
+#[derive(Debug, Clone)]
+pub enum EspSource {
+    /// A file on the host, e.g. a build output
+    File(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+/// ESP staging errors
+#[derive(Debug)]
+pub enum EspError {
+    /// An empty path, a `.` or `..` component, or a character FAT cannot
+    /// store
+    InvalidPath(String),
+    /// Two entries for one path (FAT names are case-insensitive), or a file
+    /// where another entry needs a directory
+    Conflict(String),
+    /// A file entry without a source
+    MissingSource(String),
+    /// An EFI binary the firmware would refuse to load
+    InvalidImage {
+        path: String,
+        error: PeError,
+    },
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+}
+
+impl fmt::Display for EspError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EspError::InvalidPath(path) => write!(f, "invalid ESP path {:?}", path),
+            EspError::Conflict(path) => write!(f, "{} conflicts with another entry", path),
+            EspError::MissingSource(path) => write!(f, "{} has no content", path),
+            EspError::InvalidImage { path, error } => write!(f, "{}: {}", path, error),
+            EspError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl Arch {
+    /// The removable-media boot file the firmware looks for in \EFI\BOOT.
+    pub fn boot_file_name(self) -> &'static str {
+        match self {
+            Arch::X86_64 => "BOOTX64.EFI",
+            Arch::Aarch64 => "BOOTAA64.EFI",
+            Arch::Riscv64 => "BOOTRISCV64.EFI",
+        }
+    }
+}
+
+impl EspEntry {
+    pub fn directory(path: &str) -> Self {
+        EspEntry {
+            path: path.to_string(),
+            entry_type: EspEntryType::Directory,
+            description: "Directory".to_string(),
+            source: None,
+        }
+    }
+
+    /// The default boot application for `arch`, `\EFI\BOOT\BOOT<arch>.EFI`.
+    pub fn boot_loader(arch: Arch, source: EspSource) -> Self {
+        EspEntry {
+            path: format!("/EFI/BOOT/{}", arch.boot_file_name()),
+            entry_type: EspEntryType::BootLoader,
+            description: format!("Default bootloader ({})", arch),
+            source: Some(source),
+        }
+    }
+
+    pub fn driver(path: &str, source: EspSource) -> Self {
+        EspEntry {
+            path: path.to_string(),
+            entry_type: EspEntryType::Driver,
+            description: "UEFI driver".to_string(),
+            source: Some(source),
+        }
+    }
+
+    pub fn config(path: &str, source: EspSource) -> Self {
+        EspEntry {
+            path: path.to_string(),
+            entry_type: EspEntryType::Config,
+            description: "Configuration file".to_string(),
+            source: Some(source),
+        }
+    }
+
+    /// `\startup.nsh`, which the UEFI Shell runs when it starts.
+    pub fn startup_script(commands: &str) -> Self {
+        EspEntry {
+            path: "/startup.nsh".to_string(),
+            entry_type: EspEntryType::Config,
+            description: "UEFI Shell startup script".to_string(),
+            source: Some(EspSource::Bytes(commands.as_bytes().to_vec())),
+        }
+    }
+}
+
+/// A staged ESP directory; dropping it deletes the directory.
+#[derive(Debug)]
+pub struct StagedEsp {
+    root: PathBuf,
+    files: Vec<PathBuf>,
+}
+
+impl StagedEsp {
+    /// The directory to pass to `QemuCommandBuilder::esp_dir`.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Host paths of the staged files, in entry order.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+}
+
+impl Drop for StagedEsp {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+/// Split an ESP path on either separator, rejecting anything FAT cannot
+/// store or that would leave the ESP directory.
+fn components(path: &str) -> Result<Vec<&str>, EspError> {
+    let invalid = || EspError::InvalidPath(path.to_string());
+    let parts: Vec<&str> = path
+        .split(['/', '\\'])
+        .filter(|part| !part.is_empty())
+        .collect();
+    if parts.is_empty() {
+        return Err(invalid());
+    }
+    for part in &parts {
+        let bad_char = |c: char| c.is_control() || "\"*:<>?|".contains(c);
+        if *part == "." || *part == ".." || part.contains(bad_char) {
+            return Err(invalid());
+        }
+    }
+    Ok(parts)
+}
+
+/// An entry that passed validation, with its content loaded.
+struct Planned<'a> {
+    parts: Vec<&'a str>,
+    content: Option<Vec<u8>>,
+}
+
+/// Check every entry and load its content.
+fn plan(entries: &[EspEntry]) -> Result<Vec<Planned<'_>>, EspError> {
+    let mut files = HashSet::new();
+    let mut directories = HashSet::new();
+    let mut planned = Vec::new();
+    for entry in entries {
+        let parts = components(&entry.path)?;
+        let key = parts.join("/").to_lowercase();
+        let is_directory = matches!(entry.entry_type, EspEntryType::Directory);
+        // Repeating a directory is harmless, anything else is not
+        let fresh = if is_directory {
+            directories.insert(key.clone());
+            !files.contains(&key)
+        } else {
+            files.insert(key.clone()) && !directories.contains(&key)
+        };
+        if !fresh {
+            return Err(EspError::Conflict(entry.path.clone()));
+        }
+        for depth in 1..parts.len() {
+            let parent = parts[..depth].join("/").to_lowercase();
+            if files.contains(&parent) {
+                return Err(EspError::Conflict(entry.path.clone()));
+            }
+            directories.insert(parent);
+        }
+
+        let content = if is_directory {
+            None
+        } else {
+            let content = match &entry.source {
+                None => return Err(EspError::MissingSource(entry.path.clone())),
+                Some(EspSource::Bytes(bytes)) => bytes.clone(),
+                Some(EspSource::File(path)) => fs::read(path).map_err(|error| EspError::Io {
+                    path: path.clone(),
+                    error,
+                })?,
+            };
+            let image_check = match entry.entry_type {
+                EspEntryType::BootLoader => {
+                    // The default boot file's name fixes the architecture
+                    let file_name = parts[parts.len() - 1];
+                    let arch = [Arch::X86_64, Arch::Aarch64, Arch::Riscv64]
+                        .into_iter()
+                        .find(|arch| arch.boot_file_name().eq_ignore_ascii_case(file_name));
+                    Some((arch, pe::APPLICATION_SUBSYSTEMS))
+                }
+                EspEntryType::Driver => Some((None, pe::DRIVER_SUBSYSTEMS)),
+                EspEntryType::Directory | EspEntryType::Config => None,
+            };
+            if let Some((arch, subsystems)) = image_check {
+                pe::validate(&content, arch, subsystems).map_err(|error| {
+                    EspError::InvalidImage {
+                        path: entry.path.clone(),
+                        error,
+                    }
+                })?;
+            }
+            Some(content)
+        };
+        planned.push(Planned { parts, content });
+    }
+    Ok(planned)
+}
+
+/// Check `entries` and write them into a new temporary directory.
+pub fn stage_esp(entries: &[EspEntry]) -> Result<StagedEsp, EspError> {
+    let planned = plan(entries)?;
+    let root = create_run_dir().map_err(|error| EspError::Io {
+        path: std::env::temp_dir(),
+        error,
+    })?;
+    // From here on dropping `esp` cleans up after a failed write
+    let mut esp = StagedEsp {
+        root,
+        files: Vec::new(),
+    };
+    for entry in planned {
+        let path = entry
+            .parts
+            .iter()
+            .fold(esp.root.clone(), |path, part| path.join(part));
+        let io_error = |error| EspError::Io {
+            path: path.clone(),
+            error,
+        };
+        match entry.content {
+            None => fs::create_dir_all(&path).map_err(io_error)?,
+            Some(content) => {
+                fs::create_dir_all(path.parent().unwrap()).map_err(io_error)?;
+                fs::write(&path, content).map_err(io_error)?;
+                esp.files.push(path);
+            }
+        }
+    }
+    Ok(esp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pe::{minimal_image, EFI_APPLICATION, EFI_BOOT_SERVICE_DRIVER};
+
+    fn app(arch: Arch) -> EspSource {
+        EspSource::Bytes(minimal_image(arch, EFI_APPLICATION))
+    }
+
+    #[test]
+    fn test_stage_layout() {
+        let source = create_run_dir().unwrap();
+        let built = source.join("hello.efi");
+        fs::write(&built, minimal_image(Arch::X86_64, EFI_APPLICATION)).unwrap();
+
+        let entries = [
+            EspEntry::boot_loader(Arch::X86_64, EspSource::File(built)),
+            EspEntry::boot_loader(Arch::Aarch64, app(Arch::Aarch64)),
+            EspEntry::driver(
+                "\\EFI\\drivers\\fs.efi",
+                EspSource::Bytes(minimal_image(Arch::X86_64, EFI_BOOT_SERVICE_DRIVER)),
+            ),
+            EspEntry::startup_script("fs0:\\EFI\\BOOT\\BOOTX64.EFI\n"),
+            EspEntry::config(
+                "/EFI/app/app.conf",
+                EspSource::Bytes(b"verbose=1\n".to_vec()),
+            ),
+            EspEntry::directory("/EFI/empty"),
+        ];
+        let esp = stage_esp(&entries).unwrap();
+        let root = esp.root().to_path_buf();
+        let relative: Vec<PathBuf> = esp
+            .files()
+            .iter()
+            .map(|file| file.strip_prefix(&root).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(
+            relative,
+            [
+                "EFI/BOOT/BOOTX64.EFI",
+                "EFI/BOOT/BOOTAA64.EFI",
+                "EFI/drivers/fs.efi",
+                "startup.nsh",
+                "EFI/app/app.conf",
+            ]
+            .map(PathBuf::from)
+        );
+        assert_eq!(
+            fs::read(root.join("EFI/BOOT/BOOTX64.EFI")).unwrap(),
+            minimal_image(Arch::X86_64, EFI_APPLICATION)
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("startup.nsh")).unwrap(),
+            "fs0:\\EFI\\BOOT\\BOOTX64.EFI\n"
+        );
+        assert!(root.join("EFI/empty").is_dir());
+
+        drop(esp);
+        assert!(!root.exists());
+        fs::remove_dir_all(source).unwrap();
+    }
+
+    #[test]
+    fn test_stage_rejects_bad_entries() {
+        let stage = |entries: &[EspEntry]| stage_esp(entries).unwrap_err().to_string();
+
+        // An aarch64 image placed as the x86_64 boot file
+        let mut swapped = EspEntry::boot_loader(Arch::X86_64, app(Arch::Aarch64));
+        assert_eq!(
+            stage(&[swapped]),
+            "/EFI/BOOT/BOOTX64.EFI: machine 0xaa64, expected 0x8664 (x86_64)"
+        );
+        swapped = EspEntry::driver("/EFI/drivers/app.efi", app(Arch::X86_64));
+        assert_eq!(stage(&[swapped]), "/EFI/drivers/app.efi: subsystem 10");
+
+        let duplicate = [
+            EspEntry::startup_script("echo one"),
+            EspEntry::config("/STARTUP.NSH", EspSource::Bytes(Vec::new())),
+        ];
+        assert_eq!(
+            stage(&duplicate),
+            "/STARTUP.NSH conflicts with another entry"
+        );
+        let file_as_directory = [
+            EspEntry::config("/EFI/app", EspSource::Bytes(Vec::new())),
+            EspEntry::config("/EFI/app/app.conf", EspSource::Bytes(Vec::new())),
+        ];
+        assert_eq!(
+            stage(&file_as_directory),
+            "/EFI/app/app.conf conflicts with another entry"
+        );
+
+        for path in ["/", "/EFI/../escape.efi", "/EFI/a:b"] {
+            let entry = EspEntry::config(path, EspSource::Bytes(Vec::new()));
+            assert!(matches!(stage_esp(&[entry]), Err(EspError::InvalidPath(_))));
+        }
+        let missing = EspEntry::config("/x.conf", EspSource::File("/nonexistent/x.conf".into()));
+        assert!(matches!(stage_esp(&[missing]), Err(EspError::Io { .. })));
+    }
+}
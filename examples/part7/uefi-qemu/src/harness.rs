@@ -0,0 +1,380 @@
+//! Assertions on what a booted image prints
+//!
+//! A [`SerialLog`] follows QEMU's serial (or debugcon) output while the
+//! guest runs and splits it into [`LogLine`]s. Firmware output is noisy:
+//! lines end in `\r\n` and the console sprinkles ANSI escape sequences
+//! around them, so both are stripped before a line is parsed. Lines in
+//! either common logger format carry a level:
+//!
+//! ```text
+//!   [ INFO]: src/main.rs@015: UEFI application started    (uefi-rs logger)
+//!   [WARN ] Low memory available                          (this example)
+//!   BdsDxe: starting Boot0001 ...                         (no level)
+//! ```
+//!
+//! `expect` waits for the next line containing a pattern, like `expect(1)`
+//! does for terminals. Expectations are ordered: each one only looks at
+//! lines after the previous match. A failed expectation is an
+//! [`ExpectError`] carrying the last lines of output, so a test returning
+//! `Result<(), ExpectError>` reports what the guest printed instead of just
+//! "timed out".
+
+use std::fmt;
+use std::io::Read;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::runner::Stream;
+use crate::LogLevel;
+
+/// How many lines of output a failed expectation shows.
+const TAIL_LINES: usize = 8;
+
+impl LogLevel {
+    /// Parse a level name as loggers print it, ignoring case and padding.
+    pub fn from_name(name: &str) -> Option<LogLevel> {
+        match name.trim().to_ascii_uppercase().as_str() {
+            "ERROR" => Some(LogLevel::Error),
+            "WARN" | "WARNING" => Some(LogLevel::Warn),
+            "INFO" => Some(LogLevel::Info),
+            "DEBUG" => Some(LogLevel::Debug),
+            "TRACE" => Some(LogLevel::Trace),
+            _ => None,
+        }
+    }
+}
+
+/// One line of guest output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    /// The level, if the line came from a logger
+    pub level: Option<LogLevel>,
+    /// The text after the level tag (the whole line without one)
+    pub message: String,
+    /// The whole line without escape sequences or line ending
+    pub text: String,
+}
+
+impl fmt::Display for LogLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Drop ANSI escape sequences and control characters other than tab.
+fn strip_escapes(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // CSI: ESC [ parameters intermediates final; others: ESC + one
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        break;
+                    }
+                }
+            }
+        } else if !c.is_control() || c == '\t' {
+            text.push(c);
+        }
+    }
+    text
+}
+
+/// Split `[LEVEL]` or `[LEVEL]:` off the front of a line.
+fn split_level(text: &str) -> Option<(LogLevel, &str)> {
+    let rest = text.trim_start().strip_prefix('[')?;
+    let (name, rest) = rest.split_once(']')?;
+    let level = LogLevel::from_name(name)?;
+    let rest = rest.strip_prefix(':').unwrap_or(rest);
+    Some((level, rest.trim_start()))
+}
+
+pub fn parse_line(raw: &[u8]) -> LogLine {
+    let text = strip_escapes(String::from_utf8_lossy(raw).trim_end_matches(['\r', '\n']));
+    let (level, message) = match split_level(&text) {
+        Some((level, message)) => (Some(level), message.to_string()),
+        None => (None, text.clone()),
+    };
+    LogLine {
+        level,
+        message,
+        text,
+    }
+}
+
+/// Why an expectation failed
+pub enum ExpectError {
+    /// Nothing matched before the timeout
+    TimedOut {
+        expected: String,
+        timeout: Duration,
+        tail: Vec<String>,
+    },
+    /// The output ended (QEMU exited) without a match
+    Ended { expected: String, tail: Vec<String> },
+    /// The guest logged errors
+    ErrorsLogged(Vec<LogLine>),
+}
+
+impl fmt::Display for ExpectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (tail, heading) = match self {
+            ExpectError::TimedOut {
+                expected,
+                timeout,
+                tail,
+            } => (tail, format!("expected {} within {:?}", expected, timeout)),
+            ExpectError::Ended { expected, tail } => {
+                (tail, format!("expected {}, but the output ended", expected))
+            }
+            ExpectError::ErrorsLogged(lines) => {
+                write!(f, "the guest logged {} error(s):", lines.len())?;
+                for line in lines {
+                    write!(f, "\n  | {}", line)?;
+                }
+                return Ok(());
+            }
+        };
+        write!(f, "{}; last lines of output:", heading)?;
+        if tail.is_empty() {
+            write!(f, "\n  (none)")?;
+        }
+        for line in tail {
+            write!(f, "\n  | {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// The Display form, so a failing `#[test]` that returns this error prints
+/// the output rather than a struct dump.
+impl fmt::Debug for ExpectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Guest output, followed as it arrives
+pub struct SerialLog {
+    stream: Arc<Stream>,
+    /// Offset just past the last matched line
+    position: usize,
+}
+
+impl SerialLog {
+    pub(crate) fn new(stream: Arc<Stream>) -> Self {
+        SerialLog {
+            stream,
+            position: 0,
+        }
+    }
+
+    /// Follow any byte stream, such as a `-debugcon file:` log being
+    /// written or a recorded transcript.
+    pub fn from_reader(reader: impl Read + Send + 'static) -> Self {
+        let (stream, _reader_thread) = Stream::capture(reader);
+        SerialLog::new(stream)
+    }
+
+    /// Wait for the next line containing `pattern`.
+    pub fn expect(&mut self, pattern: &str, timeout: Duration) -> Result<LogLine, ExpectError> {
+        self.expect_line(None, pattern, timeout)
+    }
+
+    /// Wait for the next line logged at `level` containing `pattern`.
+    pub fn expect_level(
+        &mut self,
+        level: LogLevel,
+        pattern: &str,
+        timeout: Duration,
+    ) -> Result<LogLine, ExpectError> {
+        self.expect_line(Some(level), pattern, timeout)
+    }
+
+    fn expect_line(
+        &mut self,
+        level: Option<LogLevel>,
+        pattern: &str,
+        timeout: Duration,
+    ) -> Result<LogLine, ExpectError> {
+        let deadline = Instant::now() + timeout;
+        let expected = match level {
+            Some(level) => format!("[{}] {:?}", level.to_string().trim(), pattern),
+            None => format!("{:?}", pattern),
+        };
+        // Lines before `scanned` have been looked at and did not match
+        let mut scanned = self.position;
+        loop {
+            let (pending, closed) = self.stream.read_from(scanned);
+            let available = scanned + pending.len();
+            for line in pending.split_inclusive(|&byte| byte == b'\n') {
+                // A partial line is only final once the output has ended
+                if !line.ends_with(b"\n") && !closed {
+                    break;
+                }
+                scanned += line.len();
+                let parsed = parse_line(line);
+                if parsed.text.contains(pattern)
+                    && level.is_none_or(|level| parsed.level == Some(level))
+                {
+                    self.position = scanned;
+                    return Ok(parsed);
+                }
+            }
+            if closed {
+                return Err(ExpectError::Ended {
+                    expected,
+                    tail: self.tail(),
+                });
+            }
+            if Instant::now() >= deadline {
+                return Err(ExpectError::TimedOut {
+                    expected,
+                    timeout,
+                    tail: self.tail(),
+                });
+            }
+            self.stream.wait_beyond(available, deadline);
+        }
+    }
+
+    /// Every line so far; the last one only if it is complete or the
+    /// output has ended.
+    pub fn lines(&self) -> Vec<LogLine> {
+        let (bytes, closed) = self.stream.read_from(0);
+        bytes
+            .split_inclusive(|&byte| byte == b'\n')
+            .filter(|line| closed || line.ends_with(b"\n"))
+            .map(parse_line)
+            .collect()
+    }
+
+    /// Fail if any line so far was logged at ERROR level.
+    pub fn expect_no_errors(&self) -> Result<(), ExpectError> {
+        let errors: Vec<LogLine> = self
+            .lines()
+            .into_iter()
+            .filter(|line| line.level == Some(LogLevel::Error))
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ExpectError::ErrorsLogged(errors))
+        }
+    }
+
+    fn tail(&self) -> Vec<String> {
+        let lines = self.lines();
+        let skip = lines.len().saturating_sub(TAIL_LINES);
+        lines.into_iter().skip(skip).map(|line| line.text).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RECORDED_TRANSCRIPT;
+    use std::io::Cursor;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn test_parse_line() {
+        let line = parse_line(b"\x1b[2J\x1b[01;01H\x1b[=3h[ INFO]: src/main.rs@015: started\r\n");
+        assert_eq!(line.level, Some(LogLevel::Info));
+        assert_eq!(line.message, "src/main.rs@015: started");
+        assert_eq!(line.text, "[ INFO]: src/main.rs@015: started");
+
+        let line = parse_line(b"[WARN ] Low memory available\n");
+        assert_eq!(line.level, Some(LogLevel::Warn));
+        assert_eq!(line.message, "Low memory available");
+        assert_eq!(parse_line(b"[warning] x").level, Some(LogLevel::Warn));
+
+        let line = parse_line(b"BdsDxe: starting Boot0001\r\n");
+        assert_eq!(line.level, None);
+        assert_eq!(line.message, "BdsDxe: starting Boot0001");
+        assert_eq!(parse_line(b"[1.234] kernel").level, None);
+    }
+
+    #[test]
+    fn test_expectations_over_transcript() -> Result<(), ExpectError> {
+        let mut log = SerialLog::from_reader(Cursor::new(RECORDED_TRANSCRIPT.as_bytes()));
+        log.expect("BdsDxe: starting", SECOND)?;
+        let line = log.expect_level(LogLevel::Info, "Found", SECOND)?;
+        assert_eq!(line.message, "src/main.rs@030: Found 4 memory regions");
+        log.expect_level(LogLevel::Warn, "Low memory", SECOND)?;
+        log.expect("0 failed", SECOND)?;
+        log.expect_no_errors()?;
+
+        // Expectations are ordered: the start line has been passed, and
+        // the output ends before any shutdown line
+        let error = log.expect("UEFI application started", SECOND).unwrap_err();
+        assert!(matches!(error, ExpectError::Ended { .. }));
+        let report = error.to_string();
+        assert!(report.starts_with(
+            "expected \"UEFI application started\", but the output ended; last lines of output:"
+        ));
+        assert!(report.ends_with("\n  | [ INFO]: src/tests.rs@042: 3 passed, 0 failed"));
+        assert_eq!(report.lines().count(), 1 + TAIL_LINES);
+
+        let failing =
+            SerialLog::from_reader(Cursor::new(&b"[ INFO]: a\n[ERROR]: disk missing\n"[..]));
+        let mut waiting = SerialLog::new(Arc::clone(&failing.stream));
+        waiting.expect("disk", SECOND)?;
+        assert_eq!(
+            failing.expect_no_errors().unwrap_err().to_string(),
+            "the guest logged 1 error(s):\n  | [ERROR]: disk missing"
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fake_qemu_replaying_transcript() -> Result<(), ExpectError> {
+        use crate::runner::tests::{spawn, stub_qemu};
+        use crate::runner::QemuExit;
+        use crate::{DebugTarget, QemuCommandBuilder};
+
+        // Replays the recording a line at a time, then exits through
+        // isa-debug-exit with 0x10
+        let stub = stub_qemu(
+            "replay",
+            "while IFS= read -r line; do printf '%s\\n' \"$line\"; sleep 0.02; done \
+             < \"$(dirname \"$0\")/ovmf-boot.log\"\nexit 33\n",
+        );
+        std::fs::write(stub.with_file_name("ovmf-boot.log"), RECORDED_TRANSCRIPT).unwrap();
+        let builder = QemuCommandBuilder::new()
+            .executable(&stub)
+            .log_to_stdio(DebugTarget::Serial)
+            .isa_debug_exit(0xf4)
+            .timeout(Duration::from_secs(30));
+        let qemu = spawn(&builder);
+        let mut log = qemu.serial_log();
+        log.expect_level(LogLevel::Info, "UEFI application started", 10 * SECOND)?;
+        log.expect("test file_io ... ok", 10 * SECOND)?;
+        log.expect("3 passed, 0 failed", 10 * SECOND)?;
+        let run = qemu.wait().unwrap();
+        assert_eq!(run.exit, QemuExit::Guest(0x10));
+        log.expect_no_errors()?;
+        assert_eq!(run.serial, RECORDED_TRANSCRIPT.as_bytes());
+
+        // A guest that hangs fails the expectation at its timeout
+        let stub = stub_qemu(
+            "hang-after-boot",
+            "echo 'BdsDxe: starting Boot0001'\nexec sleep 30\n",
+        );
+        let qemu = spawn(&QemuCommandBuilder::new().executable(&stub));
+        let mut log = qemu.serial_log();
+        let started = Instant::now();
+        match log.expect("tests passed", Duration::from_millis(200)) {
+            Err(ExpectError::TimedOut { tail, .. }) => {
+                assert_eq!(tail, ["BdsDxe: starting Boot0001"]);
+            }
+            other => panic!("{:?}", other.map(|line| line.text)),
+        }
+        assert!(started.elapsed() < 5 * SECOND);
+        Ok(())
+    }
+}
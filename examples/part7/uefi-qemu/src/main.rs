@@ -36,8 +36,25 @@
 //! Note: This example demonstrates concepts in a std environment.
 //! See the scripts/ directory for actual QEMU launch scripts.
 
-use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
 use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+mod arch;
+mod esp;
+mod harness;
+mod ovmf;
+mod pe;
+mod runner;
+
+use arch::{Accel, Host};
+use esp::{stage_esp, EspSource};
+use harness::SerialLog;
+use ovmf::{create_run_dir, override_variables};
+use runner::{QemuExit, DEFAULT_DEBUG_EXIT_IOBASE};
 
 fn main() {
     println!("=== UEFI QEMU Testing Concepts ===\n");
@@ -66,7 +83,8 @@ fn main() {
 // ============================================
 
 /// QEMU machine types for UEFI
-#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum MachineType {
     /// Standard PC (i440FX + PIIX)
     Pc,
@@ -74,6 +92,8 @@ enum MachineType {
     Q35,
     /// MicroVM (minimal, fast boot)
     Microvm,
+    /// Generic board for aarch64 and riscv64, devices on virtio/PCIe
+    Virt,
 }
 
 impl fmt::Display for MachineType {
@@ -82,6 +102,7 @@ impl fmt::Display for MachineType {
             MachineType::Pc => write!(f, "pc"),
             MachineType::Q35 => write!(f, "q35"),
             MachineType::Microvm => write!(f, "microvm"),
+            MachineType::Virt => write!(f, "virt"),
         }
     }
 }
@@ -89,9 +110,13 @@ impl fmt::Display for MachineType {
 /// QEMU configuration options
 #[derive(Debug)]
 struct QemuConfig {
+    arch: Arch,
     machine: MachineType,
+    /// CPU model; `None` picks one for the accelerator
+    cpu: Option<String>,
     memory_mb: u32,
     cpus: u32,
+    /// Use KVM when the host can run this guest with it
     enable_kvm: bool,
     serial_stdio: bool,
     no_graphics: bool,
@@ -101,7 +126,9 @@ struct QemuConfig {
 impl Default for QemuConfig {
     fn default() -> Self {
         QemuConfig {
+            arch: Arch::X86_64,
             machine: MachineType::Q35,
+            cpu: None,
             memory_mb: 256,
             cpus: 1,
             enable_kvm: true,
@@ -113,19 +140,20 @@ impl Default for QemuConfig {
 }
 
 impl QemuConfig {
-    fn to_args(&self) -> Vec<String> {
+    /// Arguments for this configuration on `host`, one argv element each.
+    fn to_args(&self, host: &Host) -> Result<Vec<String>, arch::ConfigError> {
+        self.validate()?;
+        let accel = self.accelerator(host);
         let mut args = Vec::new();
 
-        args.push(format!("-machine {}", self.machine));
-        args.push(format!("-m {}M", self.memory_mb));
-        args.push(format!("-smp {}", self.cpus));
-
-        if self.enable_kvm {
-            args.push("-enable-kvm".to_string());
-        }
+        args.extend(["-machine".to_string(), self.machine_option()]);
+        args.extend(["-accel".to_string(), accel.to_string()]);
+        args.extend(["-cpu".to_string(), self.cpu_model(accel)]);
+        args.extend(["-m".to_string(), format!("{}M", self.memory_mb)]);
+        args.extend(["-smp".to_string(), self.cpus.to_string()]);
 
         if self.serial_stdio {
-            args.push("-serial stdio".to_string());
+            args.extend(["-serial".to_string(), "stdio".to_string()]);
         }
 
         if self.no_graphics {
@@ -133,15 +161,28 @@ impl QemuConfig {
         }
 
         if let Some(port) = self.debug_port {
-            args.push(format!("-gdb tcp::{}", port));
+            args.extend(["-gdb".to_string(), format!("tcp::{}", port)]);
             args.push("-S".to_string()); // Pause at start
         }
 
-        args
+        Ok(args)
     }
 }
 
+/// One option per line, each flag with its value.
+fn print_args(args: &[String]) {
+    for arg in args {
+        if arg.starts_with('-') {
+            print!("\n    {}", arg);
+        } else {
+            print!(" {}", arg);
+        }
+    }
+    println!();
+}
+
 fn qemu_configuration() {
+    let host = Host::detect();
     let config = QemuConfig::default();
 
     println!("  Default QEMU configuration:");
@@ -150,9 +191,37 @@ fn qemu_configuration() {
     println!("    CPUs: {}", config.cpus);
     println!("    KVM: {}", config.enable_kvm);
 
-    println!("\n  Generated arguments:");
-    for arg in config.to_args() {
-        println!("    {}", arg);
+    match host.arch {
+        Some(arch) => println!("\n  Host: {}, /dev/kvm usable: {}", arch, host.kvm),
+        None => println!("\n  Host: unsupported architecture, TCG only"),
+    }
+
+    print!("\n  Generated arguments:");
+    print_args(&config.to_args(&host).expect("default config is valid"));
+
+    println!("\n  Per-architecture targets:");
+    for arch in [Arch::X86_64, Arch::Aarch64, Arch::Riscv64] {
+        let config = QemuConfig::for_arch(arch);
+        let accel = config.accelerator(&host);
+        println!(
+            "    {:<8} {:<20} -machine {:<21} -cpu {:<11} {}",
+            arch.to_string(),
+            arch.qemu_binary(),
+            config.machine_option(),
+            config.cpu_model(accel),
+            match accel {
+                Accel::Kvm => "KVM",
+                Accel::Tcg => "TCG (emulated)",
+            }
+        );
+    }
+
+    let wrong = QemuConfig {
+        machine: MachineType::Q35,
+        ..QemuConfig::for_arch(Arch::Aarch64)
+    };
+    if let Err(error) = wrong.to_args(&host) {
+        println!("\n  Mismatched configuration: {}", error);
     }
 
     // Debug configuration
@@ -162,72 +231,58 @@ fn qemu_configuration() {
         ..Default::default()
     };
 
-    println!("\n  Debug configuration:");
-    for arg in debug_config.to_args() {
-        println!("    {}", arg);
-    }
+    print!("\n  Debug configuration:");
+    print_args(&debug_config.to_args(&host).expect("debug config is valid"));
 }
 
 // ============================================
 // OVMF Firmware
 // ============================================
 
+/// Guest architectures with an edk2 firmware build for QEMU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Arch {
+    X86_64,
+    Aarch64,
+    Riscv64,
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arch::X86_64 => write!(f, "x86_64"),
+            Arch::Aarch64 => write!(f, "aarch64"),
+            Arch::Riscv64 => write!(f, "riscv64"),
+        }
+    }
+}
+
 /// OVMF firmware files
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct OvmfPaths {
     /// OVMF code (read-only firmware)
-    code: String,
+    code: PathBuf,
     /// OVMF variables (writable NVRAM)
-    vars: Option<String>,
+    vars: Option<PathBuf>,
 }
 
 impl OvmfPaths {
-    /// Common paths on different distributions
-    fn detect() -> Option<Self> {
-        let common_paths = [
-            // Debian/Ubuntu
-            (
-                "/usr/share/OVMF/OVMF_CODE.fd",
-                Some("/usr/share/OVMF/OVMF_VARS.fd"),
-            ),
-            // Fedora
-            (
-                "/usr/share/edk2/ovmf/OVMF_CODE.fd",
-                Some("/usr/share/edk2/ovmf/OVMF_VARS.fd"),
-            ),
-            // Arch Linux
-            (
-                "/usr/share/edk2-ovmf/x64/OVMF_CODE.fd",
-                Some("/usr/share/edk2-ovmf/x64/OVMF_VARS.fd"),
-            ),
-            // Single file fallback
-            ("/usr/share/qemu/OVMF.fd", None),
-        ];
-
-        for (code, vars) in common_paths {
-            // In real code, would check if file exists
-            return Some(OvmfPaths {
-                code: code.to_string(),
-                vars: vars.map(|s| s.to_string()),
-            });
-        }
-
-        None
-    }
-
-    fn to_qemu_args(&self) -> Vec<String> {
+    fn to_qemu_args(&self) -> Vec<OsString> {
         let mut args = Vec::new();
 
         if let Some(ref vars) = self.vars {
             // Recommended: separate CODE and VARS
-            args.push(format!(
-                "-drive if=pflash,format=raw,readonly=on,file={}",
-                self.code
+            args.push("-drive".into());
+            args.push(drive_option(
+                "if=pflash,format=raw,readonly=on,file=",
+                &self.code,
             ));
-            args.push(format!("-drive if=pflash,format=raw,file={}", vars));
+            args.push("-drive".into());
+            args.push(drive_option("if=pflash,format=raw,file=", vars));
         } else {
             // Simple: single BIOS file
-            args.push(format!("-bios {}", self.code));
+            args.push("-bios".into());
+            args.push(self.code.clone().into());
         }
 
         args
@@ -240,16 +295,37 @@ fn ovmf_firmware() {
     println!("    - Based on TianoCore EDK II");
     println!("    - Provides full UEFI boot services");
 
-    if let Some(paths) = OvmfPaths::detect() {
-        println!("\n  Detected paths:");
-        println!("    CODE: {}", paths.code);
+    for arch in [Arch::X86_64, Arch::Aarch64, Arch::Riscv64] {
+        let (code_variable, vars_variable) = override_variables(arch);
+        println!(
+            "\n  {} firmware (override with ${} / ${}):",
+            arch, code_variable, vars_variable
+        );
+        let paths = match OvmfPaths::detect(arch) {
+            Ok(paths) => paths,
+            Err(error) => {
+                for line in error.to_string().lines() {
+                    println!("    {}", line);
+                }
+                continue;
+            }
+        };
+        println!("    CODE: {}", paths.code.display());
         if let Some(ref vars) = paths.vars {
-            println!("    VARS: {}", vars);
+            println!("    VARS: {} (template)", vars.display());
         }
 
-        println!("\n  QEMU arguments:");
-        for arg in paths.to_qemu_args() {
-            println!("    {}", arg);
+        // Every run gets its own copy of the VARS template
+        let run = create_run_dir().and_then(|dir| Ok((paths.with_private_vars(&dir)?, dir)));
+        match run {
+            Ok((run, dir)) => {
+                println!("\n    QEMU arguments for one run:");
+                for arg in run.to_qemu_args() {
+                    println!("      {}", shell_quote(&arg));
+                }
+                let _ = fs::remove_dir_all(dir);
+            }
+            Err(error) => println!("    Could not copy the VARS template: {}", error),
         }
     }
 
@@ -270,6 +346,8 @@ struct EspEntry {
     path: String,
     entry_type: EspEntryType,
     description: String,
+    /// Content to stage; `None` for directories and descriptive entries
+    source: Option<EspSource>,
 }
 
 #[derive(Debug)]
@@ -286,31 +364,37 @@ fn esp_structure() {
             path: "/EFI".to_string(),
             entry_type: EspEntryType::Directory,
             description: "Root EFI directory".to_string(),
+            source: None,
         },
         EspEntry {
             path: "/EFI/BOOT".to_string(),
             entry_type: EspEntryType::Directory,
             description: "Default boot directory".to_string(),
+            source: None,
         },
         EspEntry {
             path: "/EFI/BOOT/BOOTX64.EFI".to_string(),
             entry_type: EspEntryType::BootLoader,
             description: "Default bootloader (x64)".to_string(),
+            source: None,
         },
         EspEntry {
             path: "/EFI/BOOT/BOOTIA32.EFI".to_string(),
             entry_type: EspEntryType::BootLoader,
             description: "Default bootloader (IA32)".to_string(),
+            source: None,
         },
         EspEntry {
             path: "/EFI/BOOT/BOOTAA64.EFI".to_string(),
             entry_type: EspEntryType::BootLoader,
             description: "Default bootloader (ARM64)".to_string(),
+            source: None,
         },
         EspEntry {
             path: "/startup.nsh".to_string(),
             entry_type: EspEntryType::Config,
             description: "UEFI Shell startup script".to_string(),
+            source: None,
         },
     ];
 
@@ -338,6 +422,64 @@ fn esp_structure() {
     println!("    IA32:    BOOTIA32.EFI");
     println!("    ARM64:   BOOTAA64.EFI");
     println!("    ARM:     BOOTARM.EFI");
+    println!("    RISC-V:  BOOTRISCV64.EFI");
+
+    // $EFI_APP names a real x86_64 build; otherwise stage a header-only
+    // placeholder
+    let app = match std::env::var_os("EFI_APP") {
+        Some(path) => EspSource::File(path.into()),
+        None => EspSource::Bytes(pe::minimal_image(Arch::X86_64, pe::EFI_APPLICATION)),
+    };
+    let entries = [
+        EspEntry::boot_loader(Arch::X86_64, app),
+        EspEntry::boot_loader(
+            Arch::Aarch64,
+            EspSource::Bytes(pe::minimal_image(Arch::Aarch64, pe::EFI_APPLICATION)),
+        ),
+        EspEntry::driver(
+            "/EFI/drivers/ext4.efi",
+            EspSource::Bytes(pe::minimal_image(Arch::X86_64, pe::EFI_BOOT_SERVICE_DRIVER)),
+        ),
+        EspEntry::startup_script("fs0:\\EFI\\BOOT\\BOOTX64.EFI\n"),
+        EspEntry::config(
+            "/EFI/myapp/config.ini",
+            EspSource::Bytes(b"log_level=debug\n".to_vec()),
+        ),
+        // Somewhere for the app to write its logs
+        EspEntry::directory("/EFI/myapp/logs"),
+    ];
+
+    println!("\n  Staging an ESP for -drive fat:rw:");
+    match stage_esp(&entries) {
+        Ok(esp) => {
+            println!("    Root: {}", esp.root().display());
+            for (entry, file) in entries.iter().zip(esp.files()) {
+                let size = fs::metadata(file).map(|m| m.len()).unwrap_or(0);
+                println!(
+                    "    {:30} {:>6} bytes  {}",
+                    entry.path, size, entry.description
+                );
+            }
+            let argv = QemuCommandBuilder::new().esp_dir(esp.root()).build();
+            println!("    QEMU: {}", shell_quote(&argv[argv.len() - 1]));
+        }
+        Err(error) => println!("    Staging failed: {}", error),
+    }
+
+    // Binaries are checked before anything is written
+    let elf = EspEntry::boot_loader(
+        Arch::Aarch64,
+        EspSource::Bytes(b"\x7fELF\x02\x01\x01\0".to_vec()),
+    );
+    let swapped = EspEntry::boot_loader(
+        Arch::Aarch64,
+        EspSource::Bytes(pe::minimal_image(Arch::X86_64, pe::EFI_APPLICATION)),
+    );
+    for entry in [elf, swapped] {
+        if let Err(error) = stage_esp(&[entry]) {
+            println!("    Rejected: {}", error);
+        }
+    }
 }
 
 // ============================================
@@ -409,6 +551,18 @@ fn debug_output() {
         println!("    [{}] {}", level, msg);
     }
 
+    println!("\n  Routing each target to QEMU's stdout for the test harness:");
+    for target in [
+        DebugTarget::Serial,
+        DebugTarget::DebugPort,
+        DebugTarget::Console,
+    ] {
+        let argv = QemuCommandBuilder::new().log_to_stdio(target).build();
+        let args: Vec<String> = argv[1..].iter().map(|arg| shell_quote(arg)).collect();
+        println!("    {:10} {}", format!("{:?}:", target), args.join(" "));
+    }
+    serial_log_harness();
+
     println!("\n  Serial output code pattern:");
     println!("    ```rust");
     println!("    // Write byte to COM1");
@@ -422,6 +576,53 @@ fn debug_output() {
     println!("    ```");
 }
 
+/// Serial output of an OVMF boot running a UEFI test binary
+const RECORDED_TRANSCRIPT: &str = include_str!("../transcripts/ovmf-boot.log");
+
+fn serial_log_harness() {
+    println!("\n  Serial log harness, replaying a recorded boot:");
+    let mut log = SerialLog::from_reader(io::Cursor::new(RECORDED_TRANSCRIPT.as_bytes()));
+    let timeout = Duration::from_secs(1);
+
+    let checks = [
+        (None, "BdsDxe: starting"),
+        (Some(LogLevel::Info), "UEFI application started"),
+        (Some(LogLevel::Info), "0 failed"),
+        // Already passed: expectations only look forward
+        (None, "Found 4 memory regions"),
+    ];
+    for (level, pattern) in checks {
+        let result = match level {
+            Some(level) => log.expect_level(level, pattern, timeout),
+            None => log.expect(pattern, timeout),
+        };
+        match result {
+            Ok(line) => println!("    PASS {:?} -> {}", pattern, line),
+            Err(error) => {
+                let mut report = error
+                    .to_string()
+                    .lines()
+                    .map(String::from)
+                    .collect::<Vec<_>>();
+                report.truncate(3);
+                println!("    FAIL {}", report.join("\n         "));
+            }
+        }
+    }
+    match log.expect_no_errors() {
+        Ok(()) => println!("    PASS no ERROR lines"),
+        Err(error) => println!("    FAIL {}", error),
+    }
+
+    println!("\n  Parsed lines:");
+    for line in log.lines() {
+        let level = line
+            .level
+            .map_or("-".to_string(), |level| level.to_string());
+        println!("    {:5} {}", level, line.message);
+    }
+}
+
 // ============================================
 // Common Issues
 // ============================================
@@ -486,103 +687,227 @@ fn common_issues() {
 // ============================================
 
 /// Builder for QEMU command lines
+///
+/// Produces an argv with one element per argument, so paths containing
+/// spaces reach QEMU intact without going through a shell. Paths inside
+/// `-drive` options have their commas doubled, which is QEMU's escape for
+/// a literal comma in an option value.
+#[derive(Debug, Clone)]
 struct QemuCommandBuilder {
-    executable: String,
-    args: Vec<String>,
-    drives: Vec<String>,
-    devices: Vec<String>,
+    arch: Arch,
+    executable: OsString,
+    args: Vec<OsString>,
+    drives: Vec<OsString>,
+    devices: Vec<OsString>,
+    debug_exit: bool,
+    timeout: Option<Duration>,
+    /// Drives attached through virtio-blk, for unique drive ids
+    virtio_drives: u32,
+}
+
+/// `prefix` followed by `path` with every `,` written as `,,`.
+fn drive_option(prefix: &str, path: &Path) -> OsString {
+    let mut option = OsString::from(prefix);
+    match path.to_str() {
+        Some(path) => option.push(path.replace(',', ",,")),
+        None => option.push(path),
+    }
+    option
+}
+
+/// Quote `arg` for a POSIX shell, only when it needs it.
+fn shell_quote(arg: &OsStr) -> String {
+    let arg = arg.to_string_lossy();
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        arg.into_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
 }
 
 impl QemuCommandBuilder {
     fn new() -> Self {
+        QemuCommandBuilder::for_arch(Arch::X86_64)
+    }
+
+    /// An empty command running `arch`'s QEMU binary.
+    fn for_arch(arch: Arch) -> Self {
         QemuCommandBuilder {
-            executable: "qemu-system-x86_64".to_string(),
+            arch,
+            executable: arch.qemu_binary().into(),
             args: Vec::new(),
             drives: Vec::new(),
             devices: Vec::new(),
+            debug_exit: false,
+            timeout: None,
+            virtio_drives: 0,
         }
     }
 
-    fn machine(mut self, machine: MachineType) -> Self {
-        self.args.push(format!("-machine {}", machine));
+    /// Run a different binary, e.g. another QEMU build or a test stub.
+    fn executable(mut self, path: impl AsRef<OsStr>) -> Self {
+        self.executable = path.as_ref().to_owned();
         self
     }
 
-    fn memory(mut self, mb: u32) -> Self {
-        self.args.push(format!("-m {}M", mb));
+    fn arg(mut self, flag: &str, value: impl Into<OsString>) -> Self {
+        self.args.push(flag.into());
+        self.args.push(value.into());
         self
     }
 
-    fn cpus(mut self, count: u32) -> Self {
-        self.args.push(format!("-smp {}", count));
+    fn flag(mut self, flag: &str) -> Self {
+        self.args.push(flag.into());
         self
     }
 
-    fn ovmf(mut self, code: &str, vars: Option<&str>) -> Self {
-        if let Some(v) = vars {
-            self.drives.push(format!(
-                "-drive if=pflash,format=raw,readonly=on,file={}",
-                code
-            ));
-            self.drives
-                .push(format!("-drive if=pflash,format=raw,file={}", v));
+    fn drive(mut self, value: OsString) -> Self {
+        self.drives.push("-drive".into());
+        self.drives.push(value);
+        self
+    }
+
+    fn machine(self, machine: MachineType) -> Self {
+        self.arg("-machine", machine.to_string())
+    }
+
+    fn memory(self, mb: u32) -> Self {
+        self.arg("-m", format!("{}M", mb))
+    }
+
+    fn cpus(self, count: u32) -> Self {
+        self.arg("-smp", count.to_string())
+    }
+
+    fn ovmf(self, code: impl AsRef<Path>, vars: Option<&Path>) -> Self {
+        if let Some(vars) = vars {
+            self.drive(drive_option(
+                "if=pflash,format=raw,readonly=on,file=",
+                code.as_ref(),
+            ))
+            .drive(drive_option("if=pflash,format=raw,file=", vars))
         } else {
-            self.args.push(format!("-bios {}", code));
+            self.arg("-bios", code.as_ref())
         }
-        self
     }
 
-    fn esp_dir(mut self, path: &str) -> Self {
+    /// A disk from `file`: the machine's default interface (IDE/AHCI) on
+    /// x86_64, virtio-blk on `virt`, which has no default disk controller.
+    fn block_drive(mut self, file: OsString) -> Self {
+        if self.arch == Arch::X86_64 {
+            let mut value = OsString::from("format=raw,");
+            value.push(file);
+            return self.drive(value);
+        }
+        let id = format!("drive{}", self.virtio_drives);
+        self.virtio_drives += 1;
+        let mut value = OsString::from(format!("if=none,id={},format=raw,", id));
+        value.push(file);
+        self = self.drive(value);
+        self.drives.push("-device".into());
         self.drives
-            .push(format!("-drive format=raw,file=fat:rw:{}", path));
+            .push(format!("virtio-blk-pci,drive={}", id).into());
         self
     }
 
-    fn disk_image(mut self, path: &str) -> Self {
-        self.drives.push(format!("-drive format=raw,file={}", path));
-        self
+    fn esp_dir(self, path: impl AsRef<Path>) -> Self {
+        self.block_drive(drive_option("file=fat:rw:", path.as_ref()))
     }
 
-    fn serial_stdio(mut self) -> Self {
-        self.args.push("-serial stdio".to_string());
-        self
+    fn disk_image(self, path: impl AsRef<Path>) -> Self {
+        self.block_drive(drive_option("file=", path.as_ref()))
     }
 
-    fn no_graphics(mut self) -> Self {
-        self.args.push("-nographic".to_string());
-        self
+    /// Serial port on QEMU's stdout, which is what `spawn()` captures.
+    fn serial_stdio(self) -> Self {
+        self.arg("-serial", "stdio")
+    }
+
+    /// No window and the serial port and monitor on stdio; do not combine
+    /// with `serial_stdio()`, QEMU refuses two users of stdio.
+    fn no_graphics(self) -> Self {
+        self.flag("-nographic")
+    }
+
+    /// No window, leaving the serial port configuration alone.
+    fn headless(self) -> Self {
+        self.arg("-display", "none")
     }
 
-    fn debug(mut self, port: u16) -> Self {
-        self.args.push(format!("-gdb tcp::{}", port));
-        self.args.push("-S".to_string());
+    fn debug(self, port: u16) -> Self {
+        self.arg("-gdb", format!("tcp::{}", port)).flag("-S")
+    }
+
+    fn no_network(self) -> Self {
+        self.arg("-net", "none")
+    }
+
+    /// Add an isa-debug-exit device at `iobase`, letting the guest choose
+    /// QEMU's exit status; the run result then reports the guest's value.
+    /// x86 only: `virt` has no ISA bus.
+    fn isa_debug_exit(mut self, iobase: u16) -> Self {
+        self.devices.push("-device".into());
+        self.devices
+            .push(format!("isa-debug-exit,iobase={:#x},iosize=0x04", iobase).into());
+        self.debug_exit = true;
         self
     }
 
-    fn no_network(mut self) -> Self {
-        self.args.push("-net none".to_string());
+    /// Send `target`'s output to QEMU's stdout, where `spawn()` captures
+    /// it. OVMF mirrors ConOut to the serial port, so `Console` is read
+    /// there too.
+    fn log_to_stdio(self, target: DebugTarget) -> Self {
+        match target {
+            DebugTarget::Serial | DebugTarget::Console => self.serial_stdio(),
+            DebugTarget::DebugPort => self.arg("-debugcon", "stdio"),
+        }
+    }
+
+    /// Kill QEMU if it is still running after `timeout`.
+    fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
         self
     }
 
-    fn build(self) -> String {
-        let mut parts = vec![self.executable];
-        parts.extend(self.args);
-        parts.extend(self.drives);
-        parts.extend(self.devices);
-        parts.join(" \\\n    ")
+    /// The full argv, executable first.
+    fn build(&self) -> Vec<OsString> {
+        let mut argv = vec![self.executable.clone()];
+        argv.extend(self.args.iter().cloned());
+        argv.extend(self.drives.iter().cloned());
+        argv.extend(self.devices.iter().cloned());
+        argv
+    }
+}
+
+/// The command as it would be typed into a shell, one option per line.
+impl fmt::Display for QemuCommandBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let argv = self.build();
+        write!(f, "{}", shell_quote(&argv[0]))?;
+        for arg in &argv[1..] {
+            if arg.to_string_lossy().starts_with('-') {
+                write!(f, " \\\n    ")?;
+            } else {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", shell_quote(arg))?;
+        }
+        Ok(())
     }
 }
 
 fn qemu_command_builder() {
+    let vars = Path::new("/tmp/OVMF_VARS.fd");
+
     println!("  Basic UEFI testing command:");
     let basic = QemuCommandBuilder::new()
         .machine(MachineType::Q35)
         .memory(256)
-        .ovmf("/usr/share/OVMF/OVMF_CODE.fd", Some("/tmp/OVMF_VARS.fd"))
+        .ovmf("/usr/share/OVMF/OVMF_CODE.fd", Some(vars))
         .esp_dir("./esp")
         .serial_stdio()
-        .no_network()
-        .build();
+        .no_network();
 
     println!("{}", basic);
 
@@ -591,28 +916,107 @@ fn qemu_command_builder() {
         .machine(MachineType::Q35)
         .memory(512)
         .cpus(2)
-        .ovmf("/usr/share/OVMF/OVMF_CODE.fd", Some("/tmp/OVMF_VARS.fd"))
+        .ovmf("/usr/share/OVMF/OVMF_CODE.fd", Some(vars))
         .esp_dir("./esp")
-        .serial_stdio()
         .no_graphics()
         .debug(1234)
-        .no_network()
-        .build();
+        .no_network();
 
     println!("{}", debug);
 
-    println!("\n  Headless CI testing command:");
+    // The ARM board image, emulated on anything but an aarch64 KVM host
+    println!("\n  aarch64 command (virt machine, AAVMF firmware):");
+    let firmware = OvmfPaths::detect(Arch::Aarch64).unwrap_or_else(|_| OvmfPaths {
+        code: "/usr/share/AAVMF/AAVMF_CODE.fd".into(),
+        vars: Some("/tmp/AAVMF_VARS.fd".into()),
+    });
+    let config = QemuConfig {
+        no_graphics: true,
+        serial_stdio: false,
+        ..QemuConfig::for_arch(Arch::Aarch64)
+    };
+    let aarch64 = QemuCommandBuilder::from_config(&config, &Host::detect())
+        .expect("virt is an aarch64 machine")
+        .ovmf(&firmware.code, firmware.vars.as_deref())
+        .esp_dir("./esp")
+        .no_network();
+
+    println!("{}", aarch64);
+
+    println!("\n  Headless CI testing command ($QEMU overrides the binary):");
+    let qemu = std::env::var_os("QEMU").unwrap_or_else(|| "qemu-system-x86_64".into());
     let ci = QemuCommandBuilder::new()
+        .executable(&qemu)
         .machine(MachineType::Q35)
         .memory(256)
         .ovmf("/usr/share/OVMF/OVMF.fd", None)
-        .disk_image("./uefi-disk.img")
+        .disk_image("./build output/uefi-disk.img")
         .serial_stdio()
-        .no_graphics()
+        .headless()
         .no_network()
-        .build();
+        .isa_debug_exit(DEFAULT_DEBUG_EXIT_IOBASE)
+        .timeout(Duration::from_secs(2));
 
     println!("{}", ci);
+
+    let argv = ci.build();
+    println!(
+        "\n  argv for std::process::Command ({} elements, no shell involved):",
+        argv.len()
+    );
+    for arg in &argv {
+        println!("    {:?}", arg);
+    }
+
+    println!("\n  Launching the CI command (2 s timeout):");
+    match ci.spawn() {
+        Ok(process) => {
+            println!("    Started QEMU, pid {}", process.id());
+            let mut log = process.serial_log();
+            match log.expect("BdsDxe: starting", Duration::from_secs(2)) {
+                Ok(line) => println!("    Firmware: {}", line),
+                Err(_) => println!("    The firmware did not start a boot option"),
+            }
+            match process.wait() {
+                Ok(run) => {
+                    let verdict = match run.exit {
+                        QemuExit::Guest(value) => format!("guest exit value {:#x}", value),
+                        QemuExit::Exited(code) => format!("QEMU exited with {}", code),
+                        QemuExit::Signaled => "QEMU killed by a signal".to_string(),
+                        QemuExit::TimedOut => "timed out, QEMU killed".to_string(),
+                    };
+                    println!(
+                        "    {} after {:.1?}, {} bytes of serial output, passed: {}",
+                        verdict,
+                        run.duration,
+                        run.serial.len(),
+                        run.passed(0x10)
+                    );
+                    if let Some(line) = run.stderr_text().lines().next() {
+                        println!("    stderr: {}", line);
+                    }
+                }
+                Err(error) => println!("    Waiting for QEMU failed: {}", error),
+            }
+        }
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            println!("    {} is not installed, skipping", qemu.to_string_lossy())
+        }
+        Err(error) => println!("    Could not start QEMU: {}", error),
+    }
+
+    println!(
+        "\n  isa-debug-exit: the guest writes a value to port {:#x} and QEMU",
+        DEFAULT_DEBUG_EXIT_IOBASE
+    );
+    println!("  exits with (value << 1) | 1:");
+    for status in [33, 3, 1, 0] {
+        println!(
+            "    exit status {:>2} -> {:?}",
+            status,
+            QemuExit::from_code(Some(status), true)
+        );
+    }
 }
 
 #[cfg(test)]
@@ -622,6 +1026,7 @@ mod tests {
     #[test]
     fn test_qemu_config_default() {
         let config = QemuConfig::default();
+        assert_eq!(config.arch, Arch::X86_64);
         assert_eq!(config.memory_mb, 256);
         assert!(config.enable_kvm);
     }
@@ -634,26 +1039,78 @@ mod tests {
             ..Default::default()
         };
 
-        let args = config.to_args();
+        let host = Host {
+            arch: Some(Arch::X86_64),
+            kvm: false,
+        };
+        let args = config.to_args(&host).unwrap();
+        assert!(args.iter().any(|a| a == "tcg"));
         assert!(args.iter().any(|a| a.contains("512M")));
         assert!(args.iter().any(|a| a.contains("1234")));
     }
 
     #[test]
     fn test_ovmf_paths() {
-        let paths = OvmfPaths::detect();
-        assert!(paths.is_some());
+        let split = OvmfPaths {
+            code: "/fw/OVMF_CODE.fd".into(),
+            vars: Some("/run 1/OVMF_VARS.fd".into()),
+        };
+        assert_eq!(
+            split.to_qemu_args(),
+            [
+                "-drive",
+                "if=pflash,format=raw,readonly=on,file=/fw/OVMF_CODE.fd",
+                "-drive",
+                "if=pflash,format=raw,file=/run 1/OVMF_VARS.fd",
+            ]
+        );
+        let combined = OvmfPaths {
+            code: "/fw/OVMF.fd".into(),
+            vars: None,
+        };
+        assert_eq!(combined.to_qemu_args(), ["-bios", "/fw/OVMF.fd"]);
     }
 
     #[test]
     fn test_command_builder() {
-        let cmd = QemuCommandBuilder::new()
+        let argv = QemuCommandBuilder::new()
             .machine(MachineType::Q35)
             .memory(256)
             .build();
 
-        assert!(cmd.contains("qemu-system-x86_64"));
-        assert!(cmd.contains("-machine q35"));
-        assert!(cmd.contains("-m 256M"));
+        assert_eq!(
+            argv,
+            ["qemu-system-x86_64", "-machine", "q35", "-m", "256M"]
+        );
+    }
+
+    #[test]
+    fn test_command_builder_paths() {
+        let builder = QemuCommandBuilder::new()
+            .executable("/opt/qemu 9/bin/qemu-system-x86_64")
+            .esp_dir("/tmp/my esp,v2")
+            .ovmf("/usr/share/OVMF/OVMF.fd", None)
+            .isa_debug_exit(0xf4);
+        let argv = builder.build();
+
+        assert_eq!(argv[0], "/opt/qemu 9/bin/qemu-system-x86_64");
+        assert_eq!(
+            argv[1..],
+            [
+                "-bios",
+                "/usr/share/OVMF/OVMF.fd",
+                "-drive",
+                "format=raw,file=fat:rw:/tmp/my esp,,v2",
+                "-device",
+                "isa-debug-exit,iobase=0xf4,iosize=0x04",
+            ]
+        );
+        assert_eq!(
+            builder.to_string(),
+            "'/opt/qemu 9/bin/qemu-system-x86_64' \\\n    \
+             -bios /usr/share/OVMF/OVMF.fd \\\n    \
+             -drive 'format=raw,file=fat:rw:/tmp/my esp,,v2' \\\n    \
+             -device isa-debug-exit,iobase=0xf4,iosize=0x04"
+        );
     }
 }
@@ -0,0 +1,536 @@
+//! Locating UEFI firmware for QEMU
+//!
+//! Every distribution installs its edk2 builds under different names:
+//!
+//! ```text
+//!   x86_64   Debian/Ubuntu  /usr/share/OVMF/OVMF_CODE_4M.fd
+//!            Fedora         /usr/share/edk2/ovmf/OVMF_CODE.fd
+//!            Arch           /usr/share/edk2/x64/OVMF_CODE.4m.fd
+//!   aarch64  Debian/Ubuntu  /usr/share/AAVMF/AAVMF_CODE.fd
+//!            Fedora         /usr/share/edk2/aarch64/QEMU_EFI-pflash.raw
+//!   riscv64  Debian/Ubuntu  /usr/share/qemu-efi-riscv64/RISCV_VIRT_CODE.fd
+//! ```
+//!
+//! `detect` walks a per-architecture list of CODE/VARS pairs and takes the
+//! first whose files exist and have a plausible size. An environment
+//! variable per image (`OVMF_CODE`/`OVMF_VARS`, `AAVMF_CODE`/`AAVMF_VARS`,
+//! `RISCV_VIRT_CODE`/`RISCV_VIRT_VARS`) overrides the search.
+//!
+//! The VARS file found this way is a read-only template. QEMU writes NVRAM
+//! variables into its VARS drive, so each run needs a private copy
+//! ([`OvmfPaths::with_private_vars`]).
+
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::{Arch, OvmfPaths};
+
+const KIB: u64 = 1024;
+const MIB: u64 = 1024 * KIB;
+
+/// Where a distribution puts one firmware build; `vars` is `None` for a
+/// combined image loaded with `-bios`.
+struct Candidate {
+    code: &'static str,
+    vars: Option<&'static str>,
+}
+
+const fn split(code: &'static str, vars: &'static str) -> Candidate {
+    Candidate {
+        code,
+        vars: Some(vars),
+    }
+}
+
+const fn combined(code: &'static str) -> Candidate {
+    Candidate { code, vars: None }
+}
+
+const X86_64_CANDIDATES: &[Candidate] = &[
+    // Debian 12+/Ubuntu 22.04+
+    split(
+        "/usr/share/OVMF/OVMF_CODE_4M.fd",
+        "/usr/share/OVMF/OVMF_VARS_4M.fd",
+    ),
+    // Older Debian/Ubuntu
+    split(
+        "/usr/share/OVMF/OVMF_CODE.fd",
+        "/usr/share/OVMF/OVMF_VARS.fd",
+    ),
+    // Fedora
+    split(
+        "/usr/share/edk2/ovmf/OVMF_CODE.fd",
+        "/usr/share/edk2/ovmf/OVMF_VARS.fd",
+    ),
+    // Arch Linux
+    split(
+        "/usr/share/edk2/x64/OVMF_CODE.4m.fd",
+        "/usr/share/edk2/x64/OVMF_VARS.4m.fd",
+    ),
+    split(
+        "/usr/share/edk2-ovmf/x64/OVMF_CODE.fd",
+        "/usr/share/edk2-ovmf/x64/OVMF_VARS.fd",
+    ),
+    // openSUSE
+    split(
+        "/usr/share/qemu/ovmf-x86_64-code.bin",
+        "/usr/share/qemu/ovmf-x86_64-vars.bin",
+    ),
+    // Single file fallback
+    combined("/usr/share/qemu/OVMF.fd"),
+    combined("/usr/share/ovmf/OVMF.fd"),
+];
+
+const AARCH64_CANDIDATES: &[Candidate] = &[
+    // Debian/Ubuntu
+    split(
+        "/usr/share/AAVMF/AAVMF_CODE.fd",
+        "/usr/share/AAVMF/AAVMF_VARS.fd",
+    ),
+    // Fedora
+    split(
+        "/usr/share/edk2/aarch64/QEMU_EFI-pflash.raw",
+        "/usr/share/edk2/aarch64/vars-template-pflash.raw",
+    ),
+    // Arch Linux
+    split(
+        "/usr/share/edk2/aarch64/QEMU_CODE.fd",
+        "/usr/share/edk2/aarch64/QEMU_VARS.fd",
+    ),
+    // Single file fallback
+    combined("/usr/share/qemu-efi-aarch64/QEMU_EFI.fd"),
+];
+
+const RISCV64_CANDIDATES: &[Candidate] = &[
+    // Debian/Ubuntu
+    split(
+        "/usr/share/qemu-efi-riscv64/RISCV_VIRT_CODE.fd",
+        "/usr/share/qemu-efi-riscv64/RISCV_VIRT_VARS.fd",
+    ),
+    // Fedora/Arch Linux
+    split(
+        "/usr/share/edk2/riscv/RISCV_VIRT_CODE.fd",
+        "/usr/share/edk2/riscv/RISCV_VIRT_VARS.fd",
+    ),
+];
+
+/// What a firmware file is loaded as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    /// Read-only pflash half of a split image
+    Code,
+    /// Writable pflash half of a split image
+    Vars,
+    /// A combined image passed to `-bios`
+    Bios,
+}
+
+/// The file size a firmware image must have
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeRule {
+    /// A whole number of 4 KiB flash sectors, at least this many bytes
+    AtLeast(u64),
+    /// Exactly this many bytes: the `virt` machines refuse pflash images
+    /// that do not fill the flash bank
+    Exactly(u64),
+}
+
+impl SizeRule {
+    fn accepts(self, size: u64) -> bool {
+        match self {
+            SizeRule::AtLeast(min) => size >= min && size.is_multiple_of(4 * KIB),
+            SizeRule::Exactly(expected) => size == expected,
+        }
+    }
+}
+
+impl fmt::Display for SizeRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SizeRule::AtLeast(min) => {
+                write!(f, "a multiple of 4 KiB of at least {} KiB", min / KIB)
+            }
+            SizeRule::Exactly(size) => write!(f, "exactly {} MiB", size / MIB),
+        }
+    }
+}
+
+/// Why a firmware file was not used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    /// Nothing at that path
+    Missing,
+    /// The path is a directory or otherwise not a regular file
+    NotAFile,
+    /// The file is too small, truncated or padded for the wrong machine
+    WrongSize { size: u64, rule: SizeRule },
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::Missing => write!(f, "missing"),
+            Rejection::NotAFile => write!(f, "not a regular file"),
+            Rejection::WrongSize { size, rule } => {
+                write!(f, "{} bytes, expected {}", size, rule)
+            }
+        }
+    }
+}
+
+/// Firmware detection errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OvmfError {
+    /// No candidate was usable; every file looked at and why it was not
+    NotFound {
+        arch: Arch,
+        rejected: Vec<(PathBuf, Rejection)>,
+    },
+    /// The file an environment variable names is unusable
+    BadOverride {
+        variable: &'static str,
+        path: PathBuf,
+        reason: Rejection,
+    },
+    /// VARS was overridden, but the only firmware found is a combined
+    /// image that has no VARS drive to put it in
+    VarsWithoutSplitImage {
+        variable: &'static str,
+        bios: PathBuf,
+    },
+}
+
+impl fmt::Display for OvmfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OvmfError::NotFound { arch, rejected } => {
+                write!(f, "no {} UEFI firmware found", arch)?;
+                for (path, reason) in rejected {
+                    write!(f, "\n  {}: {}", path.display(), reason)?;
+                }
+                Ok(())
+            }
+            OvmfError::BadOverride {
+                variable,
+                path,
+                reason,
+            } => write!(f, "${} ({}): {}", variable, path.display(), reason),
+            OvmfError::VarsWithoutSplitImage { variable, bios } => write!(
+                f,
+                "${} is set, but {} is a combined image without a VARS drive",
+                variable,
+                bios.display()
+            ),
+        }
+    }
+}
+
+/// The environment variables that override CODE and VARS for `arch`.
+pub fn override_variables(arch: Arch) -> (&'static str, &'static str) {
+    match arch {
+        Arch::X86_64 => ("OVMF_CODE", "OVMF_VARS"),
+        Arch::Aarch64 => ("AAVMF_CODE", "AAVMF_VARS"),
+        Arch::Riscv64 => ("RISCV_VIRT_CODE", "RISCV_VIRT_VARS"),
+    }
+}
+
+fn size_rule(arch: Arch, role: Role) -> SizeRule {
+    match (arch, role) {
+        (_, Role::Bios) => SizeRule::AtLeast(MIB),
+        (Arch::X86_64, Role::Code) => SizeRule::AtLeast(MIB),
+        (Arch::X86_64, Role::Vars) => SizeRule::AtLeast(64 * KIB),
+        (Arch::Aarch64, _) => SizeRule::Exactly(64 * MIB),
+        (Arch::Riscv64, _) => SizeRule::Exactly(32 * MIB),
+    }
+}
+
+fn check(path: &Path, arch: Arch, role: Role) -> Result<(), Rejection> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return Err(Rejection::Missing),
+    };
+    if !metadata.is_file() {
+        return Err(Rejection::NotAFile);
+    }
+    let rule = size_rule(arch, role);
+    if !rule.accepts(metadata.len()) {
+        return Err(Rejection::WrongSize {
+            size: metadata.len(),
+            rule,
+        });
+    }
+    Ok(())
+}
+
+impl OvmfPaths {
+    /// Find firmware for `arch` on this machine.
+    pub fn detect(arch: Arch) -> Result<Self, OvmfError> {
+        Self::detect_in(arch, Path::new("/"), |name| std::env::var_os(name))
+    }
+
+    /// `detect` with the candidate paths taken relative to `root` and the
+    /// environment read through `env`.
+    fn detect_in(
+        arch: Arch,
+        root: &Path,
+        env: impl Fn(&str) -> Option<OsString>,
+    ) -> Result<Self, OvmfError> {
+        let (code_variable, vars_variable) = override_variables(arch);
+        let vars_override = env(vars_variable).map(PathBuf::from);
+        if let Some(vars) = &vars_override {
+            check(vars, arch, Role::Vars).map_err(|reason| OvmfError::BadOverride {
+                variable: vars_variable,
+                path: vars.clone(),
+                reason,
+            })?;
+        }
+
+        // An overridden CODE image is used as is; with no VARS override
+        // it is a combined image
+        if let Some(code) = env(code_variable).map(PathBuf::from) {
+            let role = if vars_override.is_some() {
+                Role::Code
+            } else {
+                Role::Bios
+            };
+            check(&code, arch, role).map_err(|reason| OvmfError::BadOverride {
+                variable: code_variable,
+                path: code.clone(),
+                reason,
+            })?;
+            return Ok(OvmfPaths {
+                code,
+                vars: vars_override,
+            });
+        }
+
+        let candidates = match arch {
+            Arch::X86_64 => X86_64_CANDIDATES,
+            Arch::Aarch64 => AARCH64_CANDIDATES,
+            Arch::Riscv64 => RISCV64_CANDIDATES,
+        };
+        let under_root = |path: &str| root.join(path.trim_start_matches('/'));
+        let mut rejected = Vec::new();
+        for candidate in candidates {
+            let code = under_root(candidate.code);
+            // A VARS override replaces the template of split images only
+            let vars = candidate
+                .vars
+                .map(|vars| vars_override.clone().unwrap_or_else(|| under_root(vars)));
+            let role = if vars.is_some() {
+                Role::Code
+            } else {
+                Role::Bios
+            };
+            if let Err(reason) = check(&code, arch, role) {
+                rejected.push((code, reason));
+                continue;
+            }
+            match &vars {
+                Some(vars) => {
+                    if let Err(reason) = check(vars, arch, Role::Vars) {
+                        rejected.push((vars.clone(), reason));
+                        continue;
+                    }
+                }
+                // Running without the requested VARS would silently drop it
+                None if vars_override.is_some() => {
+                    return Err(OvmfError::VarsWithoutSplitImage {
+                        variable: vars_variable,
+                        bios: code,
+                    });
+                }
+                None => {}
+            }
+            return Ok(OvmfPaths { code, vars });
+        }
+        Err(OvmfError::NotFound { arch, rejected })
+    }
+
+    /// Copy the VARS template into `dir` and point at the copy, so that
+    /// every run starts from the same NVRAM and concurrent runs do not
+    /// write to one file. Combined images are returned unchanged.
+    pub fn with_private_vars(&self, dir: &Path) -> io::Result<OvmfPaths> {
+        let Some(template) = &self.vars else {
+            return Ok(self.clone());
+        };
+        let copy = dir.join(template.file_name().unwrap_or("OVMF_VARS.fd".as_ref()));
+        fs::copy(template, &copy)?;
+        // Templates are often installed read-only and fs::copy keeps that
+        let mut permissions = fs::metadata(&copy)?.permissions();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            permissions.set_mode(permissions.mode() | 0o200);
+        }
+        #[cfg(not(unix))]
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(&copy, permissions)?;
+        Ok(OvmfPaths {
+            code: self.code.clone(),
+            vars: Some(copy),
+        })
+    }
+}
+
+/// Create a new, empty directory under the system temp directory for one
+/// QEMU run's writable files. The caller removes it.
+pub fn create_run_dir() -> io::Result<PathBuf> {
+    static NEXT: AtomicU32 = AtomicU32::new(0);
+    loop {
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("uefi-qemu-run-{}-{}", process::id(), n));
+        match fs::create_dir_all(dir.parent().unwrap()).and_then(|()| fs::create_dir(&dir)) {
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            result => return result.map(|()| dir),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create `path` under `root` as a sparse file of `size` bytes.
+    fn install(root: &Path, path: &str, size: u64) -> PathBuf {
+        let path = root.join(path.trim_start_matches('/'));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::File::create(&path).unwrap().set_len(size).unwrap();
+        path
+    }
+
+    fn no_env(_: &str) -> Option<OsString> {
+        None
+    }
+
+    #[test]
+    fn test_detect_skips_missing_and_truncated_images() {
+        let root = create_run_dir().unwrap();
+        // Old Debian layout with a truncated CODE image, valid Fedora pair
+        install(&root, "/usr/share/OVMF/OVMF_CODE.fd", 0);
+        install(&root, "/usr/share/OVMF/OVMF_VARS.fd", 128 * KIB);
+        let code = install(&root, "/usr/share/edk2/ovmf/OVMF_CODE.fd", 1920 * KIB);
+        let vars = install(&root, "/usr/share/edk2/ovmf/OVMF_VARS.fd", 128 * KIB);
+
+        let paths = OvmfPaths::detect_in(Arch::X86_64, &root, no_env).unwrap();
+        assert_eq!(paths.code, code);
+        assert_eq!(paths.vars, Some(vars));
+
+        // AAVMF padded for the wrong bank size, nothing else installed
+        install(&root, "/usr/share/AAVMF/AAVMF_CODE.fd", 2 * MIB);
+        match OvmfPaths::detect_in(Arch::Aarch64, &root, no_env) {
+            Err(OvmfError::NotFound { arch, rejected }) => {
+                assert_eq!(arch, Arch::Aarch64);
+                assert_eq!(rejected.len(), AARCH64_CANDIDATES.len());
+                assert_eq!(
+                    rejected[0].1,
+                    Rejection::WrongSize {
+                        size: 2 * MIB,
+                        rule: SizeRule::Exactly(64 * MIB)
+                    }
+                );
+                assert_eq!(rejected[1].1, Rejection::Missing);
+            }
+            other => panic!("{:?}", other),
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_environment_overrides() {
+        let root = create_run_dir().unwrap();
+        let code = install(&root, "custom/QEMU_CODE.fd", 64 * MIB);
+        let vars = install(&root, "custom/QEMU_VARS.fd", 64 * MIB);
+        let env = |name: &str| match name {
+            "AAVMF_CODE" => Some(code.clone().into()),
+            "AAVMF_VARS" => Some(vars.clone().into()),
+            _ => None,
+        };
+        let paths = OvmfPaths::detect_in(Arch::Aarch64, &root, env).unwrap();
+        assert_eq!(paths.code, code);
+        assert_eq!(paths.vars, Some(vars.clone()));
+
+        // A bad override is an error, not a reason to look elsewhere
+        let missing = root.join("missing.fd");
+        let env = |name: &str| (name == "AAVMF_CODE").then(|| missing.clone().into());
+        assert_eq!(
+            OvmfPaths::detect_in(Arch::Aarch64, &root, env),
+            Err(OvmfError::BadOverride {
+                variable: "AAVMF_CODE",
+                path: missing.clone(),
+                reason: Rejection::Missing
+            })
+        );
+
+        // A VARS override alone swaps the template of the detected pair
+        let code = install(&root, "/usr/share/edk2/riscv/RISCV_VIRT_CODE.fd", 32 * MIB);
+        install(&root, "/usr/share/edk2/riscv/RISCV_VIRT_VARS.fd", 32 * MIB);
+        let enrolled = install(&root, "custom/RISCV_VARS.enrolled.fd", 32 * MIB);
+        let env = |name: &str| (name == "RISCV_VIRT_VARS").then(|| enrolled.clone().into());
+        let paths = OvmfPaths::detect_in(Arch::Riscv64, &root, env).unwrap();
+        assert_eq!(paths.code, code);
+        assert_eq!(paths.vars, Some(enrolled));
+
+        // ...but cannot be honoured when only a combined image is installed
+        let bios = install(&root, "/usr/share/qemu/OVMF.fd", 2 * MIB);
+        let custom = install(&root, "custom/OVMF_VARS.fd", 128 * KIB);
+        let env = |name: &str| (name == "OVMF_VARS").then(|| custom.clone().into());
+        assert_eq!(
+            OvmfPaths::detect_in(Arch::X86_64, &root, env),
+            Err(OvmfError::VarsWithoutSplitImage {
+                variable: "OVMF_VARS",
+                bios: bios.clone()
+            })
+        );
+        assert_eq!(
+            OvmfPaths::detect_in(Arch::X86_64, &root, no_env),
+            Ok(OvmfPaths {
+                code: bios,
+                vars: None
+            })
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_private_vars_copies() {
+        let root = create_run_dir().unwrap();
+        let template = root.join("OVMF_VARS.fd");
+        fs::write(&template, [0xAB; 4096]).unwrap();
+        let mut permissions = fs::metadata(&template).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&template, permissions).unwrap();
+        let paths = OvmfPaths {
+            code: root.join("OVMF_CODE.fd"),
+            vars: Some(template.clone()),
+        };
+
+        let (first, second) = (create_run_dir().unwrap(), create_run_dir().unwrap());
+        assert_ne!(first, second);
+        let run = paths.with_private_vars(&first).unwrap();
+        let other = paths.with_private_vars(&second).unwrap();
+        let copy = run.vars.unwrap();
+        assert_eq!(copy, first.join("OVMF_VARS.fd"));
+        assert_eq!(run.code, paths.code);
+
+        // The copy is writable and independent of the template and other runs
+        fs::write(&copy, [0u8; 4096]).unwrap();
+        assert_eq!(fs::read(&template).unwrap(), [0xAB; 4096]);
+        assert_eq!(fs::read(other.vars.unwrap()).unwrap(), [0xAB; 4096]);
+
+        let combined = OvmfPaths {
+            code: template,
+            vars: None,
+        };
+        assert_eq!(combined.with_private_vars(&first).unwrap(), combined);
+        for dir in [root, first, second] {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
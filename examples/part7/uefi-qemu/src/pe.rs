@@ -0,0 +1,280 @@
+//! PE/COFF header checks for EFI binaries
+//!
+//! UEFI loads PE32+ images. Firmware rejects, usually with nothing more
+//! than "Unsupported" or a silent fall-through to the next boot option, an
+//! image that is truncated, built for another machine, or has a non-EFI
+//! subsystem. Checking the headers before staging turns those into errors
+//! that name the file.
+//!
+//! ```text
+//!   0x00  DOS header    "MZ" ... e_lfanew (u32 at 0x3C)
+//!   e_lfanew            "PE\0\0"
+//!     +4  COFF header   Machine, NumberOfSections, SizeOfOptionalHeader,
+//!                       Characteristics
+//!    +24  Optional hdr  Magic (0x20B = PE32+) ... Subsystem (at +68)
+//!         Sections      40 bytes each: raw data offset and size
+//! ```
+
+use std::fmt;
+
+use crate::Arch;
+
+pub const EFI_APPLICATION: u16 = 10;
+pub const EFI_BOOT_SERVICE_DRIVER: u16 = 11;
+pub const EFI_RUNTIME_DRIVER: u16 = 12;
+
+/// Subsystems a boot loader or application may have.
+pub const APPLICATION_SUBSYSTEMS: &[u16] = &[EFI_APPLICATION];
+/// Subsystems a driver may have.
+pub const DRIVER_SUBSYSTEMS: &[u16] = &[EFI_BOOT_SERVICE_DRIVER, EFI_RUNTIME_DRIVER];
+
+const PE32_PLUS_MAGIC: u16 = 0x20B;
+const IMAGE_FILE_EXECUTABLE_IMAGE: u16 = 0x0002;
+const COFF_HEADER_SIZE: usize = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+/// Optional header bytes up to and including Subsystem.
+const MIN_OPTIONAL_HEADER_SIZE: usize = 70;
+
+impl Arch {
+    /// The COFF Machine value of images for this architecture.
+    pub fn pe_machine(self) -> u16 {
+        match self {
+            Arch::X86_64 => 0x8664,
+            Arch::Aarch64 => 0xAA64,
+            Arch::Riscv64 => 0x5064,
+        }
+    }
+
+    pub fn from_pe_machine(machine: u16) -> Option<Arch> {
+        [Arch::X86_64, Arch::Aarch64, Arch::Riscv64]
+            .into_iter()
+            .find(|arch| arch.pe_machine() == machine)
+    }
+}
+
+/// Why an EFI binary would not load
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeError {
+    /// No DOS header or PE signature: an ELF file, a script, ...
+    NotPe,
+    /// Headers or section data run past the end of the file
+    Truncated,
+    /// Not a PE32+ image; this is the optional header magic
+    NotPe32Plus(u16),
+    /// The image is an object file, not a linked executable
+    NotExecutable,
+    /// Built for a machine this firmware does not run
+    WrongMachine { found: u16, expected: Arch },
+    /// A machine type none of the supported architectures use
+    UnknownMachine(u16),
+    /// A subsystem the entry does not allow (an EFI driver placed as a
+    /// boot loader, or not an EFI image at all)
+    WrongSubsystem(u16),
+}
+
+impl fmt::Display for PeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeError::NotPe => write!(f, "not a PE/COFF image"),
+            PeError::Truncated => write!(f, "image is truncated"),
+            PeError::NotPe32Plus(magic) => {
+                write!(f, "optional header magic {:#x}, expected PE32+", magic)
+            }
+            PeError::NotExecutable => write!(f, "not an executable image"),
+            PeError::WrongMachine { found, expected } => write!(
+                f,
+                "machine {:#06x}, expected {:#06x} ({})",
+                found,
+                expected.pe_machine(),
+                expected
+            ),
+            PeError::UnknownMachine(machine) => write!(f, "unsupported machine {:#06x}", machine),
+            PeError::WrongSubsystem(subsystem) => write!(f, "subsystem {}", subsystem),
+        }
+    }
+}
+
+fn u16_at(image: &[u8], offset: usize) -> Result<u16, PeError> {
+    image
+        .get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or(PeError::Truncated)
+}
+
+fn u32_at(image: &[u8], offset: usize) -> Result<u32, PeError> {
+    image
+        .get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or(PeError::Truncated)
+}
+
+/// Check that `image` is a PE32+ executable for `arch` (any supported
+/// architecture if `None`) with one of `subsystems`; returns its
+/// architecture.
+pub fn validate(image: &[u8], arch: Option<Arch>, subsystems: &[u16]) -> Result<Arch, PeError> {
+    if !image.starts_with(b"MZ") {
+        return Err(PeError::NotPe);
+    }
+    let pe = u32_at(image, 0x3C)? as usize;
+    if image.get(pe..pe + 4) != Some(b"PE\0\0") {
+        return Err(PeError::NotPe);
+    }
+
+    let coff = pe + 4;
+    let machine = u16_at(image, coff)?;
+    let sections = u16_at(image, coff + 2)? as usize;
+    let optional_size = u16_at(image, coff + 16)? as usize;
+    let characteristics = u16_at(image, coff + 18)?;
+    if characteristics & IMAGE_FILE_EXECUTABLE_IMAGE == 0 {
+        return Err(PeError::NotExecutable);
+    }
+    let found = match (Arch::from_pe_machine(machine), arch) {
+        (Some(found), Some(expected)) if found != expected => {
+            return Err(PeError::WrongMachine {
+                found: machine,
+                expected,
+            })
+        }
+        (None, Some(expected)) => {
+            return Err(PeError::WrongMachine {
+                found: machine,
+                expected,
+            })
+        }
+        (None, None) => return Err(PeError::UnknownMachine(machine)),
+        (Some(found), _) => found,
+    };
+
+    let optional = coff + COFF_HEADER_SIZE;
+    if optional_size < MIN_OPTIONAL_HEADER_SIZE {
+        return Err(PeError::Truncated);
+    }
+    let magic = u16_at(image, optional)?;
+    if magic != PE32_PLUS_MAGIC {
+        return Err(PeError::NotPe32Plus(magic));
+    }
+    let subsystem = u16_at(image, optional + 68)?;
+    if !subsystems.contains(&subsystem) {
+        return Err(PeError::WrongSubsystem(subsystem));
+    }
+
+    // Every section's file data must be present
+    let table = optional + optional_size;
+    for section in 0..sections {
+        let header = table + section * SECTION_HEADER_SIZE;
+        let raw_size = u32_at(image, header + 16)? as u64;
+        let raw_offset = u32_at(image, header + 20)? as u64;
+        if raw_offset + raw_size > image.len() as u64 {
+            return Err(PeError::Truncated);
+        }
+    }
+    if table + sections * SECTION_HEADER_SIZE > image.len() {
+        return Err(PeError::Truncated);
+    }
+    Ok(found)
+}
+
+/// A header-only PE32+ image for `arch` with `subsystem`: one empty
+/// `.text` section and no code. It passes [`validate`] but does nothing
+/// useful when run; demos and tests stage it in place of a real build.
+pub fn minimal_image(arch: Arch, subsystem: u16) -> Vec<u8> {
+    const PE: usize = 0x40;
+    const OPTIONAL_SIZE: usize = 240;
+    const ALIGNMENT: usize = 0x200;
+
+    let mut image = vec![0u8; 2 * ALIGNMENT];
+    let mut put = |offset: usize, bytes: &[u8]| {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    };
+    put(0, b"MZ");
+    put(0x3C, &(PE as u32).to_le_bytes());
+    put(PE, b"PE\0\0");
+
+    let coff = PE + 4;
+    put(coff, &arch.pe_machine().to_le_bytes());
+    put(coff + 2, &1u16.to_le_bytes());
+    put(coff + 16, &(OPTIONAL_SIZE as u16).to_le_bytes());
+    // Executable, large address aware
+    put(coff + 18, &0x0022u16.to_le_bytes());
+
+    let optional = coff + COFF_HEADER_SIZE;
+    put(optional, &PE32_PLUS_MAGIC.to_le_bytes());
+    put(optional + 16, &0x1000u32.to_le_bytes()); // AddressOfEntryPoint
+    put(optional + 32, &0x1000u32.to_le_bytes()); // SectionAlignment
+    put(optional + 36, &(ALIGNMENT as u32).to_le_bytes()); // FileAlignment
+    put(optional + 56, &0x2000u32.to_le_bytes()); // SizeOfImage
+    put(optional + 60, &(ALIGNMENT as u32).to_le_bytes()); // SizeOfHeaders
+    put(optional + 68, &subsystem.to_le_bytes());
+    put(optional + 108, &16u32.to_le_bytes()); // NumberOfRvaAndSizes
+
+    let section = optional + OPTIONAL_SIZE;
+    put(section, b".text\0\0\0");
+    put(section + 8, &0x10u32.to_le_bytes()); // VirtualSize
+    put(section + 12, &0x1000u32.to_le_bytes()); // VirtualAddress
+    put(section + 16, &(ALIGNMENT as u32).to_le_bytes()); // SizeOfRawData
+    put(section + 20, &(ALIGNMENT as u32).to_le_bytes()); // PointerToRawData
+    put(section + 36, &0x6000_0020u32.to_le_bytes()); // code, execute, read
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_accepts_efi_images() {
+        for arch in [Arch::X86_64, Arch::Aarch64, Arch::Riscv64] {
+            let app = minimal_image(arch, EFI_APPLICATION);
+            assert_eq!(validate(&app, Some(arch), APPLICATION_SUBSYSTEMS), Ok(arch));
+            assert_eq!(validate(&app, None, APPLICATION_SUBSYSTEMS), Ok(arch));
+        }
+        let driver = minimal_image(Arch::X86_64, EFI_RUNTIME_DRIVER);
+        assert_eq!(validate(&driver, None, DRIVER_SUBSYSTEMS), Ok(Arch::X86_64));
+    }
+
+    #[test]
+    fn test_validate_rejects_broken_images() {
+        let app = minimal_image(Arch::X86_64, EFI_APPLICATION);
+        let check = |image: &[u8]| validate(image, Some(Arch::X86_64), APPLICATION_SUBSYSTEMS);
+
+        assert_eq!(check(b"\x7fELF\x02\x01\x01"), Err(PeError::NotPe));
+        assert_eq!(check(&app[..0x3E]), Err(PeError::Truncated));
+        assert_eq!(check(&app[..0x300]), Err(PeError::Truncated));
+
+        let mut bad_signature = app.clone();
+        bad_signature[0x42] = b'X';
+        assert_eq!(check(&bad_signature), Err(PeError::NotPe));
+
+        assert_eq!(
+            check(&minimal_image(Arch::Aarch64, EFI_APPLICATION)),
+            Err(PeError::WrongMachine {
+                found: 0xAA64,
+                expected: Arch::X86_64
+            })
+        );
+        let mut i386 = app.clone();
+        i386[0x44..0x46].copy_from_slice(&0x014Cu16.to_le_bytes());
+        assert_eq!(
+            validate(&i386, None, APPLICATION_SUBSYSTEMS),
+            Err(PeError::UnknownMachine(0x014C))
+        );
+
+        // A boot service driver is not an application; a Windows console
+        // program (subsystem 3) is neither
+        assert_eq!(
+            check(&minimal_image(Arch::X86_64, EFI_BOOT_SERVICE_DRIVER)),
+            Err(PeError::WrongSubsystem(EFI_BOOT_SERVICE_DRIVER))
+        );
+        assert_eq!(
+            check(&minimal_image(Arch::X86_64, 3)),
+            Err(PeError::WrongSubsystem(3))
+        );
+
+        let mut object = app.clone();
+        object[0x44 + 18] = 0;
+        assert_eq!(check(&object), Err(PeError::NotExecutable));
+        let mut pe32 = app;
+        pe32[0x58..0x5A].copy_from_slice(&0x10Bu16.to_le_bytes());
+        assert_eq!(check(&pe32), Err(PeError::NotPe32Plus(0x10B)));
+    }
+}
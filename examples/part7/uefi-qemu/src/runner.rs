@@ -0,0 +1,384 @@
+//! Launching QEMU from a [`QemuCommandBuilder`](crate::QemuCommandBuilder)
+//!
+//! The guest's serial port is QEMU's stdout (`-serial stdio`). A thread
+//! drains it into a shared [`Stream`] so a chatty guest never blocks on a
+//! full pipe, and a [`SerialLog`](crate::harness::SerialLog) can watch the
+//! output while the guest runs. stderr, where QEMU reports its own errors,
+//! is drained the same way.
+//!
+//! With `-device isa-debug-exit` the guest ends the run by writing a value
+//! to the device's I/O port, and QEMU exits with `(value << 1) | 1`:
+//!
+//! ```text
+//!   guest: out 0xf4, 0x10  ──►  QEMU exit status 33  ──►  QemuExit::Guest(0x10)
+//! ```
+//!
+//! Exit statuses are 8 bits on most hosts, so only the low 7 bits of the
+//! value survive. Status 1 is also what QEMU returns for its own errors, so
+//! guests should signal success with a non-zero value (0x10 by convention).
+
+use std::io::{self, Read};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::harness::SerialLog;
+use crate::QemuCommandBuilder;
+
+/// The I/O port most UEFI test setups use for isa-debug-exit.
+pub const DEFAULT_DEBUG_EXIT_IOBASE: u16 = 0xf4;
+
+/// How often `wait()` checks on QEMU while a timeout is running.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long `wait()` keeps draining stdout/stderr once QEMU has exited.
+/// A helper process QEMU (or a wrapper script) forked may still hold the
+/// pipes open; after this, whatever was captured is returned.
+const DRAIN_GRACE: Duration = Duration::from_millis(500);
+
+/// How a QEMU run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QemuExit {
+    /// The guest wrote this value to isa-debug-exit
+    Guest(u32),
+    /// QEMU exited by itself: 0 after a guest shutdown, 1 on QEMU errors
+    Exited(i32),
+    /// QEMU was killed by a signal
+    Signaled,
+    /// QEMU ran past the timeout and was killed
+    TimedOut,
+}
+
+impl QemuExit {
+    /// Map QEMU's exit code (`None` when a signal ended it). Only with
+    /// isa-debug-exit are odd codes above 1 guest values.
+    pub fn from_code(code: Option<i32>, debug_exit: bool) -> Self {
+        match code {
+            None => QemuExit::Signaled,
+            Some(code) if debug_exit && code > 1 && code % 2 == 1 => {
+                QemuExit::Guest((code >> 1) as u32)
+            }
+            Some(code) => QemuExit::Exited(code),
+        }
+    }
+}
+
+/// Everything a finished QEMU run produced.
+#[derive(Debug, Clone)]
+pub struct QemuRun {
+    pub exit: QemuExit,
+    pub serial: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub duration: Duration,
+}
+
+impl QemuRun {
+    /// Whether the guest reported `success` through isa-debug-exit.
+    pub fn passed(&self, success: u32) -> bool {
+        self.exit == QemuExit::Guest(success)
+    }
+
+    pub fn stderr_text(&self) -> String {
+        String::from_utf8_lossy(&self.stderr).into_owned()
+    }
+}
+
+/// Bytes read from a pipe by a background thread, appended as they arrive.
+#[derive(Default)]
+pub(crate) struct Stream {
+    state: Mutex<StreamState>,
+    arrived: Condvar,
+}
+
+#[derive(Default)]
+struct StreamState {
+    bytes: Vec<u8>,
+    closed: bool,
+}
+
+impl Stream {
+    /// Start draining `pipe` into a new stream.
+    pub(crate) fn capture(mut pipe: impl Read + Send + 'static) -> (Arc<Stream>, JoinHandle<()>) {
+        let stream = Arc::new(Stream::default());
+        let sink = Arc::clone(&stream);
+        let thread = thread::spawn(move || {
+            let mut chunk = [0u8; 4096];
+            // A read error means the pipe is gone; keep what arrived
+            while let Ok(n @ 1..) = pipe.read(&mut chunk) {
+                sink.state
+                    .lock()
+                    .unwrap()
+                    .bytes
+                    .extend_from_slice(&chunk[..n]);
+                sink.arrived.notify_all();
+            }
+            sink.state.lock().unwrap().closed = true;
+            sink.arrived.notify_all();
+        });
+        (stream, thread)
+    }
+
+    /// Everything from `offset` on, and whether the pipe has closed.
+    pub(crate) fn read_from(&self, offset: usize) -> (Vec<u8>, bool) {
+        let state = self.state.lock().unwrap();
+        (
+            state.bytes[offset.min(state.bytes.len())..].to_vec(),
+            state.closed,
+        )
+    }
+
+    /// Block until the stream is longer than `len` bytes, closes, or
+    /// `deadline` passes.
+    pub(crate) fn wait_beyond(&self, len: usize, deadline: Instant) {
+        let mut state = self.state.lock().unwrap();
+        while state.bytes.len() <= len && !state.closed {
+            let now = Instant::now();
+            if now >= deadline {
+                return;
+            }
+            state = self.arrived.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+
+    /// Block until the pipe closes or `deadline` passes; true if it closed.
+    fn wait_closed(&self, deadline: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        while !state.closed {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            state = self.arrived.wait_timeout(state, deadline - now).unwrap().0;
+        }
+        true
+    }
+}
+
+/// A pipe being drained into a [`Stream`].
+struct Captured {
+    stream: Arc<Stream>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Captured {
+    fn spawn(pipe: impl Read + Send + 'static) -> Self {
+        let (stream, thread) = Stream::capture(pipe);
+        Captured {
+            stream,
+            thread: Some(thread),
+        }
+    }
+
+    /// Wait (until `deadline` at most) for every writer to close the pipe,
+    /// and copy everything written so far. A reader still blocked at the
+    /// deadline is left to finish on its own.
+    fn finish(&mut self, deadline: Instant) -> Vec<u8> {
+        if self.stream.wait_closed(deadline) {
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+        self.stream.read_from(0).0
+    }
+}
+
+/// A running QEMU. Dropping it kills QEMU.
+pub struct QemuProcess {
+    child: Child,
+    serial: Captured,
+    stderr: Captured,
+    debug_exit: bool,
+    timeout: Option<Duration>,
+    started: Instant,
+}
+
+impl QemuCommandBuilder {
+    /// Start QEMU with stdin closed and stdout (the serial port, with
+    /// `serial_stdio()`) and stderr captured.
+    pub fn spawn(&self) -> io::Result<QemuProcess> {
+        let argv = self.build();
+        let mut child = Command::new(&argv[0])
+            .args(&argv[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let serial = Captured::spawn(child.stdout.take().expect("stdout is piped"));
+        let stderr = Captured::spawn(child.stderr.take().expect("stderr is piped"));
+        Ok(QemuProcess {
+            child,
+            serial,
+            stderr,
+            debug_exit: self.debug_exit,
+            timeout: self.timeout,
+            started: Instant::now(),
+        })
+    }
+}
+
+impl QemuProcess {
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Watch the serial output as it arrives; `wait()` still returns all
+    /// of it.
+    pub fn serial_log(&self) -> SerialLog {
+        SerialLog::new(Arc::clone(&self.serial.stream))
+    }
+
+    /// Wait for QEMU to exit, killing it once the builder's timeout has
+    /// passed since `spawn()`. Output still arriving after the exit is
+    /// collected for at most [`DRAIN_GRACE`].
+    pub fn wait(mut self) -> io::Result<QemuRun> {
+        let exit = match self.timeout {
+            None => {
+                let status = self.child.wait()?;
+                QemuExit::from_code(status.code(), self.debug_exit)
+            }
+            Some(timeout) => loop {
+                if let Some(status) = self.child.try_wait()? {
+                    break QemuExit::from_code(status.code(), self.debug_exit);
+                }
+                let elapsed = self.started.elapsed();
+                if elapsed >= timeout {
+                    self.child.kill()?;
+                    self.child.wait()?;
+                    break QemuExit::TimedOut;
+                }
+                thread::sleep(POLL_INTERVAL.min(timeout - elapsed));
+            },
+        };
+        let duration = self.started.elapsed();
+        let drained = Instant::now() + DRAIN_GRACE;
+        Ok(QemuRun {
+            exit,
+            serial: self.serial.finish(drained),
+            stderr: self.stderr.finish(drained),
+            duration,
+        })
+    }
+}
+
+impl Drop for QemuProcess {
+    fn drop(&mut self) {
+        // Already reaped after wait(); otherwise don't leave QEMU running
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code_mapping() {
+        assert_eq!(QemuExit::from_code(Some(33), true), QemuExit::Guest(0x10));
+        assert_eq!(QemuExit::from_code(Some(3), true), QemuExit::Guest(1));
+        assert_eq!(QemuExit::from_code(Some(1), true), QemuExit::Exited(1));
+        assert_eq!(QemuExit::from_code(Some(0), true), QemuExit::Exited(0));
+        assert_eq!(QemuExit::from_code(Some(33), false), QemuExit::Exited(33));
+        assert_eq!(QemuExit::from_code(None, true), QemuExit::Signaled);
+    }
+
+    /// Write an executable shell script standing in for QEMU.
+    #[cfg(unix)]
+    pub(crate) fn stub_qemu(name: &str, script: &str) -> std::path::PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("uefi-qemu-stub-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{}", script)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    /// Spawn, retrying while another test thread's fork still holds the
+    /// freshly written script open ("text file busy").
+    #[cfg(unix)]
+    pub(crate) fn spawn(builder: &QemuCommandBuilder) -> QemuProcess {
+        const ETXTBSY: i32 = 26;
+        for _ in 0..50 {
+            match builder.spawn() {
+                Err(error) if error.raw_os_error() == Some(ETXTBSY) => {
+                    thread::sleep(Duration::from_millis(10))
+                }
+                result => return result.unwrap(),
+            }
+        }
+        panic!("stub stayed busy");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stub_run_captures_serial_and_guest_exit() {
+        let stub = stub_qemu(
+            "exit-0x10",
+            "echo 'BdsDxe: starting Boot0001'\n\
+             echo 'Hello from the guest'\n\
+             printf '%s\\n' \"$@\" >&2\n\
+             exit 33\n",
+        );
+        let builder = QemuCommandBuilder::new()
+            .executable(&stub)
+            .esp_dir("/tmp/my esp")
+            .serial_stdio()
+            .isa_debug_exit(DEFAULT_DEBUG_EXIT_IOBASE)
+            .timeout(Duration::from_secs(30));
+        let run = spawn(&builder).wait().unwrap();
+
+        assert_eq!(run.exit, QemuExit::Guest(0x10));
+        assert!(run.passed(0x10));
+        assert_eq!(
+            String::from_utf8_lossy(&run.serial),
+            "BdsDxe: starting Boot0001\nHello from the guest\n"
+        );
+        // Each argv element arrives as one argument, spaces included
+        let args: Vec<String> = run.stderr_text().lines().map(String::from).collect();
+        assert_eq!(
+            args,
+            [
+                "-serial",
+                "stdio",
+                "-drive",
+                "format=raw,file=fat:rw:/tmp/my esp",
+                "-device",
+                "isa-debug-exit,iobase=0xf4,iosize=0x04",
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_timeout_kills_qemu() {
+        let stub = stub_qemu("hang", "echo 'booting'\nexec sleep 30\n");
+        let builder = QemuCommandBuilder::new()
+            .executable(&stub)
+            .timeout(Duration::from_millis(200));
+        let run = spawn(&builder).wait().unwrap();
+
+        assert_eq!(run.exit, QemuExit::TimedOut);
+        assert!(!run.passed(0x10));
+        assert!(run.duration < Duration::from_secs(10));
+        assert_eq!(run.serial, b"booting\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_timeout_with_orphan_holding_pipes() {
+        // The shell forks sleep, which keeps stdout open after the kill
+        let stub = stub_qemu("hang-forked", "echo 'booting'\nsleep 30\necho 'late'\n");
+        let builder = QemuCommandBuilder::new()
+            .executable(&stub)
+            .timeout(Duration::from_millis(200));
+        let started = Instant::now();
+        let run = spawn(&builder).wait().unwrap();
+
+        assert_eq!(run.exit, QemuExit::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(run.serial, b"booting\n");
+    }
+}